@@ -38,6 +38,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "./proto/opentelemetry/resource.proto",
                 "./proto/opentelemetry/trace.proto",
                 "./proto/opentelemetry/trace_service.proto",
+                "./proto/opentelemetry/metrics.proto",
+                "./proto/opentelemetry/metrics_service.proto",
+                "./proto/opentelemetry/logs.proto",
+                "./proto/opentelemetry/logs_service.proto",
             ],
             &["proto"],
         )?;
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use actix_web::{http::header::CONTENT_TYPE, HttpRequest};
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -61,3 +62,30 @@ pub async fn get_api_key_from_raw_value(
         }
     }
 }
+
+/// Wire encoding of an OTLP/HTTP request body.
+///
+/// Compression (`Content-Encoding: gzip/deflate`) is handled transparently by actix
+/// when the body is extracted, so only the `Content-Type` needs to be negotiated here.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OtlpEncoding {
+    Protobuf,
+    Json,
+}
+
+/// Defaults to protobuf if the `Content-Type` header is missing, for backwards compatibility
+/// with exporters that don't set it.
+pub fn get_otlp_encoding(req: &HttpRequest) -> OtlpEncoding {
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    if content_type.starts_with("application/json") {
+        OtlpEncoding::Json
+    } else {
+        OtlpEncoding::Protobuf
+    }
+}
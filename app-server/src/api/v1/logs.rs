@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use prost::Message;
+
+use crate::{
+    api::utils::{get_otlp_encoding, OtlpEncoding},
+    db::project_api_keys::ProjectApiKey,
+    logs::producer::push_logs_to_queue,
+    mq::MessageQueue,
+    opentelemetry::opentelemetry::proto::collector::logs::v1::ExportLogsServiceRequest,
+    routes::{error::Error, types::ResponseResult},
+};
+
+#[post("logs")]
+pub async fn process_logs(
+    req: HttpRequest,
+    body: Bytes,
+    project_api_key: ProjectApiKey,
+    message_queue: web::Data<Arc<MessageQueue>>,
+) -> ResponseResult {
+    if get_otlp_encoding(&req) == OtlpEncoding::Json {
+        return Err(Error::invalid_request(Some(
+            "Only protobuf encoded OTLP logs are supported",
+        )));
+    }
+
+    let request = ExportLogsServiceRequest::decode(body).map_err(|e| {
+        anyhow::anyhow!("Failed to decode ExportLogsServiceRequest from bytes. {e}")
+    })?;
+    let response = push_logs_to_queue(
+        request,
+        project_api_key.project_id,
+        message_queue.as_ref().clone(),
+    )
+    .await?;
+
+    let keep_alive = req.headers().get("connection").map_or(false, |v| {
+        v.to_str().unwrap_or_default().trim().to_lowercase() == "keep-alive"
+    });
+    let mut http_response = HttpResponse::Ok();
+    if keep_alive {
+        http_response.keep_alive();
+    }
+    Ok(http_response
+        .content_type("application/x-protobuf")
+        .body(response.encode_to_vec()))
+}
@@ -1,18 +1,50 @@
-use actix_web::{post, HttpRequest, HttpResponse};
+use std::sync::Arc;
 
-use crate::routes::types::ResponseResult;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use prost::Message;
+
+use crate::{
+    api::utils::{get_otlp_encoding, OtlpEncoding},
+    db::project_api_keys::ProjectApiKey,
+    metrics::producer::push_metrics_to_queue,
+    mq::MessageQueue,
+    opentelemetry::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest,
+    routes::types::ResponseResult,
+};
 
 #[post("metrics")]
-pub async fn process_metrics(req: HttpRequest) -> ResponseResult {
-    // This is a placeholder that just returns ok, so that client otel exporters
-    // don't fail when trying to send metrics to the server.
-    // TODO: Implement metrics processing
+pub async fn process_metrics(
+    req: HttpRequest,
+    body: Bytes,
+    project_api_key: ProjectApiKey,
+    message_queue: web::Data<Arc<MessageQueue>>,
+) -> ResponseResult {
+    // Only protobuf exports are ingested. JSON exports are rejected rather than acknowledged,
+    // so that exporters do not treat their dropped data points as delivered.
+    if get_otlp_encoding(&req) == OtlpEncoding::Json {
+        return Ok(HttpResponse::UnsupportedMediaType()
+            .body("Only protobuf encoded OTLP metrics are supported"));
+    }
+
+    let request = ExportMetricsServiceRequest::decode(body).map_err(|e| {
+        anyhow::anyhow!("Failed to decode ExportMetricsServiceRequest from bytes. {e}")
+    })?;
+    let response = push_metrics_to_queue(
+        request,
+        project_api_key.project_id,
+        message_queue.as_ref().clone(),
+    )
+    .await?;
+
     let keep_alive = req.headers().get("connection").map_or(false, |v| {
         v.to_str().unwrap_or_default().trim().to_lowercase() == "keep-alive"
     });
+    let mut http_response = HttpResponse::Ok();
     if keep_alive {
-        Ok(HttpResponse::Ok().keep_alive().finish())
-    } else {
-        Ok(HttpResponse::Ok().finish())
+        http_response.keep_alive();
     }
+    Ok(http_response
+        .content_type("application/x-protobuf")
+        .body(response.encode_to_vec()))
 }
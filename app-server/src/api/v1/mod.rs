@@ -2,8 +2,10 @@ pub mod browser_sessions;
 pub mod datasets;
 pub mod evals;
 pub mod evaluations;
+pub mod logs;
 pub mod machine_manager;
 pub mod metrics;
+pub mod payloads;
 pub mod pipelines;
 pub mod queues;
 pub mod semantic_search;
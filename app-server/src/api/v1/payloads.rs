@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use actix_web::{get, web};
+
+use crate::{
+    db::project_api_keys::ProjectApiKey,
+    routes::{
+        payloads::{payload_response, GetPayloadParams},
+        types::ResponseResult,
+    },
+    storage::Storage,
+};
+
+#[get("payloads/{payload_id}")]
+pub async fn get_payload(
+    path: web::Path<String>,
+    project_api_key: ProjectApiKey,
+    storage: web::Data<Arc<Storage>>,
+    params: web::Query<GetPayloadParams>,
+) -> ResponseResult {
+    let payload_id = path.into_inner();
+    let params = params.into_inner();
+
+    payload_response(
+        &storage,
+        &project_api_key.project_id,
+        &payload_id,
+        params.payload_type.as_deref(),
+    )
+    .await
+}
@@ -1,18 +1,30 @@
 use std::{collections::HashMap, sync::Arc};
 
 use actix_web::{get, post, web, HttpResponse};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
     api::utils::query_target_pipeline_version,
     cache::Cache,
-    db::{project_api_keys::ProjectApiKey, trace::CurrentTraceAndSpan, DB},
+    db::{
+        self,
+        pipelines::{NewPipelineRun, PipelineRunStatus},
+        project_api_keys::ProjectApiKey,
+        trace::CurrentTraceAndSpan,
+        DB,
+    },
+    mq::MessageQueue,
     pipeline::{
         nodes::{GraphOutput, GraphRunOutput, NodeInput, RunEndpointEventError, StreamChunk},
         runner::{PipelineRunner, PipelineRunnerError},
         Graph, RunType,
     },
+    pipeline_jobs::{
+        producer::{publish_pipeline_job, push_pipeline_job_to_queue},
+        webhook::validate_webhook_url,
+        PipelineJobMessage,
+    },
     routes::{
         error::{self, pipeline_runner_to_http_error},
         types::ResponseResult,
@@ -33,6 +45,13 @@ struct GraphRequest {
     metadata: HashMap<String, String>,
     #[serde(default)]
     stream: bool,
+    /// If true, the messages of the nodes are checkpointed under the run id, so that the run
+    /// can be queried with `pipeline/runs/{run_id}` and resumed if it fails or is interrupted
+    #[serde(default)]
+    durable: bool,
+    /// Id of the durable run. If None, new id will be generated
+    #[serde(default)]
+    run_id: Option<Uuid>,
 }
 
 #[post("pipeline/run")]
@@ -63,8 +82,11 @@ async fn run_pipeline_graph(
         return Err(error::Error::no_target_pipeline(&req.pipeline));
     };
     let pipeline_version_name = format!("{}.{}", req.pipeline, pipeline_version.name);
+    let pipeline_version_id = pipeline_version.id;
 
-    let run_id = Uuid::new_v4(); // used to uniquely identify the related log or run trace
+    // used to uniquely identify the related log or run trace
+    let run_id = req.run_id.unwrap_or_else(Uuid::new_v4);
+    let durable = req.durable;
     let run_type = RunType::Endpoint;
     let mut graph = serde_json::from_value::<Graph>(pipeline_version.runnable_graph)
         .map_err(|e| error::Error::deserialization_error(Some(e)))?;
@@ -78,7 +100,19 @@ async fn run_pipeline_graph(
             let (tx, mut rx) = tokio::sync::mpsc::channel::<StreamChunk>(8);
 
             tokio::spawn(async move {
-                let run_result = pipeline_runner.run(graph, Some(tx.clone())).await;
+                let run_result = if durable {
+                    pipeline_runner.run_durable(
+                        graph,
+                        run_id,
+                        &project_id,
+                        &pipeline_version_id,
+                        &pipeline_version_name,
+                        Some(tx.clone()),
+                    )
+                    .await
+                } else {
+                    pipeline_runner.run(graph, &project_id, Some(tx.clone())).await
+                };
                 // write the trace
                 pipeline_runner.record_observations(
                     &run_result,
@@ -139,8 +173,45 @@ async fn run_pipeline_graph(
         Ok(HttpResponse::Ok()
             .content_type("text/event-stream")
             .streaming(stream))
+    } else if durable {
+        // the run is spawned, so that it completes, is checkpointed and is recorded even
+        // if the client disconnects
+        let run_result = tokio::spawn(async move {
+            let run_result = pipeline_runner
+                .run_durable(
+                    graph,
+                    run_id,
+                    &project_id,
+                    &pipeline_version_id,
+                    &pipeline_version_name,
+                    None,
+                )
+                .await;
+            pipeline_runner
+                .record_observations(
+                    &run_result,
+                    &project_id,
+                    &pipeline_version_name,
+                    current_trace_and_span,
+                    None,
+                )
+                .await
+                .map(|_| run_result)
+        })
+        .await
+        .map_err(anyhow::Error::from)??;
+
+        let run_result = run_result.map_err(|e| pipeline_runner_to_http_error(e, run_id))?;
+        let outputs = run_result
+            .output_values()
+            .into_iter()
+            .map(|(node_name, value)| (node_name, GraphOutput { value }))
+            .collect();
+        let res = GraphRunOutput { outputs, run_id };
+
+        Ok(HttpResponse::Ok().json(res))
     } else {
-        let run_result = pipeline_runner.run(graph, None).await;
+        let run_result = pipeline_runner.run(graph, &project_id, None).await;
 
         pipeline_runner
             .record_observations(
@@ -164,6 +235,196 @@ async fn run_pipeline_graph(
     }
 }
 
+#[derive(Deserialize)]
+struct PipelineRunWebhook {
+    url: String,
+    /// Secret the webhook calls are signed with
+    secret: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmitRunRequest {
+    /// Name of the pipeline to run
+    pipeline: String,
+    inputs: HashMap<String, NodeInput>,
+    /// If None, new trace will be generated
+    #[serde(default, flatten)]
+    current_trace_and_span: Option<CurrentTraceAndSpan>,
+    env: HashMap<String, String>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    /// Webhook that is called once the run finishes
+    #[serde(default)]
+    webhook: Option<PipelineRunWebhook>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmitRunResponse {
+    run_id: Uuid,
+}
+
+/// Submit a run to the pipeline jobs queue, instead of running it on the web worker.
+/// The run is durable, and can be polled with `pipeline/runs/{run_id}`
+#[post("pipeline/runs")]
+async fn submit_pipeline_run(
+    params: web::Json<SubmitRunRequest>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+    queue: web::Data<Arc<MessageQueue>>,
+    project_api_key: ProjectApiKey,
+) -> ResponseResult {
+    let req = params.into_inner();
+    let db = db.into_inner();
+    let cache = cache.into_inner();
+    let project_id = project_api_key.project_id;
+    let mut env = req.env;
+
+    if let Some(webhook) = &req.webhook {
+        validate_webhook_url(&webhook.url)
+            .map_err(|e| error::Error::invalid_request(Some(&e.to_string())))?;
+        if webhook.secret.is_empty() {
+            return Err(error::Error::invalid_request(Some(
+                "Webhook secret must not be empty",
+            )));
+        }
+    }
+
+    env.insert("collection_name".to_string(), project_id.to_string());
+
+    let pipeline_version =
+        query_target_pipeline_version(db.clone(), cache.clone(), project_id, req.pipeline.clone())
+            .await?;
+
+    let Some(pipeline_version) = pipeline_version else {
+        return Err(error::Error::no_target_pipeline(&req.pipeline));
+    };
+
+    let mut graph = serde_json::from_value::<Graph>(pipeline_version.runnable_graph)
+        .map_err(|e| error::Error::deserialization_error(Some(e)))?;
+    graph
+        .setup(&req.inputs, &env, &req.metadata, &RunType::Endpoint)
+        .map_err(error::graph_error_to_http_error)?;
+
+    let run_id = Uuid::new_v4();
+    let (webhook_url, webhook_secret) = match req.webhook {
+        Some(webhook) => (Some(webhook.url), Some(webhook.secret)),
+        None => (None, None),
+    };
+    let run = NewPipelineRun {
+        id: run_id,
+        project_id,
+        pipeline_version_id: pipeline_version.id,
+        pipeline_version_name: format!("{}.{}", req.pipeline, pipeline_version.name),
+        status: PipelineRunStatus::QUEUED,
+        graph: serde_json::to_value(&graph)
+            .map_err(|e| error::Error::deserialization_error(Some(e)))?,
+        metadata: serde_json::to_value(&req.metadata)
+            .map_err(|e| error::Error::deserialization_error(Some(e)))?,
+        webhook_url,
+        webhook_secret,
+    };
+
+    push_pipeline_job_to_queue(
+        db,
+        queue.as_ref().clone(),
+        run,
+        env,
+        req.current_trace_and_span,
+    )
+    .await?;
+
+    Ok(HttpResponse::Accepted().json(SubmitRunResponse { run_id }))
+}
+
+#[get("pipeline/runs/{run_id}")]
+async fn get_pipeline_run(
+    run_id: web::Path<Uuid>,
+    db: web::Data<DB>,
+    project_api_key: ProjectApiKey,
+) -> ResponseResult {
+    let run_id = run_id.into_inner();
+    let project_id = project_api_key.project_id;
+
+    let Some(run) = db::pipelines::get_pipeline_run(&db.pool, &project_id, &run_id).await? else {
+        return Ok(HttpResponse::NotFound().body(format!("Pipeline run not found: {}", run_id)));
+    };
+
+    Ok(HttpResponse::Ok().json(run))
+}
+
+/// Cancel a queued or running run. Running runs stop within a few seconds, and the nodes
+/// that are running are aborted
+#[post("pipeline/runs/{run_id}/cancel")]
+async fn cancel_pipeline_run(
+    run_id: web::Path<Uuid>,
+    db: web::Data<DB>,
+    project_api_key: ProjectApiKey,
+) -> ResponseResult {
+    let run_id = run_id.into_inner();
+    let project_id = project_api_key.project_id;
+
+    let Some(run) = db::pipelines::cancel_pipeline_run(&db.pool, &project_id, &run_id).await?
+    else {
+        return Ok(HttpResponse::Conflict().body(format!(
+            "Pipeline run not found or already finished: {}",
+            run_id
+        )));
+    };
+
+    Ok(HttpResponse::Ok().json(run))
+}
+
+#[derive(Deserialize)]
+struct ResumeRunRequest {
+    env: HashMap<String, String>,
+}
+
+/// Resume a durable run that failed or was interrupted from the messages it checkpointed.
+/// The run is submitted to the pipeline jobs queue again, and its webhook, if any, is
+/// called once it finishes. It can be polled with `pipeline/runs/{run_id}`.
+///
+/// The nodes that completed are not run again. Map and Subpipeline nodes that did not
+/// complete are run again as a whole, as the progress of their inner runs is not checkpointed.
+///
+/// Running runs send heartbeats, and are only considered interrupted once they stop doing so.
+#[post("pipeline/runs/{run_id}/resume")]
+async fn resume_pipeline_run(
+    run_id: web::Path<Uuid>,
+    params: web::Json<ResumeRunRequest>,
+    db: web::Data<DB>,
+    queue: web::Data<Arc<MessageQueue>>,
+    project_api_key: ProjectApiKey,
+) -> ResponseResult {
+    let db = db.into_inner();
+    let run_id = run_id.into_inner();
+    let project_id = project_api_key.project_id;
+    let mut env = params.into_inner().env;
+
+    env.insert("collection_name".to_string(), project_id.to_string());
+
+    if db::pipelines::restart_pipeline_run(&db.pool, &project_id, &run_id)
+        .await?
+        .is_none()
+    {
+        return Ok(HttpResponse::Conflict().body(format!(
+            "Pipeline run not found, succeeded or still running: {}",
+            run_id
+        )));
+    }
+
+    let message = PipelineJobMessage {
+        run_id,
+        project_id,
+        env,
+        current_trace_and_span: None,
+    };
+    publish_pipeline_job(db, queue.as_ref().clone(), message).await?;
+
+    Ok(HttpResponse::Accepted().json(SubmitRunResponse { run_id }))
+}
+
 #[get("healthcheck")]
 async fn ping_healthcheck() -> ResponseResult {
     Ok(HttpResponse::Ok().finish())
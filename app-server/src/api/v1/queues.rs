@@ -10,7 +10,7 @@ use crate::{
     cache::Cache,
     db::{
         project_api_keys::ProjectApiKey,
-        spans::{Span, SpanType},
+        spans::{Span, SpanStatus, SpanType},
         trace::TraceType,
         DB,
     },
@@ -64,6 +64,7 @@ async fn push_to_queue(
     };
 
     let mut span_ids = Vec::with_capacity(request_items.len());
+    let mut spans = Vec::with_capacity(request_items.len());
     let num_spans = request_items.len();
     crate::db::stats::add_spans_and_events_to_project_usage_stats(
         &db.pool,
@@ -87,6 +88,17 @@ async fn push_to_queue(
         }
     }
 
+    let redaction_rules = crate::traces::redaction::get_project_redaction_rules(
+        db.clone(),
+        cache.clone(),
+        &project_id,
+    )
+    .await?;
+    let redactor = crate::traces::redaction::Redactor::new(
+        &redaction_rules,
+        crate::traces::redaction::project_hash_key(&project_id),
+    );
+
     for request_item in request_items {
         let mut attributes = request_item.attributes;
         attributes.insert(
@@ -110,20 +122,35 @@ async fn push_to_queue(
             // TODO: store the input and output in storage if they are too large
             input_url: None,
             output_url: None,
+            status: SpanStatus::UNSET,
+            status_message: None,
         };
 
+        if !redactor.is_empty() {
+            redactor.redact_span(&mut span, &mut []);
+        }
+
         let span_usage = crate::traces::utils::get_llm_usage_for_span(
             &mut span.get_attributes(),
             db.clone(),
             cache.clone(),
+            &project_id,
+            &span.start_time,
         )
         .await;
 
-        crate::traces::utils::record_span_to_db(db.clone(), &span_usage, &project_id, &mut span)
-            .await?;
+        let trace_attributes =
+            crate::traces::utils::prepare_span_for_recording(&span_usage, &mut span);
         span_ids.push(span.span_id);
+        spans.push((span, trace_attributes));
     }
 
+    let records = spans
+        .iter()
+        .map(|(span, trace_attributes)| (project_id, span, trace_attributes))
+        .collect::<Vec<_>>();
+    crate::traces::utils::record_spans_to_db(db.clone(), &records).await?;
+
     let queue_entries = span_ids
         .iter()
         .map(|span_id| LabelingQueueEntry {
@@ -6,12 +6,17 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
+    api::utils::{get_otlp_encoding, OtlpEncoding},
     db::{events::Event, project_api_keys::ProjectApiKey, spans::Span, DB},
     features::{is_feature_enabled, Feature},
     mq::MessageQueue,
     opentelemetry::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest,
     routes::types::ResponseResult,
-    traces::{limits::get_workspace_limit_exceeded_by_project_id, producer::push_spans_to_queue},
+    traces::{
+        limits::get_workspace_limit_exceeded_by_project_id,
+        otlp_json,
+        producer::{push_spans_to_queue, reject_all_spans},
+    },
 };
 use prost::Message;
 
@@ -33,36 +38,68 @@ pub async fn process_traces(
 ) -> ResponseResult {
     let db = db.into_inner();
     let cache = cache.into_inner();
-    let request = ExportTraceServiceRequest::decode(body).map_err(|e| {
-        anyhow::anyhow!("Failed to decode ExportTraceServiceRequest from bytes. {e}")
-    })?;
+    let encoding = get_otlp_encoding(&req);
+    let request = match encoding {
+        OtlpEncoding::Protobuf => ExportTraceServiceRequest::decode(body).map_err(|e| {
+            anyhow::anyhow!("Failed to decode ExportTraceServiceRequest from bytes. {e}")
+        })?,
+        OtlpEncoding::Json => {
+            otlp_json::decode_export_trace_service_request(&body).map_err(|e| {
+                anyhow::anyhow!("Failed to decode ExportTraceServiceRequest from JSON. {e}")
+            })?
+        }
+    };
     let spans_message_queue = spans_message_queue.as_ref().clone();
 
-    if is_feature_enabled(Feature::UsageLimit) {
-        let limits_exceeded = get_workspace_limit_exceeded_by_project_id(
+    let limits_exceeded = if is_feature_enabled(Feature::UsageLimit) {
+        get_workspace_limit_exceeded_by_project_id(
             db.clone(),
             cache.clone(),
             project_api_key.project_id,
         )
-        .await?;
+        .await?
+        .spans
+    } else {
+        false
+    };
 
-        if limits_exceeded.spans {
-            return Ok(HttpResponse::Forbidden().json("Workspace span limit exceeded"));
+    let response = if limits_exceeded {
+        reject_all_spans(&request)
+    } else {
+        match push_spans_to_queue(request, project_api_key.project_id, spans_message_queue).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!(
+                    "Failed to process traces. project_id [{}]: {:?}",
+                    project_api_key.project_id,
+                    e
+                );
+                // 503 is retryable by OTLP exporters, unlike 500
+                return Ok(HttpResponse::ServiceUnavailable().finish());
+            }
         }
-    }
-
-    let response =
-        push_spans_to_queue(request, project_api_key.project_id, spans_message_queue).await?;
-    if response.partial_success.is_some() {
-        return Err(anyhow::anyhow!("There has been an error during trace processing.").into());
+    };
+    if let Some(partial_success) = &response.partial_success {
+        log::debug!(
+            "Partially accepted trace export. project_id [{}]: {}",
+            project_api_key.project_id,
+            partial_success.error_message
+        );
     }
 
     let keep_alive = req.headers().get("connection").map_or(false, |v| {
         v.to_str().unwrap_or_default().trim().to_lowercase() == "keep-alive"
     });
+    let mut http_response = HttpResponse::Ok();
     if keep_alive {
-        Ok(HttpResponse::Ok().keep_alive().finish())
-    } else {
-        Ok(HttpResponse::Ok().finish())
+        http_response.keep_alive();
+    }
+    match encoding {
+        OtlpEncoding::Protobuf => Ok(http_response
+            .content_type("application/x-protobuf")
+            .body(response.encode_to_vec())),
+        OtlpEncoding::Json => {
+            Ok(http_response.json(otlp_json::encode_export_trace_service_response(&response)))
+        }
     }
 }
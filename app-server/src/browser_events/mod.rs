@@ -9,7 +9,10 @@ use crate::{
         EventBatch, BROWSER_SESSIONS_EXCHANGE, BROWSER_SESSIONS_QUEUE, BROWSER_SESSIONS_ROUTING_KEY,
     },
     ch::browser_events::insert_browser_events,
-    mq::{MessageQueue, MessageQueueDeliveryTrait, MessageQueueReceiverTrait, MessageQueueTrait},
+    mq::{
+        dead_letter_delivery, MessageOrigin, MessageQueue, MessageQueueDeliveryTrait,
+        MessageQueueReceiverTrait, MessageQueueTrait,
+    },
 };
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -38,6 +41,12 @@ async fn inner_process_browser_events(clickhouse: clickhouse::Client, queue: Arc
         .await
         .unwrap();
 
+    let origin = MessageOrigin::new(
+        BROWSER_SESSIONS_QUEUE,
+        BROWSER_SESSIONS_EXCHANGE,
+        BROWSER_SESSIONS_ROUTING_KEY,
+    );
+
     while let Some(delivery) = receiver.receive().await {
         if let Err(e) = delivery {
             log::error!("Failed to receive message from queue: {:?}", e);
@@ -45,11 +54,13 @@ async fn inner_process_browser_events(clickhouse: clickhouse::Client, queue: Arc
         }
         let delivery = delivery.unwrap();
         let acker = delivery.acker();
-        let message = match serde_json::from_slice::<QueueBrowserEventMessage>(&delivery.data()) {
+        let payload = delivery.data();
+        let message = match serde_json::from_slice::<QueueBrowserEventMessage>(&payload) {
             Ok(message) => message,
             Err(e) => {
                 log::error!("Failed to deserialize message from queue: {:?}", e);
-                let _ = acker.reject(false).await;
+                let reason = format!("Failed to deserialize message: {}", e);
+                dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
                 continue;
             }
         };
@@ -91,10 +102,8 @@ async fn inner_process_browser_events(clickhouse: clickhouse::Client, queue: Arc
                     "Exhausted backoff retries. Failed to insert browser events: {:?}",
                     e
                 );
-                // TODO: Implement proper nacks and DLX
-                if let Err(e) = acker.reject(false).await {
-                    log::error!("Failed to reject MQ delivery (browser events): {:?}", e);
-                }
+                let reason = format!("Failed to insert browser events: {:?}", e);
+                dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
             }
         }
     }
@@ -1,21 +1,91 @@
 use serde::{Deserialize, Serialize};
-use std::result::Result;
+use std::{result::Result, time::Duration};
 
 use async_trait::async_trait;
 
-use super::{CacheError, CacheTrait};
+use super::{
+    invalidation::{self, CacheInvalidator},
+    keys, CacheError, CacheTrait,
+};
+
+// Number of entries, the cache holds API keys, projects, prices and rules of all projects
+const DEFAULT_CACHE_SIZE: u64 = 10_000;
+
+#[derive(Clone)]
+pub struct CacheEntry {
+    bytes: Vec<u8>,
+    ttl: Option<Duration>,
+}
+
+/// Expires every entry after its own TTL
+struct CacheEntryExpiry;
+
+impl moka::Expiry<String, CacheEntry> for CacheEntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        value: &CacheEntry,
+        _created_at: std::time::Instant,
+    ) -> Option<Duration> {
+        value.ttl
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        value: &CacheEntry,
+        _updated_at: std::time::Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        value.ttl
+    }
+}
 
-const DEFAULT_CACHE_SIZE: u64 = 100;
 pub struct InMemoryCache {
-    cache: moka::future::Cache<String, Vec<u8>>,
+    cache: moka::future::Cache<String, CacheEntry>,
+    invalidator: Option<CacheInvalidator>,
 }
 
 impl InMemoryCache {
     pub fn new(capacity: Option<u64>) -> Self {
         Self {
-            cache: moka::future::Cache::new(capacity.unwrap_or(DEFAULT_CACHE_SIZE)),
+            cache: moka::future::Cache::builder()
+                .max_capacity(capacity.unwrap_or(DEFAULT_CACHE_SIZE))
+                .expire_after(CacheEntryExpiry)
+                .build(),
+            invalidator: None,
         }
     }
+
+    /// In-memory cache that shares removals with the other instances through Redis pub/sub
+    pub async fn with_invalidation(
+        capacity: Option<u64>,
+        redis_url: &str,
+    ) -> Result<Self, CacheError> {
+        let client = redis::Client::open(redis_url).map_err(anyhow::Error::from)?;
+        let invalidator = CacheInvalidator::new(&client).await?;
+
+        let mut cache = Self::new(capacity);
+        invalidation::spawn_listener(client, cache.cache.clone());
+        cache.invalidator = Some(invalidator);
+        Ok(cache)
+    }
+
+    async fn insert_entry<T>(
+        &self,
+        key: &str,
+        value: T,
+        ttl: Option<Duration>,
+    ) -> Result<(), CacheError>
+    where
+        T: Serialize + Send,
+    {
+        let bytes = serde_json::to_vec(&value).map_err(|e| CacheError::SerDeError(e))?;
+        self.cache
+            .insert(String::from(key), CacheEntry { bytes, ttl })
+            .await;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -24,11 +94,11 @@ impl CacheTrait for InMemoryCache {
     where
         T: for<'de> Deserialize<'de>,
     {
-        let Some(bytes) = self.cache.get(key).await else {
+        let Some(entry) = self.cache.get(key).await else {
             return Ok(None);
         };
 
-        let value = serde_json::from_slice(&bytes).map_err(|e| CacheError::SerDeError(e))?;
+        let value = serde_json::from_slice(&entry.bytes).map_err(|e| CacheError::SerDeError(e))?;
         Ok(Some(value))
     }
 
@@ -36,13 +106,21 @@ impl CacheTrait for InMemoryCache {
     where
         T: Serialize + Send,
     {
-        let bytes = serde_json::to_vec(&value).map_err(|e| CacheError::SerDeError(e))?;
-        self.cache.insert(String::from(key), bytes).await;
-        Ok(())
+        self.insert_entry(key, value, keys::default_ttl(key)).await
+    }
+
+    async fn insert_with_ttl<T>(&self, key: &str, value: T, ttl: Duration) -> Result<(), CacheError>
+    where
+        T: Serialize + Send,
+    {
+        self.insert_entry(key, value, Some(ttl)).await
     }
 
     async fn remove(&self, key: &str) -> Result<(), CacheError> {
         self.cache.remove(key).await;
+        if let Some(invalidator) = &self.invalidator {
+            invalidator.publish(key).await?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,79 @@
+//! Invalidation of in-memory caches across app-server instances.
+//!
+//! Removed keys are published to a Redis channel, and every instance that listens
+//! to the channel drops them from its own cache. Inserts are not published, so that
+//! instances populating their caches on misses do not evict each other's entries.
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use redis::{aio::MultiplexedConnection, AsyncCommands, Client};
+
+use super::in_memory::CacheEntry;
+
+const CACHE_INVALIDATION_CHANNEL: &str = "cache_invalidation";
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct CacheInvalidator {
+    connection: MultiplexedConnection,
+}
+
+impl CacheInvalidator {
+    pub async fn new(client: &Client) -> anyhow::Result<Self> {
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(Self { connection })
+    }
+
+    pub async fn publish(&self, key: &str) -> anyhow::Result<()> {
+        let _: usize = self
+            .connection
+            .clone()
+            .publish(CACHE_INVALIDATION_CHANNEL, key)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Remove the published keys from the cache until the process exits.
+///
+/// Invalidations published while the listener is disconnected are lost,
+/// so the whole cache is dropped when it reconnects.
+pub fn spawn_listener(client: Client, cache: moka::future::Cache<String, CacheEntry>) {
+    tokio::spawn(async move {
+        let mut reconnect = false;
+        loop {
+            if let Err(e) = listen(&client, &cache, reconnect).await {
+                log::error!("Cache invalidation listener failed: {:?}", e);
+            } else {
+                log::warn!("Cache invalidation listener exited");
+            }
+            reconnect = true;
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn listen(
+    client: &Client,
+    cache: &moka::future::Cache<String, CacheEntry>,
+    reconnect: bool,
+) -> anyhow::Result<()> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(CACHE_INVALIDATION_CHANNEL).await?;
+    if reconnect {
+        cache.invalidate_all();
+    }
+
+    let mut messages = pubsub.into_on_message();
+    while let Some(message) = messages.next().await {
+        match message.get_payload::<String>() {
+            Ok(key) => cache.remove(&key).await,
+            Err(e) => {
+                log::error!("Invalid cache invalidation message: {:?}", e);
+                continue;
+            }
+        };
+    }
+    Ok(())
+}
@@ -1,8 +1,41 @@
 //! This module contains the prefixes for the cache keys.
 //! Keys are used across modules and need to be stored in a single place
 
+use std::time::Duration;
+
 pub const LLM_PRICES_CACHE_KEY: &str = "llm_prices";
+pub const MODEL_NAME_RULES_CACHE_KEY: &str = "model_name_rules";
 pub const PROJECT_API_KEY_CACHE_KEY: &str = "project_api_key";
 pub const PROJECT_CACHE_KEY: &str = "project";
+pub const PROJECT_LLM_PRICES_CACHE_KEY: &str = "project_llm_prices";
+pub const REDACTION_RULES_CACHE_KEY: &str = "redaction_rules";
+pub const SAMPLING_DECISION_CACHE_KEY: &str = "sampling_decision";
+pub const SAMPLING_POLICY_CACHE_KEY: &str = "sampling_policy";
 pub const TARGET_PIPELINE_VERSION_CACHE_KEY: &str = "target_pipeline_version";
 pub const WORKSPACE_LIMITS_CACHE_KEY: &str = "workspace_limits";
+
+/// Time to live of the entries inserted without an explicit TTL, by key prefix.
+/// Keys are formatted as `{prefix}:{...}`, and keys with other prefixes do not expire.
+const DEFAULT_TTLS: &[(&str, Duration)] = &[
+    (LLM_PRICES_CACHE_KEY, Duration::from_secs(60 * 60)),
+    (MODEL_NAME_RULES_CACHE_KEY, Duration::from_secs(5 * 60)),
+    (PROJECT_API_KEY_CACHE_KEY, Duration::from_secs(10 * 60)),
+    (PROJECT_CACHE_KEY, Duration::from_secs(10 * 60)),
+    (PROJECT_LLM_PRICES_CACHE_KEY, Duration::from_secs(5 * 60)),
+    (REDACTION_RULES_CACHE_KEY, Duration::from_secs(5 * 60)),
+    (SAMPLING_DECISION_CACHE_KEY, Duration::from_secs(15 * 60)),
+    (SAMPLING_POLICY_CACHE_KEY, Duration::from_secs(5 * 60)),
+    (
+        TARGET_PIPELINE_VERSION_CACHE_KEY,
+        Duration::from_secs(5 * 60),
+    ),
+    (WORKSPACE_LIMITS_CACHE_KEY, Duration::from_secs(10 * 60)),
+];
+
+pub fn default_ttl(key: &str) -> Option<Duration> {
+    let prefix = key.split(':').next().unwrap_or_default();
+    DEFAULT_TTLS
+        .iter()
+        .find(|(default_prefix, _)| *default_prefix == prefix)
+        .map(|(_, ttl)| *ttl)
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
 use serde::{Deserialize, Serialize};
@@ -6,6 +8,7 @@ use in_memory::InMemoryCache;
 use redis::RedisCache;
 
 pub mod in_memory;
+pub mod invalidation;
 pub mod keys;
 pub mod redis;
 
@@ -29,7 +32,16 @@ pub trait CacheTrait {
     async fn get<T>(&self, key: &str) -> Result<Option<T>, CacheError>
     where
         T: for<'de> Deserialize<'de>;
+    /// Insert with the default TTL of the key prefix, see [`keys::default_ttl`]
     async fn insert<T>(&self, key: &str, value: T) -> Result<(), CacheError>
+    where
+        T: Serialize + Send;
+    async fn insert_with_ttl<T>(
+        &self,
+        key: &str,
+        value: T,
+        ttl: Duration,
+    ) -> Result<(), CacheError>
     where
         T: Serialize + Send;
     async fn remove(&self, key: &str) -> Result<(), CacheError>;
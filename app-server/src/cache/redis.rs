@@ -1,8 +1,10 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
 use serde::{Deserialize, Serialize};
 
-use super::{CacheError, CacheTrait};
+use super::{keys, CacheError, CacheTrait};
 
 pub struct RedisCache {
     connection: MultiplexedConnection,
@@ -21,6 +23,39 @@ impl RedisCache {
             .map_err(CacheError::InternalError)?;
         Ok(Self { connection })
     }
+
+    async fn set<T>(&self, key: &str, value: T, ttl: Option<Duration>) -> Result<(), CacheError>
+    where
+        T: Serialize + Send,
+    {
+        let bytes = match serde_json::to_vec(&value) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::error!("Serialization error: {}", e);
+                return Err(CacheError::SerDeError(e));
+            }
+        };
+
+        let mut connection = self.connection.clone();
+        let result = match ttl {
+            Some(ttl) => {
+                connection
+                    .pset_ex::<_, Vec<u8>, ()>(String::from(key), bytes, ttl.as_millis() as u64)
+                    .await
+            }
+            None => {
+                connection
+                    .set::<_, Vec<u8>, ()>(String::from(key), bytes)
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            log::error!("Redis set error: {}", e);
+            Err(CacheError::InternalError(anyhow::Error::from(e)))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 #[async_trait]
@@ -54,25 +89,14 @@ impl CacheTrait for RedisCache {
     where
         T: Serialize + Send,
     {
-        let bytes = match serde_json::to_vec(&value) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                log::error!("Serialization error: {}", e);
-                return Err(CacheError::SerDeError(e));
-            }
-        };
+        self.set(key, value, keys::default_ttl(key)).await
+    }
 
-        if let Err(e) = self
-            .connection
-            .clone()
-            .set::<_, Vec<u8>, ()>(String::from(key), bytes)
-            .await
-        {
-            log::error!("Redis set error: {}", e);
-            Err(CacheError::InternalError(anyhow::Error::from(e)))
-        } else {
-            Ok(())
-        }
+    async fn insert_with_ttl<T>(&self, key: &str, value: T, ttl: Duration) -> Result<(), CacheError>
+    where
+        T: Serialize + Send,
+    {
+        self.set(key, value, Some(ttl)).await
     }
 
     async fn remove(&self, key: &str) -> Result<(), CacheError> {
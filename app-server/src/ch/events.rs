@@ -1,9 +1,11 @@
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Result;
 use clickhouse::Row;
 use serde::Serialize;
 use uuid::Uuid;
 
-use crate::db::events::Event;
+use crate::{db::events::Event, traces::utils::json_value_to_string};
 
 use super::utils::chrono_to_nanoseconds;
 
@@ -13,9 +15,13 @@ pub struct CHEvent {
     pub id: Uuid,
     #[serde(with = "clickhouse::serde::uuid")]
     pub project_id: Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub span_id: Uuid,
     /// Timestamp in nanoseconds
     pub timestamp: i64,
     pub name: String,
+    /// Map(String, String) is serialized as an array of key-value tuples
+    pub attributes: Vec<(String, String)>,
 }
 
 impl CHEvent {
@@ -25,11 +31,54 @@ impl CHEvent {
             timestamp: chrono_to_nanoseconds(event.timestamp),
             name: event.name.clone(),
             project_id: event.project_id,
+            span_id: event.span_id,
+            attributes: event
+                .attributes
+                .as_object()
+                .map(|attributes| {
+                    attributes
+                        .iter()
+                        .map(|(key, value)| (key.clone(), json_value_to_string(value)))
+                        .collect()
+                })
+                .unwrap_or_default(),
         }
     }
 }
 
-pub async fn insert_events(clickhouse: clickhouse::Client, events: Vec<CHEvent>) -> Result<()> {
+/// Ids of the events that are already in Clickhouse, so that events which are recorded
+/// again, e.g. when their batch is replayed, are not inserted twice
+pub async fn get_existing_event_ids(
+    clickhouse: &clickhouse::Client,
+    events: &[CHEvent],
+) -> Result<HashSet<Uuid>> {
+    let mut event_ids_by_project: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for event in events {
+        event_ids_by_project
+            .entry(event.project_id)
+            .or_default()
+            .push(event.id.to_string());
+    }
+
+    let mut existing_event_ids = HashSet::new();
+    for (project_id, event_ids) in event_ids_by_project {
+        let rows = clickhouse
+            .query(
+                "SELECT DISTINCT toString(id)
+                FROM events
+                WHERE project_id = ? AND toString(id) IN ?",
+            )
+            .bind(project_id)
+            .bind(&event_ids)
+            .fetch_all::<String>()
+            .await?;
+        existing_event_ids.extend(rows.iter().filter_map(|id| Uuid::parse_str(id).ok()));
+    }
+
+    Ok(existing_event_ids)
+}
+
+pub async fn insert_events(clickhouse: &clickhouse::Client, events: &[&CHEvent]) -> Result<()> {
     if events.is_empty() {
         return Ok(());
     }
@@ -38,7 +87,7 @@ pub async fn insert_events(clickhouse: clickhouse::Client, events: Vec<CHEvent>)
     match ch_insert {
         Ok(mut ch_insert) => {
             for event in events {
-                ch_insert.write(&event).await?;
+                ch_insert.write(*event).await?;
             }
             let ch_insert_end_res = ch_insert.end().await;
             match ch_insert_end_res {
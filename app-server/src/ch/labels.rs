@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Result;
 use chrono::Utc;
 use clickhouse::Row;
@@ -107,6 +109,76 @@ pub async fn insert_label(
     }
 }
 
+/// Label classes and span ids of the labels set from span attributes that are already in
+/// Clickhouse, so that spans which are recorded again do not insert their labels twice
+pub async fn get_existing_code_labels(
+    client: &clickhouse::Client,
+    labels: &[CHLabel],
+) -> Result<HashSet<(Uuid, Uuid)>> {
+    let mut span_ids_by_project: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for label in labels {
+        span_ids_by_project
+            .entry(label.project_id)
+            .or_default()
+            .push(label.span_id.to_string());
+    }
+    let code_source: u8 = LabelSource::CODE.into();
+
+    let mut existing_labels = HashSet::new();
+    for (project_id, span_ids) in span_ids_by_project {
+        let rows = client
+            .query(
+                "SELECT DISTINCT toString(class_id), toString(span_id)
+                FROM labels
+                WHERE project_id = ? AND label_source = ? AND toString(span_id) IN ?",
+            )
+            .bind(project_id)
+            .bind(code_source)
+            .bind(&span_ids)
+            .fetch_all::<(String, String)>()
+            .await?;
+        existing_labels.extend(rows.iter().filter_map(|(class_id, span_id)| {
+            Some((
+                Uuid::parse_str(class_id).ok()?,
+                Uuid::parse_str(span_id).ok()?,
+            ))
+        }));
+    }
+
+    Ok(existing_labels)
+}
+
+pub async fn insert_labels(client: &clickhouse::Client, labels: &[&CHLabel]) -> Result<()> {
+    if labels.is_empty() {
+        return Ok(());
+    }
+
+    let ch_insert = client.insert("labels");
+    match ch_insert {
+        Ok(mut ch_insert) => {
+            for label in labels {
+                ch_insert.write(*label).await?;
+            }
+            let ch_insert_end_res = ch_insert.end().await;
+            match ch_insert_end_res {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Clickhouse labels insertion failed: {:?}",
+                        e
+                    ));
+                }
+            }
+        }
+        Err(e) => {
+            return Err(anyhow::anyhow!(
+                "Failed to insert labels into Clickhouse: {:?}",
+                e
+            ));
+        }
+    }
+}
+
 pub async fn delete_label(
     client: clickhouse::Client,
     project_id: Uuid,
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::logs::Log;
+
+use super::utils::{chrono_to_nanoseconds, nanoseconds_to_chrono};
+
+#[derive(Row, Serialize, Deserialize)]
+pub struct CHLog {
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub id: Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub project_id: Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub trace_id: Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub span_id: Uuid,
+    /// Timestamp in nanoseconds
+    pub timestamp: i64,
+    /// Timestamp in nanoseconds
+    pub observed_timestamp: i64,
+    pub severity_number: u8,
+    pub severity_text: String,
+    pub body: String,
+    /// Map(String, String) is serialized as an array of key-value tuples
+    pub attributes: Vec<(String, String)>,
+    pub resource_attributes: Vec<(String, String)>,
+    pub scope_name: String,
+}
+
+impl CHLog {
+    pub fn from_log(log: Log, project_id: Uuid) -> Self {
+        CHLog {
+            id: log.id,
+            project_id,
+            trace_id: log.trace_id,
+            span_id: log.span_id,
+            timestamp: chrono_to_nanoseconds(log.time),
+            observed_timestamp: chrono_to_nanoseconds(log.observed_time),
+            severity_number: log.severity_number,
+            severity_text: log.severity_text,
+            body: log.body,
+            attributes: log.attributes.into_iter().collect(),
+            resource_attributes: log.resource_attributes.into_iter().collect(),
+            scope_name: log.scope_name,
+        }
+    }
+
+    pub fn into_log(self) -> Log {
+        Log {
+            id: self.id,
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            time: nanoseconds_to_chrono(self.timestamp),
+            observed_time: nanoseconds_to_chrono(self.observed_timestamp),
+            severity_number: self.severity_number,
+            severity_text: self.severity_text,
+            body: self.body,
+            attributes: self.attributes.into_iter().collect(),
+            resource_attributes: self.resource_attributes.into_iter().collect(),
+            scope_name: self.scope_name,
+        }
+    }
+}
+
+pub async fn insert_logs(clickhouse: &clickhouse::Client, logs: &[CHLog]) -> Result<()> {
+    if logs.is_empty() {
+        return Ok(());
+    }
+
+    let mut ch_insert = clickhouse
+        .insert("logs")
+        .map_err(|e| anyhow::anyhow!("Failed to insert logs into Clickhouse: {:?}", e))?;
+    for log in logs {
+        ch_insert.write(log).await?;
+    }
+    ch_insert
+        .end()
+        .await
+        .map_err(|e| anyhow::anyhow!("Clickhouse logs insertion failed: {:?}", e))
+}
+
+pub struct LogsFilter {
+    pub trace_id: Option<Uuid>,
+    pub span_id: Option<Uuid>,
+    pub min_severity: Option<u8>,
+    /// Case-insensitive substring of the log body
+    pub search: Option<String>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+/// Get log records of a project in chronological order
+pub async fn get_logs(
+    clickhouse: &clickhouse::Client,
+    project_id: Uuid,
+    filter: LogsFilter,
+) -> Result<Vec<Log>> {
+    let mut conditions = vec!["project_id = ?"];
+    if filter.trace_id.is_some() {
+        conditions.push("trace_id = ?");
+    }
+    if filter.span_id.is_some() {
+        conditions.push("span_id = ?");
+    }
+    if filter.min_severity.is_some() {
+        conditions.push("severity_number >= ?");
+    }
+    if filter.search.is_some() {
+        conditions.push("body_lower LIKE ?");
+    }
+
+    let query_string = format!(
+        "SELECT
+            id,
+            project_id,
+            trace_id,
+            span_id,
+            timestamp,
+            observed_timestamp,
+            severity_number,
+            severity_text,
+            body,
+            attributes,
+            resource_attributes,
+            scope_name
+        FROM logs
+        WHERE {}
+        ORDER BY timestamp ASC
+        LIMIT ? OFFSET ?",
+        conditions.join(" AND ")
+    );
+
+    let mut query = clickhouse.query(&query_string).bind(project_id);
+    if let Some(trace_id) = filter.trace_id {
+        query = query.bind(trace_id);
+    }
+    if let Some(span_id) = filter.span_id {
+        query = query.bind(span_id);
+    }
+    if let Some(min_severity) = filter.min_severity {
+        query = query.bind(min_severity);
+    }
+    if let Some(search) = filter.search {
+        query = query.bind(format!("%{}%", escape_like_pattern(&search.to_lowercase())));
+    }
+
+    let rows = query
+        .bind(filter.limit)
+        .bind(filter.offset)
+        .fetch_all::<CHLog>()
+        .await?;
+
+    Ok(rows.into_iter().map(CHLog::into_log).collect())
+}
+
+fn escape_like_pattern(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::metrics::{MetricPoint, MetricType};
+
+use super::{
+    modifiers::GroupByInterval,
+    utils::{
+        chrono_to_nanoseconds, group_by_time_absolute_statement, group_by_time_relative_statement,
+    },
+    Aggregation, MetricTimeValue,
+};
+
+/// for inserting into clickhouse
+///
+/// Don't change the order of the fields or their values
+impl Into<u8> for MetricType {
+    fn into(self) -> u8 {
+        match self {
+            MetricType::Gauge => 0,
+            MetricType::Sum => 1,
+            MetricType::Histogram => 2,
+            MetricType::ExponentialHistogram => 3,
+            MetricType::Summary => 4,
+        }
+    }
+}
+
+#[derive(Row, Serialize)]
+pub struct CHMetric {
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub project_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub unit: String,
+    pub metric_type: u8,
+    /// Timestamp in nanoseconds
+    pub timestamp: i64,
+    /// Start timestamp in nanoseconds, 0 if not reported
+    pub start_timestamp: i64,
+    pub value: f64,
+    pub count: u64,
+    pub sum: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub bucket_counts: Vec<u64>,
+    pub explicit_bounds: Vec<f64>,
+    pub is_monotonic: bool,
+    pub aggregation_temporality: u8,
+    /// Map(String, String) is serialized as an array of key-value tuples
+    pub attributes: Vec<(String, String)>,
+    pub resource_attributes: Vec<(String, String)>,
+    pub scope_name: String,
+}
+
+impl CHMetric {
+    pub fn from_metric_point(point: MetricPoint, project_id: Uuid) -> Self {
+        CHMetric {
+            project_id,
+            name: point.name,
+            description: point.description,
+            unit: point.unit,
+            metric_type: point.metric_type.into(),
+            timestamp: chrono_to_nanoseconds(point.time),
+            start_timestamp: point.start_time.map(chrono_to_nanoseconds).unwrap_or(0),
+            value: point.value,
+            count: point.count,
+            sum: point.sum,
+            min: point.min,
+            max: point.max,
+            bucket_counts: point.bucket_counts,
+            explicit_bounds: point.explicit_bounds,
+            is_monotonic: point.is_monotonic,
+            aggregation_temporality: point.aggregation_temporality as u8,
+            attributes: point.attributes.into_iter().collect(),
+            resource_attributes: point.resource_attributes.into_iter().collect(),
+            scope_name: point.scope_name,
+        }
+    }
+}
+
+pub async fn insert_metrics(clickhouse: &clickhouse::Client, metrics: &[CHMetric]) -> Result<()> {
+    if metrics.is_empty() {
+        return Ok(());
+    }
+
+    let mut ch_insert = clickhouse
+        .insert("metrics")
+        .map_err(|e| anyhow::anyhow!("Failed to insert metrics into Clickhouse: {:?}", e))?;
+    for metric in metrics {
+        ch_insert.write(metric).await?;
+    }
+    ch_insert
+        .end()
+        .await
+        .map_err(|e| anyhow::anyhow!("Clickhouse metrics insertion failed: {:?}", e))
+}
+
+/// Column of the data points to aggregate
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum MetricField {
+    /// Gauge or sum value, or the mean observation of histograms and summaries
+    #[default]
+    Value,
+    /// Number of observations in histograms and summaries
+    Count,
+    /// Sum of observations in histograms and summaries
+    Sum,
+}
+
+impl MetricField {
+    fn to_ch_column(&self) -> &'static str {
+        match self {
+            MetricField::Value => "value",
+            MetricField::Count => "toFloat64(count)",
+            MetricField::Sum => "sum",
+        }
+    }
+}
+
+#[derive(Deserialize, Row, Serialize)]
+pub struct MetricName {
+    pub name: String,
+    pub unit: String,
+    pub description: String,
+    pub metric_type: u8,
+}
+
+pub async fn get_metric_names(
+    clickhouse: &clickhouse::Client,
+    project_id: Uuid,
+) -> Result<Vec<MetricName>> {
+    let rows = clickhouse
+        .query(
+            "SELECT name, any(unit) as unit, any(description) as description, any(metric_type) as metric_type
+            FROM metrics
+            WHERE project_id = ?
+            GROUP BY name
+            ORDER BY name",
+        )
+        .bind(project_id)
+        .fetch_all::<MetricName>()
+        .await?;
+
+    Ok(rows)
+}
+
+pub async fn get_metric_values_relative(
+    clickhouse: &clickhouse::Client,
+    project_id: Uuid,
+    name: &str,
+    field: MetricField,
+    attributes: &HashMap<String, String>,
+    group_by_interval: GroupByInterval,
+    past_hours: i64,
+    aggregation: Aggregation,
+) -> Result<Vec<MetricTimeValue<f64>>> {
+    let ch_round_time = group_by_interval.to_ch_truncate_time();
+    let ch_aggregation = aggregation.to_ch_agg_function();
+    let ch_column = field.to_ch_column();
+
+    let query_string = format!(
+        "SELECT
+            {ch_round_time}(timestamp) as time,
+            {ch_aggregation}({ch_column}) as value
+        FROM metrics
+        WHERE project_id = ?
+            AND name = ?
+            AND timestamp >= now() - INTERVAL ? HOUR
+            {}
+        {}",
+        attributes_filter(attributes),
+        group_by_time_relative_statement(past_hours, group_by_interval)
+    );
+
+    let mut query = clickhouse
+        .query(&query_string)
+        .bind(project_id)
+        .bind(name)
+        .bind(past_hours);
+    for (key, value) in attributes {
+        query = query.bind(key).bind(value);
+    }
+
+    let rows = query.fetch_all::<MetricTimeValue<f64>>().await?;
+
+    Ok(rows)
+}
+
+pub async fn get_metric_values_absolute(
+    clickhouse: &clickhouse::Client,
+    project_id: Uuid,
+    name: &str,
+    field: MetricField,
+    attributes: &HashMap<String, String>,
+    group_by_interval: GroupByInterval,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    aggregation: Aggregation,
+) -> Result<Vec<MetricTimeValue<f64>>> {
+    let ch_round_time = group_by_interval.to_ch_truncate_time();
+    let ch_aggregation = aggregation.to_ch_agg_function();
+    let ch_column = field.to_ch_column();
+
+    let query_string = format!(
+        "SELECT
+            {ch_round_time}(timestamp) as time,
+            {ch_aggregation}({ch_column}) as value
+        FROM metrics
+        WHERE project_id = ?
+            AND name = ?
+            AND timestamp >= fromUnixTimestamp(?)
+            AND timestamp <= fromUnixTimestamp(?)
+            {}
+        {}",
+        attributes_filter(attributes),
+        group_by_time_absolute_statement(start_time, end_time, group_by_interval)
+    );
+
+    let mut query = clickhouse
+        .query(&query_string)
+        .bind(project_id)
+        .bind(name)
+        .bind(start_time.timestamp())
+        .bind(end_time.timestamp());
+    for (key, value) in attributes {
+        query = query.bind(key).bind(value);
+    }
+
+    let rows = query.fetch_all::<MetricTimeValue<f64>>().await?;
+
+    Ok(rows)
+}
+
+/// Attribute keys and values are bound as query parameters, in the iteration order of the map
+fn attributes_filter(attributes: &HashMap<String, String>) -> String {
+    attributes
+        .iter()
+        .map(|_| "AND attributes[?] = ?")
+        .collect::<Vec<_>>()
+        .join("\n            ")
+}
@@ -5,6 +5,8 @@ pub mod browser_events;
 pub mod evaluation_scores;
 pub mod events;
 pub mod labels;
+pub mod logs;
+pub mod metrics;
 pub mod modifiers;
 pub mod spans;
 pub mod utils;
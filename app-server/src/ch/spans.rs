@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use clickhouse::Row;
@@ -6,14 +8,15 @@ use serde_json::Value;
 use uuid::Uuid;
 
 use crate::{
-    db::spans::{Span, SpanType},
+    db::spans::{Span, SpanStatus, SpanType},
     traces::{spans::SpanUsage, utils::json_value_to_string},
 };
 
 use super::{
     modifiers::GroupByInterval,
     utils::{
-        chrono_to_nanoseconds, group_by_time_absolute_statement, group_by_time_relative_statement,
+        chrono_to_nanoseconds, group_by_time_and_column_absolute_statement,
+        group_by_time_and_column_relative_statement,
     },
     Aggregation, MetricTimeValue,
 };
@@ -35,6 +38,17 @@ impl Into<u8> for SpanType {
     }
 }
 
+/// for inserting into clickhouse, same values as the OpenTelemetry status codes
+impl Into<u8> for SpanStatus {
+    fn into(self) -> u8 {
+        match self {
+            SpanStatus::UNSET => 0,
+            SpanStatus::OK => 1,
+            SpanStatus::ERROR => 2,
+        }
+    }
+}
+
 #[derive(Row, Serialize, Deserialize, Debug)]
 pub struct CHSpan {
     #[serde(with = "clickhouse::serde::uuid")]
@@ -63,24 +77,29 @@ pub struct CHSpan {
     pub path: String,
     pub input: String,
     pub output: String,
+    pub status_code: u8,
+    pub status_message: String,
+    pub service_name: String,
+    /// Map(String, String) is serialized as an array of key-value tuples
+    pub resource_attributes: Vec<(String, String)>,
+    pub scope_name: String,
+    pub scope_version: String,
+    /// Breakdown of the input and output tokens and costs
+    pub cache_read_input_tokens: i64,
+    pub cache_creation_input_tokens: i64,
+    pub reasoning_tokens: i64,
+    pub cache_read_input_cost: f64,
+    pub cache_creation_input_cost: f64,
+    pub reasoning_cost: f64,
+    /// Flattened span attributes, see
+    /// [`crate::traces::spans::SpanAttributes::queryable_attributes`]
+    pub attributes: Vec<(String, String)>,
 }
 
 impl CHSpan {
     pub fn from_db_span(span: &Span, usage: SpanUsage, project_id: Uuid) -> Self {
         let span_attributes = span.get_attributes();
 
-        let span_input_string = json_value_to_string(
-            span.input
-                .as_ref()
-                .unwrap_or(&Value::String(String::from(""))),
-        );
-
-        let span_output_string = json_value_to_string(
-            span.output
-                .as_ref()
-                .unwrap_or(&Value::String(String::from(""))),
-        );
-
         CHSpan {
             span_id: span.span_id,
             name: span.name.clone(),
@@ -107,17 +126,91 @@ impl CHSpan {
             path: span_attributes
                 .flat_path()
                 .unwrap_or(String::from("<null>")),
-            input: span_input_string,
-            output: span_output_string,
+            input: payload_string(&span.input),
+            output: payload_string(&span.output),
+            status_code: span.status.into(),
+            status_message: span.status_message.clone().unwrap_or_default(),
+            service_name: span_attributes.service_name().unwrap_or_default(),
+            resource_attributes: span_attributes.resource_attributes().into_iter().collect(),
+            scope_name: span_attributes.scope_name().unwrap_or_default(),
+            scope_version: span_attributes.scope_version().unwrap_or_default(),
+            cache_read_input_tokens: usage.cache_read_input_tokens,
+            cache_creation_input_tokens: usage.cache_creation_input_tokens,
+            reasoning_tokens: usage.reasoning_tokens,
+            cache_read_input_cost: usage.cache_read_input_cost,
+            cache_creation_input_cost: usage.cache_creation_input_cost,
+            reasoning_cost: usage.reasoning_cost,
+            attributes: span_attributes.queryable_attributes().into_iter().collect(),
         }
     }
+
+    /// Update the input and output, e.g. after the payloads of the span have been stored
+    pub fn set_payloads(&mut self, span: &Span) {
+        self.input = payload_string(&span.input);
+        self.output = payload_string(&span.output);
+    }
 }
 
-pub async fn insert_span(clickhouse: clickhouse::Client, span: &CHSpan) -> Result<()> {
+fn payload_string(payload: &Option<Value>) -> String {
+    json_value_to_string(payload.as_ref().unwrap_or(&Value::String(String::from(""))))
+}
+
+/// Ids of the spans that are already in Clickhouse, so that spans which are recorded
+/// again, e.g. when their batch is replayed, are not inserted twice
+pub async fn get_existing_span_ids(
+    clickhouse: &clickhouse::Client,
+    spans: &[CHSpan],
+) -> Result<HashSet<Uuid>> {
+    let mut spans_by_project: HashMap<Uuid, Vec<&CHSpan>> = HashMap::new();
+    for span in spans {
+        spans_by_project
+            .entry(span.project_id)
+            .or_default()
+            .push(span);
+    }
+
+    let mut existing_span_ids = HashSet::new();
+    for (project_id, spans) in spans_by_project {
+        let span_ids = spans
+            .iter()
+            .map(|span| span.span_id.to_string())
+            .collect::<Vec<_>>();
+        let min_start_time = spans.iter().map(|span| span.start_time).min();
+        let max_start_time = spans.iter().map(|span| span.start_time).max();
+
+        // The start time range lets Clickhouse skip the granules of other spans of the project
+        let rows = clickhouse
+            .query(
+                "SELECT DISTINCT toString(span_id)
+                FROM spans
+                WHERE project_id = ?
+                    AND start_time >= fromUnixTimestamp64Nano(toInt64(?))
+                    AND start_time <= fromUnixTimestamp64Nano(toInt64(?))
+                    AND toString(span_id) IN ?",
+            )
+            .bind(project_id)
+            .bind(min_start_time.unwrap_or_default())
+            .bind(max_start_time.unwrap_or_default())
+            .bind(&span_ids)
+            .fetch_all::<String>()
+            .await?;
+        existing_span_ids.extend(rows.iter().filter_map(|id| Uuid::parse_str(id).ok()));
+    }
+
+    Ok(existing_span_ids)
+}
+
+pub async fn insert_spans(clickhouse: &clickhouse::Client, spans: &[&CHSpan]) -> Result<()> {
+    if spans.is_empty() {
+        return Ok(());
+    }
+
     let ch_insert = clickhouse.insert("spans");
     match ch_insert {
         Ok(mut ch_insert) => {
-            ch_insert.write(span).await?;
+            for span in spans {
+                ch_insert.write(*span).await?;
+            }
             let ch_insert_end_res = ch_insert.end().await;
             match ch_insert_end_res {
                 Ok(_) => Ok(()),
@@ -128,18 +221,107 @@ pub async fn insert_span(clickhouse: clickhouse::Client, span: &CHSpan) -> Resul
         }
         Err(e) => {
             return Err(anyhow::anyhow!(
-                "Failed to insert span into Clickhouse: {:?}",
+                "Failed to insert spans into Clickhouse: {:?}",
                 e
             ));
         }
     }
 }
 
+/// Replace the costs of multiple spans of the project, e.g. after the price of their model
+/// changed
+pub async fn update_spans_costs(
+    clickhouse: &clickhouse::Client,
+    project_id: Uuid,
+    spans: &[(Uuid, SpanUsage)],
+) -> Result<()> {
+    if spans.is_empty() {
+        return Ok(());
+    }
+    let span_ids = spans
+        .iter()
+        .map(|(span_id, _)| span_id.to_string())
+        .collect::<Vec<_>>();
+    let costs = |cost: fn(&SpanUsage) -> f64| {
+        spans
+            .iter()
+            .map(|(_, usage)| cost(usage))
+            .collect::<Vec<f64>>()
+    };
+
+    // Like deletes, this is a mutation that is applied to the data in the background.
+    // https://clickhouse.com/docs/en/sql-reference/statements/alter/update
+    clickhouse
+        .query(
+            "ALTER TABLE spans UPDATE
+                input_cost = transform(toString(span_id), ?, ?, input_cost),
+                output_cost = transform(toString(span_id), ?, ?, output_cost),
+                total_cost = transform(toString(span_id), ?, ?, total_cost),
+                cache_read_input_cost = transform(toString(span_id), ?, ?, cache_read_input_cost),
+                cache_creation_input_cost =
+                    transform(toString(span_id), ?, ?, cache_creation_input_cost),
+                reasoning_cost = transform(toString(span_id), ?, ?, reasoning_cost)
+            WHERE project_id = ? AND toString(span_id) IN ?",
+        )
+        .bind(&span_ids)
+        .bind(costs(|usage| usage.input_cost))
+        .bind(&span_ids)
+        .bind(costs(|usage| usage.output_cost))
+        .bind(&span_ids)
+        .bind(costs(|usage| usage.total_cost))
+        .bind(&span_ids)
+        .bind(costs(|usage| usage.cache_read_input_cost))
+        .bind(&span_ids)
+        .bind(costs(|usage| usage.cache_creation_input_cost))
+        .bind(&span_ids)
+        .bind(costs(|usage| usage.reasoning_cost))
+        .bind(project_id)
+        .bind(&span_ids)
+        .execute()
+        .await?;
+
+    Ok(())
+}
+
+pub const TRACE_COUNT_METRIC: &str = "COUNT(DISTINCT(trace_id))";
+pub const TRACE_LATENCY_SECONDS_METRIC: &str =
+    "(toUnixTimestamp64Nano(MAX(end_time)) - toUnixTimestamp64Nano(MIN(start_time))) / 1e9";
+pub const TOTAL_TOKEN_COUNT_METRIC: &str = "SUM(total_tokens)";
+pub const COST_USD_METRIC: &str = "SUM(total_cost)";
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AttributeFilterOperator {
+    #[default]
+    Eq,
+    Ne,
+}
+
+/// Condition on a span attribute, e.g. `metadata.customer_id` equal to `acme`. Spans
+/// without the attribute have the empty value.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeFilter {
+    pub key: String,
+    #[serde(default)]
+    pub operator: AttributeFilterOperator,
+    pub value: String,
+}
+
+/// Conditions on the spans that the trace metrics are calculated from
+#[derive(Default)]
+pub struct SpanFilters {
+    /// Only include spans of this service, i.e. with this `service.name` resource attribute
+    pub service_name: Option<String>,
+    pub attributes: Vec<AttributeFilter>,
+}
+
 pub async fn get_total_trace_count_metrics_relative(
     clickhouse: clickhouse::Client,
     group_by_interval: GroupByInterval,
     project_id: Uuid,
     past_hours: i64,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<i64>>> {
     let query = trace_metric_query_relative(
         &clickhouse,
@@ -147,7 +329,9 @@ pub async fn get_total_trace_count_metrics_relative(
         group_by_interval,
         past_hours,
         Aggregation::Total,
-        "COUNT(DISTINCT(trace_id))",
+        filters,
+        None,
+        TRACE_COUNT_METRIC,
     );
 
     let rows = query.fetch_all().await?;
@@ -162,6 +346,7 @@ pub async fn get_total_trace_count_metrics_absolute(
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<i64>>> {
     let query = trace_metric_query_absolute(
         &clickhouse,
@@ -170,7 +355,9 @@ pub async fn get_total_trace_count_metrics_absolute(
         start_time,
         end_time,
         aggregation,
-        "COUNT(DISTINCT(trace_id))",
+        filters,
+        None,
+        TRACE_COUNT_METRIC,
     );
 
     let rows = query.fetch_all().await?;
@@ -184,6 +371,7 @@ pub async fn get_trace_latency_seconds_metrics_relative(
     project_id: Uuid,
     past_hours: i64,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<f64>>> {
     let query = trace_metric_query_relative(
         &clickhouse,
@@ -191,7 +379,9 @@ pub async fn get_trace_latency_seconds_metrics_relative(
         group_by_interval,
         past_hours,
         aggregation,
-        "(toUnixTimestamp64Nano(MAX(end_time)) - toUnixTimestamp64Nano(MIN(start_time))) / 1e9",
+        filters,
+        None,
+        TRACE_LATENCY_SECONDS_METRIC,
     );
 
     let res = query.fetch_all::<MetricTimeValue<f64>>().await?;
@@ -206,6 +396,7 @@ pub async fn get_trace_latency_seconds_metrics_absolute(
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<f64>>> {
     let query = trace_metric_query_absolute(
         &clickhouse,
@@ -214,7 +405,9 @@ pub async fn get_trace_latency_seconds_metrics_absolute(
         start_time,
         end_time,
         aggregation,
-        "(toUnixTimestamp64Nano(MAX(end_time)) - toUnixTimestamp64Nano(MIN(start_time))) / 1e9",
+        filters,
+        None,
+        TRACE_LATENCY_SECONDS_METRIC,
     );
 
     let res = query.fetch_all::<MetricTimeValue<f64>>().await?;
@@ -228,6 +421,7 @@ pub async fn get_total_token_count_metrics_relative(
     project_id: Uuid,
     past_hours: i64,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<i64>>> {
     let query = trace_metric_query_relative(
         &clickhouse,
@@ -235,7 +429,9 @@ pub async fn get_total_token_count_metrics_relative(
         group_by_interval,
         past_hours,
         aggregation,
-        "SUM(total_tokens)",
+        filters,
+        None,
+        TOTAL_TOKEN_COUNT_METRIC,
     );
 
     let res = query.fetch_all::<MetricTimeValue<i64>>().await?;
@@ -250,6 +446,7 @@ pub async fn get_total_token_count_metrics_absolute(
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<i64>>> {
     let query = trace_metric_query_absolute(
         &clickhouse,
@@ -258,7 +455,9 @@ pub async fn get_total_token_count_metrics_absolute(
         start_time,
         end_time,
         aggregation,
-        "SUM(total_tokens)",
+        filters,
+        None,
+        TOTAL_TOKEN_COUNT_METRIC,
     );
 
     let res = query.fetch_all().await?;
@@ -272,6 +471,7 @@ pub async fn get_cost_usd_metrics_relative(
     project_id: Uuid,
     past_hours: i64,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<f64>>> {
     let query = trace_metric_query_relative(
         &clickhouse,
@@ -279,7 +479,9 @@ pub async fn get_cost_usd_metrics_relative(
         group_by_interval,
         past_hours,
         aggregation,
-        "SUM(total_cost)",
+        filters,
+        None,
+        COST_USD_METRIC,
     );
 
     let res = query.fetch_all().await?;
@@ -294,6 +496,7 @@ pub async fn get_cost_usd_metrics_absolute(
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> Result<Vec<MetricTimeValue<f64>>> {
     let query = trace_metric_query_absolute(
         &clickhouse,
@@ -302,7 +505,9 @@ pub async fn get_cost_usd_metrics_absolute(
         start_time,
         end_time,
         aggregation,
-        "SUM(total_cost)",
+        filters,
+        None,
+        COST_USD_METRIC,
     );
 
     let res = query.fetch_all().await?;
@@ -310,17 +515,136 @@ pub async fn get_cost_usd_metrics_absolute(
     Ok(res)
 }
 
+/// Values of the metrics grouped by the value of a span attribute
+#[derive(Deserialize, Row, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricTimeGroupValue {
+    pub time: u32,
+    pub group_value: String,
+    pub value: f64,
+}
+
+/// Get the values of a trace metric, e.g. [`COST_USD_METRIC`], for each value of the span
+/// attribute, e.g. `metadata.customer_id`. Traces without the attribute are in the group
+/// with the empty value.
+pub async fn get_trace_metrics_by_attribute_relative(
+    clickhouse: clickhouse::Client,
+    group_by_interval: GroupByInterval,
+    project_id: Uuid,
+    past_hours: i64,
+    aggregation: Aggregation,
+    filters: &SpanFilters,
+    group_by_attribute: &str,
+    metric: &str,
+) -> Result<Vec<MetricTimeGroupValue>> {
+    let query = trace_metric_query_relative(
+        &clickhouse,
+        project_id,
+        group_by_interval,
+        past_hours,
+        aggregation,
+        filters,
+        Some(group_by_attribute),
+        &format!("toFloat64({metric})"),
+    );
+
+    let rows = query.fetch_all().await?;
+
+    Ok(rows)
+}
+
+pub async fn get_trace_metrics_by_attribute_absolute(
+    clickhouse: clickhouse::Client,
+    group_by_interval: GroupByInterval,
+    project_id: Uuid,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    aggregation: Aggregation,
+    filters: &SpanFilters,
+    group_by_attribute: &str,
+    metric: &str,
+) -> Result<Vec<MetricTimeGroupValue>> {
+    let query = trace_metric_query_absolute(
+        &clickhouse,
+        project_id,
+        group_by_interval,
+        start_time,
+        end_time,
+        aggregation,
+        filters,
+        Some(group_by_attribute),
+        &format!("toFloat64({metric})"),
+    );
+
+    let rows = query.fetch_all().await?;
+
+    Ok(rows)
+}
+
+/// Columns that the traces are grouped by in addition to the time, and the conditions on
+/// the spans that the trace metrics are calculated from
+fn trace_metric_statements(
+    filters: &SpanFilters,
+    group_by_attribute: Option<&str>,
+) -> (&'static str, String) {
+    let group_column = if group_by_attribute.is_some() {
+        "anyIf(attributes[?], mapContains(attributes, ?)) as group_value,"
+    } else {
+        ""
+    };
+
+    let mut span_conditions = String::new();
+    if filters.service_name.is_some() {
+        span_conditions.push_str(" AND service_name = ?");
+    }
+    for filter in &filters.attributes {
+        span_conditions.push_str(match filter.operator {
+            AttributeFilterOperator::Eq => " AND attributes[?] = ?",
+            AttributeFilterOperator::Ne => " AND attributes[?] != ?",
+        });
+    }
+
+    (group_column, span_conditions)
+}
+
+/// Bind the parameters of [`trace_metric_statements`] in the order they appear in the query
+fn bind_trace_metric_statements(
+    mut query: clickhouse::query::Query,
+    filters: &SpanFilters,
+    group_by_attribute: Option<&str>,
+) -> clickhouse::query::Query {
+    if let Some(attribute) = group_by_attribute {
+        query = query.bind(attribute).bind(attribute);
+    }
+    let types: Vec<u8> = vec![SpanType::DEFAULT.into(), SpanType::LLM.into()];
+    query = query.bind(types);
+    if let Some(service_name) = &filters.service_name {
+        query = query.bind(service_name);
+    }
+    for filter in &filters.attributes {
+        query = query.bind(&filter.key).bind(&filter.value);
+    }
+    query
+}
+
 fn trace_metric_query_relative(
     clickhouse: &clickhouse::Client,
     project_id: Uuid,
     group_by_interval: GroupByInterval,
     past_hours: i64,
     aggregation: Aggregation,
+    filters: &SpanFilters,
+    group_by_attribute: Option<&str>,
     metric: &str,
 ) -> clickhouse::query::Query {
     let ch_round_time = group_by_interval.to_ch_truncate_time();
     let ch_aggregation = aggregation.to_ch_agg_function();
-    let types: Vec<u8> = vec![SpanType::DEFAULT.into(), SpanType::LLM.into()];
+    let (group_column, span_conditions) = trace_metric_statements(filters, group_by_attribute);
+    let (select_group, group_by_column) = if group_by_attribute.is_some() {
+        ("group_value,", Some("group_value"))
+    } else {
+        ("", None)
+    };
 
     let query_string = format!(
         "
@@ -329,25 +653,26 @@ fn trace_metric_query_relative(
         trace_id,
         project_id,
         {ch_round_time}(MIN(start_time)) as time,
+        {group_column}
         {metric} as value
     FROM spans
-    WHERE span_type in ?
+    WHERE span_type in ?{span_conditions}
     GROUP BY project_id, trace_id
     )
     SELECT
         time,
+        {select_group}
         {ch_aggregation}(value) as value
     FROM traces
     WHERE
         project_id = ?
         AND time >= now() - INTERVAL ? HOUR
     {}",
-        group_by_time_relative_statement(past_hours, group_by_interval)
+        group_by_time_and_column_relative_statement(past_hours, group_by_interval, group_by_column)
     );
 
-    clickhouse
-        .query(&query_string)
-        .bind(types)
+    let query = clickhouse.query(&query_string);
+    bind_trace_metric_statements(query, filters, group_by_attribute)
         .bind(project_id)
         .bind(past_hours)
 }
@@ -359,13 +684,20 @@ fn trace_metric_query_absolute(
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     aggregation: Aggregation,
+    filters: &SpanFilters,
+    group_by_attribute: Option<&str>,
     metric: &str,
 ) -> clickhouse::query::Query {
     let ch_round_time = group_by_interval.to_ch_truncate_time();
     let ch_start_time = start_time.timestamp();
     let ch_end_time = end_time.timestamp();
     let ch_aggregation = aggregation.to_ch_agg_function();
-    let types: Vec<u8> = vec![SpanType::DEFAULT.into(), SpanType::LLM.into()];
+    let (group_column, span_conditions) = trace_metric_statements(filters, group_by_attribute);
+    let (select_group, group_by_column) = if group_by_attribute.is_some() {
+        ("group_value,", Some("group_value"))
+    } else {
+        ("", None)
+    };
 
     let query_string = format!(
         "
@@ -374,13 +706,15 @@ fn trace_metric_query_absolute(
         trace_id,
         project_id,
         {ch_round_time}(MIN(start_time)) as time,
+        {group_column}
         {metric} as value
     FROM spans
-    WHERE span_type in ?
+    WHERE span_type in ?{span_conditions}
     GROUP BY project_id, trace_id
     )
     SELECT
         time,
+        {select_group}
         {ch_aggregation}(value) as value
     FROM traces
     WHERE
@@ -388,13 +722,59 @@ fn trace_metric_query_absolute(
         AND time >= fromUnixTimestamp(?)
         AND time <= fromUnixTimestamp(?)
     {}",
-        group_by_time_absolute_statement(start_time, end_time, group_by_interval)
+        group_by_time_and_column_absolute_statement(
+            start_time,
+            end_time,
+            group_by_interval,
+            group_by_column
+        )
     );
 
-    clickhouse
-        .query(&query_string)
-        .bind(types)
+    let query = clickhouse.query(&query_string);
+    bind_trace_metric_statements(query, filters, group_by_attribute)
         .bind(project_id)
         .bind(ch_start_time)
         .bind(ch_end_time)
 }
+
+#[derive(Row, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathErrorRate {
+    pub path: String,
+    pub span_count: u64,
+    pub error_count: u64,
+    pub error_rate: f64,
+}
+
+/// Share of spans with the error status for each span path, paths with the most errors first
+pub async fn get_error_rates_by_path(
+    clickhouse: &clickhouse::Client,
+    project_id: Uuid,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<PathErrorRate>> {
+    let error_status: u8 = SpanStatus::ERROR.into();
+    let rows = clickhouse
+        .query(
+            "SELECT
+                path,
+                count() AS span_count,
+                countIf(status_code = ?) AS error_count,
+                error_count / span_count AS error_rate
+            FROM spans
+            WHERE
+                project_id = ?
+                AND start_time >= fromUnixTimestamp(?)
+                AND start_time <= fromUnixTimestamp(?)
+            GROUP BY path
+            ORDER BY error_count DESC, path ASC",
+        )
+        .bind(error_status)
+        .bind(project_id)
+        .bind(start_time.timestamp())
+        .bind(end_time.timestamp())
+        .fetch_all::<PathErrorRate>()
+        .await?;
+
+    Ok(rows)
+}
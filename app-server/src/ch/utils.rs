@@ -43,18 +43,32 @@ pub fn group_by_time_absolute_statement(
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     group_by_interval: GroupByInterval,
+) -> String {
+    group_by_time_and_column_absolute_statement(start_time, end_time, group_by_interval, None)
+}
+
+/// Group by the time and optionally by another column first, in which case the missing
+/// times are filled in for each value of the column
+pub fn group_by_time_and_column_absolute_statement(
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    group_by_interval: GroupByInterval,
+    column: Option<&str>,
 ) -> String {
     let ch_round_time = group_by_interval.to_ch_truncate_time();
     let ch_interval = group_by_interval.to_interval();
     let ch_step = group_by_interval.to_ch_step();
     let ch_start_time = start_time.timestamp();
     let ch_end_time = end_time.timestamp();
+    let column = column
+        .map(|column| format!("{column}, "))
+        .unwrap_or_default();
 
     format!(
         "GROUP BY
-            time
+            {column}time
         ORDER BY
-            time
+            {column}time
         WITH FILL
         FROM {ch_round_time}(fromUnixTimestamp({ch_start_time}))
         TO {ch_round_time}(fromUnixTimestamp({ch_end_time}) + INTERVAL {ch_interval})
@@ -65,16 +79,27 @@ pub fn group_by_time_absolute_statement(
 pub fn group_by_time_relative_statement(
     past_hours: i64,
     group_by_interval: GroupByInterval,
+) -> String {
+    group_by_time_and_column_relative_statement(past_hours, group_by_interval, None)
+}
+
+pub fn group_by_time_and_column_relative_statement(
+    past_hours: i64,
+    group_by_interval: GroupByInterval,
+    column: Option<&str>,
 ) -> String {
     let ch_round_time = group_by_interval.to_ch_truncate_time();
     let ch_interval = group_by_interval.to_interval();
     let ch_step = group_by_interval.to_ch_step();
+    let column = column
+        .map(|column| format!("{column}, "))
+        .unwrap_or_default();
 
     format!(
         "GROUP BY
-            time
+            {column}time
         ORDER BY
-            time
+            {column}time
         WITH FILL
         FROM {ch_round_time}(NOW() - INTERVAL {past_hours} HOUR + INTERVAL {ch_interval})
         TO {ch_round_time}(NOW() + INTERVAL {ch_interval})
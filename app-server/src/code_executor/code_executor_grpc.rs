@@ -55,6 +55,17 @@ pub mod chat_message_content {
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChatMessageToolCall {
+    /// empty if the provider does not assign ids to tool calls
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    /// serialized JSON
+    #[prost(string, tag = "3")]
+    pub arguments: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ChatMessageList {
     #[prost(message, repeated, tag = "1")]
     pub messages: ::prost::alloc::vec::Vec<chat_message_list::ChatMessage>,
@@ -67,6 +78,11 @@ pub mod chat_message_list {
         pub role: ::prost::alloc::string::String,
         #[prost(message, optional, tag = "2")]
         pub content: ::core::option::Option<super::ChatMessageContent>,
+        #[prost(message, repeated, tag = "3")]
+        pub tool_calls: ::prost::alloc::vec::Vec<super::ChatMessageToolCall>,
+        /// set on messages with the tool role
+        #[prost(string, tag = "4")]
+        pub tool_call_id: ::prost::alloc::string::String,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
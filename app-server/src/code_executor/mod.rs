@@ -7,7 +7,7 @@ use enum_dispatch::enum_dispatch;
 use crate::{
     language_model::{
         ChatMessageContent, ChatMessageContentPart, ChatMessageImage, ChatMessageImageUrl,
-        ChatMessageText,
+        ChatMessageText, ChatMessageToolCall,
     },
     pipeline::nodes::{HandleType, NodeInput},
 };
@@ -17,8 +17,8 @@ use code_executor_grpc::{
     Arg, ChatMessageContent as ArgChatMessageContent,
     ChatMessageContentPart as ArgChatMessageContentPart, ChatMessageImage as ArgChatMessageImage,
     ChatMessageImageUrl as ArgChatMessageImageUrl, ChatMessageList,
-    ChatMessageText as ArgChatMessageText, ContentPartList as ArgContentPartList,
-    ExecuteCodeResponse, StringList,
+    ChatMessageText as ArgChatMessageText, ChatMessageToolCall as ArgChatMessageToolCall,
+    ContentPartList as ArgContentPartList, ExecuteCodeResponse, StringList,
 };
 
 use code_executor_impl::CodeExecutorImpl;
@@ -140,6 +140,29 @@ impl Into<ChatMessageContent> for ArgChatMessageContent {
     }
 }
 
+impl From<ChatMessageToolCall> for ArgChatMessageToolCall {
+    fn from(tool_call: ChatMessageToolCall) -> Self {
+        let arguments = tool_call.arguments_string();
+        Self {
+            id: tool_call.id.unwrap_or_default(),
+            name: tool_call.name,
+            arguments,
+        }
+    }
+}
+
+impl From<ArgChatMessageToolCall> for ChatMessageToolCall {
+    fn from(tool_call: ArgChatMessageToolCall) -> Self {
+        Self {
+            id: Some(tool_call.id).filter(|id| !id.is_empty()),
+            name: tool_call.name,
+            arguments: ChatMessageToolCall::parse_arguments(serde_json::Value::String(
+                tool_call.arguments,
+            )),
+        }
+    }
+}
+
 impl Into<Arg> for NodeInput {
     fn into(self) -> Arg {
         match self {
@@ -156,6 +179,8 @@ impl Into<Arg> for NodeInput {
                         .map(|m| chat_message_list::ChatMessage {
                             content: Some(m.content.into()),
                             role: m.role,
+                            tool_calls: m.tool_calls.into_iter().map(Into::into).collect(),
+                            tool_call_id: m.tool_call_id.unwrap_or_default(),
                         })
                         .collect(),
                 })),
@@ -185,6 +210,8 @@ impl TryInto<NodeInput> for ExecuteCodeResponse {
                         .map(|m| crate::language_model::ChatMessage {
                             role: m.role,
                             content: m.content.unwrap().into(),
+                            tool_calls: m.tool_calls.into_iter().map(Into::into).collect(),
+                            tool_call_id: Some(m.tool_call_id).filter(|id| !id.is_empty()),
                         })
                         .collect(),
                 )),
@@ -0,0 +1,114 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+const COST_BACKFILL_COLUMNS: &str = "id,
+    created_at,
+    updated_at,
+    project_id,
+    start_time,
+    end_time,
+    status,
+    span_count,
+    error";
+
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[sqlx(type_name = "cost_backfill_status")]
+pub enum CostBackfillStatus {
+    RUNNING,
+    SUCCEEDED,
+    FAILED,
+}
+
+/// Recalculation of the costs of the LLM spans of a project that started in
+/// `[start_time, end_time)`, which runs in the background
+#[derive(Serialize, FromRow, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CostBackfill {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub project_id: Uuid,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub status: CostBackfillStatus,
+    /// Number of spans whose costs have been recalculated so far
+    pub span_count: i64,
+    pub error: Option<String>,
+}
+
+pub async fn create_cost_backfill(
+    pool: &PgPool,
+    project_id: &Uuid,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<CostBackfill> {
+    let backfill = sqlx::query_as::<_, CostBackfill>(&format!(
+        "INSERT INTO cost_backfills (project_id, start_time, end_time)
+        VALUES ($1, $2, $3)
+        RETURNING {COST_BACKFILL_COLUMNS}"
+    ))
+    .bind(project_id)
+    .bind(start_time)
+    .bind(end_time)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(backfill)
+}
+
+pub async fn get_cost_backfill(
+    pool: &PgPool,
+    project_id: &Uuid,
+    backfill_id: &Uuid,
+) -> Result<Option<CostBackfill>> {
+    let backfill = sqlx::query_as::<_, CostBackfill>(&format!(
+        "SELECT {COST_BACKFILL_COLUMNS}
+        FROM cost_backfills
+        WHERE project_id = $1 AND id = $2"
+    ))
+    .bind(project_id)
+    .bind(backfill_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(backfill)
+}
+
+pub async fn update_cost_backfill_progress(
+    pool: &PgPool,
+    backfill_id: &Uuid,
+    span_count: i64,
+) -> Result<()> {
+    sqlx::query("UPDATE cost_backfills SET span_count = $2, updated_at = now() WHERE id = $1")
+        .bind(backfill_id)
+        .bind(span_count)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn finish_cost_backfill(
+    pool: &PgPool,
+    backfill_id: &Uuid,
+    status: CostBackfillStatus,
+    span_count: i64,
+    error: Option<String>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE cost_backfills
+        SET status = $2, span_count = $3, error = $4, updated_at = now()
+        WHERE id = $1",
+    )
+    .bind(backfill_id)
+    .bind(status)
+    .bind(span_count)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
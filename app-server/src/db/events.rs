@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
@@ -9,6 +11,9 @@ use crate::opentelemetry::opentelemetry_proto_trace_v1::span::Event as OtelEvent
 
 use super::utils::convert_any_value_to_json_value;
 
+/// Name of the events recorded for exceptions by OpenTelemetry SDKs
+pub const EXCEPTION_EVENT_NAME: &str = "exception";
+
 #[derive(Deserialize, Serialize, Clone, FromRow)]
 #[serde(rename_all = "camelCase")]
 pub struct Event {
@@ -41,7 +46,12 @@ impl Event {
     }
 }
 
-pub async fn insert_events(pool: &PgPool, events: &Vec<Event>) -> Result<()> {
+/// Insert the events that have not been recorded yet, e.g. by an earlier delivery of the
+/// same span message.
+///
+/// Returns the number of events that were inserted, by project.
+pub async fn insert_events(pool: &PgPool, events: &Vec<Event>) -> Result<HashMap<Uuid, i64>> {
+    let ids = events.iter().map(|e| e.id).collect::<Vec<Uuid>>();
     let span_ids = events.iter().map(|e| e.span_id).collect::<Vec<Uuid>>();
     let project_ids = events.iter().map(|e| e.project_id).collect::<Vec<Uuid>>();
     let timestamps = events
@@ -57,18 +67,67 @@ pub async fn insert_events(pool: &PgPool, events: &Vec<Event>) -> Result<()> {
         .map(|e| e.attributes.clone())
         .collect::<Vec<Value>>();
 
-    sqlx::query(
-        "INSERT INTO events (span_id, project_id, timestamp, name, attributes)
-        VALUES (UNNEST($1), UNNEST($2), UNNEST($3), UNNEST($4), UNNEST($5))
-        ",
+    let inserted_project_ids = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO events (id, span_id, project_id, timestamp, name, attributes)
+        SELECT * FROM UNNEST($1::uuid[], $2::uuid[], $3::uuid[], $4::timestamptz[], $5::text[], $6::jsonb[])
+        ON CONFLICT (id) DO NOTHING
+        RETURNING project_id",
     )
+    .bind(ids)
     .bind(span_ids)
     .bind(project_ids)
     .bind(timestamps)
     .bind(names)
     .bind(attributes)
-    .execute(pool)
+    .fetch_all(pool)
+    .await?;
+
+    let mut inserted_by_project = HashMap::new();
+    for project_id in inserted_project_ids {
+        *inserted_by_project.entry(project_id).or_default() += 1;
+    }
+    Ok(inserted_by_project)
+}
+
+/// Event of a trace, with the name of the span it was recorded in
+#[derive(FromRow)]
+pub struct TraceEvent {
+    pub id: Uuid,
+    pub span_id: Uuid,
+    pub span_name: String,
+    pub timestamp: DateTime<Utc>,
+    pub name: String,
+    pub attributes: Value,
+}
+
+/// Get the events of all spans of a trace in chronological order
+pub async fn get_trace_events(
+    pool: &PgPool,
+    project_id: Uuid,
+    trace_id: Uuid,
+    exceptions_only: bool,
+) -> Result<Vec<TraceEvent>> {
+    let events = sqlx::query_as::<_, TraceEvent>(
+        "SELECT
+            events.id,
+            events.span_id,
+            spans.name AS span_name,
+            events.timestamp,
+            events.name,
+            events.attributes
+        FROM events
+        JOIN spans ON spans.span_id = events.span_id AND spans.project_id = events.project_id
+        WHERE spans.project_id = $1
+            AND spans.trace_id = $2
+            AND (NOT $3 OR events.name = $4)
+        ORDER BY events.timestamp ASC",
+    )
+    .bind(project_id)
+    .bind(trace_id)
+    .bind(exceptions_only)
+    .bind(EXCEPTION_EVENT_NAME)
+    .fetch_all(pool)
     .await?;
 
-    Ok(())
+    Ok(events)
 }
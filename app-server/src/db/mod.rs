@@ -1,5 +1,6 @@
 use sqlx::PgPool;
 
+pub mod cost_backfills;
 pub mod datapoints;
 pub mod datasets;
 pub mod evaluations;
@@ -7,12 +8,14 @@ pub mod events;
 pub mod labeling_queues;
 pub mod labels;
 pub mod machine_manager;
+pub mod model_name_rules;
 pub mod modifiers;
 pub mod pipelines;
 pub mod prices;
 pub mod project_api_keys;
 pub mod projects;
 pub mod provider_api_keys;
+pub mod redaction_rules;
 pub mod spans;
 pub mod stats;
 pub mod trace;
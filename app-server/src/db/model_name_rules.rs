@@ -0,0 +1,111 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[sqlx(type_name = "model_name_rule_type")]
+pub enum ModelNameRuleType {
+    /// `pattern` is a model name that is priced as `model`
+    ALIAS,
+    /// `pattern` is a regular expression, and the part of the model name it matches is
+    /// replaced with `model`, which can refer to capture groups, e.g. `$1`
+    REGEX,
+}
+
+/// Rule that maps the model names recorded on spans to the model names of the prices,
+/// e.g. dated snapshots or deployment names to the base model
+#[derive(Serialize, Deserialize, FromRow, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelNameRule {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub project_id: Uuid,
+    /// The rule applies to the models of all providers if not set
+    pub provider: Option<String>,
+    pub rule_type: ModelNameRuleType,
+    pub pattern: String,
+    pub model: String,
+}
+
+/// Fields of a rule that can be set when creating or updating it
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelNameRuleFields {
+    #[serde(default)]
+    pub provider: Option<String>,
+    pub rule_type: ModelNameRuleType,
+    pub pattern: String,
+    pub model: String,
+}
+
+/// Rules of the project in the order they are applied
+pub async fn get_model_name_rules(pool: &PgPool, project_id: &Uuid) -> Result<Vec<ModelNameRule>> {
+    let rules = sqlx::query_as::<_, ModelNameRule>(
+        "SELECT id, created_at, project_id, provider, rule_type, pattern, model
+        FROM model_name_rules
+        WHERE project_id = $1
+        ORDER BY created_at ASC",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rules)
+}
+
+pub async fn create_model_name_rule(
+    pool: &PgPool,
+    project_id: &Uuid,
+    fields: &ModelNameRuleFields,
+) -> Result<ModelNameRule> {
+    let rule = sqlx::query_as::<_, ModelNameRule>(
+        "INSERT INTO model_name_rules (project_id, provider, rule_type, pattern, model)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, created_at, project_id, provider, rule_type, pattern, model",
+    )
+    .bind(project_id)
+    .bind(&fields.provider)
+    .bind(&fields.rule_type)
+    .bind(&fields.pattern)
+    .bind(&fields.model)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+pub async fn update_model_name_rule(
+    pool: &PgPool,
+    project_id: &Uuid,
+    id: &Uuid,
+    fields: &ModelNameRuleFields,
+) -> Result<Option<ModelNameRule>> {
+    let rule = sqlx::query_as::<_, ModelNameRule>(
+        "UPDATE model_name_rules
+        SET provider = $3, rule_type = $4, pattern = $5, model = $6
+        WHERE project_id = $1 AND id = $2
+        RETURNING id, created_at, project_id, provider, rule_type, pattern, model",
+    )
+    .bind(project_id)
+    .bind(id)
+    .bind(&fields.provider)
+    .bind(&fields.rule_type)
+    .bind(&fields.pattern)
+    .bind(&fields.model)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+pub async fn delete_model_name_rule(pool: &PgPool, project_id: &Uuid, id: &Uuid) -> Result<()> {
+    sqlx::query("DELETE FROM model_name_rules WHERE project_id = $1 AND id = $2")
+        .bind(project_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -1,7 +1,9 @@
 pub use pipeline::*;
+pub use pipeline_run::*;
 pub use pipeline_templates::*;
 pub use pipeline_version::*;
 
 pub mod pipeline;
+pub mod pipeline_run;
 pub mod pipeline_templates;
 pub mod pipeline_version;
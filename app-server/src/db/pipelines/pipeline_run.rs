@@ -0,0 +1,302 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{prelude::FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::pipeline::nodes::Message;
+
+/// Runs that are still queued or running, but have not checkpointed a message or sent a
+/// heartbeat for this long, are considered interrupted, e.g. by a restart of the app server,
+/// and can be resumed. Must be well above the heartbeat interval of the pipeline runner.
+const STALE_RUN_INTERVAL: &str = "15 minutes";
+
+const PIPELINE_RUN_COLUMNS: &str = "id,
+    created_at,
+    updated_at,
+    project_id,
+    pipeline_version_id,
+    pipeline_version_name,
+    status,
+    graph,
+    metadata,
+    outputs,
+    error,
+    webhook_url,
+    webhook_secret";
+
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[sqlx(type_name = "pipeline_run_status")]
+pub enum PipelineRunStatus {
+    RUNNING,
+    SUCCEEDED,
+    FAILED,
+    /// Submitted to the pipeline jobs queue, and not yet taken by a worker
+    QUEUED,
+    CANCELLED,
+}
+
+/// Run of a pipeline version, whose node messages are checkpointed, so that it can be
+/// resumed if it fails or is interrupted
+#[derive(Serialize, FromRow, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineRun {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub project_id: Uuid,
+    pub pipeline_version_id: Uuid,
+    pub pipeline_version_name: String,
+    pub status: PipelineRunStatus,
+    /// Runnable graph with the inputs of the run set, without the env
+    #[serde(skip_serializing)]
+    pub graph: Value,
+    pub metadata: Value,
+    /// Values of the output nodes by node name, once the run succeeds
+    pub outputs: Option<Value>,
+    pub error: Option<String>,
+    /// URL that is called once the run finishes
+    pub webhook_url: Option<String>,
+    /// Secret the webhook calls are signed with
+    #[serde(skip_serializing)]
+    pub webhook_secret: Option<String>,
+}
+
+pub struct NewPipelineRun {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub pipeline_version_id: Uuid,
+    pub pipeline_version_name: String,
+    pub status: PipelineRunStatus,
+    pub graph: Value,
+    pub metadata: Value,
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+}
+
+pub async fn create_pipeline_run(pool: &PgPool, run: &NewPipelineRun) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO pipeline_runs (
+            id,
+            project_id,
+            pipeline_version_id,
+            pipeline_version_name,
+            status,
+            graph,
+            metadata,
+            webhook_url,
+            webhook_secret
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(run.id)
+    .bind(run.project_id)
+    .bind(run.pipeline_version_id)
+    .bind(&run.pipeline_version_name)
+    .bind(&run.status)
+    .bind(&run.graph)
+    .bind(&run.metadata)
+    .bind(&run.webhook_url)
+    .bind(&run.webhook_secret)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_pipeline_run(
+    pool: &PgPool,
+    project_id: &Uuid,
+    id: &Uuid,
+) -> Result<Option<PipelineRun>> {
+    let run = sqlx::query_as::<_, PipelineRun>(&format!(
+        "SELECT {PIPELINE_RUN_COLUMNS}
+        FROM pipeline_runs
+        WHERE project_id = $1 AND id = $2"
+    ))
+    .bind(project_id)
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(run)
+}
+
+pub async fn get_pipeline_run_status(
+    pool: &PgPool,
+    id: &Uuid,
+) -> Result<Option<PipelineRunStatus>> {
+    let status = sqlx::query_scalar::<_, PipelineRunStatus>(
+        "SELECT status FROM pipeline_runs WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(status)
+}
+
+/// Mark a queued run as running, once a worker takes it from the queue.
+///
+/// Returns None if the run is no longer queued, e.g. because it was cancelled.
+pub async fn start_pipeline_run(pool: &PgPool, id: &Uuid) -> Result<Option<PipelineRun>> {
+    let run = sqlx::query_as::<_, PipelineRun>(&format!(
+        "UPDATE pipeline_runs
+        SET status = 'RUNNING', updated_at = now()
+        WHERE id = $1 AND status = 'QUEUED'
+        RETURNING {PIPELINE_RUN_COLUMNS}"
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(run)
+}
+
+/// Mark a queued or running run as cancelled. Running runs check their status
+/// periodically and stop once they see it.
+///
+/// Returns None if the run does not exist or has already finished.
+pub async fn cancel_pipeline_run(
+    pool: &PgPool,
+    project_id: &Uuid,
+    id: &Uuid,
+) -> Result<Option<PipelineRun>> {
+    let run = sqlx::query_as::<_, PipelineRun>(&format!(
+        "UPDATE pipeline_runs
+        SET status = 'CANCELLED', updated_at = now()
+        WHERE project_id = $1 AND id = $2 AND status IN ('QUEUED', 'RUNNING')
+        RETURNING {PIPELINE_RUN_COLUMNS}"
+    ))
+    .bind(project_id)
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(run)
+}
+
+/// Mark a failed or interrupted run as queued again, before it is published to the
+/// pipeline jobs queue.
+///
+/// Returns None if the run does not exist, succeeded, or is still running, so that
+/// concurrent requests cannot resume the same run twice.
+pub async fn restart_pipeline_run(
+    pool: &PgPool,
+    project_id: &Uuid,
+    id: &Uuid,
+) -> Result<Option<PipelineRun>> {
+    let run = sqlx::query_as::<_, PipelineRun>(&format!(
+        "UPDATE pipeline_runs
+        SET status = 'QUEUED', updated_at = now(), outputs = NULL, error = NULL
+        WHERE project_id = $1
+            AND id = $2
+            AND (
+                status = 'FAILED'
+                OR (
+                    status IN ('QUEUED', 'RUNNING')
+                    AND updated_at < now() - interval '{STALE_RUN_INTERVAL}'
+                )
+            )
+        RETURNING {PIPELINE_RUN_COLUMNS}"
+    ))
+    .bind(project_id)
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(run)
+}
+
+/// Set the final status of a queued or running run. Cancelled runs keep their status.
+pub async fn finish_pipeline_run(
+    pool: &PgPool,
+    id: &Uuid,
+    status: PipelineRunStatus,
+    outputs: Option<&Value>,
+    error: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE pipeline_runs
+        SET status = $2, outputs = $3, error = $4, updated_at = now()
+        WHERE id = $1 AND status IN ('QUEUED', 'RUNNING')",
+    )
+    .bind(id)
+    .bind(status)
+    .bind(outputs)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Mark a running run as alive, so that it is not considered interrupted while a node
+/// takes long to complete.
+///
+/// Returns the status of the run, or None if it does not exist.
+pub async fn heartbeat_pipeline_run(pool: &PgPool, id: &Uuid) -> Result<Option<PipelineRunStatus>> {
+    let status = sqlx::query_scalar::<_, PipelineRunStatus>(
+        "UPDATE pipeline_runs
+        SET updated_at = CASE WHEN status = 'RUNNING' THEN now() ELSE updated_at END
+        WHERE id = $1
+        RETURNING status",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(status)
+}
+
+/// Checkpoint the message of a node that completed, and mark the run as alive
+pub async fn insert_pipeline_run_message(
+    pool: &PgPool,
+    run_id: &Uuid,
+    message: &Message,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "INSERT INTO pipeline_run_messages (id, run_id, node_id, message)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (run_id, id) DO NOTHING",
+    )
+    .bind(message.id)
+    .bind(run_id)
+    .bind(message.node_id)
+    .bind(serde_json::to_value(message)?)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE pipeline_runs SET updated_at = now() WHERE id = $1")
+        .bind(run_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Checkpointed messages of the run, in the order the nodes completed.
+///
+/// Messages that cannot be deserialized, e.g. the conditioned values of switch nodes, are
+/// skipped, so that their nodes are run again.
+pub async fn get_pipeline_run_messages(pool: &PgPool, run_id: &Uuid) -> Result<Vec<Message>> {
+    let messages = sqlx::query_scalar::<_, Value>(
+        "SELECT message
+        FROM pipeline_run_messages
+        WHERE run_id = $1
+        ORDER BY created_at ASC",
+    )
+    .bind(run_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(messages
+        .into_iter()
+        .filter_map(|message| serde_json::from_value::<Message>(message).ok())
+        .collect())
+}
@@ -1,5 +1,9 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
 
 #[derive(FromRow)]
 pub struct DBPriceEntry {
@@ -9,31 +13,208 @@ pub struct DBPriceEntry {
     pub output_price_per_million: f64,
     pub input_cached_price_per_million: Option<f64>,
     pub additional_prices: Value,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_to: Option<DateTime<Utc>>,
 }
 
-pub async fn get_price(pool: &PgPool, provider: &str, model: &str) -> anyhow::Result<DBPriceEntry> {
-    let price = sqlx::query_as::<_, DBPriceEntry>(
+/// All prices of the model over time, the most recent ones first
+pub async fn get_prices(pool: &PgPool, provider: &str, model: &str) -> Result<Vec<DBPriceEntry>> {
+    let prices = sqlx::query_as::<_, DBPriceEntry>(
         "SELECT
             provider,
             model,
             input_price_per_million,
             output_price_per_million,
             input_cached_price_per_million,
-            additional_prices
+            additional_prices,
+            valid_from,
+            valid_to
         FROM
             llm_prices
         WHERE
             provider = $1
             AND model = $2
         ORDER BY
+            valid_from DESC NULLS LAST,
             updated_at DESC,
             created_at DESC
         ",
     )
     .bind(provider)
     .bind(model)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(prices)
+}
+
+/// Price of a model that overrides the global price in a project, e.g. for fine-tuned
+/// and self-hosted models or negotiated rates
+#[derive(Serialize, Deserialize, FromRow, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectPrice {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub project_id: Uuid,
+    pub provider: String,
+    pub model: String,
+    pub input_price_per_million: f64,
+    pub output_price_per_million: f64,
+    pub input_cached_price_per_million: Option<f64>,
+    pub additional_prices: Value,
+    /// The price applies to the spans that started in `[valid_from, valid_to)`, and
+    /// either bound can be open
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+/// Fields of a price that can be set when creating or updating it
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectPriceFields {
+    pub provider: String,
+    pub model: String,
+    pub input_price_per_million: f64,
+    pub output_price_per_million: f64,
+    #[serde(default)]
+    pub input_cached_price_per_million: Option<f64>,
+    #[serde(default = "default_additional_prices")]
+    pub additional_prices: Value,
+    #[serde(default)]
+    pub valid_from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+fn default_additional_prices() -> Value {
+    Value::Object(Default::default())
+}
+
+/// Price overrides of the project, the most recent ones first
+pub async fn get_project_prices(pool: &PgPool, project_id: &Uuid) -> Result<Vec<ProjectPrice>> {
+    let prices = sqlx::query_as::<_, ProjectPrice>(
+        "SELECT
+            id,
+            created_at,
+            project_id,
+            provider,
+            model,
+            input_price_per_million,
+            output_price_per_million,
+            input_cached_price_per_million,
+            additional_prices,
+            valid_from,
+            valid_to
+        FROM project_llm_prices
+        WHERE project_id = $1
+        ORDER BY valid_from DESC NULLS LAST, created_at DESC",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(prices)
+}
+
+pub async fn create_project_price(
+    pool: &PgPool,
+    project_id: &Uuid,
+    fields: &ProjectPriceFields,
+) -> Result<ProjectPrice> {
+    let price = sqlx::query_as::<_, ProjectPrice>(
+        "INSERT INTO project_llm_prices (
+            project_id,
+            provider,
+            model,
+            input_price_per_million,
+            output_price_per_million,
+            input_cached_price_per_million,
+            additional_prices,
+            valid_from,
+            valid_to
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING
+            id,
+            created_at,
+            project_id,
+            provider,
+            model,
+            input_price_per_million,
+            output_price_per_million,
+            input_cached_price_per_million,
+            additional_prices,
+            valid_from,
+            valid_to",
+    )
+    .bind(project_id)
+    .bind(&fields.provider)
+    .bind(&fields.model)
+    .bind(fields.input_price_per_million)
+    .bind(fields.output_price_per_million)
+    .bind(fields.input_cached_price_per_million)
+    .bind(&fields.additional_prices)
+    .bind(fields.valid_from)
+    .bind(fields.valid_to)
     .fetch_one(pool)
     .await?;
 
     Ok(price)
 }
+
+pub async fn update_project_price(
+    pool: &PgPool,
+    project_id: &Uuid,
+    id: &Uuid,
+    fields: &ProjectPriceFields,
+) -> Result<Option<ProjectPrice>> {
+    let price = sqlx::query_as::<_, ProjectPrice>(
+        "UPDATE project_llm_prices
+        SET
+            provider = $3,
+            model = $4,
+            input_price_per_million = $5,
+            output_price_per_million = $6,
+            input_cached_price_per_million = $7,
+            additional_prices = $8,
+            valid_from = $9,
+            valid_to = $10
+        WHERE project_id = $1 AND id = $2
+        RETURNING
+            id,
+            created_at,
+            project_id,
+            provider,
+            model,
+            input_price_per_million,
+            output_price_per_million,
+            input_cached_price_per_million,
+            additional_prices,
+            valid_from,
+            valid_to",
+    )
+    .bind(project_id)
+    .bind(id)
+    .bind(&fields.provider)
+    .bind(&fields.model)
+    .bind(fields.input_price_per_million)
+    .bind(fields.output_price_per_million)
+    .bind(fields.input_cached_price_per_million)
+    .bind(&fields.additional_prices)
+    .bind(fields.valid_from)
+    .bind(fields.valid_to)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(price)
+}
+
+pub async fn delete_project_price(pool: &PgPool, project_id: &Uuid, id: &Uuid) -> Result<()> {
+    sqlx::query("DELETE FROM project_llm_prices WHERE project_id = $1 AND id = $2")
+        .bind(project_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
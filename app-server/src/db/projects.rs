@@ -1,8 +1,12 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{types::Json, FromRow, PgPool};
 use uuid::Uuid;
 
+use super::trace::TraceType;
+
 #[derive(Deserialize, Serialize, FromRow, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Project {
@@ -57,3 +61,83 @@ pub async fn delete_project(pool: &PgPool, project_id: &Uuid) -> Result<()> {
 
     Ok(())
 }
+
+#[derive(Deserialize, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadSettings {
+    /// Expiry of the presigned urls to the project payloads. If not set, the server default is used
+    pub payload_url_expiry_seconds: Option<i32>,
+}
+
+pub async fn get_payload_settings(pool: &PgPool, project_id: &Uuid) -> Result<PayloadSettings> {
+    let settings = sqlx::query_as::<_, PayloadSettings>(
+        "SELECT payload_url_expiry_seconds FROM projects WHERE id = $1",
+    )
+    .bind(project_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(settings)
+}
+
+pub async fn update_payload_settings(
+    pool: &PgPool,
+    project_id: &Uuid,
+    settings: &PayloadSettings,
+) -> Result<()> {
+    sqlx::query("UPDATE projects SET payload_url_expiry_seconds = $2 WHERE id = $1")
+        .bind(project_id)
+        .bind(settings.payload_url_expiry_seconds)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Server-side sampling of the project traces.
+///
+/// Traces are first sampled by their trace id, at the rate of their trace type. The
+/// other rules are evaluated on every span, and keep the whole trace if any span
+/// matches, even if it was not sampled by its trace id.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SamplingPolicy {
+    /// Fraction of the traces kept, between 0 and 1. Traces of other types are all kept
+    #[serde(default)]
+    pub rates: HashMap<TraceType, f64>,
+    /// Keep the traces with an errored span or an exception event
+    #[serde(default)]
+    pub keep_errors: bool,
+    /// Keep the traces with an LLM span that costs at least this much
+    pub llm_cost_threshold: Option<f64>,
+    /// Keep the traces with an LLM span that lasts at least this long
+    pub llm_latency_threshold_ms: Option<i64>,
+    /// Keep the traces of these sessions
+    #[serde(default)]
+    pub session_ids: Vec<String>,
+}
+
+pub async fn get_sampling_policy(pool: &PgPool, project_id: &Uuid) -> Result<SamplingPolicy> {
+    let policy = sqlx::query_scalar::<_, Option<Json<SamplingPolicy>>>(
+        "SELECT sampling_policy FROM projects WHERE id = $1",
+    )
+    .bind(project_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(policy.map(|policy| policy.0).unwrap_or_default())
+}
+
+pub async fn update_sampling_policy(
+    pool: &PgPool,
+    project_id: &Uuid,
+    policy: &SamplingPolicy,
+) -> Result<()> {
+    sqlx::query("UPDATE projects SET sampling_policy = $2 WHERE id = $1")
+        .bind(project_id)
+        .bind(Json(policy))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
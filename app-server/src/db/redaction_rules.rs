@@ -0,0 +1,125 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[sqlx(type_name = "redaction_rule_type")]
+#[allow(non_camel_case_types)]
+pub enum RedactionRuleType {
+    /// `pattern` is a regular expression matched against string values
+    REGEX,
+    /// `pattern` is the name of a built-in detector, e.g. `email`
+    DETECTOR,
+    /// `pattern` is a regular expression matched against attribute and object keys
+    ATTRIBUTE_KEY,
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[sqlx(type_name = "redaction_action")]
+pub enum RedactionAction {
+    MASK,
+    HASH,
+    DROP,
+}
+
+#[derive(Serialize, Deserialize, FromRow, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionRule {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub project_id: Uuid,
+    pub name: String,
+    pub rule_type: RedactionRuleType,
+    pub pattern: String,
+    pub action: RedactionAction,
+    pub enabled: bool,
+}
+
+/// Fields of a rule that can be set when creating or updating it
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionRuleFields {
+    pub name: String,
+    pub rule_type: RedactionRuleType,
+    pub pattern: String,
+    pub action: RedactionAction,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+pub async fn get_redaction_rules(pool: &PgPool, project_id: &Uuid) -> Result<Vec<RedactionRule>> {
+    let rules = sqlx::query_as::<_, RedactionRule>(
+        "SELECT id, created_at, project_id, name, rule_type, pattern, action, enabled
+        FROM redaction_rules
+        WHERE project_id = $1
+        ORDER BY created_at ASC",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rules)
+}
+
+pub async fn create_redaction_rule(
+    pool: &PgPool,
+    project_id: &Uuid,
+    fields: &RedactionRuleFields,
+) -> Result<RedactionRule> {
+    let rule = sqlx::query_as::<_, RedactionRule>(
+        "INSERT INTO redaction_rules (project_id, name, rule_type, pattern, action, enabled)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, created_at, project_id, name, rule_type, pattern, action, enabled",
+    )
+    .bind(project_id)
+    .bind(&fields.name)
+    .bind(&fields.rule_type)
+    .bind(&fields.pattern)
+    .bind(&fields.action)
+    .bind(fields.enabled)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+pub async fn update_redaction_rule(
+    pool: &PgPool,
+    project_id: &Uuid,
+    id: &Uuid,
+    fields: &RedactionRuleFields,
+) -> Result<Option<RedactionRule>> {
+    let rule = sqlx::query_as::<_, RedactionRule>(
+        "UPDATE redaction_rules
+        SET name = $3, rule_type = $4, pattern = $5, action = $6, enabled = $7
+        WHERE project_id = $1 AND id = $2
+        RETURNING id, created_at, project_id, name, rule_type, pattern, action, enabled",
+    )
+    .bind(project_id)
+    .bind(id)
+    .bind(&fields.name)
+    .bind(&fields.rule_type)
+    .bind(&fields.pattern)
+    .bind(&fields.action)
+    .bind(fields.enabled)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+pub async fn delete_redaction_rule(pool: &PgPool, project_id: &Uuid, id: &Uuid) -> Result<()> {
+    sqlx::query("DELETE FROM redaction_rules WHERE project_id = $1 AND id = $2")
+        .bind(project_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -1,10 +1,10 @@
-use std::str::FromStr;
+use std::{collections::HashSet, str::FromStr};
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgConnection, PgPool};
 use uuid::Uuid;
 
 use super::utils::sanitize_value;
@@ -41,6 +41,16 @@ impl FromStr for SpanType {
     }
 }
 
+/// OpenTelemetry status of the span
+#[derive(sqlx::Type, Deserialize, Serialize, PartialEq, Clone, Copy, Debug, Default)]
+#[sqlx(type_name = "span_status")]
+pub enum SpanStatus {
+    #[default]
+    UNSET,
+    OK,
+    ERROR,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, Default, FromRow)]
 #[serde(rename_all = "camelCase")]
 pub struct Span {
@@ -58,41 +68,104 @@ pub struct Span {
     pub labels: Option<Value>,
     pub input_url: Option<String>,
     pub output_url: Option<String>,
+    // Defaults for span messages that were queued before the status was recorded
+    #[serde(default)]
+    pub status: SpanStatus,
+    #[serde(default)]
+    pub status_message: Option<String>,
 }
 
-pub async fn record_span(pool: &PgPool, span: &Span, project_id: &Uuid) -> Result<()> {
-    let sanitized_input = match &span.input {
-        Some(v) => Some(sanitize_value(v)),
-        None => None,
-    };
-
-    let sanitized_output = match &span.output {
-        Some(v) => Some(sanitize_value(v)),
-        None => None,
-    };
-
-    let input_preview = match &sanitized_input {
-        &Some(Value::String(ref s)) => Some(s.chars().take(PREVIEW_CHARACTERS).collect::<String>()),
-        &Some(ref v) => Some(
+fn preview(value: &Option<Value>) -> Option<String> {
+    match value {
+        Some(Value::String(s)) => Some(s.chars().take(PREVIEW_CHARACTERS).collect::<String>()),
+        Some(v) => Some(
             v.to_string()
                 .chars()
                 .take(PREVIEW_CHARACTERS)
                 .collect::<String>(),
         ),
-        &None => None,
-    };
-    let output_preview = match &sanitized_output {
-        &Some(Value::String(ref s)) => Some(s.chars().take(PREVIEW_CHARACTERS).collect::<String>()),
-        &Some(ref v) => Some(
-            v.to_string()
-                .chars()
-                .take(PREVIEW_CHARACTERS)
-                .collect::<String>(),
-        ),
-        &None => None,
-    };
+        None => None,
+    }
+}
 
-    sqlx::query(
+/// Insert or update multiple spans with a single query.
+///
+/// If the same span appears more than once, only its last occurrence is recorded.
+///
+/// Returns the project and span ids of the spans that were inserted rather than updated.
+pub async fn record_spans(
+    conn: &mut PgConnection,
+    spans: &[(Uuid, &Span)],
+) -> Result<HashSet<(Uuid, Uuid)>> {
+    // A single INSERT ... ON CONFLICT DO UPDATE cannot affect the same row twice
+    let mut seen = HashSet::new();
+    let mut spans = spans
+        .iter()
+        .rev()
+        .filter(|(project_id, span)| seen.insert((span.span_id, *project_id)))
+        .collect::<Vec<_>>();
+    if spans.is_empty() {
+        return Ok(HashSet::new());
+    }
+    // Upsert in a consistent order to avoid deadlocks between concurrent batches
+    spans.sort_by_key(|(project_id, span)| (span.span_id, *project_id));
+
+    let sanitized_inputs = spans
+        .iter()
+        .map(|(_, span)| span.input.as_ref().map(sanitize_value))
+        .collect::<Vec<Option<Value>>>();
+    let sanitized_outputs = spans
+        .iter()
+        .map(|(_, span)| span.output.as_ref().map(sanitize_value))
+        .collect::<Vec<Option<Value>>>();
+    let input_previews = sanitized_inputs.iter().map(preview).collect::<Vec<_>>();
+    let output_previews = sanitized_outputs.iter().map(preview).collect::<Vec<_>>();
+
+    let span_ids = spans.iter().map(|(_, s)| s.span_id).collect::<Vec<Uuid>>();
+    let trace_ids = spans.iter().map(|(_, s)| s.trace_id).collect::<Vec<Uuid>>();
+    let parent_span_ids = spans
+        .iter()
+        .map(|(_, s)| s.parent_span_id)
+        .collect::<Vec<Option<Uuid>>>();
+    let start_times = spans
+        .iter()
+        .map(|(_, s)| s.start_time)
+        .collect::<Vec<DateTime<Utc>>>();
+    let end_times = spans
+        .iter()
+        .map(|(_, s)| s.end_time)
+        .collect::<Vec<DateTime<Utc>>>();
+    let names = spans
+        .iter()
+        .map(|(_, s)| s.name.clone())
+        .collect::<Vec<String>>();
+    let attributes = spans
+        .iter()
+        .map(|(_, s)| s.attributes.clone())
+        .collect::<Vec<Value>>();
+    let span_types = spans
+        .iter()
+        .map(|(_, s)| s.span_type.clone())
+        .collect::<Vec<SpanType>>();
+    let input_urls = spans
+        .iter()
+        .map(|(_, s)| s.input_url.clone())
+        .collect::<Vec<Option<String>>>();
+    let output_urls = spans
+        .iter()
+        .map(|(_, s)| s.output_url.clone())
+        .collect::<Vec<Option<String>>>();
+    let project_ids = spans.iter().map(|(p, _)| *p).collect::<Vec<Uuid>>();
+    let statuses = spans
+        .iter()
+        .map(|(_, s)| s.status)
+        .collect::<Vec<SpanStatus>>();
+    let status_messages = spans
+        .iter()
+        .map(|(_, s)| s.status_message.clone())
+        .collect::<Vec<Option<String>>>();
+
+    let rows = sqlx::query_as::<_, (Uuid, Uuid, bool)>(
         "INSERT INTO spans
             (span_id,
             trace_id,
@@ -108,24 +181,28 @@ pub async fn record_span(pool: &PgPool, span: &Span, project_id: &Uuid) -> Resul
             output_preview,
             input_url,
             output_url,
-            project_id
+            project_id,
+            status,
+            status_message
         )
-        VALUES(
-            $1,
-            $2,
-            $3,
-            $4,
-            $5,
-            $6,
-            $7,
-            $8,
-            $9,
-            $10,
-            $11,
-            $12,
-            $13,
-            $14,
-            $15)
+        SELECT * FROM UNNEST(
+            $1::uuid[],
+            $2::uuid[],
+            $3::uuid[],
+            $4::timestamptz[],
+            $5::timestamptz[],
+            $6::text[],
+            $7::jsonb[],
+            $8::jsonb[],
+            $9::jsonb[],
+            $10::span_type[],
+            $11::text[],
+            $12::text[],
+            $13::text[],
+            $14::text[],
+            $15::uuid[],
+            $16::span_status[],
+            $17::text[])
         ON CONFLICT (span_id, project_id) DO UPDATE SET
             trace_id = EXCLUDED.trace_id,
             parent_span_id = EXCLUDED.parent_span_id,
@@ -139,24 +216,134 @@ pub async fn record_span(pool: &PgPool, span: &Span, project_id: &Uuid) -> Resul
             input_preview = EXCLUDED.input_preview,
             output_preview = EXCLUDED.output_preview,
             input_url = EXCLUDED.input_url,
-            output_url = EXCLUDED.output_url
+            output_url = EXCLUDED.output_url,
+            status = EXCLUDED.status,
+            status_message = EXCLUDED.status_message
+        RETURNING project_id, span_id, (xmax = 0) AS inserted
     ",
     )
-    .bind(&span.span_id)
-    .bind(&span.trace_id)
-    .bind(&span.parent_span_id as &Option<Uuid>)
-    .bind(&span.start_time)
-    .bind(&span.end_time)
-    .bind(&span.name)
-    .bind(&span.attributes)
-    .bind(&sanitized_input as &Option<Value>)
-    .bind(&sanitized_output as &Option<Value>)
-    .bind(&span.span_type as &SpanType)
-    .bind(&input_preview)
-    .bind(&output_preview)
-    .bind(&span.input_url as &Option<String>)
-    .bind(&span.output_url as &Option<String>)
-    .bind(&project_id)
+    .bind(span_ids)
+    .bind(trace_ids)
+    .bind(parent_span_ids)
+    .bind(start_times)
+    .bind(end_times)
+    .bind(names)
+    .bind(attributes)
+    .bind(sanitized_inputs)
+    .bind(sanitized_outputs)
+    .bind(span_types)
+    .bind(input_previews)
+    .bind(output_previews)
+    .bind(input_urls)
+    .bind(output_urls)
+    .bind(project_ids)
+    .bind(statuses)
+    .bind(status_messages)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter(|(_, _, inserted)| *inserted)
+        .map(|(project_id, span_id, _)| (project_id, span_id))
+        .collect())
+}
+
+#[derive(FromRow)]
+pub struct ErrorSpan {
+    pub span_id: Uuid,
+    pub name: String,
+    pub status_message: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+/// Get the spans of a trace that ended with an error status, in chronological order
+pub async fn get_trace_error_spans(
+    pool: &PgPool,
+    project_id: Uuid,
+    trace_id: Uuid,
+) -> Result<Vec<ErrorSpan>> {
+    let spans = sqlx::query_as::<_, ErrorSpan>(
+        "SELECT span_id, name, status_message, start_time, end_time
+        FROM spans
+        WHERE project_id = $1 AND trace_id = $2 AND status = 'ERROR'
+        ORDER BY start_time ASC",
+    )
+    .bind(project_id)
+    .bind(trace_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(spans)
+}
+
+#[derive(FromRow)]
+pub struct LLMSpanAttributes {
+    pub span_id: Uuid,
+    pub trace_id: Uuid,
+    pub start_time: DateTime<Utc>,
+    pub attributes: Value,
+}
+
+/// A page of the LLM spans that started in `[start_time, end_time)`, ordered by their
+/// start time and id. The page starts after the start time and id in `after`.
+pub async fn get_llm_spans_attributes(
+    pool: &PgPool,
+    project_id: &Uuid,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    after: Option<(DateTime<Utc>, Uuid)>,
+    limit: i64,
+) -> Result<Vec<LLMSpanAttributes>> {
+    let spans = sqlx::query_as::<_, LLMSpanAttributes>(
+        "SELECT span_id, trace_id, start_time, attributes
+        FROM spans
+        WHERE
+            project_id = $1
+            AND span_type = 'LLM'
+            AND start_time >= $2
+            AND start_time < $3
+            AND ($4::timestamptz IS NULL OR (start_time, span_id) > ($4, $5))
+        ORDER BY start_time ASC, span_id ASC
+        LIMIT $6",
+    )
+    .bind(project_id)
+    .bind(start_time)
+    .bind(end_time)
+    .bind(after.map(|(start_time, _)| start_time))
+    .bind(after.map(|(_, span_id)| span_id))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(spans)
+}
+
+/// Replace the attributes of multiple spans of the project with a single query
+pub async fn update_spans_attributes(
+    pool: &PgPool,
+    project_id: &Uuid,
+    spans: &[(Uuid, Value)],
+) -> Result<()> {
+    if spans.is_empty() {
+        return Ok(());
+    }
+    let span_ids = spans.iter().map(|(id, _)| *id).collect::<Vec<Uuid>>();
+    let attributes = spans
+        .iter()
+        .map(|(_, attributes)| attributes.clone())
+        .collect::<Vec<Value>>();
+
+    sqlx::query(
+        "UPDATE spans
+        SET attributes = batch.attributes
+        FROM UNNEST($2::uuid[], $3::jsonb[]) AS batch(span_id, attributes)
+        WHERE spans.project_id = $1 AND spans.span_id = batch.span_id",
+    )
+    .bind(project_id)
+    .bind(span_ids)
+    .bind(attributes)
     .execute(pool)
     .await?;
 
@@ -2,13 +2,19 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
-use crate::traces::attributes::TraceAttributes;
+use crate::traces::{
+    attributes::TraceAttributes,
+    span_attributes::{
+        GEN_AI_INPUT_COST, GEN_AI_INPUT_TOKENS, GEN_AI_OUTPUT_COST, GEN_AI_OUTPUT_TOKENS,
+        GEN_AI_TOTAL_COST,
+    },
+};
 
 /// Helper struct to pass current trace info, if exists, if pipeline is called from remote trace context
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CurrentTraceAndSpan {
     pub trace_id: Uuid,
@@ -18,7 +24,7 @@ pub struct CurrentTraceAndSpan {
     pub parent_span_path: Option<String>,
 }
 
-#[derive(sqlx::Type, Deserialize, Serialize, PartialEq, Clone, Debug, Default)]
+#[derive(sqlx::Type, Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Debug, Default)]
 #[sqlx(type_name = "trace_type")]
 pub enum TraceType {
     #[default]
@@ -46,13 +52,103 @@ pub struct Trace {
     project_id: Uuid,
 }
 
-pub async fn update_trace_attributes(
-    pool: &PgPool,
-    project_id: &Uuid,
-    attributes: &TraceAttributes,
+/// Insert or update the aggregated attributes of multiple traces with a single query.
+///
+/// Each trace must appear at most once, i.e. attributes of spans of the same trace
+/// must be merged beforehand. Token counts and costs are added to the existing ones, so
+/// they must only include the usage of spans that have not been recorded before.
+pub async fn update_traces_attributes(
+    conn: &mut PgConnection,
+    traces: &[(Uuid, TraceAttributes)],
 ) -> Result<()> {
+    if traces.is_empty() {
+        return Ok(());
+    }
+
+    // Upsert in a consistent order to avoid deadlocks between concurrent batches
+    let mut traces = traces.iter().collect::<Vec<_>>();
+    traces.sort_by_key(|(_, attributes)| attributes.id);
+
+    let ids = traces.iter().map(|(_, a)| a.id).collect::<Vec<Uuid>>();
+    let project_ids = traces.iter().map(|(p, _)| *p).collect::<Vec<Uuid>>();
+    let input_token_counts = traces
+        .iter()
+        .map(|(_, a)| a.input_token_count)
+        .collect::<Vec<_>>();
+    let output_token_counts = traces
+        .iter()
+        .map(|(_, a)| a.output_token_count)
+        .collect::<Vec<_>>();
+    let total_token_counts = traces
+        .iter()
+        .map(|(_, a)| a.total_token_count)
+        .collect::<Vec<_>>();
+    let input_costs = traces.iter().map(|(_, a)| a.input_cost).collect::<Vec<_>>();
+    let output_costs = traces
+        .iter()
+        .map(|(_, a)| a.output_cost)
+        .collect::<Vec<_>>();
+    let costs = traces.iter().map(|(_, a)| a.cost).collect::<Vec<_>>();
+    let start_times = traces.iter().map(|(_, a)| a.start_time).collect::<Vec<_>>();
+    let end_times = traces.iter().map(|(_, a)| a.end_time).collect::<Vec<_>>();
+    let session_ids = traces
+        .iter()
+        .map(|(_, a)| a.session_id.clone())
+        .collect::<Vec<_>>();
+    let trace_types = traces
+        .iter()
+        .map(|(_, a)| a.trace_type.clone())
+        .collect::<Vec<_>>();
+    let metadata = traces
+        .iter()
+        .map(|(_, a)| serde_json::to_value(&a.metadata).unwrap())
+        .collect::<Vec<Value>>();
+    let has_browser_sessions = traces
+        .iter()
+        .map(|(_, a)| a.has_browser_session)
+        .collect::<Vec<_>>();
+    let top_span_ids = traces
+        .iter()
+        .map(|(_, a)| a.top_span_id)
+        .collect::<Vec<_>>();
+
     sqlx::query(
         "
+        WITH batch AS (
+            SELECT * FROM UNNEST(
+                $1::uuid[],
+                $2::uuid[],
+                $3::int8[],
+                $4::int8[],
+                $5::int8[],
+                $6::float8[],
+                $7::float8[],
+                $8::float8[],
+                $9::timestamptz[],
+                $10::timestamptz[],
+                $11::text[],
+                $12::trace_type[],
+                $13::jsonb[],
+                $14::bool[],
+                $15::uuid[]
+            ) AS t(
+                id,
+                project_id,
+                input_token_count,
+                output_token_count,
+                total_token_count,
+                input_cost,
+                output_cost,
+                cost,
+                start_time,
+                end_time,
+                session_id,
+                trace_type,
+                metadata,
+                has_browser_session,
+                top_span_id
+            )
+        )
         INSERT INTO traces (
             id,
             project_id,
@@ -70,57 +166,131 @@ pub async fn update_trace_attributes(
             has_browser_session,
             top_span_id
         )
-        VALUES (
-            $1,
-            $2,
-            COALESCE($3, 0::int8),
-            COALESCE($4, 0::int8),
-            COALESCE($5, 0::int8),
-            COALESCE($6, 0::float8),
-            COALESCE($7, 0::float8),
-            COALESCE($8, 0::float8),
-            $9,
-            $10,
-            $11,
-            COALESCE($12, 'DEFAULT'::trace_type),
-            $13,
-            $14,
-            $15
-        )
+        SELECT
+            id,
+            project_id,
+            COALESCE(input_token_count, 0::int8),
+            COALESCE(output_token_count, 0::int8),
+            COALESCE(total_token_count, 0::int8),
+            COALESCE(input_cost, 0::float8),
+            COALESCE(output_cost, 0::float8),
+            COALESCE(cost, 0::float8),
+            start_time,
+            end_time,
+            session_id,
+            COALESCE(trace_type, 'DEFAULT'::trace_type),
+            metadata,
+            has_browser_session,
+            top_span_id
+        FROM batch
+        ORDER BY id
         ON CONFLICT(id) DO
         UPDATE
         SET
-            input_token_count = traces.input_token_count + COALESCE($3, 0),
-            output_token_count = traces.output_token_count + COALESCE($4, 0),
-            total_token_count = traces.total_token_count + COALESCE($5, 0),
-            input_cost = traces.input_cost + COALESCE($6, 0),
-            output_cost = traces.output_cost + COALESCE($7, 0),
-            cost = traces.cost + COALESCE($8, 0),
-            start_time = CASE WHEN traces.start_time IS NULL OR traces.start_time > $9 THEN $9 ELSE traces.start_time END,
-            end_time = CASE WHEN traces.end_time IS NULL OR traces.end_time < $10 THEN $10 ELSE traces.end_time END,
-            session_id = COALESCE(traces.session_id, $11),
-            trace_type = CASE WHEN $12 IS NULL THEN traces.trace_type ELSE COALESCE($12, 'DEFAULT'::trace_type) END,
-            metadata = COALESCE($13, traces.metadata),
-            has_browser_session = COALESCE($14, traces.has_browser_session),
-            top_span_id = COALESCE(traces.top_span_id, $15)
+            input_token_count = traces.input_token_count + EXCLUDED.input_token_count,
+            output_token_count = traces.output_token_count + EXCLUDED.output_token_count,
+            total_token_count = traces.total_token_count + EXCLUDED.total_token_count,
+            input_cost = traces.input_cost + EXCLUDED.input_cost,
+            output_cost = traces.output_cost + EXCLUDED.output_cost,
+            cost = traces.cost + EXCLUDED.cost,
+            start_time = CASE WHEN traces.start_time IS NULL OR traces.start_time > EXCLUDED.start_time THEN EXCLUDED.start_time ELSE traces.start_time END,
+            end_time = CASE WHEN traces.end_time IS NULL OR traces.end_time < EXCLUDED.end_time THEN EXCLUDED.end_time ELSE traces.end_time END,
+            session_id = COALESCE(traces.session_id, EXCLUDED.session_id),
+            trace_type = COALESCE((SELECT batch.trace_type FROM batch WHERE batch.id = EXCLUDED.id), traces.trace_type),
+            metadata = COALESCE(EXCLUDED.metadata, traces.metadata),
+            has_browser_session = COALESCE(EXCLUDED.has_browser_session, traces.has_browser_session),
+            top_span_id = COALESCE(traces.top_span_id, EXCLUDED.top_span_id)
         "
     )
-    .bind(attributes.id)
+    .bind(ids)
+    .bind(project_ids)
+    .bind(input_token_counts)
+    .bind(output_token_counts)
+    .bind(total_token_counts)
+    .bind(input_costs)
+    .bind(output_costs)
+    .bind(costs)
+    .bind(start_times)
+    .bind(end_times)
+    .bind(session_ids)
+    .bind(trace_types)
+    .bind(metadata)
+    .bind(has_browser_sessions)
+    .bind(top_span_ids)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// SQL expression for the numeric value of the span attribute, or 0 if the attribute is
+/// missing or is not a number
+fn numeric_attribute(key: &str) -> String {
+    format!(
+        "CASE WHEN jsonb_typeof(attributes->'{key}') = 'number' \
+        THEN (attributes->>'{key}')::numeric ELSE 0 END"
+    )
+}
+
+/// Recalculate the token counts and costs of the traces from all their LLM spans, e.g. after
+/// the costs of the spans were recalculated.
+///
+/// Attribute values that are not numbers count as 0, rather than failing the update.
+///
+/// The traces are locked first, so that concurrent recalculations of the same trace are
+/// serialized and the last one sees the spans recorded by all of them.
+pub async fn update_traces_usage_from_spans(
+    pool: &PgPool,
+    project_id: &Uuid,
+    trace_ids: &[Uuid],
+) -> Result<()> {
+    if trace_ids.is_empty() {
+        return Ok(());
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query(
+        "SELECT id FROM traces WHERE project_id = $1 AND id = ANY($2) ORDER BY id FOR UPDATE",
+    )
+    .bind(project_id)
+    .bind(trace_ids)
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query(&format!(
+        "UPDATE traces
+        SET
+            input_token_count = usage.input_token_count,
+            output_token_count = usage.output_token_count,
+            total_token_count = usage.input_token_count + usage.output_token_count,
+            input_cost = usage.input_cost,
+            output_cost = usage.output_cost,
+            cost = usage.cost
+        FROM (
+            SELECT
+                trace_id,
+                SUM({input_tokens})::int8 AS input_token_count,
+                SUM({output_tokens})::int8 AS output_token_count,
+                SUM({input_cost})::float8 AS input_cost,
+                SUM({output_cost})::float8 AS output_cost,
+                SUM({cost})::float8 AS cost
+            FROM spans
+            WHERE project_id = $1 AND trace_id = ANY($2) AND span_type = 'LLM'
+            GROUP BY trace_id
+        ) AS usage
+        WHERE traces.project_id = $1 AND traces.id = usage.trace_id",
+        input_tokens = numeric_attribute(GEN_AI_INPUT_TOKENS),
+        output_tokens = numeric_attribute(GEN_AI_OUTPUT_TOKENS),
+        input_cost = numeric_attribute(GEN_AI_INPUT_COST),
+        output_cost = numeric_attribute(GEN_AI_OUTPUT_COST),
+        cost = numeric_attribute(GEN_AI_TOTAL_COST),
+    ))
     .bind(project_id)
-    .bind(attributes.input_token_count)
-    .bind(attributes.output_token_count)
-    .bind(attributes.total_token_count)
-    .bind(attributes.input_cost)
-    .bind(attributes.output_cost)
-    .bind(attributes.cost)
-    .bind(attributes.start_time)
-    .bind(attributes.end_time)
-    .bind(&attributes.session_id)
-    .bind(&attributes.trace_type)
-    .bind(&serde_json::to_value(&attributes.metadata).unwrap())
-    .bind(attributes.has_browser_session)
-    .bind(attributes.top_span_id)
-    .execute(pool)
+    .bind(trace_ids)
+    .execute(&mut *transaction)
     .await?;
+
+    transaction.commit().await?;
+
     Ok(())
 }
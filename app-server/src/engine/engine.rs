@@ -1,5 +1,7 @@
 use crate::{
+    db,
     engine::{
+        policy::NodeFallback,
         task::{State, Task},
         RunOutput,
     },
@@ -45,6 +47,8 @@ pub struct Engine {
     control_semaphore: Arc<tokio::sync::Semaphore>,
     /// Tasks which will stop the execution of the graph and wait until continue signal is received.
     breakpoint_task_ids: Arc<DashSet<Uuid>>,
+    /// Run under which the messages of the completed tasks are checkpointed to the database.
+    checkpoint_run_id: Option<Uuid>,
 }
 
 #[derive(Debug)]
@@ -80,12 +84,15 @@ impl Engine {
             active_tasks: Arc::new(DashSet::new()),
             idle_tasks: Arc::new(DashSet::new()),
             node_messages: Arc::new(DashMap::new()),
-            context: Arc::new(context),
             depths: Arc::new(DashMap::new()),
             output_ids: Arc::new(DashSet::new()),
             handles: Arc::new(DashMap::new()),
-            control_semaphore: Arc::new(tokio::sync::Semaphore::new(20)),
+            control_semaphore: Arc::new(tokio::sync::Semaphore::new(
+                context.concurrency.max_concurrent_nodes,
+            )),
             breakpoint_task_ids: Arc::new(DashSet::new()),
+            checkpoint_run_id: None,
+            context: Arc::new(context),
         }
     }
 
@@ -117,24 +124,7 @@ impl Engine {
 
                 // prefill next tasks with the message
                 for next_task_id in task.next.iter() {
-                    let next_task = engine.tasks.get(next_task_id).unwrap().clone();
-
-                    let handle_names = next_task
-                        .action
-                        .handles_mapping()
-                        .iter()
-                        .filter(|(k, _)| *k == task.action.output_handle_id())
-                        .map(|(_, v)| v.clone())
-                        .collect::<Vec<_>>();
-
-                    for handle in handle_names.iter() {
-                        let next_state = next_task
-                            .input_states
-                            .get(&handle.name_force())
-                            .unwrap()
-                            .clone();
-                        next_state.set_state_and_permits(State::new(message.clone()), 1);
-                    }
+                    engine.prefill_next_task(&task, next_task_id, &message);
                 }
             }
             // reset all input_states
@@ -145,6 +135,78 @@ impl Engine {
         engine
     }
 
+    /// Checkpoint the messages of the completed tasks to the database under the run id.
+    pub fn set_checkpoint_run_id(&mut self, run_id: Uuid) {
+        self.checkpoint_run_id = Some(run_id);
+    }
+
+    /// Restore the messages checkpointed by a previous run of the same graph.
+    ///
+    /// Returns the tasks to resume the run from, i.e. the tasks that have not completed,
+    /// but whose inputs are all set by the completed tasks.
+    pub fn restore_checkpoint(&self, messages: Vec<Message>) -> Vec<Uuid> {
+        // tasks in cycles can complete several times, and the messages are in the order of completion
+        let mut last_messages = HashMap::new();
+        for message in messages {
+            if self.tasks.contains_key(&message.node_id) {
+                last_messages.insert(message.node_id, message);
+            }
+        }
+
+        for message in last_messages.values() {
+            let task = self.tasks.get(&message.node_id).unwrap().clone();
+            let fell_back = matches!(
+                &message.meta_log,
+                Some(MetaLog::Retry(retry_meta)) if retry_meta.fell_back
+            );
+
+            for next_task_id in task.next.iter() {
+                if task.policy.follows_edge(next_task_id, fell_back) {
+                    self.prefill_next_task(&task, next_task_id, message);
+                }
+            }
+            if task.next.is_empty() {
+                self.output_ids.insert(message.id);
+            }
+            self.node_messages.insert(message.id, message.clone());
+        }
+
+        self.tasks
+            .iter()
+            .filter(|task| {
+                !last_messages.contains_key(&task.id)
+                    && task
+                        .input_states
+                        .values()
+                        .all(|state| state.get_state().is_success())
+            })
+            .map(|task| task.id)
+            .collect()
+    }
+
+    /// Set the message of the task as the input of the next task, on every handle
+    /// that takes the output of the task.
+    fn prefill_next_task(&self, task: &Task, next_task_id: &Uuid, message: &Message) {
+        let next_task = self.tasks.get(next_task_id).unwrap().clone();
+
+        let handle_names = next_task
+            .action
+            .handles_mapping()
+            .iter()
+            .filter(|(k, _)| *k == task.action.output_handle_id())
+            .map(|(_, v)| v.clone())
+            .collect::<Vec<_>>();
+
+        for handle in handle_names.iter() {
+            let next_state = next_task
+                .input_states
+                .get(&handle.name_force())
+                .unwrap()
+                .clone();
+            next_state.set_state_and_permits(State::new(message.clone()), 1);
+        }
+    }
+
     fn propagate_reset_input_states(
         &self,
         task_id: Uuid,
@@ -193,8 +255,6 @@ impl Engine {
         interrupt_recv: Option<Receiver<GraphInterruptMessage>>,
         start_task_id: Option<Uuid>,
     ) -> Result<EngineOutput, EngineOutput> {
-        let (task_send, mut task_recv) = tokio::sync::mpsc::channel::<ScheduledTask>(10);
-
         let input_tasks = if let Some(start_task_id) = start_task_id {
             vec![start_task_id]
         } else {
@@ -205,6 +265,24 @@ impl Engine {
                 .collect::<Vec<_>>()
         };
 
+        self.run_from(stream_send, interrupt_recv, input_tasks)
+            .await
+    }
+
+    /// Start task scheduler and execute tasks, starting from the given tasks.
+    pub async fn run_from(
+        &mut self,
+        stream_send: Option<Sender<StreamChunk>>,
+        interrupt_recv: Option<Receiver<GraphInterruptMessage>>,
+        input_tasks: Vec<Uuid>,
+    ) -> Result<EngineOutput, EngineOutput> {
+        // e.g. a restored run whose tasks have all completed
+        if input_tasks.is_empty() {
+            return Ok(self.get_outputs());
+        }
+
+        let (task_send, mut task_recv) = tokio::sync::mpsc::channel::<ScheduledTask>(10);
+
         // push input tasks to the channel
         let tx = task_send.clone();
         tokio::spawn(async move {
@@ -218,6 +296,7 @@ impl Engine {
             let active_tasks = self.active_tasks.clone();
             let handles = self.handles.clone();
             let control_semaphore = self.control_semaphore.clone();
+            let max_concurrent_nodes = self.context.concurrency.max_concurrent_nodes;
             tokio::spawn(async move {
                 while let Some(interrupt) = interrupt_recv.recv().await {
                     if matches!(interrupt, GraphInterruptMessage::Cancel) {
@@ -227,7 +306,7 @@ impl Engine {
                         tx.send(ScheduledTask::Err).await.unwrap();
                     } else if matches!(interrupt, GraphInterruptMessage::Continue) {
                         // continue execution
-                        control_semaphore.add_permits(20);
+                        control_semaphore.add_permits(max_concurrent_nodes);
                     }
                 }
             });
@@ -301,6 +380,7 @@ impl Engine {
         let context = self.context.clone();
         let task_id = task.id;
         let action = task.action.clone();
+        let policy = task.policy.clone();
         let next = task.next.clone();
         let input_states = task.input_states.clone();
         let active_tasks = self.active_tasks.clone();
@@ -312,6 +392,9 @@ impl Engine {
         let node_messages = self.node_messages.clone();
         let control_semaphore = self.control_semaphore.clone();
         let breakpoint_task_ids = self.breakpoint_task_ids.clone();
+        let checkpoint = self
+            .checkpoint_run_id
+            .map(|run_id| (run_id, self.context.db.clone()));
 
         tokio::spawn(async move {
             // acquire semaphore to control the number of active tasks
@@ -358,7 +441,7 @@ impl Engine {
                 stream_send.send(stream_chunk).await.unwrap();
             }
 
-            match AssertUnwindSafe(action.run(inputs, context))
+            match AssertUnwindSafe(policy.run(&action, inputs, context))
                 .catch_unwind()
                 .await
             {
@@ -386,7 +469,22 @@ impl Engine {
                     // release semaphore
                     drop(control_permit);
                 }
-                Ok(out) => {
+                Ok((out, attempts)) => {
+                    // if the last attempt failed, the node outputs its fallback instead of failing the graph
+                    let (out, fell_back) = match (out, &policy.fallback) {
+                        (Err(err), Some(fallback)) => {
+                            debug!(
+                                "Execution failed [id: {}], falling back, err: {}",
+                                task_id, err
+                            );
+                            let value = match fallback {
+                                NodeFallback::Value { value } => value.clone(),
+                                NodeFallback::Edge { .. } => err.to_string().into(),
+                            };
+                            (Ok(RunOutput::Success((value, None))), true)
+                        }
+                        (out, _) => (out, false),
+                    };
                     match out {
                         Ok(run_output) => {
                             let state = match run_output {
@@ -400,9 +498,13 @@ impl Engine {
                                             }
                                             MetaLog::Zenguard(_)
                                             | MetaLog::Subpipeline(_)
-                                            | MetaLog::Map(_) => {}
+                                            | MetaLog::Map(_)
+                                            | MetaLog::Loop(_)
+                                            | MetaLog::Retry(_) => {}
                                         }
                                     }
+                                    let meta_log =
+                                        MetaLog::with_attempts(meta_log, attempts, fell_back);
                                     let message = Message {
                                         id,
                                         value,
@@ -416,6 +518,19 @@ impl Engine {
                                     };
                                     node_messages.insert(id, message.clone());
 
+                                    if let Some((run_id, db)) = &checkpoint {
+                                        if let Err(e) = db::pipelines::insert_pipeline_run_message(
+                                            &db.pool, run_id, &message,
+                                        )
+                                        .await
+                                        {
+                                            error!(
+                                                "Failed to checkpoint message of run {}: {}",
+                                                run_id, e
+                                            );
+                                        }
+                                    }
+
                                     State::new(message)
                                 }
                                 RunOutput::Termination => State::termination(),
@@ -500,6 +615,11 @@ impl Engine {
                                     break;
                                 }
 
+                                // skip the fallback edge of the node unless it fell back, and the other edges if it did
+                                if !policy.follows_edge(next_task_id, fell_back) {
+                                    continue;
+                                }
+
                                 // we set the inputs of the next tasks to the outputs of the current task
                                 let next_task = tasks.get(next_task_id).unwrap().clone();
 
@@ -563,7 +683,7 @@ impl Engine {
                                 node_name: action.node_name(),
                                 node_type: action.node_type(),
                                 input_message_ids,
-                                meta_log: None,
+                                meta_log: MetaLog::with_attempts(None, attempts, false),
                                 start_time,
                                 end_time: Utc::now(),
                             };
@@ -2,7 +2,9 @@ extern crate bimap;
 extern crate tokio;
 
 pub use engine::Engine;
+pub use policy::NodePolicy;
 pub use task::{RunOutput, RunnableNode, Task};
 
 pub mod engine;
+pub mod policy;
 pub mod task;
@@ -0,0 +1,272 @@
+//! Retry, timeout and fallback policies of the nodes in a graph.
+//!
+//! Policies are set per node in the `policies` of the graph. A node without a policy
+//! is run once, without a time limit, and its error fails the whole graph.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::Utc;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    language_model::providers::utils::ProviderError,
+    pipeline::{context::Context, nodes::NodeInput, trace::NodeAttempt},
+};
+
+use super::task::{Action, RunOutput};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodePolicy {
+    /// Number of times the node is run before it fails, including the first run
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the second attempt, multiplied by `backoff_multiplier` before every next one
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Time limit of each attempt, not of all the attempts together
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Errors that are retried. Other errors fail the node right away
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryErrorClass>,
+    /// What the node outputs if its last attempt fails, instead of failing the graph
+    #[serde(default)]
+    pub fallback: Option<NodeFallback>,
+}
+
+fn default_max_attempts() -> u32 {
+    1
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+
+fn default_retry_on() -> Vec<RetryErrorClass> {
+    vec![
+        RetryErrorClass::RateLimit,
+        RetryErrorClass::ServerError,
+        RetryErrorClass::Timeout,
+    ]
+}
+
+impl Default for NodePolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            backoff_multiplier: default_backoff_multiplier(),
+            max_backoff_ms: default_max_backoff_ms(),
+            timeout_ms: None,
+            retry_on: default_retry_on(),
+            fallback: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum RetryErrorClass {
+    /// 429 responses of the LLM providers
+    RateLimit,
+    /// 5xx responses of the LLM providers and other HTTP APIs
+    ServerError,
+    /// Attempts that exceed the `timeout_ms` of the policy, and HTTP requests that time out
+    Timeout,
+}
+
+impl RetryErrorClass {
+    fn matches(&self, error: &anyhow::Error) -> bool {
+        let reqwest_error = error.downcast_ref::<reqwest::Error>();
+        let status = error
+            .downcast_ref::<ProviderError>()
+            .map(|error| error.status)
+            .or_else(|| reqwest_error.and_then(|error| error.status()));
+
+        match self {
+            Self::RateLimit => status == Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+            Self::ServerError => status.is_some_and(|status| status.is_server_error()),
+            Self::Timeout => {
+                error.is::<AttemptTimeoutError>()
+                    || reqwest_error.is_some_and(|error| error.is_timeout())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum NodeFallback {
+    /// The node outputs the value, and all its successors are run
+    Value { value: NodeInput },
+    /// The node outputs the error message, and only this successor is run. The successor
+    /// is skipped if the node succeeds
+    Edge {
+        #[serde(rename = "nodeId")]
+        node_id: Uuid,
+    },
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Node timed out after {0} ms")]
+pub struct AttemptTimeoutError(u64);
+
+impl NodePolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_attempts == 0 {
+            return Err(anyhow::anyhow!("maxAttempts must be at least 1"));
+        }
+        if self.backoff_multiplier < 1.0 {
+            return Err(anyhow::anyhow!("backoffMultiplier must be at least 1"));
+        }
+        if self.timeout_ms == Some(0) {
+            return Err(anyhow::anyhow!("timeoutMs must be positive"));
+        }
+        Ok(())
+    }
+
+    /// Run the node until an attempt succeeds, fails with an error that is not retried, or
+    /// the attempts run out.
+    ///
+    /// Returns the result of the last attempt, and all the attempts.
+    pub async fn run(
+        &self,
+        action: &Action,
+        inputs: HashMap<String, NodeInput>,
+        context: Arc<Context>,
+    ) -> (Result<RunOutput>, Vec<NodeAttempt>) {
+        let mut attempts = Vec::new();
+        loop {
+            let start_time = Utc::now();
+            let result = self
+                .run_attempt(action, inputs.clone(), context.clone())
+                .await;
+            attempts.push(NodeAttempt {
+                start_time,
+                end_time: Utc::now(),
+                error: result.as_ref().err().map(|error| error.to_string()),
+            });
+
+            match &result {
+                Err(error)
+                    if attempts.len() < self.max_attempts as usize && self.is_retried(error) =>
+                {
+                    debug!(
+                        "Retrying node {} after attempt {}, err: {}",
+                        action.node_id(),
+                        attempts.len(),
+                        error
+                    );
+                    tokio::time::sleep(self.backoff(attempts.len())).await;
+                }
+                _ => return (result, attempts),
+            }
+        }
+    }
+
+    async fn run_attempt(
+        &self,
+        action: &Action,
+        inputs: HashMap<String, NodeInput>,
+        context: Arc<Context>,
+    ) -> Result<RunOutput> {
+        let Some(timeout_ms) = self.timeout_ms else {
+            return action.run(inputs, context).await;
+        };
+        match tokio::time::timeout(
+            Duration::from_millis(timeout_ms),
+            action.run(inputs, context),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(AttemptTimeoutError(timeout_ms).into()),
+        }
+    }
+
+    fn is_retried(&self, error: &anyhow::Error) -> bool {
+        self.retry_on.iter().any(|class| class.matches(error))
+    }
+
+    /// Delay before the attempt that follows `attempt_count` attempts
+    fn backoff(&self, attempt_count: usize) -> Duration {
+        let delay = self.initial_backoff_ms as f64
+            * self
+                .backoff_multiplier
+                .powi(attempt_count.saturating_sub(1) as i32);
+        Duration::from_millis(delay.min(self.max_backoff_ms as f64) as u64)
+    }
+
+    /// Whether the successor of the node is run, given whether the node fell back
+    pub fn follows_edge(&self, next_task_id: &Uuid, fell_back: bool) -> bool {
+        match &self.fallback {
+            Some(NodeFallback::Edge { node_id }) => (node_id == next_task_id) == fell_back,
+            Some(NodeFallback::Value { .. }) | None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_defaults_and_backoff() {
+        let policy = serde_json::from_value::<NodePolicy>(serde_json::json!({
+            "maxAttempts": 4,
+            "initialBackoffMs": 500,
+            "maxBackoffMs": 1500,
+        }))
+        .unwrap();
+
+        assert_eq!(policy.retry_on, default_retry_on());
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(3), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_retried_errors() {
+        let policy = NodePolicy {
+            retry_on: vec![RetryErrorClass::RateLimit, RetryErrorClass::Timeout],
+            ..Default::default()
+        };
+
+        let rate_limit = ProviderError::new(reqwest::StatusCode::TOO_MANY_REQUESTS, "Rate limit");
+        let server_error = ProviderError::new(reqwest::StatusCode::BAD_GATEWAY, "Bad gateway");
+        assert!(policy.is_retried(&rate_limit.into()));
+        assert!(!policy.is_retried(&server_error.into()));
+        assert!(policy.is_retried(&AttemptTimeoutError(100).into()));
+        assert!(!policy.is_retried(&anyhow::anyhow!("Model not found")));
+    }
+
+    #[test]
+    fn test_fallback_edge() {
+        let fallback_node_id = Uuid::new_v4();
+        let other_node_id = Uuid::new_v4();
+        let policy = serde_json::from_value::<NodePolicy>(serde_json::json!({
+            "fallback": {"type": "Edge", "nodeId": fallback_node_id},
+        }))
+        .unwrap();
+
+        assert!(policy.follows_edge(&other_node_id, false));
+        assert!(!policy.follows_edge(&fallback_node_id, false));
+        assert!(policy.follows_edge(&fallback_node_id, true));
+        assert!(!policy.follows_edge(&other_node_id, true));
+    }
+}
@@ -31,6 +31,8 @@ pub(crate) use self::state::ExecState;
 pub use self::state::State;
 use uuid::Uuid;
 
+use super::policy::NodePolicy;
+
 mod action;
 mod state;
 /// The Task trait
@@ -54,6 +56,8 @@ pub struct Task {
     pub next: Vec<Uuid>,
     /// Map from input handle name to input state.
     pub input_states: HashMap<String, Arc<ExecState>>,
+    /// Retry, timeout and fallback policy of the node.
+    pub policy: NodePolicy,
 }
 
 impl Task {
@@ -74,6 +78,7 @@ impl Task {
             prev: Vec::new(),
             next: Vec::new(),
             input_states: inputs,
+            policy: NodePolicy::default(),
         }
     }
 
@@ -4,7 +4,7 @@ use std::env;
 
 pub enum Feature {
     UsageLimit,
-    /// Remote storage, such as S3
+    /// Payload storage, such as S3 or a local directory
     Storage,
     /// Build all containers. If false, only lite part is used: app-server, postgres, frontend
     FullBuild,
@@ -16,9 +16,10 @@ pub fn is_feature_enabled(feature: Feature) -> bool {
     match feature {
         Feature::UsageLimit => env::var("ENVIRONMENT") == Ok("PRODUCTION".to_string()),
         Feature::Storage => {
-            env::var("AWS_ACCESS_KEY_ID").is_ok()
-                && env::var("AWS_SECRET_ACCESS_KEY").is_ok()
-                && env::var("S3_TRACE_PAYLOADS_BUCKET").is_ok()
+            // S3 credentials are resolved from S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY,
+            // or the default AWS credentials chain
+            env::var("STORAGE_TYPE") == Ok("local".to_string())
+                || env::var("S3_TRACE_PAYLOADS_BUCKET").is_ok()
         }
         Feature::FullBuild => ["FULL", "PRODUCTION"].contains(
             &env::var("ENVIRONMENT")
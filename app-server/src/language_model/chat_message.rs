@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
 use crate::storage::{Storage, StorageTrait};
@@ -72,10 +73,123 @@ pub enum ChatMessageContent {
     ContentPartList(Vec<ChatMessageContentPart>),
 }
 
+impl Default for ChatMessageContent {
+    fn default() -> Self {
+        ChatMessageContent::Text(String::new())
+    }
+}
+
+/// Tool call requested by the model in an assistant message
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(from = "ToolCallFormat")]
+pub struct ChatMessageToolCall {
+    pub id: Option<String>,
+    pub name: String,
+    /// Usually a JSON object, or the raw string if the model returned invalid JSON
+    pub arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct ToolCallFunction {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Tool calls are either in our format, or in the OpenAI format which nests them in
+/// `function` and serializes the arguments
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ToolCallFormat {
+    OpenAI {
+        #[serde(default)]
+        id: Option<String>,
+        function: ToolCallFunction,
+    },
+    Laminar {
+        #[serde(default)]
+        id: Option<String>,
+        name: String,
+        #[serde(default)]
+        arguments: Value,
+    },
+}
+
+impl From<ToolCallFormat> for ChatMessageToolCall {
+    fn from(format: ToolCallFormat) -> Self {
+        let (id, name, arguments) = match format {
+            ToolCallFormat::OpenAI { id, function } => (id, function.name, function.arguments),
+            ToolCallFormat::Laminar {
+                id,
+                name,
+                arguments,
+            } => (id, name, arguments),
+        };
+        Self {
+            id,
+            name,
+            arguments: ChatMessageToolCall::parse_arguments(arguments),
+        }
+    }
+}
+
+impl ChatMessageToolCall {
+    /// Arguments are usually sent as a serialized JSON object
+    pub fn parse_arguments(arguments: Value) -> Value {
+        match arguments {
+            Value::String(s) => serde_json::from_str::<Value>(&s)
+                .ok()
+                .filter(|value| value.is_object())
+                .unwrap_or(Value::String(s)),
+            _ => arguments,
+        }
+    }
+
+    /// Arguments serialized as a JSON string, as most providers expect them
+    pub fn arguments_string(&self) -> String {
+        match &self.arguments {
+            Value::String(s) => s.clone(),
+            Value::Null => "{}".to_string(),
+            arguments => arguments.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ChatMessage {
     pub role: String,
+    /// Assistant messages that only call tools may have null content
+    #[serde(deserialize_with = "deserialize_nullable_content")]
     pub content: ChatMessageContent,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ChatMessageToolCall>,
+    /// Id of the tool call that a message with the `tool` role is the result of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+fn deserialize_nullable_content<'de, D>(deserializer: D) -> Result<ChatMessageContent, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<ChatMessageContent>::deserialize(deserializer).map(Option::unwrap_or_default)
+}
+
+impl ChatMessage {
+    /// Text of the message, ignoring the non-text content parts
+    pub fn text(&self) -> String {
+        match &self.content {
+            ChatMessageContent::Text(text) => text.clone(),
+            ChatMessageContent::ContentPartList(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ChatMessageContentPart::Text(text) => Some(text.text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(""),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -105,8 +219,17 @@ impl ChatCompletion {
         }
     }
 
+    pub fn message(&self) -> &ChatMessage {
+        &self.choices.first().unwrap().message
+    }
+
+    /// Text of the first choice. If the model only called tools, the tool calls are
+    /// rendered as JSON instead, so that they are not lost in text outputs.
     pub fn text_message(&self) -> String {
-        let chat_message = &self.choices.first().unwrap().message;
+        let chat_message = self.message();
+        if chat_message.text().is_empty() && !chat_message.tool_calls.is_empty() {
+            return serde_json::to_string_pretty(&chat_message.tool_calls).unwrap();
+        }
         match &chat_message.content {
             ChatMessageContent::Text(ref text) => text.clone(),
             ChatMessageContent::ContentPartList(parts) => parts
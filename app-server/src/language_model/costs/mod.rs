@@ -1,123 +1,469 @@
 use std::{collections::HashMap, sync::Arc};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::{
-    cache::{keys::LLM_PRICES_CACHE_KEY, Cache, CacheTrait},
+    cache::{
+        keys::{LLM_PRICES_CACHE_KEY, MODEL_NAME_RULES_CACHE_KEY, PROJECT_LLM_PRICES_CACHE_KEY},
+        Cache, CacheTrait,
+    },
     db::{
-        prices::{get_price, DBPriceEntry},
+        self,
+        model_name_rules::ModelNameRule,
+        prices::{DBPriceEntry, ProjectPrice},
         DB,
     },
 };
 
 use super::providers::utils::calculate_cost;
-pub enum TokensKind {
-    Input,
-    Output,
+
+pub mod model_names;
+
+const INPUT_PRICE: &str = "input_price_per_million";
+const OUTPUT_PRICE: &str = "output_price_per_million";
+const INPUT_CACHED_PRICE: &str = "input_cached_price_per_million";
+// Prices that only some models have are in `additional_prices`
+const INPUT_CACHE_WRITE_PRICE: &str = "input_cache_write_price_per_million";
+const REASONING_PRICE: &str = "reasoning_price_per_million";
+/// Tiered prices are stored in `additional_prices` as the name of the price with the size
+/// of the input above which they apply, e.g. `input_price_per_million_above_128k`
+const TIER_SEPARATOR: &str = "_above_";
+
+/// Token counts of an LLM call, by the way they are priced
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokenUsage {
+    /// All input tokens, including the ones read from and written to the prompt cache
+    pub input_tokens: u32,
+    /// All output tokens, including the reasoning tokens
+    pub output_tokens: u32,
+    pub cache_read_input_tokens: u32,
+    pub cache_creation_input_tokens: u32,
+    pub reasoning_tokens: u32,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
 pub struct LLMPriceEntry {
-    _provider: String,
-    _model: String,
+    provider: String,
+    model: String,
     input_price_per_million: f64,
     output_price_per_million: f64,
-    _input_cached_price_per_million: Option<f64>,
-    _additional_prices: HashMap<String, f64>,
+    input_cached_price_per_million: Option<f64>,
+    additional_prices: HashMap<String, f64>,
+    valid_from: Option<DateTime<Utc>>,
+    valid_to: Option<DateTime<Utc>>,
 }
 
 impl LLMPriceEntry {
-    pub fn get_price(&self, kind: TokensKind) -> Option<f64> {
-        match kind {
-            TokensKind::Input => Some(self.input_price_per_million),
-            TokensKind::Output => Some(self.output_price_per_million),
+    fn is_valid_at(&self, time: &DateTime<Utc>) -> bool {
+        !self.valid_from.is_some_and(|valid_from| *time < valid_from)
+            && !self.valid_to.is_some_and(|valid_to| *time >= valid_to)
+    }
+
+    /// Price per million tokens, or the price of the highest tier the input is above
+    fn price(&self, name: &str, base_price: Option<f64>, input_tokens: u32) -> Option<f64> {
+        let tier_price = self
+            .additional_prices
+            .iter()
+            .filter_map(|(key, price)| {
+                let (price_name, threshold) = key.split_once(TIER_SEPARATOR)?;
+                let threshold = parse_tier_threshold(threshold)?;
+                (price_name == name && input_tokens > threshold).then_some((threshold, *price))
+            })
+            .max_by_key(|(threshold, _)| *threshold)
+            .map(|(_, price)| price);
+        tier_price.or(base_price)
+    }
+
+    /// Cached input and reasoning tokens are priced as regular input and output tokens, if
+    /// the model has no separate price for them
+    pub fn cost(&self, usage: &TokenUsage) -> CostEntry {
+        let input_tokens = usage.input_tokens;
+        let input_price = self
+            .price(
+                INPUT_PRICE,
+                Some(self.input_price_per_million),
+                input_tokens,
+            )
+            .unwrap_or_default();
+        let output_price = self
+            .price(
+                OUTPUT_PRICE,
+                Some(self.output_price_per_million),
+                input_tokens,
+            )
+            .unwrap_or_default();
+        let cache_read_price = self
+            .price(
+                INPUT_CACHED_PRICE,
+                self.input_cached_price_per_million,
+                input_tokens,
+            )
+            .unwrap_or(input_price);
+        let cache_write_price = self
+            .price(
+                INPUT_CACHE_WRITE_PRICE,
+                self.additional_prices.get(INPUT_CACHE_WRITE_PRICE).copied(),
+                input_tokens,
+            )
+            .unwrap_or(input_price);
+        let reasoning_price = self
+            .price(
+                REASONING_PRICE,
+                self.additional_prices.get(REASONING_PRICE).copied(),
+                input_tokens,
+            )
+            .unwrap_or(output_price);
+
+        let uncached_input_tokens = input_tokens
+            .saturating_sub(usage.cache_read_input_tokens)
+            .saturating_sub(usage.cache_creation_input_tokens);
+        let cache_read_input_cost = calculate_cost(usage.cache_read_input_tokens, cache_read_price);
+        let cache_creation_input_cost =
+            calculate_cost(usage.cache_creation_input_tokens, cache_write_price);
+        let input_cost = calculate_cost(uncached_input_tokens, input_price)
+            + cache_read_input_cost
+            + cache_creation_input_cost;
+
+        let reasoning_tokens = usage.reasoning_tokens.min(usage.output_tokens);
+        let reasoning_cost = calculate_cost(reasoning_tokens, reasoning_price);
+        let output_cost =
+            calculate_cost(usage.output_tokens - reasoning_tokens, output_price) + reasoning_cost;
+
+        CostEntry {
+            input_cost,
+            output_cost,
+            cache_read_input_cost,
+            cache_creation_input_cost,
+            reasoning_cost,
         }
     }
 }
 
+/// Threshold in tokens, e.g. `128k` or `200000`
+fn parse_tier_threshold(threshold: &str) -> Option<u32> {
+    match threshold.strip_suffix('k') {
+        Some(thousands) => thousands.parse::<u32>().ok().map(|n| n * 1000),
+        None => threshold.parse::<u32>().ok(),
+    }
+}
+
 impl From<DBPriceEntry> for LLMPriceEntry {
     fn from(value: DBPriceEntry) -> Self {
         Self {
-            _provider: value.provider,
-            _model: value.model,
+            provider: value.provider,
+            model: value.model,
+            input_price_per_million: value.input_price_per_million,
+            output_price_per_million: value.output_price_per_million,
+            input_cached_price_per_million: value.input_cached_price_per_million,
+            additional_prices: serde_json::from_value(value.additional_prices).unwrap_or_default(),
+            valid_from: value.valid_from,
+            valid_to: value.valid_to,
+        }
+    }
+}
+
+impl From<ProjectPrice> for LLMPriceEntry {
+    fn from(value: ProjectPrice) -> Self {
+        Self {
+            provider: value.provider,
+            model: value.model,
             input_price_per_million: value.input_price_per_million,
             output_price_per_million: value.output_price_per_million,
-            _input_cached_price_per_million: value.input_cached_price_per_million,
-            _additional_prices: serde_json::from_value(value.additional_prices).unwrap(),
+            input_cached_price_per_million: value.input_cached_price_per_million,
+            additional_prices: serde_json::from_value(value.additional_prices).unwrap_or_default(),
+            valid_from: value.valid_from,
+            valid_to: value.valid_to,
         }
     }
 }
 
-pub async fn estimate_cost(
+/// All prices of the model over time, the most recent ones first
+async fn get_llm_prices(
     db: Arc<DB>,
     cache: Arc<Cache>,
     provider: &str,
     model: &str,
-    num_tokens: u32,
-    tokens_kind: TokensKind,
-) -> Option<f64> {
+) -> Vec<LLMPriceEntry> {
     let cache_key = format!("{LLM_PRICES_CACHE_KEY}:{provider}:{model}");
-    let cache_res = cache.get::<LLMPriceEntry>(&cache_key).await.ok()?;
-    let price_per_million_tokens = match cache_res {
-        Some(price) => price.input_price_per_million,
-        None => {
-            let price = get_price(&db.pool, provider, model).await.ok()?;
-            let price = LLMPriceEntry::from(price);
+    match cache.get::<Vec<LLMPriceEntry>>(&cache_key).await {
+        Ok(Some(prices)) => prices,
+        Ok(None) | Err(_) => {
+            let prices: Vec<LLMPriceEntry> =
+                match db::prices::get_prices(&db.pool, provider, model).await {
+                    Ok(prices) => prices.into_iter().map(LLMPriceEntry::from).collect(),
+                    Err(e) => {
+                        log::error!("Failed to get prices of {provider} model {model}: {:?}", e);
+                        return Vec::new();
+                    }
+                };
             let _ = cache
-                .insert::<LLMPriceEntry>(&cache_key, price.clone())
+                .insert::<Vec<LLMPriceEntry>>(&cache_key, prices.clone())
                 .await;
-            price.get_price(tokens_kind)?
+            prices
         }
-    };
-    Some(calculate_cost(num_tokens, price_per_million_tokens))
+    }
+}
+
+/// Price overrides of the project, the most recent ones first
+async fn get_project_llm_prices(
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    project_id: &Uuid,
+) -> Vec<LLMPriceEntry> {
+    let cache_key = format!("{PROJECT_LLM_PRICES_CACHE_KEY}:{project_id}");
+    match cache.get::<Vec<LLMPriceEntry>>(&cache_key).await {
+        Ok(Some(prices)) => prices,
+        Ok(None) | Err(_) => {
+            let prices: Vec<LLMPriceEntry> =
+                match db::prices::get_project_prices(&db.pool, project_id).await {
+                    Ok(prices) => prices.into_iter().map(LLMPriceEntry::from).collect(),
+                    Err(e) => {
+                        log::error!(
+                            "Failed to get project prices. project_id [{}]: {:?}",
+                            project_id,
+                            e
+                        );
+                        return Vec::new();
+                    }
+                };
+            let _ = cache
+                .insert::<Vec<LLMPriceEntry>>(&cache_key, prices.clone())
+                .await;
+            prices
+        }
+    }
+}
+
+async fn get_model_name_rules(
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    project_id: &Uuid,
+) -> Vec<ModelNameRule> {
+    let cache_key = format!("{MODEL_NAME_RULES_CACHE_KEY}:{project_id}");
+    match cache.get::<Vec<ModelNameRule>>(&cache_key).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) | Err(_) => {
+            let rules = match db::model_name_rules::get_model_name_rules(&db.pool, project_id).await
+            {
+                Ok(rules) => rules,
+                Err(e) => {
+                    log::error!(
+                        "Failed to get model name rules. project_id [{}]: {:?}",
+                        project_id,
+                        e
+                    );
+                    return Vec::new();
+                }
+            };
+            let _ = cache
+                .insert::<Vec<ModelNameRule>>(&cache_key, rules.clone())
+                .await;
+            rules
+        }
+    }
+}
+
+/// Price of the model at the given time. Price overrides of the project take precedence
+/// over the global prices.
+pub async fn get_llm_price(
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    project_id: Option<&Uuid>,
+    provider: &str,
+    model: &str,
+    time: &DateTime<Utc>,
+) -> Option<LLMPriceEntry> {
+    if let Some(project_id) = project_id {
+        let project_price = get_project_llm_prices(db.clone(), cache.clone(), project_id)
+            .await
+            .into_iter()
+            .find(|price| {
+                price.provider.eq_ignore_ascii_case(provider)
+                    && price.model == model
+                    && price.is_valid_at(time)
+            });
+        if project_price.is_some() {
+            return project_price;
+        }
+    }
+
+    get_llm_prices(db, cache, provider, model)
+        .await
+        .into_iter()
+        .find(|price| price.is_valid_at(time))
 }
 
+/// Costs of an LLM call. The input and output costs include the costs of the cached input
+/// and reasoning tokens respectively.
+#[derive(Clone, Copy, Debug, Default)]
 pub struct CostEntry {
     pub input_cost: f64,
     pub output_cost: f64,
+    pub cache_read_input_cost: f64,
+    pub cache_creation_input_cost: f64,
+    pub reasoning_cost: f64,
+}
+
+impl CostEntry {
+    pub fn total_cost(&self) -> f64 {
+        self.input_cost + self.output_cost
+    }
 }
 
 // This is a simpler function than per-provider implementation.
-// For now, we default to this, but if language model providers keep making quirky prices like
-// gemini with their additional price over 128k tokens, we will have to switch to per-provider
-// implementation.
+// Quirky prices, like gemini with their additional price over 128k tokens, are stored as
+// tiers in `additional_prices`.
+//
+// The model name is first normalized with the rules of the project, and dated snapshots
+// without a price of their own are priced as their base model.
 pub async fn estimate_cost_by_provider_name(
     db: Arc<DB>,
     cache: Arc<Cache>,
+    project_id: Option<&Uuid>,
     provider_name: &str,
     model: &str,
-    input_tokens: u32,
-    output_tokens: u32,
+    usage: &TokenUsage,
+    time: &DateTime<Utc>,
 ) -> Option<CostEntry> {
-    let input_cost = estimate_cost(
+    let model = match project_id {
+        Some(project_id) => {
+            let rules = get_model_name_rules(db.clone(), cache.clone(), project_id).await;
+            model_names::normalize_model_name(&rules, provider_name, model)
+        }
+        None => model.to_string(),
+    };
+
+    let mut price = get_llm_price(
         db.clone(),
         cache.clone(),
+        project_id,
         provider_name,
-        model,
-        input_tokens,
-        TokensKind::Input,
+        &model,
+        time,
     )
-    .await
-    .or_else(|| {
+    .await;
+    if price.is_none() {
+        if let Some(base_model) = model_names::snapshot_base_model(&model) {
+            price = get_llm_price(db, cache, project_id, provider_name, base_model, time).await;
+        }
+    }
+
+    let Some(price) = price else {
         log::warn!(
             "No stored price found for provider: {}, model: {}",
             provider_name,
             model,
         );
-        None
-    })?;
-    let output_cost = estimate_cost(
-        db.clone(),
-        cache.clone(),
-        provider_name,
-        model,
-        output_tokens,
-        TokensKind::Output,
-    )
-    .await?;
+        return None;
+    };
+
+    Some(price.cost(usage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price_entry(
+        input_cached_price_per_million: Option<f64>,
+        additional_prices: &[(&str, f64)],
+    ) -> LLMPriceEntry {
+        LLMPriceEntry {
+            provider: "provider".to_string(),
+            model: "model".to_string(),
+            input_price_per_million: 2.0,
+            output_price_per_million: 8.0,
+            input_cached_price_per_million,
+            additional_prices: additional_prices
+                .iter()
+                .map(|(key, price)| (key.to_string(), *price))
+                .collect(),
+            valid_from: None,
+            valid_to: None,
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_cost_by_token_class() {
+        let price = price_entry(
+            Some(0.5),
+            &[(INPUT_CACHE_WRITE_PRICE, 2.5), (REASONING_PRICE, 10.0)],
+        );
+        let cost = price.cost(&TokenUsage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            cache_read_input_tokens: 400_000,
+            cache_creation_input_tokens: 200_000,
+            reasoning_tokens: 500_000,
+        });
+
+        assert_close(cost.cache_read_input_cost, 0.2);
+        assert_close(cost.cache_creation_input_cost, 0.5);
+        assert_close(cost.input_cost, 0.8 + 0.2 + 0.5);
+        assert_close(cost.reasoning_cost, 5.0);
+        assert_close(cost.output_cost, 4.0 + 5.0);
+    }
 
-    Some(CostEntry {
-        input_cost,
-        output_cost,
-    })
+    #[test]
+    fn test_cost_without_separate_prices() {
+        let price = price_entry(None, &[]);
+        let cost = price.cost(&TokenUsage {
+            input_tokens: 1_000_000,
+            output_tokens: 500_000,
+            cache_read_input_tokens: 500_000,
+            reasoning_tokens: 100_000,
+            ..Default::default()
+        });
+
+        assert_close(cost.input_cost, 2.0);
+        assert_close(cost.output_cost, 4.0);
+    }
+
+    #[test]
+    fn test_tiered_cost() {
+        let price = price_entry(
+            None,
+            &[
+                ("input_price_per_million_above_128k", 4.0),
+                ("output_price_per_million_above_128k", 16.0),
+                ("input_price_per_million_above_1000k", 6.0),
+            ],
+        );
+
+        let short = price.cost(&TokenUsage {
+            input_tokens: 128_000,
+            output_tokens: 1_000,
+            ..Default::default()
+        });
+        assert_close(short.input_cost, 0.256);
+        assert_close(short.output_cost, 0.008);
+
+        let long = price.cost(&TokenUsage {
+            input_tokens: 200_000,
+            output_tokens: 1_000,
+            ..Default::default()
+        });
+        assert_close(long.input_cost, 0.8);
+        assert_close(long.output_cost, 0.016);
+    }
+
+    #[test]
+    fn test_price_valid_at() {
+        let time = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let mut old_price = price_entry(None, &[]);
+        old_price.valid_to = Some(time("2025-01-01T00:00:00Z"));
+        let mut new_price = price_entry(None, &[]);
+        new_price.valid_from = Some(time("2025-01-01T00:00:00Z"));
+
+        assert!(old_price.is_valid_at(&time("2024-12-31T23:59:59Z")));
+        assert!(!old_price.is_valid_at(&time("2025-01-01T00:00:00Z")));
+        assert!(new_price.is_valid_at(&time("2025-01-01T00:00:00Z")));
+        assert!(!new_price.is_valid_at(&time("2024-12-31T23:59:59Z")));
+    }
 }
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::db::model_name_rules::{ModelNameRule, ModelNameRuleType};
+
+lazy_static! {
+    /// Dated snapshot suffixes, e.g. `gpt-4o-2024-08-06`, `claude-3-5-sonnet-20241022` or
+    /// `claude-3-5-sonnet@20240620`
+    static ref SNAPSHOT_SUFFIX: Regex = Regex::new(r"(-\d{4}-\d{2}-\d{2}|[-@]\d{8})$").unwrap();
+    /// Rule patterns are compiled once per process, rules are re-read from the cache
+    static ref COMPILED_PATTERNS: moka::sync::Cache<String, Arc<Regex>> =
+        moka::sync::Cache::new(10_000);
+}
+
+fn compile_pattern(pattern: &str) -> Result<Arc<Regex>, regex::Error> {
+    if let Some(regex) = COMPILED_PATTERNS.get(pattern) {
+        return Ok(regex);
+    }
+    let regex = Arc::new(Regex::new(pattern)?);
+    COMPILED_PATTERNS.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Model name to look up the price of, after applying the first matching rule of the project
+pub fn normalize_model_name(rules: &[ModelNameRule], provider: &str, model: &str) -> String {
+    for rule in rules {
+        if rule
+            .provider
+            .as_ref()
+            .is_some_and(|rule_provider| !rule_provider.eq_ignore_ascii_case(provider))
+        {
+            continue;
+        }
+        match rule.rule_type {
+            ModelNameRuleType::ALIAS => {
+                if rule.pattern == model {
+                    return rule.model.clone();
+                }
+            }
+            ModelNameRuleType::REGEX => {
+                // Rules are validated when they are saved, so invalid patterns are skipped
+                let Ok(regex) = compile_pattern(&rule.pattern) else {
+                    continue;
+                };
+                if regex.is_match(model) {
+                    return regex.replace(model, rule.model.as_str()).into_owned();
+                }
+            }
+        }
+    }
+    model.to_string()
+}
+
+/// Base model of a dated snapshot, used if the snapshot has no price of its own
+pub fn snapshot_base_model(model: &str) -> Option<&str> {
+    let suffix = SNAPSHOT_SUFFIX.find(model)?;
+    let base = &model[..suffix.start()];
+    (!base.is_empty()).then_some(base)
+}
+
+pub fn validate_rule(rule_type: &ModelNameRuleType, pattern: &str) -> anyhow::Result<()> {
+    if pattern.is_empty() {
+        return Err(anyhow::anyhow!("Pattern must not be empty"));
+    }
+    if *rule_type == ModelNameRuleType::REGEX {
+        compile_pattern(pattern)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn rule(
+        provider: Option<&str>,
+        rule_type: ModelNameRuleType,
+        pattern: &str,
+        model: &str,
+    ) -> ModelNameRule {
+        ModelNameRule {
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            project_id: Uuid::nil(),
+            provider: provider.map(String::from),
+            rule_type,
+            pattern: pattern.to_string(),
+            model: model.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_model_name() {
+        let rules = vec![
+            rule(
+                Some("azure"),
+                ModelNameRuleType::ALIAS,
+                "prod-deployment",
+                "gpt-4o",
+            ),
+            rule(None, ModelNameRuleType::REGEX, r"^ft:([^:]+):.*$", "ft-$1"),
+        ];
+
+        assert_eq!(
+            normalize_model_name(&rules, "azure", "prod-deployment"),
+            "gpt-4o"
+        );
+        assert_eq!(
+            normalize_model_name(&rules, "openai", "prod-deployment"),
+            "prod-deployment"
+        );
+        assert_eq!(
+            normalize_model_name(&rules, "openai", "ft:gpt-4o-mini:acme::abc123"),
+            "ft-gpt-4o-mini"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_base_model() {
+        assert_eq!(snapshot_base_model("gpt-4o-2024-08-06"), Some("gpt-4o"));
+        assert_eq!(
+            snapshot_base_model("claude-3-5-sonnet-20241022"),
+            Some("claude-3-5-sonnet")
+        );
+        assert_eq!(
+            snapshot_base_model("claude-3-5-sonnet@20240620"),
+            Some("claude-3-5-sonnet")
+        );
+        assert_eq!(snapshot_base_model("gpt-4o"), None);
+    }
+}
@@ -1,6 +1,7 @@
 mod chat_message;
 pub mod costs;
 pub mod providers;
+pub mod rate_limit;
 mod runner;
 
 pub use chat_message::*;
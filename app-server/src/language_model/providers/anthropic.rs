@@ -3,7 +3,9 @@ use std::sync::Arc;
 
 use crate::cache::Cache;
 use crate::db::DB;
-use crate::language_model::chat_message::{ChatChoice, ChatCompletion, ChatMessage, ChatUsage};
+use crate::language_model::chat_message::{
+    ChatChoice, ChatCompletion, ChatMessage, ChatMessageToolCall, ChatUsage,
+};
 use crate::language_model::runner::ExecuteChatCompletion;
 use crate::language_model::{
     ChatMessageContent, ChatMessageContentPart, EstimateCost, LanguageModelProviderName, NodeInfo,
@@ -17,6 +19,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::sync::mpsc::Sender;
 
+use super::utils::ProviderError;
+
 #[derive(Clone, Debug)]
 pub struct Anthropic {
     client: reqwest::Client,
@@ -35,10 +39,19 @@ struct AnthropicResponseUsage {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct AnthropicResponseContentBlock {
-    #[serde(rename = "type")]
-    type_field: String,
-    text: String,
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum AnthropicResponseContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -53,6 +66,7 @@ struct AnthropicResponse {
 #[serde(rename_all = "snake_case")]
 enum ChatCompletionChunk {
     MessageStart(MessageStart),
+    ContentBlockStart(ContentBlockStart),
     ContentBlockDelta(ContentBlockDelta),
     MessageStop,
 }
@@ -74,14 +88,27 @@ struct Usage {
     input_tokens: u32,
 }
 
+#[derive(Debug, Deserialize)]
+struct ContentBlockStart {
+    content_block: AnthropicResponseContentBlock,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ContentBlockDelta {
     pub delta: Delta,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct Delta {
-    pub text: String,
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum Delta {
+    TextDelta {
+        text: String,
+    },
+    /// Part of the serialized input of the tool use block that is being streamed
+    InputJsonDelta {
+        partial_json: String,
+    },
 }
 
 impl TryFrom<AnthropicResponse> for ChatCompletion {
@@ -95,16 +122,31 @@ impl TryFrom<AnthropicResponse> for ChatCompletion {
             approximate_cost: None,
         };
 
-        let choices = value
-            .content
-            .into_iter()
-            .map(|content_block| {
-                ChatChoice::new(ChatMessage {
-                    role: String::from("assistant"),
-                    content: ChatMessageContent::Text(content_block.text),
-                })
-            })
-            .collect();
+        // All content blocks are parts of the same assistant message
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        for content_block in value.content {
+            match content_block {
+                AnthropicResponseContentBlock::Text { text: block_text } => {
+                    text.push_str(&block_text)
+                }
+                AnthropicResponseContentBlock::ToolUse { id, name, input } => {
+                    tool_calls.push(ChatMessageToolCall {
+                        id: Some(id),
+                        name,
+                        arguments: input,
+                    })
+                }
+                AnthropicResponseContentBlock::Other => {}
+            }
+        }
+
+        let choices = vec![ChatChoice::new(ChatMessage {
+            role: String::from("assistant"),
+            content: ChatMessageContent::Text(text),
+            tool_calls,
+            tool_call_id: None,
+        })];
 
         Ok(ChatCompletion::new(choices, usage, value.model))
     }
@@ -112,13 +154,55 @@ impl TryFrom<AnthropicResponse> for ChatCompletion {
 
 /// Convert to Anthropic message
 ///
-/// This functions is mainly needed to convert the images to correct format
+/// This functions is mainly needed to convert the images to correct format, and the tool
+/// calls and tool results to content blocks
 fn to_value(message: &ChatMessage) -> Result<Value> {
-    match &message.content {
-        ChatMessageContent::Text(text) => Ok(json!({
+    let content = content_to_value(&message.content)?;
+
+    // Anthropic has no tool role, tool results are content blocks of user messages
+    if message.role == "tool" {
+        return Ok(json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": message.tool_call_id,
+                "content": content,
+            }],
+        }));
+    }
+    if message.tool_calls.is_empty() {
+        return Ok(json!({
             "role": message.role,
-            "content": text,
-        })),
+            "content": content,
+        }));
+    }
+
+    let mut blocks = match content {
+        Value::String(text) if text.is_empty() => vec![],
+        Value::String(text) => vec![json!({
+            "type": "text",
+            "text": text,
+        })],
+        Value::Array(parts) => parts,
+        _ => vec![],
+    };
+    blocks.extend(message.tool_calls.iter().map(|tool_call| {
+        json!({
+            "type": "tool_use",
+            "id": tool_call.id,
+            "name": tool_call.name,
+            "input": tool_call.arguments,
+        })
+    }));
+    Ok(json!({
+        "role": message.role,
+        "content": blocks,
+    }))
+}
+
+fn content_to_value(content: &ChatMessageContent) -> Result<Value> {
+    match content {
+        ChatMessageContent::Text(text) => Ok(json!(text)),
         ChatMessageContent::ContentPartList(parts) => {
             let mut json_parts: Vec<Value> = Vec::new();
             for part in parts.into_iter() {
@@ -156,10 +240,7 @@ fn to_value(message: &ChatMessage) -> Result<Value> {
                 }
             }
 
-            Ok(json!({
-                "role": message.role,
-                "content": json_parts,
-            }))
+            Ok(json!(json_parts))
         }
     }
 }
@@ -189,6 +270,7 @@ impl ExecuteChatCompletion for Anthropic {
             let user_message = ChatMessage {
                 role: "user".to_string(),
                 content: messages[0].content.clone(),
+                ..Default::default()
             };
             let json_user_message = to_value(&user_message)?;
             body["messages"] = serde_json::json!(vec![json_user_message]);
@@ -220,6 +302,8 @@ impl ExecuteChatCompletion for Anthropic {
             let mut eventsource = EventSource::new(req)?;
 
             let mut message = String::new();
+            let mut tool_calls = Vec::new();
+            let mut tool_call_arguments: Vec<String> = Vec::new();
             let mut prompt_tokens = 0;
             let mut completion_tokens = 0;
 
@@ -231,9 +315,13 @@ impl ExecuteChatCompletion for Anthropic {
                         reqwest_eventsource::Error::InvalidStatusCode(status, _) => {
                             // handle separately to not display SET-COOKIE header from response
                             if matches!(status, reqwest::StatusCode::UNAUTHORIZED) {
-                                return Err(anyhow::anyhow!("Invalid API key"));
+                                return Err(ProviderError::new(status, "Invalid API key").into());
                             } else {
-                                return Err(anyhow::anyhow!("Error. Status code: {}", status));
+                                return Err(ProviderError::new(
+                                    status,
+                                    format!("Error. Status code: {}", status),
+                                )
+                                .into());
                             };
                         }
                         _ => {
@@ -255,8 +343,28 @@ impl ExecuteChatCompletion for Anthropic {
                     ChatCompletionChunk::MessageStart(message_start) => {
                         prompt_tokens = message_start.message.usage.input_tokens;
                     }
+                    ChatCompletionChunk::ContentBlockStart(start) => {
+                        if let AnthropicResponseContentBlock::ToolUse { id, name, .. } =
+                            start.content_block
+                        {
+                            tool_calls.push(ChatMessageToolCall {
+                                id: Some(id),
+                                name,
+                                arguments: Value::Null,
+                            });
+                            tool_call_arguments.push(String::new());
+                        }
+                    }
                     ChatCompletionChunk::ContentBlockDelta(chunk) => {
-                        let content = chunk.delta.text;
+                        let content = match chunk.delta {
+                            Delta::TextDelta { text } => text,
+                            Delta::InputJsonDelta { partial_json } => {
+                                if let Some(arguments) = tool_call_arguments.last_mut() {
+                                    arguments.push_str(&partial_json);
+                                }
+                                continue;
+                            }
+                        };
 
                         message.extend(content.chars());
 
@@ -278,9 +386,19 @@ impl ExecuteChatCompletion for Anthropic {
                 }
             }
 
+            for (tool_call, arguments) in tool_calls.iter_mut().zip(tool_call_arguments) {
+                tool_call.arguments = if arguments.is_empty() {
+                    json!({})
+                } else {
+                    ChatMessageToolCall::parse_arguments(Value::String(arguments))
+                };
+            }
+
             let chat_message = ChatMessage {
                 role: "assistant".to_string(),
                 content: ChatMessageContent::Text(message),
+                tool_calls,
+                tool_call_id: None,
             };
 
             let chat_choice = ChatChoice::new(chat_message);
@@ -312,13 +430,15 @@ impl ExecuteChatCompletion for Anthropic {
                 .send()
                 .await
                 .unwrap();
-            if !res.status().is_success() {
+            let status = res.status();
+            if !status.is_success() {
                 let error = res.text().await?;
                 log::error!("Anthropic message request failed: {}", error);
-                return Err(anyhow::anyhow!(
-                    "Anthropic message request failed: {}",
-                    error
-                ));
+                return Err(ProviderError::new(
+                    status,
+                    format!("Anthropic message request failed: {}", error),
+                )
+                .into());
             }
 
             let res_body = res.json::<AnthropicResponse>().await?;
@@ -215,6 +215,7 @@ impl ExecuteChatCompletion for AnthropicBedrock {
                 choices: vec![ChatChoice::new(ChatMessage {
                     role: "assistant".to_string(),
                     content: ChatMessageContent::Text(message),
+                    ..Default::default()
                 })],
                 model: model.to_string(),
                 usage: ChatUsage {
@@ -266,9 +267,11 @@ impl ExecuteChatCompletion for AnthropicBedrock {
                         content
                             .content()
                             .iter()
-                            .map(|block| block.as_text().unwrap())
+                            // Tool use blocks are not supported for Anthropic Bedrock yet
+                            .filter_map(|block| block.as_text().ok())
                             .join(""),
                     ),
+                    ..Default::default()
                 })],
                 model: model.to_string(),
                 usage: ChatUsage {
@@ -11,13 +11,15 @@ use crate::{
     cache::Cache,
     db::DB,
     language_model::{
-        chat_message::{ChatCompletion, ChatMessage},
+        chat_message::{ChatCompletion, ChatMessage, ChatMessageToolCall},
         ChatChoice, ChatMessageContent, ChatMessageContentPart, ChatUsage, EstimateCost,
         ExecuteChatCompletion, LanguageModelProviderName, NodeInfo,
     },
     pipeline::nodes::{NodeStreamChunk, StreamChunk},
 };
 
+use super::utils::ProviderError;
+
 #[derive(Clone, Debug)]
 pub struct Gemini {
     client: reqwest::Client,
@@ -40,10 +42,30 @@ struct GeminiError {
     error: GeminiErrorMessage,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Part {
-    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    function_call: Option<FunctionCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    function_response: Option<FunctionResponse>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionResponse {
+    name: String,
+    response: Value,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -74,28 +96,91 @@ pub struct GeminiResponse {
     pub usage_metadata: UsageMetadata,
 }
 
-fn to_content(message: ChatMessage) -> Content {
-    Content {
-        parts: vec![Part {
-            text: match message.content {
-                ChatMessageContent::Text(text) => text,
-                ChatMessageContent::ContentPartList(parts) => parts
-                    .iter()
-                    .map(|part| match part {
-                        ChatMessageContentPart::Text(text) => text.text.clone(),
-                        _ => {
-                            panic!("We don't support images for Gemini yet")
-                        }
-                    })
-                    .collect::<Vec<String>>()
-                    .join(""),
-            },
-        }],
-        role: if message.role == "user" {
-            "user".to_string()
-        } else {
-            "model".to_string() // "assistant" is turned into "model"
-        },
+fn content_text(content: &ChatMessageContent) -> String {
+    match content {
+        ChatMessageContent::Text(text) => text.clone(),
+        ChatMessageContent::ContentPartList(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ChatMessageContentPart::Text(text) => text.text.clone(),
+                _ => {
+                    panic!("We don't support images for Gemini yet")
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(""),
+    }
+}
+
+/// Gemini function calls have no ids, so tool results reference the function by name.
+/// The name is looked up from the tool calls of the previous messages, and the tool call
+/// id is used as the name if it is not found.
+fn to_contents(messages: &[ChatMessage]) -> Vec<Content> {
+    let mut tool_call_names = HashMap::new();
+    messages
+        .iter()
+        .map(|message| {
+            let text = content_text(&message.content);
+
+            if message.role == "tool" {
+                let tool_call_id = message.tool_call_id.clone().unwrap_or_default();
+                let name = tool_call_names
+                    .get(&tool_call_id)
+                    .cloned()
+                    .unwrap_or(tool_call_id);
+                // The response must be an object
+                let response = match serde_json::from_str::<Value>(&text) {
+                    Ok(Value::Object(response)) => Value::Object(response),
+                    _ => json!({ "content": text }),
+                };
+                return Content {
+                    parts: vec![Part {
+                        function_response: Some(FunctionResponse { name, response }),
+                        ..Default::default()
+                    }],
+                    role: "user".to_string(),
+                };
+            }
+
+            let mut parts = Vec::new();
+            if !text.is_empty() || message.tool_calls.is_empty() {
+                parts.push(Part {
+                    text: Some(text),
+                    ..Default::default()
+                });
+            }
+            for tool_call in &message.tool_calls {
+                if let Some(id) = &tool_call.id {
+                    tool_call_names.insert(id.clone(), tool_call.name.clone());
+                }
+                parts.push(Part {
+                    function_call: Some(FunctionCall {
+                        name: tool_call.name.clone(),
+                        args: tool_call.arguments.clone(),
+                    }),
+                    ..Default::default()
+                });
+            }
+
+            Content {
+                parts,
+                role: if message.role == "user" {
+                    "user".to_string()
+                } else {
+                    "model".to_string() // "assistant" is turned into "model"
+                },
+            }
+        })
+        .collect()
+}
+
+impl From<FunctionCall> for ChatMessageToolCall {
+    fn from(function_call: FunctionCall) -> Self {
+        Self {
+            id: None,
+            name: function_call.name,
+            arguments: function_call.args,
+        }
     }
 }
 
@@ -106,17 +191,24 @@ fn to_chat_completion(res: GeminiResponse, model: &str) -> ChatCompletion {
         .into_iter()
         .next()
         .expect("No candidates found");
-    let part = candidate
-        .content
-        .parts
-        .into_iter()
-        .next()
-        .expect("No parts found");
+
+    let mut text = String::new();
+    let mut tool_calls: Vec<ChatMessageToolCall> = Vec::new();
+    for part in candidate.content.parts {
+        if let Some(part_text) = part.text {
+            text.push_str(&part_text);
+        }
+        if let Some(function_call) = part.function_call {
+            tool_calls.push(function_call.into());
+        }
+    }
 
     ChatCompletion {
         choices: vec![ChatChoice::new(ChatMessage {
             role: candidate.content.role,
-            content: ChatMessageContent::Text(part.text),
+            content: ChatMessageContent::Text(text),
+            tool_calls,
+            tool_call_id: None,
         })],
         usage: ChatUsage {
             completion_tokens: res.usage_metadata.candidates_token_count,
@@ -155,16 +247,10 @@ impl ExecuteChatCompletion for Gemini {
                     json!([{ "parts": [{ "text": messages[0].content }], "role": "user" }]);
             } else {
                 body["system_instruction"] = json!({"parts": [{"text": messages[0].content}]});
-                body["contents"] = json!(messages[1..]
-                    .iter()
-                    .map(|m| to_content(m.clone()))
-                    .collect::<Vec<_>>());
+                body["contents"] = json!(to_contents(&messages[1..]));
             }
         } else {
-            body["contents"] = json!(messages
-                .iter()
-                .map(|m| to_content(m.clone()))
-                .collect::<Vec<_>>());
+            body["contents"] = json!(to_contents(messages));
         }
 
         body["generation_config"] = params.clone();
@@ -185,6 +271,7 @@ impl ExecuteChatCompletion for Gemini {
             let mut eventsource = EventSource::new(req)?;
 
             let mut message = String::new();
+            let mut tool_calls: Vec<ChatMessageToolCall> = Vec::new();
             let mut prompt_tokens = 0;
             let mut completion_tokens = 0;
 
@@ -199,9 +286,13 @@ impl ExecuteChatCompletion for Gemini {
                         reqwest_eventsource::Error::InvalidStatusCode(status, _) => {
                             // handle separately to not display SET-COOKIE header from response
                             if matches!(status, reqwest::StatusCode::UNAUTHORIZED) {
-                                return Err(anyhow::anyhow!("Invalid API key"));
+                                return Err(ProviderError::new(status, "Invalid API key").into());
                             } else {
-                                return Err(anyhow::anyhow!("Error. Status code: {}", status));
+                                return Err(ProviderError::new(
+                                    status,
+                                    format!("Error. Status code: {}", status),
+                                )
+                                .into());
                             };
                         }
                         _ => {
@@ -219,14 +310,21 @@ impl ExecuteChatCompletion for Gemini {
                 let partial_response = serde_json::from_str::<GeminiResponse>(&item)?;
                 for candidate in partial_response.candidates {
                     for part in candidate.content.parts {
-                        message.push_str(&part.text);
+                        // Function calls are not split across chunks
+                        if let Some(function_call) = part.function_call {
+                            tool_calls.push(function_call.into());
+                        }
+                        let Some(text) = part.text else {
+                            continue;
+                        };
+                        message.push_str(&text);
 
                         let stream_chunk = StreamChunk::NodeChunk(NodeStreamChunk {
                             id: node_info.id,
                             node_id: node_info.node_id,
                             node_name: node_info.node_name.clone(),
                             node_type: node_info.node_type.clone(),
-                            content: part.text.into(),
+                            content: text.into(),
                         });
 
                         tx.send(stream_chunk).await.unwrap();
@@ -244,6 +342,8 @@ impl ExecuteChatCompletion for Gemini {
             let chat_message = ChatMessage {
                 role: "assistant".to_string(),
                 content: ChatMessageContent::Text(message),
+                tool_calls,
+                tool_call_id: None,
             };
 
             let chat_choice = ChatChoice::new(chat_message);
@@ -279,11 +379,11 @@ impl ExecuteChatCompletion for Gemini {
             let status = res.status();
             if status != 200 {
                 let res_body = res.json::<GeminiError>().await?;
-                return Err(anyhow::anyhow!(
-                    "Status: {}, Error:\n{}",
+                return Err(ProviderError::new(
                     status,
-                    res_body.error.message
-                ));
+                    format!("Status: {}, Error:\n{}", status, res_body.error.message),
+                )
+                .into());
             }
 
             let res_body = res.json::<GeminiResponse>().await?;
@@ -308,36 +408,4 @@ impl EstimateCost for Gemini {
     fn db_provider_name(&self) -> &str {
         "gemini"
     }
-
-    // override the default implementation, because Gemini charges differently when input_tokens > 128_000
-    async fn estimate_cost(
-        &self,
-        db: Arc<DB>,
-        cache: Arc<Cache>,
-        model: &str,
-        input_tokens: u32,
-        output_tokens: u32,
-    ) -> Option<f64> {
-        let model = if model.starts_with("gemini-1.5") && input_tokens > 128_000 {
-            format!("{model}--long-context")
-        } else {
-            model.to_string()
-        };
-        let input_cost = self
-            .estimate_input_cost(db.clone(), cache.clone(), model.as_str(), input_tokens)
-            .await
-            .or_else(|| {
-                log::warn!(
-                    "No stored price found for provider: {}, model: {}",
-                    self.db_provider_name(),
-                    model,
-                );
-                None
-            })?;
-        let output_cost = self
-            .estimate_output_cost(db.clone(), cache.clone(), model.as_str(), output_tokens)
-            .await?;
-
-        Some(input_cost + output_cost)
-    }
 }
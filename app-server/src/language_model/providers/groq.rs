@@ -20,6 +20,9 @@ use crate::{
     pipeline::nodes::{NodeStreamChunk, StreamChunk},
 };
 
+use super::openai::to_value;
+use super::utils::ProviderError;
+
 #[derive(Clone, Debug)]
 pub struct Groq {
     client: reqwest::Client,
@@ -68,9 +71,14 @@ impl ExecuteChatCompletion for Groq {
         db: Arc<DB>,
         cache: Arc<Cache>,
     ) -> Result<ChatCompletion> {
+        let json_messages = messages
+            .iter()
+            .map(to_value)
+            .collect::<Result<Vec<Value>>>()?;
+
         let mut body = json!({
             "model": model,
-            "messages": messages,
+            "messages": json_messages,
         });
 
         body.merge(params);
@@ -109,9 +117,13 @@ impl ExecuteChatCompletion for Groq {
                         reqwest_eventsource::Error::InvalidStatusCode(status, _) => {
                             // handle separately to not display SET-COOKIE header from response
                             if matches!(status, reqwest::StatusCode::UNAUTHORIZED) {
-                                return Err(anyhow::anyhow!("Invalid API key"));
+                                return Err(ProviderError::new(status, "Invalid API key").into());
                             } else {
-                                return Err(anyhow::anyhow!("Error. Status code: {}", status));
+                                return Err(ProviderError::new(
+                                    status,
+                                    format!("Error. Status code: {}", status),
+                                )
+                                .into());
                             };
                         }
                         _ => {
@@ -152,6 +164,7 @@ impl ExecuteChatCompletion for Groq {
             let chat_message = ChatMessage {
                 role: "system".to_string(),
                 content: ChatMessageContent::Text(message),
+                ..Default::default()
             };
 
             let chat_choice = ChatChoice::new(chat_message);
@@ -181,9 +194,10 @@ impl ExecuteChatCompletion for Groq {
                 .send()
                 .await?;
 
-            if res.status() != 200 {
+            let status = res.status();
+            if status != 200 {
                 let res_body = res.json::<GroqError>().await?;
-                return Err(anyhow::anyhow!(res_body.error.message));
+                return Err(ProviderError::new(status, res_body.error.message).into());
             }
 
             let mut res_body = res.json::<ChatCompletion>().await?;
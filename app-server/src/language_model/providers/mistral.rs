@@ -12,6 +12,9 @@ use json_value_merge::Merge;
 use serde_json::{json, Value};
 use tokio::sync::mpsc::Sender;
 
+use super::openai::to_value;
+use super::utils::ProviderError;
+
 #[derive(Clone, Debug)]
 pub struct Mistral {
     client: reqwest::Client,
@@ -36,9 +39,14 @@ impl ExecuteChatCompletion for Mistral {
         db: Arc<DB>,
         cache: Arc<Cache>,
     ) -> Result<ChatCompletion> {
+        let json_messages = messages
+            .iter()
+            .map(to_value)
+            .collect::<Result<Vec<Value>>>()?;
+
         let mut body = json!({
             "model": model,
-            "messages": messages,
+            "messages": json_messages,
         });
 
         body.merge(params);
@@ -54,10 +62,15 @@ impl ExecuteChatCompletion for Mistral {
             .send()
             .await?;
 
-        if !res.status().is_success() {
+        let status = res.status();
+        if !status.is_success() {
             let error = res.text().await?;
             log::error!("Mistral chat completion failed: {}", error);
-            return Err(anyhow::anyhow!("Mistral chat completion failed: {}", error));
+            return Err(ProviderError::new(
+                status,
+                format!("Mistral chat completion failed: {}", error),
+            )
+            .into());
         }
 
         let mut res_body = res.json::<ChatCompletion>().await?;
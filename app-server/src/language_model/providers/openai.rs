@@ -16,13 +16,15 @@ use tokio::sync::mpsc::Sender;
 use crate::cache::Cache;
 use crate::db::DB;
 use crate::language_model::{
-    ChatChoice, ChatCompletion, ChatMessage, ChatMessageContent, ChatMessageContentPart, ChatUsage,
-    EstimateCost, LanguageModelProviderName, NodeInfo,
+    ChatChoice, ChatCompletion, ChatMessage, ChatMessageContent, ChatMessageContentPart,
+    ChatMessageToolCall, ChatUsage, EstimateCost, LanguageModelProviderName, NodeInfo,
 };
 
 use crate::language_model::runner::ExecuteChatCompletion;
 use crate::pipeline::nodes::{NodeStreamChunk, StreamChunk};
 
+use super::utils::ProviderError;
+
 #[derive(Clone, Debug)]
 pub struct OpenAI {
     client: reqwest::Client,
@@ -78,6 +80,18 @@ struct OpenAIToolCall {
     function: OpenAIToolCallFunction,
 }
 
+impl From<OpenAIToolCall> for ChatMessageToolCall {
+    fn from(tool_call: OpenAIToolCall) -> Self {
+        ChatMessageToolCall {
+            id: tool_call.id,
+            name: tool_call.function.name.unwrap_or_default(),
+            arguments: ChatMessageToolCall::parse_arguments(Value::String(
+                tool_call.function.arguments,
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct OpenAIChatMessage {
     role: String,
@@ -158,6 +172,12 @@ pub fn num_tokens_from_messages(model: &str, messages: &Vec<ChatMessage>) -> Res
                 }
             }
         }
+        for tool_call in &message.tool_calls {
+            num_tokens += bpe.encode_with_special_tokens(&tool_call.name).len() as u32;
+            num_tokens += bpe
+                .encode_with_special_tokens(&tool_call.arguments_string())
+                .len() as u32;
+        }
     }
     num_tokens += 3; // every reply is primed with <|start|>assistant<|message|>
     Ok(num_tokens)
@@ -165,15 +185,16 @@ pub fn num_tokens_from_messages(model: &str, messages: &Vec<ChatMessage>) -> Res
 
 /// Convert to OpenAI message
 ///
-/// This functions is mainly needed to convert the images to correct format
+/// This functions is mainly needed to convert the images and tool calls to correct format.
+/// It is also used for the providers with OpenAI-compatible APIs.
 ///
 /// TODO: This must convert to special OpenAI structs and later serialized by Serde
-fn to_value(message: &ChatMessage) -> Result<Value> {
-    match &message.content {
-        ChatMessageContent::Text(text) => Ok(json!({
+pub fn to_value(message: &ChatMessage) -> Result<Value> {
+    let mut value = match &message.content {
+        ChatMessageContent::Text(text) => json!({
             "role": message.role,
             "content": text,
-        })),
+        }),
         ChatMessageContent::ContentPartList(parts) => {
             let parts: Vec<Value> = parts
                 .into_iter()
@@ -200,12 +221,38 @@ fn to_value(message: &ChatMessage) -> Result<Value> {
                     }
                 })
                 .collect::<Result<Vec<Value>>>()?;
-            Ok(json!({
+            json!({
                 "role": message.role,
                 "content": parts,
-            }))
+            })
         }
+    };
+
+    if !message.tool_calls.is_empty() {
+        let tool_calls = message
+            .tool_calls
+            .iter()
+            .map(|tool_call| {
+                json!({
+                    "id": tool_call.id,
+                    "type": "function",
+                    "function": {
+                        "name": tool_call.name,
+                        "arguments": tool_call.arguments_string(),
+                    }
+                })
+            })
+            .collect::<Vec<Value>>();
+        value["tool_calls"] = json!(tool_calls);
+        if message.text().is_empty() {
+            value["content"] = Value::Null;
+        }
+    }
+    if let Some(tool_call_id) = &message.tool_call_id {
+        value["tool_call_id"] = json!(tool_call_id);
     }
+
+    Ok(value)
 }
 
 impl ExecuteChatCompletion for OpenAI {
@@ -229,7 +276,7 @@ impl ExecuteChatCompletion for OpenAI {
                     if message.role == "system" {
                         ChatMessage {
                             role: "user".to_string(),
-                            content: message.content.clone(),
+                            ..message.clone()
                         }
                     } else {
                         message.clone()
@@ -281,9 +328,13 @@ impl ExecuteChatCompletion for OpenAI {
                         reqwest_eventsource::Error::InvalidStatusCode(status, _) => {
                             // handle separately to not display SET-COOKIE header from response
                             if matches!(status, reqwest::StatusCode::UNAUTHORIZED) {
-                                return Err(anyhow::anyhow!("Invalid API key"));
+                                return Err(ProviderError::new(status, "Invalid API key").into());
                             } else {
-                                return Err(anyhow::anyhow!("Error. Status code: {}", status));
+                                return Err(ProviderError::new(
+                                    status,
+                                    format!("Error. Status code: {}", status),
+                                )
+                                .into());
                             };
                         }
                         _ => {
@@ -355,14 +406,11 @@ impl ExecuteChatCompletion for OpenAI {
 
             eventsource.close();
 
-            // if tools are provided, override output with tool call objects
-            if !tool_calls.is_empty() {
-                message = serde_json::to_string_pretty(&tool_calls).unwrap();
-            }
-
             let chat_message = ChatMessage {
                 role: "assistant".to_string(),
                 content: ChatMessageContent::Text(message),
+                tool_calls: tool_calls.into_iter().map(Into::into).collect(),
+                tool_call_id: None,
             };
 
             let chat_choice = ChatChoice::new(chat_message);
@@ -391,9 +439,10 @@ impl ExecuteChatCompletion for OpenAI {
                 .send()
                 .await?;
 
-            if res.status() != 200 {
+            let status = res.status();
+            if status != 200 {
                 let res_body = res.json::<OpenAIError>().await?;
-                return Err(anyhow::anyhow!(res_body.error.message));
+                return Err(ProviderError::new(status, res_body.error.message).into());
             }
 
             let mut res_body = res.json::<OpenAIChatCompletion>().await?;
@@ -410,22 +459,22 @@ impl ExecuteChatCompletion for OpenAI {
             let chat_completion = ChatCompletion {
                 choices: res_body
                     .choices
-                    .iter()
+                    .into_iter()
                     .map(|choice| {
-                        // if content is None, use tool_calls as content
-                        let content = if choice.message.content.is_none()
-                            && choice.message.tool_calls.is_some()
-                        {
-                            serde_json::to_string_pretty(
-                                choice.message.tool_calls.as_ref().unwrap(),
-                            )
-                            .unwrap()
-                        } else {
-                            choice.message.content.clone().unwrap()
-                        };
+                        // content is None if the model only called tools
                         let message = ChatMessage {
-                            role: choice.message.role.clone(),
-                            content: ChatMessageContent::Text(content),
+                            role: choice.message.role,
+                            content: ChatMessageContent::Text(
+                                choice.message.content.unwrap_or_default(),
+                            ),
+                            tool_calls: choice
+                                .message
+                                .tool_calls
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(Into::into)
+                                .collect(),
+                            tool_call_id: None,
                         };
 
                         ChatChoice::new(message)
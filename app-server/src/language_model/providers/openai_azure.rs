@@ -20,7 +20,8 @@ use crate::language_model::{
 };
 use crate::pipeline::nodes::{NodeStreamChunk, StreamChunk};
 
-use super::openai::{num_tokens_from_messages, ChatCompletionChunk};
+use super::openai::{num_tokens_from_messages, to_value, ChatCompletionChunk};
+use super::utils::ProviderError;
 
 pub const OPENAI_AZURE_RESOURCE_ID: &str = "OPENAI_AZURE_RESOURCE_ID";
 pub const OPENAI_AZURE_DEPLOYMENT_NAME: &str = "OPENAI_AZURE_DEPLOYMENT_NAME";
@@ -58,8 +59,13 @@ impl ExecuteChatCompletion for OpenAIAzure {
         db: Arc<DB>,
         cache: Arc<Cache>,
     ) -> Result<ChatCompletion> {
+        let json_messages = messages
+            .iter()
+            .map(to_value)
+            .collect::<Result<Vec<Value>>>()?;
+
         let mut body = json!({
-            "messages": messages,
+            "messages": json_messages,
         });
 
         body.merge(params);
@@ -93,9 +99,13 @@ impl ExecuteChatCompletion for OpenAIAzure {
                         reqwest_eventsource::Error::InvalidStatusCode(status, _) => {
                             // handle separately to not display SET-COOKIE header from response
                             if matches!(status, reqwest::StatusCode::UNAUTHORIZED) {
-                                return Err(anyhow::anyhow!("Invalid API key"));
+                                return Err(ProviderError::new(status, "Invalid API key").into());
                             } else {
-                                return Err(anyhow::anyhow!("Error. Status code: {}", status));
+                                return Err(ProviderError::new(
+                                    status,
+                                    format!("Error. Status code: {}", status),
+                                )
+                                .into());
                             };
                         }
                         _ => {
@@ -146,6 +156,7 @@ impl ExecuteChatCompletion for OpenAIAzure {
             let chat_message = ChatMessage {
                 role: "assistant".to_string(),
                 content: ChatMessageContent::Text(message),
+                ..Default::default()
             };
 
             let chat_choice = ChatChoice::new(chat_message);
@@ -174,9 +185,10 @@ impl ExecuteChatCompletion for OpenAIAzure {
                 .send()
                 .await?;
 
-            if res.status() != 200 {
+            let status = res.status();
+            if status != 200 {
                 let res_body = res.json::<OpenAIError>().await?;
-                return Err(anyhow::anyhow!(res_body.error.message));
+                return Err(ProviderError::new(status, res_body.error.message).into());
             }
 
             let mut res_body = res.json::<ChatCompletion>().await?;
@@ -22,3 +22,21 @@ pub fn get_required_env_vars_for_model(model: &str) -> HashSet<String> {
 pub fn calculate_cost(tokens: u32, price_per_million_tokens: f64) -> f64 {
     tokens as f64 * price_per_million_tokens / 1_000_000.0
 }
+
+/// Error response of a provider API. The status is kept, so that the pipeline engine can
+/// tell rate limits and server errors, which are worth retrying, from other errors
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+pub struct ProviderError {
+    pub status: reqwest::StatusCode,
+    pub message: String,
+}
+
+impl ProviderError {
+    pub fn new(status: reqwest::StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
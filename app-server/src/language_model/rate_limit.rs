@@ -0,0 +1,357 @@
+//! Limits on the LLM calls of the pipelines, so that concurrent runs queue up instead of
+//! hitting the rate limits of the providers.
+//!
+//! A call waits for a permit of the concurrency limit of its project, for a permit of the
+//! concurrency limit of its model, and until the tokens-per-minute budget of its model has
+//! room for its estimated input tokens. Calls that are rate limited by the provider anyway
+//! pause all the calls to the model, and are retried with backoff.
+
+use std::{
+    collections::VecDeque,
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+use uuid::Uuid;
+
+use super::{ChatMessage, ChatMessageContent, ChatMessageContentPart};
+
+const TOKENS_WINDOW: Duration = Duration::from_secs(60);
+/// Rough number of characters per token, to estimate the input tokens before the call
+const CHARS_PER_TOKEN: usize = 4;
+/// Rough number of tokens of an image or a document
+const ATTACHMENT_TOKENS: usize = 1000;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRateLimit {
+    /// Provider as in the model of the LLM node, e.g. "openai"
+    pub provider: String,
+    /// If None, the limit applies to each model of the provider that has no limit of its own
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_concurrent_requests: Option<usize>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub max_concurrent_requests_per_project: Option<usize>,
+    pub model_limits: Vec<ModelRateLimit>,
+    /// Number of times a call that the provider rate limited is retried
+    pub max_rate_limit_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests_per_project: None,
+            model_limits: Vec::new(),
+            max_rate_limit_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Read the limits from `LLM_MAX_CONCURRENT_REQUESTS_PER_PROJECT`, `LLM_RATE_LIMITS`,
+    /// a JSON array of model limits, and `LLM_MAX_RATE_LIMIT_RETRIES`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        let model_limits = match env::var("LLM_RATE_LIMITS") {
+            Ok(model_limits) => serde_json::from_str(&model_limits).unwrap_or_else(|e| {
+                log::error!("Failed to parse LLM_RATE_LIMITS: {}", e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        Self {
+            max_concurrent_requests_per_project: env::var(
+                "LLM_MAX_CONCURRENT_REQUESTS_PER_PROJECT",
+            )
+            .ok()
+            .and_then(|v| v.parse().ok()),
+            model_limits,
+            max_rate_limit_retries: env::var("LLM_MAX_RATE_LIMIT_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_rate_limit_retries),
+            ..default
+        }
+    }
+
+    fn model_limit(&self, provider: &str, model: &str) -> Option<&ModelRateLimit> {
+        let provider_limits = || {
+            self.model_limits
+                .iter()
+                .filter(move |limit| limit.provider == provider)
+        };
+        provider_limits()
+            .find(|limit| limit.model.as_deref() == Some(model))
+            .or_else(|| provider_limits().find(|limit| limit.model.is_none()))
+    }
+}
+
+struct TokensPerMinute {
+    limit: usize,
+    /// Tokens used in the last minute, with the time they were used at
+    used: Mutex<VecDeque<(Instant, usize)>>,
+}
+
+impl TokensPerMinute {
+    async fn acquire(&self, tokens: usize) {
+        loop {
+            let wait = {
+                let mut used = self.used.lock().unwrap();
+                let now = Instant::now();
+                while used
+                    .front()
+                    .is_some_and(|(time, _)| *time + TOKENS_WINDOW <= now)
+                {
+                    used.pop_front();
+                }
+
+                let used_tokens = used.iter().map(|(_, tokens)| tokens).sum::<usize>();
+                // a call that alone exceeds the limit is made once the window is empty
+                if used_tokens + tokens <= self.limit || used.is_empty() {
+                    used.push_back((now, tokens));
+                    return;
+                }
+                used.front().unwrap().0 + TOKENS_WINDOW - now
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    fn record(&self, tokens: usize) {
+        self.used
+            .lock()
+            .unwrap()
+            .push_back((Instant::now(), tokens));
+    }
+}
+
+struct ModelLimiter {
+    concurrency: Option<Arc<Semaphore>>,
+    tokens: Option<TokensPerMinute>,
+    /// Calls to the model wait until then, after the provider rate limited a call
+    paused_until: Mutex<Option<Instant>>,
+}
+
+impl ModelLimiter {
+    fn new(limit: Option<&ModelRateLimit>) -> Self {
+        Self {
+            concurrency: limit
+                .and_then(|limit| limit.max_concurrent_requests)
+                .map(|permits| Arc::new(Semaphore::new(permits))),
+            tokens: limit
+                .and_then(|limit| limit.tokens_per_minute)
+                .map(|limit| TokensPerMinute {
+                    limit,
+                    used: Mutex::new(VecDeque::new()),
+                }),
+            paused_until: Mutex::new(None),
+        }
+    }
+
+    async fn wait_until_resumed(&self) {
+        loop {
+            let paused_until = *self.paused_until.lock().unwrap();
+            match paused_until {
+                Some(until) if until > Instant::now() => tokio::time::sleep_until(until).await,
+                _ => return,
+            }
+        }
+    }
+}
+
+/// Permits of a call, which are released when it is dropped
+pub struct RateLimitPermit {
+    _project_permit: Option<OwnedSemaphorePermit>,
+    _model_permit: Option<OwnedSemaphorePermit>,
+    limiter: Arc<ModelLimiter>,
+    estimated_tokens: usize,
+}
+
+impl RateLimitPermit {
+    /// Count the tokens the call used, beyond the estimated ones, towards the
+    /// tokens-per-minute limit of the model
+    pub fn record_usage(&self, total_tokens: usize) {
+        if let Some(tokens) = &self.limiter.tokens {
+            tokens.record(total_tokens.saturating_sub(self.estimated_tokens));
+        }
+    }
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    projects: DashMap<Uuid, Arc<Semaphore>>,
+    models: DashMap<(String, String), Arc<ModelLimiter>>,
+}
+
+impl std::fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            projects: DashMap::new(),
+            models: DashMap::new(),
+        }
+    }
+
+    /// Wait until the call can be made without exceeding the limits
+    pub async fn acquire(
+        &self,
+        project_id: Option<&Uuid>,
+        provider: &str,
+        model: &str,
+        estimated_tokens: usize,
+    ) -> RateLimitPermit {
+        let project_semaphore = match (project_id, self.config.max_concurrent_requests_per_project)
+        {
+            (Some(project_id), Some(permits)) => Some(
+                self.projects
+                    .entry(*project_id)
+                    .or_insert_with(|| Arc::new(Semaphore::new(permits)))
+                    .clone(),
+            ),
+            _ => None,
+        };
+        let project_permit = match project_semaphore {
+            Some(semaphore) => Some(semaphore.acquire_owned().await.unwrap()),
+            None => None,
+        };
+
+        let limiter = self.model_limiter(provider, model);
+        limiter.wait_until_resumed().await;
+        let model_permit = match &limiter.concurrency {
+            Some(semaphore) => Some(semaphore.clone().acquire_owned().await.unwrap()),
+            None => None,
+        };
+        if let Some(tokens) = &limiter.tokens {
+            tokens.acquire(estimated_tokens).await;
+        }
+
+        RateLimitPermit {
+            _project_permit: project_permit,
+            _model_permit: model_permit,
+            limiter,
+            estimated_tokens,
+        }
+    }
+
+    /// Pause the calls to the model after the provider rate limited a call
+    pub fn pause(&self, provider: &str, model: &str, delay: Duration) {
+        let limiter = self.model_limiter(provider, model);
+        let until = Instant::now() + delay;
+        let mut paused_until = limiter.paused_until.lock().unwrap();
+        if !paused_until.is_some_and(|paused_until| paused_until >= until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    pub fn max_rate_limit_retries(&self) -> u32 {
+        self.config.max_rate_limit_retries
+    }
+
+    /// Delay before the retry that follows `attempt_count` rate limited attempts
+    pub fn backoff(&self, attempt_count: u32) -> Duration {
+        let multiplier = 2u32.saturating_pow(attempt_count.saturating_sub(1));
+        self.config
+            .initial_backoff
+            .saturating_mul(multiplier)
+            .min(self.config.max_backoff)
+    }
+
+    fn model_limiter(&self, provider: &str, model: &str) -> Arc<ModelLimiter> {
+        self.models
+            .entry((provider.to_string(), model.to_string()))
+            .or_insert_with(|| {
+                Arc::new(ModelLimiter::new(self.config.model_limit(provider, model)))
+            })
+            .clone()
+    }
+}
+
+/// Rough estimate of the input tokens of the messages, to count towards the
+/// tokens-per-minute limit before the call is made
+pub fn estimate_input_tokens(messages: &[ChatMessage]) -> usize {
+    let mut chars = 0;
+    let mut attachments = 0;
+    for message in messages {
+        match &message.content {
+            ChatMessageContent::Text(text) => chars += text.len(),
+            ChatMessageContent::ContentPartList(parts) => {
+                for part in parts {
+                    match part {
+                        ChatMessageContentPart::Text(text) => chars += text.text.len(),
+                        _ => attachments += 1,
+                    }
+                }
+            }
+        }
+        chars += message
+            .tool_calls
+            .iter()
+            .map(|tool_call| tool_call.name.len() + tool_call.arguments.to_string().len())
+            .sum::<usize>();
+    }
+    chars / CHARS_PER_TOKEN + attachments * ATTACHMENT_TOKENS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_limit_precedence() {
+        let config = RateLimitConfig {
+            model_limits: serde_json::from_value(serde_json::json!([
+                {"provider": "openai", "maxConcurrentRequests": 10},
+                {"provider": "openai", "model": "gpt-4o", "tokensPerMinute": 30000},
+            ]))
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let gpt_4o = config.model_limit("openai", "gpt-4o").unwrap();
+        assert_eq!(gpt_4o.tokens_per_minute, Some(30000));
+        assert_eq!(gpt_4o.max_concurrent_requests, None);
+        let gpt_4o_mini = config.model_limit("openai", "gpt-4o-mini").unwrap();
+        assert_eq!(gpt_4o_mini.max_concurrent_requests, Some(10));
+        assert!(config.model_limit("anthropic", "gpt-4o").is_none());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let rate_limiter = RateLimiter::new(RateLimitConfig {
+            max_backoff: Duration::from_secs(3),
+            ..Default::default()
+        });
+
+        assert_eq!(rate_limiter.backoff(1), Duration::from_secs(1));
+        assert_eq!(rate_limiter.backoff(2), Duration::from_secs(2));
+        assert_eq!(rate_limiter.backoff(3), Duration::from_secs(3));
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{Context, Result};
+use chrono::Utc;
 use enum_dispatch::enum_dispatch;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -14,12 +15,13 @@ use crate::{cache::Cache, db::DB, pipeline::nodes::StreamChunk};
 
 use super::{
     chat_message::ChatCompletion,
-    costs::TokensKind,
+    costs::{estimate_cost_by_provider_name, TokenUsage},
     providers::{
         anthropic_bedrock::{AWS_ACCESS_KEY_ID, AWS_REGION, AWS_SECRET_ACCESS_KEY},
         openai_azure::{OPENAI_AZURE_DEPLOYMENT_NAME, OPENAI_AZURE_RESOURCE_ID},
-        utils::get_provider,
+        utils::{get_provider, ProviderError},
     },
+    rate_limit::{estimate_input_tokens, RateLimiter},
     Anthropic, AnthropicBedrock, ChatMessage, Gemini, Groq, Mistral, OpenAI, OpenAIAzure,
 };
 
@@ -66,66 +68,31 @@ pub trait ExecuteChatCompletion {
 pub trait EstimateCost {
     fn db_provider_name(&self) -> &str;
 
-    async fn estimate_input_cost(
+    async fn estimate_cost(
         &self,
         db: Arc<DB>,
         cache: Arc<Cache>,
         model: &str,
         input_tokens: u32,
-    ) -> Option<f64> {
-        super::costs::estimate_cost(
-            db.clone(),
-            cache.clone(),
-            self.db_provider_name(),
-            model,
-            input_tokens,
-            TokensKind::Input,
-        )
-        .await
-    }
-
-    async fn estimate_output_cost(
-        &self,
-        db: Arc<DB>,
-        cache: Arc<Cache>,
-        model: &str,
         output_tokens: u32,
     ) -> Option<f64> {
-        super::costs::estimate_cost(
-            db.clone(),
-            cache.clone(),
+        let usage = TokenUsage {
+            input_tokens,
+            output_tokens,
+            ..Default::default()
+        };
+        // Calls made by pipelines are priced at the current global price
+        estimate_cost_by_provider_name(
+            db,
+            cache,
+            None,
             self.db_provider_name(),
             model,
-            output_tokens,
-            TokensKind::Output,
+            &usage,
+            &Utc::now(),
         )
         .await
-    }
-
-    async fn estimate_cost(
-        &self,
-        db: Arc<DB>,
-        cache: Arc<Cache>,
-        model: &str,
-        input_tokens: u32,
-        output_tokens: u32,
-    ) -> Option<f64> {
-        let input_cost = self
-            .estimate_input_cost(db.clone(), cache.clone(), model, input_tokens)
-            .await
-            .or_else(|| {
-                log::warn!(
-                    "No stored price found for provider: {}, model: {}",
-                    self.db_provider_name(),
-                    model,
-                );
-                None
-            })?;
-        let output_cost = self
-            .estimate_output_cost(db.clone(), cache.clone(), model, output_tokens)
-            .await?;
-
-        Some(input_cost + output_cost)
+        .map(|cost| cost.total_cost())
     }
 }
 
@@ -181,11 +148,18 @@ impl LanguageModelProviderName {
 #[derive(Debug)]
 pub struct LanguageModelRunner {
     pub models: HashMap<LanguageModelProviderName, LanguageModelProvider>,
+    rate_limiter: RateLimiter,
 }
 
 impl LanguageModelRunner {
-    pub fn new(models: HashMap<LanguageModelProviderName, LanguageModelProvider>) -> Self {
-        Self { models }
+    pub fn new(
+        models: HashMap<LanguageModelProviderName, LanguageModelProvider>,
+        rate_limiter: RateLimiter,
+    ) -> Self {
+        Self {
+            models,
+            rate_limiter,
+        }
     }
 
     /// Completes the chat by calling model's executor
@@ -199,6 +173,10 @@ impl LanguageModelRunner {
     /// * messages - list of messages in the chat.
     ///     If system message is passed, then it must be put as first message!
     ///     Next, alternating user and assistant messages are passed starting from user message.
+    ///
+    /// * project_id - project the call is limited under, if any.
+    ///     The call waits until the rate limits of the project and of the model allow it,
+    ///     and is retried with backoff if the provider rate limits it anyway.
     pub async fn chat_completion(
         &self,
         model: &str,
@@ -207,6 +185,7 @@ impl LanguageModelRunner {
         env: &HashMap<String, String>,
         tx: Option<Sender<StreamChunk>>,
         node_info: &NodeInfo,
+        project_id: Option<&Uuid>,
         db: Arc<DB>,
         cache: Arc<Cache>,
     ) -> Result<ChatCompletion> {
@@ -218,22 +197,57 @@ impl LanguageModelRunner {
         let provider_name = LanguageModelProviderName::from_str(provider)?;
 
         let executor = self.models.get(&provider_name).unwrap();
-        executor
-            .chat_completion(
-                model_name.as_str(),
-                provider_name,
-                messages,
-                params,
-                env,
-                tx,
-                node_info,
-                db,
-                cache,
-            )
-            .await
+        let estimated_tokens = estimate_input_tokens(messages);
+        let mut rate_limited_count = 0;
+        loop {
+            let permit = self
+                .rate_limiter
+                .acquire(project_id, provider, &model_name, estimated_tokens)
+                .await;
+            let completion = executor
+                .chat_completion(
+                    model_name.as_str(),
+                    provider_name.clone(),
+                    messages,
+                    params,
+                    env,
+                    tx.clone(),
+                    node_info,
+                    db.clone(),
+                    cache.clone(),
+                )
+                .await;
+
+            match &completion {
+                Ok(completion) => permit.record_usage(completion.usage.total_tokens as usize),
+                Err(e)
+                    if is_rate_limited(e)
+                        && rate_limited_count < self.rate_limiter.max_rate_limit_retries() =>
+                {
+                    rate_limited_count += 1;
+                    let backoff = self.rate_limiter.backoff(rate_limited_count);
+                    log::debug!(
+                        "Rate limited by {}, retrying in {:?}, err: {}",
+                        model,
+                        backoff,
+                        e
+                    );
+                    self.rate_limiter.pause(provider, &model_name, backoff);
+                    continue;
+                }
+                Err(_) => {}
+            }
+            return completion;
+        }
     }
 }
 
+fn is_rate_limited(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<ProviderError>()
+        .is_some_and(|error| error.status == reqwest::StatusCode::TOO_MANY_REQUESTS)
+}
+
 /// Information on the node to send along the streaming
 #[derive(Debug, Clone)]
 pub struct NodeInfo {
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use crate::{
+    cache::Cache,
+    db::DB,
+    mq::MessageQueue,
+    opentelemetry::opentelemetry::proto::collector::logs::v1::{
+        logs_service_server::LogsService, ExportLogsServiceRequest, ExportLogsServiceResponse,
+    },
+    traces::grpc_service::authenticate_request,
+};
+use tonic::{Request, Response, Status};
+
+use super::producer::push_logs_to_queue;
+
+pub struct ProcessLogsService {
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    queue: Arc<MessageQueue>,
+}
+
+impl ProcessLogsService {
+    pub fn new(db: Arc<DB>, cache: Arc<Cache>, queue: Arc<MessageQueue>) -> Self {
+        Self { db, cache, queue }
+    }
+}
+
+#[tonic::async_trait]
+impl LogsService for ProcessLogsService {
+    async fn export(
+        &self,
+        request: Request<ExportLogsServiceRequest>,
+    ) -> Result<Response<ExportLogsServiceResponse>, Status> {
+        let api_key = authenticate_request(request.metadata(), &self.db.pool, self.cache.clone())
+            .await
+            .map_err(|_| Status::unauthenticated("Failed to authenticate request"))?;
+        let project_id = api_key.project_id;
+        let request = request.into_inner();
+
+        let response = push_logs_to_queue(request, project_id, self.queue.clone())
+            .await
+            .map_err(|e| {
+                log::error!("Failed to process logs: {:?}", e);
+                // UNAVAILABLE is retryable by OTLP exporters
+                Status::unavailable("Failed to process logs")
+            })?;
+
+        Ok(Response::new(response))
+    }
+}
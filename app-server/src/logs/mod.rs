@@ -0,0 +1,123 @@
+//! OpenTelemetry logs ingestion.
+//!
+//! Log records are stored in the `logs` ClickHouse table together with the trace and span
+//! they were emitted in, so that they can be shown alongside the span.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::utils::{convert_any_value_to_json_value, span_id_to_uuid},
+    opentelemetry::{
+        opentelemetry::proto::collector::logs::v1::ExportLogsServiceRequest,
+        opentelemetry_proto_common_v1::{AnyValue, KeyValue},
+    },
+    traces::utils::json_value_to_string,
+};
+
+pub mod consumer;
+pub mod grpc_service;
+pub mod producer;
+
+pub const LOGS_QUEUE: &str = "logs_queue";
+pub const LOGS_EXCHANGE: &str = "logs_exchange";
+pub const LOGS_ROUTING_KEY: &str = "logs_routing_key";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Log {
+    pub id: Uuid,
+    /// Nil if the log record was emitted outside of a trace
+    pub trace_id: Uuid,
+    /// Nil if the log record was emitted outside of a span
+    pub span_id: Uuid,
+    pub time: DateTime<Utc>,
+    pub observed_time: DateTime<Utc>,
+    /// OpenTelemetry severity number, 1 (TRACE) to 24 (FATAL4), 0 if unspecified
+    pub severity_number: u8,
+    pub severity_text: String,
+    pub body: String,
+    pub attributes: HashMap<String, String>,
+    pub resource_attributes: HashMap<String, String>,
+    pub scope_name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QueueLogsMessage {
+    pub project_id: Uuid,
+    pub logs: Vec<Log>,
+}
+
+pub fn logs_from_otel(request: ExportLogsServiceRequest) -> Vec<Log> {
+    let mut logs = Vec::new();
+
+    for resource_logs in request.resource_logs {
+        let resource_attributes = resource_logs
+            .resource
+            .map(|resource| attributes_to_map(resource.attributes))
+            .unwrap_or_default();
+
+        for scope_logs in resource_logs.scope_logs {
+            let scope_name = scope_logs.scope.map(|scope| scope.name).unwrap_or_default();
+
+            for log_record in scope_logs.log_records {
+                let observed_time = if log_record.observed_time_unix_nano == 0 {
+                    Utc::now()
+                } else {
+                    Utc.timestamp_nanos(log_record.observed_time_unix_nano as i64)
+                };
+                // Time of the event is optional, the collector always sets the observed time
+                let time = if log_record.time_unix_nano == 0 {
+                    observed_time
+                } else {
+                    Utc.timestamp_nanos(log_record.time_unix_nano as i64)
+                };
+                let trace_id = if log_record.trace_id.len() == 16 {
+                    Uuid::from_slice(&log_record.trace_id).unwrap()
+                } else {
+                    Uuid::nil()
+                };
+                let span_id = if log_record.span_id.len() == 8 {
+                    span_id_to_uuid(&log_record.span_id)
+                } else {
+                    Uuid::nil()
+                };
+
+                logs.push(Log {
+                    id: Uuid::new_v4(),
+                    trace_id,
+                    span_id,
+                    time,
+                    observed_time,
+                    severity_number: log_record.severity_number.clamp(0, 24) as u8,
+                    severity_text: log_record.severity_text,
+                    body: any_value_to_string(log_record.body),
+                    attributes: attributes_to_map(log_record.attributes),
+                    resource_attributes: resource_attributes.clone(),
+                    scope_name: scope_name.clone(),
+                });
+            }
+        }
+    }
+
+    logs
+}
+
+fn any_value_to_string(value: Option<AnyValue>) -> String {
+    if value.as_ref().is_some_and(|v| v.value.is_some()) {
+        json_value_to_string(&convert_any_value_to_json_value(value))
+    } else {
+        String::new()
+    }
+}
+
+fn attributes_to_map(attributes: Vec<KeyValue>) -> HashMap<String, String> {
+    attributes
+        .into_iter()
+        .filter(|kv| kv.value.as_ref().is_some_and(|v| v.value.is_some()))
+        .map(|kv| (kv.key, any_value_to_string(kv.value)))
+        .collect()
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::{
+    mq::{MessageQueue, MessageQueueTrait},
+    opentelemetry::opentelemetry::proto::collector::logs::v1::{
+        ExportLogsServiceRequest, ExportLogsServiceResponse,
+    },
+};
+
+use super::{logs_from_otel, QueueLogsMessage, LOGS_EXCHANGE, LOGS_ROUTING_KEY};
+
+// Number of log records published in a single queue message
+const LOGS_PER_MESSAGE: usize = 1000;
+
+pub async fn push_logs_to_queue(
+    request: ExportLogsServiceRequest,
+    project_id: Uuid,
+    queue: Arc<MessageQueue>,
+) -> Result<ExportLogsServiceResponse> {
+    let logs = logs_from_otel(request);
+
+    for chunk in logs.chunks(LOGS_PER_MESSAGE) {
+        let message = QueueLogsMessage {
+            project_id,
+            logs: chunk.to_vec(),
+        };
+
+        queue
+            .publish(
+                &serde_json::to_vec(&message)?,
+                LOGS_EXCHANGE,
+                LOGS_ROUTING_KEY,
+            )
+            .await?;
+    }
+
+    Ok(ExportLogsServiceResponse {
+        partial_success: None,
+    })
+}
@@ -11,22 +11,34 @@ use code_executor::{code_executor_grpc::code_executor_client::CodeExecutorClient
 use dashmap::DashMap;
 use features::{is_feature_enabled, Feature};
 use lapin::{
-    options::{ExchangeDeclareOptions, QueueDeclareOptions},
+    options::{ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions},
     types::FieldTable,
     Connection, ConnectionProperties, ExchangeKind,
 };
+use logs::{
+    consumer::process_queue_logs, grpc_service::ProcessLogsService, LOGS_EXCHANGE, LOGS_QUEUE,
+};
 use machine_manager::{
     machine_manager_service_client::MachineManagerServiceClient, MachineManager, MachineManagerImpl,
 };
-use mq::MessageQueue;
+use metrics::{
+    consumer::process_queue_metrics, grpc_service::ProcessMetricsService, METRICS_EXCHANGE,
+    METRICS_QUEUE,
+};
+use mq::{MessageQueue, DEAD_LETTER_EXCHANGE, DEAD_LETTER_QUEUE, DEAD_LETTER_ROUTING_KEY};
 use names::NameGenerator;
-use opentelemetry::opentelemetry::proto::collector::trace::v1::trace_service_server::TraceServiceServer;
+use opentelemetry::opentelemetry::proto::collector::{
+    logs::v1::logs_service_server::LogsServiceServer,
+    metrics::v1::metrics_service_server::MetricsServiceServer,
+    trace::v1::trace_service_server::TraceServiceServer,
+};
+use pipeline_jobs::{consumer::process_pipeline_jobs, PIPELINE_JOBS_EXCHANGE, PIPELINE_JOBS_QUEUE};
 use runtime::{create_general_purpose_runtime, wait_stop_signal};
 use storage::{mock::MockStorage, Storage};
 use tonic::transport::Server;
 use traces::{
-    consumer::process_queue_spans, grpc_service::ProcessTracesService, OBSERVATIONS_EXCHANGE,
-    OBSERVATIONS_QUEUE,
+    consumer::process_queue_spans, grpc_service::ProcessTracesService, sampling::TraceDecisions,
+    OBSERVATIONS_EXCHANGE, OBSERVATIONS_QUEUE,
 };
 
 use cache::{in_memory::InMemoryCache, redis::RedisCache, Cache};
@@ -64,11 +76,14 @@ mod evaluations;
 mod features;
 mod labels;
 mod language_model;
+mod logs;
 mod machine_manager;
+mod metrics;
 mod mq;
 mod names;
 mod opentelemetry;
 mod pipeline;
+mod pipeline_jobs;
 mod provider_api_keys;
 mod routes;
 mod runtime;
@@ -122,16 +137,35 @@ fn main() -> anyhow::Result<()> {
 
     // == Stuff that is needed both for HTTP and gRPC servers ==
     // === 1. Cache ===
+    let in_memory_cache_size = env::var("IN_MEMORY_CACHE_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok());
     let cache = if let Ok(redis_url) = env::var("REDIS_URL") {
         runtime_handle.block_on(async {
-            let redis_cache = RedisCache::new(&redis_url).await.unwrap();
-            Cache::Redis(redis_cache)
+            if env::var("CACHE_TYPE") == Ok("memory".to_string()) {
+                // Each instance caches in memory, and removals are shared through Redis
+                let in_memory_cache =
+                    InMemoryCache::with_invalidation(in_memory_cache_size, &redis_url)
+                        .await
+                        .unwrap();
+                Cache::InMemory(in_memory_cache)
+            } else {
+                let redis_cache = RedisCache::new(&redis_url).await.unwrap();
+                Cache::Redis(redis_cache)
+            }
         })
     } else {
-        Cache::InMemory(InMemoryCache::new(None))
+        Cache::InMemory(InMemoryCache::new(in_memory_cache_size))
     };
     let cache = Arc::new(cache);
 
+    // Sampling decisions are stored apart from the cache, so that they do not evict its entries
+    let redis_url = env::var("REDIS_URL").ok();
+    let trace_decisions = runtime_handle
+        .block_on(TraceDecisions::new(redis_url.as_deref()))
+        .unwrap();
+    let trace_decisions = Arc::new(trace_decisions);
+
     // === 2. Database ===
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
@@ -192,6 +226,100 @@ fn main() -> anyhow::Result<()> {
                 .await
                 .unwrap();
 
+            // Metrics and logs are published through the spans message queue
+            channel
+                .exchange_declare(
+                    METRICS_EXCHANGE,
+                    ExchangeKind::Fanout,
+                    ExchangeDeclareOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            channel
+                .queue_declare(
+                    METRICS_QUEUE,
+                    QueueDeclareOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            channel
+                .exchange_declare(
+                    LOGS_EXCHANGE,
+                    ExchangeKind::Fanout,
+                    ExchangeDeclareOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            channel
+                .queue_declare(
+                    LOGS_QUEUE,
+                    QueueDeclareOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            // Pipeline runs submitted through the API are published through the spans message queue
+            channel
+                .exchange_declare(
+                    PIPELINE_JOBS_EXCHANGE,
+                    ExchangeKind::Fanout,
+                    ExchangeDeclareOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            channel
+                .queue_declare(
+                    PIPELINE_JOBS_QUEUE,
+                    QueueDeclareOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            // Messages that could not be processed by any of the consumers
+            channel
+                .exchange_declare(
+                    DEAD_LETTER_EXCHANGE,
+                    ExchangeKind::Fanout,
+                    ExchangeDeclareOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            channel
+                .queue_declare(
+                    DEAD_LETTER_QUEUE,
+                    QueueDeclareOptions {
+                        durable: true,
+                        ..Default::default()
+                    },
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
+            // Dead letters are never consumed, so the queue is bound here and not by a receiver
+            channel
+                .queue_bind(
+                    DEAD_LETTER_QUEUE,
+                    DEAD_LETTER_EXCHANGE,
+                    DEAD_LETTER_ROUTING_KEY,
+                    QueueBindOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap();
+
             let max_channel_pool_size = env::var("RABBITMQ_MAX_CHANNEL_POOL_SIZE")
                 .ok()
                 .and_then(|v| v.parse().ok())
@@ -200,6 +328,14 @@ fn main() -> anyhow::Result<()> {
             let rabbit_mq = mq::rabbit::RabbitMQ::new(connection.clone(), max_channel_pool_size);
             Arc::new(rabbit_mq.into())
         })
+    } else if env::var("MESSAGE_QUEUE_TYPE") == Ok("redis".to_string()) {
+        let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
+        runtime_handle.block_on(async {
+            let redis_queue = mq::redis_streams::RedisStreamsQueue::new(&redis_url)
+                .await
+                .unwrap();
+            Arc::new(redis_queue.into())
+        })
     } else {
         Arc::new(mq::tokio_mpsc::TokioMpscQueue::new().into())
     };
@@ -237,12 +373,15 @@ fn main() -> anyhow::Result<()> {
             Arc::new(rabbit_mq.into())
         })
     } else {
-        Arc::new(mq::tokio_mpsc::TokioMpscQueue::new().into())
+        // Both queues are registered as the same app data type in the HTTP server,
+        // so the in-process or Redis queue is shared for handlers and dead letters to see all messages
+        spans_message_queue.clone()
     };
 
     let runtime_handle_for_http = runtime_handle.clone();
     let db_for_http = db.clone();
     let cache_for_http = cache.clone();
+    let trace_decisions_for_http = trace_decisions.clone();
     let spans_mq_for_http = spans_message_queue.clone();
 
     // == HTTP server and listener workers ==
@@ -260,13 +399,16 @@ fn main() -> anyhow::Result<()> {
 
                 // == Storage ==
                 let storage: Arc<Storage> = if is_feature_enabled(Feature::Storage) {
-                    let s3_client = aws_sdk_s3::Client::new(&aws_sdk_config);
-                    let s3_storage = storage::s3::S3Storage::new(
-                        s3_client,
-                        env::var("S3_TRACE_PAYLOADS_BUCKET")
-                            .expect("S3_TRACE_PAYLOADS_BUCKET must be set"),
-                    );
-                    Arc::new(s3_storage.into())
+                    if env::var("STORAGE_TYPE") == Ok("local".to_string()) {
+                        let local_storage =
+                            storage::local_fs::LocalFsStorage::new(std::path::PathBuf::from(
+                                env::var("LOCAL_STORAGE_PATH").unwrap_or("storage".to_string()),
+                            ));
+                        Arc::new(local_storage.into())
+                    } else {
+                        let s3_storage = storage::s3::S3Storage::from_env(&aws_sdk_config);
+                        Arc::new(s3_storage.into())
+                    }
                 } else {
                     Arc::new(MockStorage {}.into())
                 };
@@ -405,8 +547,12 @@ fn main() -> anyhow::Result<()> {
                         aws_sdk_bedrockruntime::Client::new(&aws_sdk_config),
                     )),
                 );
-                let language_model_runner =
-                    Arc::new(language_model::LanguageModelRunner::new(language_models));
+                let language_model_runner = Arc::new(language_model::LanguageModelRunner::new(
+                    language_models,
+                    language_model::rate_limit::RateLimiter::new(
+                        language_model::rate_limit::RateLimitConfig::from_env(),
+                    ),
+                ));
 
                 // == Pipeline runner ==
                 let pipeline_runner = Arc::new(pipeline::runner::PipelineRunner::new(
@@ -418,6 +564,27 @@ fn main() -> anyhow::Result<()> {
                     cache_for_http.clone(),
                 ));
 
+                // == Pipeline jobs workers ==
+                // Pipeline runs take long, so they run on the general-purpose runtime rather
+                // than on the actix worker threads
+                let num_pipeline_jobs_workers = env::var("NUM_PIPELINE_JOBS_WORKERS")
+                    .unwrap_or(String::from("8"))
+                    .parse::<u8>()
+                    .unwrap_or(8);
+                let webhook_client = pipeline_jobs::webhook::webhook_client();
+                for _ in 0..num_pipeline_jobs_workers {
+                    tokio::spawn(process_pipeline_jobs(
+                        pipeline_runner.clone(),
+                        db_for_http.clone(),
+                        spans_mq_for_http.clone(),
+                        webhook_client.clone(),
+                    ));
+                }
+
+                // Long-running background jobs started by requests, e.g. cost backfills, run on
+                // the general-purpose runtime rather than on the actix worker threads
+                let general_runtime_handle = tokio::runtime::Handle::current();
+
                 HttpServer::new(move || {
                     let auth = HttpAuthentication::bearer(auth::validator);
                     let project_auth = HttpAuthentication::bearer(auth::project_validator);
@@ -435,11 +602,22 @@ fn main() -> anyhow::Result<()> {
                             .parse::<u8>()
                             .unwrap_or(4);
 
+                    let num_metrics_workers_per_thread = env::var("NUM_METRICS_WORKERS_PER_THREAD")
+                        .unwrap_or(String::from("2"))
+                        .parse::<u8>()
+                        .unwrap_or(2);
+
+                    let num_logs_workers_per_thread = env::var("NUM_LOGS_WORKERS_PER_THREAD")
+                        .unwrap_or(String::from("2"))
+                        .parse::<u8>()
+                        .unwrap_or(2);
+
                     for _ in 0..num_spans_workers_per_thread {
                         tokio::spawn(process_queue_spans(
                             pipeline_runner.clone(),
                             db_for_http.clone(),
                             cache_for_http.clone(),
+                            trace_decisions_for_http.clone(),
                             spans_mq_for_http.clone(),
                             clickhouse.clone(),
                             storage.clone(),
@@ -453,6 +631,20 @@ fn main() -> anyhow::Result<()> {
                         ));
                     }
 
+                    for _ in 0..num_metrics_workers_per_thread {
+                        tokio::spawn(process_queue_metrics(
+                            clickhouse.clone(),
+                            spans_mq_for_http.clone(),
+                        ));
+                    }
+
+                    for _ in 0..num_logs_workers_per_thread {
+                        tokio::spawn(process_queue_logs(
+                            clickhouse.clone(),
+                            spans_mq_for_http.clone(),
+                        ));
+                    }
+
                     App::new()
                         .wrap(Logger::default())
                         .wrap(NormalizePath::trim())
@@ -473,12 +665,20 @@ fn main() -> anyhow::Result<()> {
                         .app_data(web::Data::new(machine_manager.clone()))
                         .app_data(web::Data::new(browser_events_message_queue.clone()))
                         .app_data(web::Data::new(connection_for_health.clone()))
+                        .app_data(web::Data::new(general_runtime_handle.clone()))
                         // Scopes with specific auth or no auth
                         .service(
                             web::scope("api/v1/auth")
                                 .wrap(shared_secret_auth.clone())
                                 .service(routes::auth::signin),
                         )
+                        .service(
+                            web::scope("api/v1/admin")
+                                .wrap(shared_secret_auth.clone())
+                                .service(routes::dead_letters::get_dead_letters)
+                                .service(routes::dead_letters::replay_dead_letters)
+                                .service(routes::dead_letters::purge_dead_letters),
+                        )
                         .service(api::v1::machine_manager::vnc_stream) // vnc stream does not need auth
                         .service(
                             web::scope("/v1/browser-sessions")
@@ -493,11 +693,17 @@ fn main() -> anyhow::Result<()> {
                             web::scope("/v1")
                                 .wrap(project_auth.clone())
                                 .service(api::v1::pipelines::run_pipeline_graph)
+                                .service(api::v1::pipelines::submit_pipeline_run)
+                                .service(api::v1::pipelines::get_pipeline_run)
+                                .service(api::v1::pipelines::cancel_pipeline_run)
+                                .service(api::v1::pipelines::resume_pipeline_run)
                                 .service(api::v1::pipelines::ping_healthcheck)
                                 .service(api::v1::traces::process_traces)
                                 .service(api::v1::datasets::get_datapoints)
                                 .service(api::v1::evaluations::create_evaluation)
                                 .service(api::v1::metrics::process_metrics)
+                                .service(api::v1::logs::process_logs)
+                                .service(api::v1::payloads::get_payload)
                                 .service(api::v1::semantic_search::semantic_search)
                                 .service(api::v1::queues::push_to_queue)
                                 .service(api::v1::machine_manager::start_machine)
@@ -566,7 +772,33 @@ fn main() -> anyhow::Result<()> {
                                 .service(routes::labels::get_registered_label_classes_for_path)
                                 .service(routes::labels::update_label_class)
                                 .service(routes::traces::get_traces_metrics)
-                                .service(routes::provider_api_keys::save_api_key),
+                                .service(routes::traces::get_trace_timeline)
+                                .service(routes::traces::get_span_error_rates)
+                                .service(routes::traces::get_sampling_policy)
+                                .service(routes::traces::update_sampling_policy)
+                                .service(routes::metrics::get_metric_names)
+                                .service(routes::metrics::get_metric_values)
+                                .service(routes::logs::get_logs)
+                                .service(routes::payloads::get_payload)
+                                .service(routes::payloads::get_payload_url)
+                                .service(routes::payloads::get_payload_settings)
+                                .service(routes::payloads::update_payload_settings)
+                                .service(routes::provider_api_keys::save_api_key)
+                                .service(routes::redaction_rules::get_redaction_rules)
+                                .service(routes::redaction_rules::get_redaction_detectors)
+                                .service(routes::redaction_rules::create_redaction_rule)
+                                .service(routes::redaction_rules::update_redaction_rule)
+                                .service(routes::redaction_rules::delete_redaction_rule)
+                                .service(routes::prices::get_project_prices)
+                                .service(routes::prices::create_project_price)
+                                .service(routes::prices::backfill_costs)
+                                .service(routes::prices::get_cost_backfill)
+                                .service(routes::prices::update_project_price)
+                                .service(routes::prices::delete_project_price)
+                                .service(routes::prices::get_model_name_rules)
+                                .service(routes::prices::create_model_name_rule)
+                                .service(routes::prices::update_model_name_rule)
+                                .service(routes::prices::delete_model_name_rule),
                         )
                         .service(routes::probes::check_health)
                         .service(routes::probes::check_ready)
@@ -588,6 +820,13 @@ fn main() -> anyhow::Result<()> {
                     cache.clone(),
                     spans_message_queue.clone(),
                 );
+                let process_metrics_service = ProcessMetricsService::new(
+                    db.clone(),
+                    cache.clone(),
+                    spans_message_queue.clone(),
+                );
+                let process_logs_service =
+                    ProcessLogsService::new(db.clone(), cache.clone(), spans_message_queue.clone());
 
                 Server::builder()
                     .add_service(
@@ -596,6 +835,18 @@ fn main() -> anyhow::Result<()> {
                             .send_compressed(tonic::codec::CompressionEncoding::Gzip)
                             .max_decoding_message_size(grpc_payload_limit),
                     )
+                    .add_service(
+                        MetricsServiceServer::new(process_metrics_service)
+                            .accept_compressed(tonic::codec::CompressionEncoding::Gzip)
+                            .send_compressed(tonic::codec::CompressionEncoding::Gzip)
+                            .max_decoding_message_size(grpc_payload_limit),
+                    )
+                    .add_service(
+                        LogsServiceServer::new(process_logs_service)
+                            .accept_compressed(tonic::codec::CompressionEncoding::Gzip)
+                            .send_compressed(tonic::codec::CompressionEncoding::Gzip)
+                            .max_decoding_message_size(grpc_payload_limit),
+                    )
                     .serve_with_shutdown(grpc_address, async {
                         wait_stop_signal("gRPC service").await;
                     })
@@ -0,0 +1,91 @@
+//! This module reads metric data points from the queue and inserts them into Clickhouse.
+
+use std::sync::Arc;
+
+use backoff::ExponentialBackoffBuilder;
+
+use crate::{
+    ch::metrics::{insert_metrics, CHMetric},
+    mq::{
+        dead_letter_delivery, MessageOrigin, MessageQueue, MessageQueueDeliveryTrait,
+        MessageQueueReceiverTrait, MessageQueueTrait,
+    },
+};
+
+use super::{QueueMetricsMessage, METRICS_EXCHANGE, METRICS_QUEUE, METRICS_ROUTING_KEY};
+
+pub async fn process_queue_metrics(clickhouse: clickhouse::Client, queue: Arc<MessageQueue>) {
+    loop {
+        inner_process_queue_metrics(clickhouse.clone(), queue.clone()).await;
+    }
+}
+
+async fn inner_process_queue_metrics(clickhouse: clickhouse::Client, queue: Arc<MessageQueue>) {
+    let mut receiver = queue
+        .get_receiver(METRICS_QUEUE, METRICS_EXCHANGE, METRICS_ROUTING_KEY)
+        .await
+        .unwrap();
+
+    let origin = MessageOrigin::new(METRICS_QUEUE, METRICS_EXCHANGE, METRICS_ROUTING_KEY);
+
+    while let Some(delivery) = receiver.receive().await {
+        if let Err(e) = delivery {
+            log::error!("Failed to receive message from queue: {:?}", e);
+            continue;
+        }
+        let delivery = delivery.unwrap();
+        let acker = delivery.acker();
+        let payload = delivery.data();
+        let message = match serde_json::from_slice::<QueueMetricsMessage>(&payload) {
+            Ok(message) => message,
+            Err(e) => {
+                log::error!("Failed to deserialize message from queue: {:?}", e);
+                let reason = format!("Failed to deserialize message: {}", e);
+                dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
+                continue;
+            }
+        };
+
+        let project_id = message.project_id;
+        let ch_metrics = message
+            .points
+            .into_iter()
+            .map(|point| CHMetric::from_metric_point(point, project_id))
+            .collect::<Vec<_>>();
+
+        let insert_metrics = || async {
+            insert_metrics(&clickhouse, &ch_metrics).await.map_err(|e| {
+                log::error!("Failed attempt to insert metrics. Will retry according to backoff policy. Error: {:?}", e);
+                backoff::Error::transient(e)
+            })?;
+
+            Ok::<(), backoff::Error<anyhow::Error>>(())
+        };
+        // Starting with 1 second delay, delay multiplies by random factor between 1 and 2
+        // up to 1 minute and until the total elapsed time is 1 minute
+        // https://docs.rs/backoff/latest/backoff/default/index.html
+        let exponential_backoff = ExponentialBackoffBuilder::new()
+            .with_initial_interval(std::time::Duration::from_millis(1000))
+            .with_multiplier(1.5)
+            .with_randomization_factor(0.5)
+            .with_max_interval(std::time::Duration::from_secs(1 * 60))
+            .with_max_elapsed_time(Some(std::time::Duration::from_secs(1 * 60)))
+            .build();
+
+        match backoff::future::retry(exponential_backoff, insert_metrics).await {
+            Ok(_) => {
+                if let Err(e) = acker.ack().await {
+                    log::error!("Failed to ack MQ delivery (metrics): {:?}", e);
+                }
+            }
+            Err(e) => {
+                log::error!(
+                    "Exhausted backoff retries. Failed to insert metrics: {:?}",
+                    e
+                );
+                let reason = format!("Failed to insert metrics: {:?}", e);
+                dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use crate::{
+    cache::Cache,
+    db::DB,
+    mq::MessageQueue,
+    opentelemetry::opentelemetry::proto::collector::metrics::v1::{
+        metrics_service_server::MetricsService, ExportMetricsServiceRequest,
+        ExportMetricsServiceResponse,
+    },
+    traces::grpc_service::authenticate_request,
+};
+use tonic::{Request, Response, Status};
+
+use super::producer::push_metrics_to_queue;
+
+pub struct ProcessMetricsService {
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    queue: Arc<MessageQueue>,
+}
+
+impl ProcessMetricsService {
+    pub fn new(db: Arc<DB>, cache: Arc<Cache>, queue: Arc<MessageQueue>) -> Self {
+        Self { db, cache, queue }
+    }
+}
+
+#[tonic::async_trait]
+impl MetricsService for ProcessMetricsService {
+    async fn export(
+        &self,
+        request: Request<ExportMetricsServiceRequest>,
+    ) -> Result<Response<ExportMetricsServiceResponse>, Status> {
+        let api_key = authenticate_request(request.metadata(), &self.db.pool, self.cache.clone())
+            .await
+            .map_err(|_| Status::unauthenticated("Failed to authenticate request"))?;
+        let project_id = api_key.project_id;
+        let request = request.into_inner();
+
+        let response = push_metrics_to_queue(request, project_id, self.queue.clone())
+            .await
+            .map_err(|e| {
+                log::error!("Failed to process metrics: {:?}", e);
+                // UNAVAILABLE is retryable by OTLP exporters
+                Status::unavailable("Failed to process metrics")
+            })?;
+
+        Ok(Response::new(response))
+    }
+}
@@ -0,0 +1,273 @@
+//! OpenTelemetry metrics ingestion.
+//!
+//! Metric data points are flattened into one row per data point and stored in the
+//! `metrics` ClickHouse table, so that they can be queried with the same aggregation
+//! and time-grouping machinery as trace metrics.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::utils::convert_any_value_to_json_value,
+    opentelemetry::{
+        opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest,
+        opentelemetry_proto_common_v1::KeyValue,
+        opentelemetry_proto_metrics_v1::{
+            metric::Data, number_data_point, DataPointFlags, HistogramDataPoint, NumberDataPoint,
+        },
+    },
+    traces::utils::json_value_to_string,
+};
+
+pub mod consumer;
+pub mod grpc_service;
+pub mod producer;
+
+pub const METRICS_QUEUE: &str = "metrics_queue";
+pub const METRICS_EXCHANGE: &str = "metrics_exchange";
+pub const METRICS_ROUTING_KEY: &str = "metrics_routing_key";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum MetricType {
+    Gauge,
+    Sum,
+    Histogram,
+    ExponentialHistogram,
+    Summary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MetricPoint {
+    pub name: String,
+    pub description: String,
+    pub unit: String,
+    pub metric_type: MetricType,
+    pub time: DateTime<Utc>,
+    pub start_time: Option<DateTime<Utc>>,
+    /// Value of gauges and sums. For histograms and summaries, this is the mean
+    /// of the observations, i.e. `sum / count`.
+    pub value: f64,
+    pub count: u64,
+    pub sum: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub bucket_counts: Vec<u64>,
+    pub explicit_bounds: Vec<f64>,
+    pub is_monotonic: bool,
+    pub aggregation_temporality: i32,
+    pub attributes: HashMap<String, String>,
+    pub resource_attributes: HashMap<String, String>,
+    pub scope_name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QueueMetricsMessage {
+    pub project_id: Uuid,
+    pub points: Vec<MetricPoint>,
+}
+
+/// Flatten an OTLP metrics export into individual data points.
+///
+/// Data points flagged with "no recorded value" are skipped.
+pub fn metric_points_from_otel(request: ExportMetricsServiceRequest) -> Vec<MetricPoint> {
+    let mut points = Vec::new();
+
+    for resource_metrics in request.resource_metrics {
+        let resource_attributes = resource_metrics
+            .resource
+            .map(|resource| attributes_to_map(resource.attributes))
+            .unwrap_or_default();
+
+        for scope_metrics in resource_metrics.scope_metrics {
+            let scope_name = scope_metrics
+                .scope
+                .map(|scope| scope.name)
+                .unwrap_or_default();
+
+            for metric in scope_metrics.metrics {
+                let base = MetricPoint {
+                    name: metric.name,
+                    description: metric.description,
+                    unit: metric.unit,
+                    metric_type: MetricType::Gauge,
+                    time: Utc::now(),
+                    start_time: None,
+                    value: 0.0,
+                    count: 0,
+                    sum: 0.0,
+                    min: None,
+                    max: None,
+                    bucket_counts: Vec::new(),
+                    explicit_bounds: Vec::new(),
+                    is_monotonic: false,
+                    aggregation_temporality: 0,
+                    attributes: HashMap::new(),
+                    resource_attributes: resource_attributes.clone(),
+                    scope_name: scope_name.clone(),
+                };
+
+                match metric.data {
+                    Some(Data::Gauge(gauge)) => {
+                        for data_point in gauge.data_points {
+                            if let Some(point) =
+                                number_data_point(&base, MetricType::Gauge, data_point)
+                            {
+                                points.push(point);
+                            }
+                        }
+                    }
+                    Some(Data::Sum(sum)) => {
+                        for data_point in sum.data_points {
+                            if let Some(mut point) =
+                                number_data_point(&base, MetricType::Sum, data_point)
+                            {
+                                point.is_monotonic = sum.is_monotonic;
+                                point.aggregation_temporality = sum.aggregation_temporality;
+                                points.push(point);
+                            }
+                        }
+                    }
+                    Some(Data::Histogram(histogram)) => {
+                        for data_point in histogram.data_points {
+                            if let Some(mut point) = histogram_data_point(&base, data_point) {
+                                point.aggregation_temporality = histogram.aggregation_temporality;
+                                points.push(point);
+                            }
+                        }
+                    }
+                    Some(Data::ExponentialHistogram(histogram)) => {
+                        // Exponential buckets are not stored, only the summary statistics
+                        for data_point in histogram.data_points {
+                            if has_no_recorded_value(data_point.flags) {
+                                continue;
+                            }
+                            let sum = data_point.sum.unwrap_or_default();
+                            points.push(MetricPoint {
+                                metric_type: MetricType::ExponentialHistogram,
+                                time: nanos_to_chrono(data_point.time_unix_nano),
+                                start_time: optional_nanos_to_chrono(
+                                    data_point.start_time_unix_nano,
+                                ),
+                                value: mean(sum, data_point.count),
+                                count: data_point.count,
+                                sum,
+                                min: data_point.min,
+                                max: data_point.max,
+                                aggregation_temporality: histogram.aggregation_temporality,
+                                attributes: attributes_to_map(data_point.attributes),
+                                ..base.clone()
+                            });
+                        }
+                    }
+                    Some(Data::Summary(summary)) => {
+                        for data_point in summary.data_points {
+                            if has_no_recorded_value(data_point.flags) {
+                                continue;
+                            }
+                            points.push(MetricPoint {
+                                metric_type: MetricType::Summary,
+                                time: nanos_to_chrono(data_point.time_unix_nano),
+                                start_time: optional_nanos_to_chrono(
+                                    data_point.start_time_unix_nano,
+                                ),
+                                value: mean(data_point.sum, data_point.count),
+                                count: data_point.count,
+                                sum: data_point.sum,
+                                attributes: attributes_to_map(data_point.attributes),
+                                ..base.clone()
+                            });
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    points
+}
+
+fn number_data_point(
+    base: &MetricPoint,
+    metric_type: MetricType,
+    data_point: NumberDataPoint,
+) -> Option<MetricPoint> {
+    if has_no_recorded_value(data_point.flags) {
+        return None;
+    }
+    let value = match data_point.value? {
+        number_data_point::Value::AsDouble(v) => v,
+        number_data_point::Value::AsInt(v) => v as f64,
+    };
+
+    Some(MetricPoint {
+        metric_type,
+        time: nanos_to_chrono(data_point.time_unix_nano),
+        start_time: optional_nanos_to_chrono(data_point.start_time_unix_nano),
+        value,
+        attributes: attributes_to_map(data_point.attributes),
+        ..base.clone()
+    })
+}
+
+fn histogram_data_point(base: &MetricPoint, data_point: HistogramDataPoint) -> Option<MetricPoint> {
+    if has_no_recorded_value(data_point.flags) {
+        return None;
+    }
+    let sum = data_point.sum.unwrap_or_default();
+
+    Some(MetricPoint {
+        metric_type: MetricType::Histogram,
+        time: nanos_to_chrono(data_point.time_unix_nano),
+        start_time: optional_nanos_to_chrono(data_point.start_time_unix_nano),
+        value: mean(sum, data_point.count),
+        count: data_point.count,
+        sum,
+        min: data_point.min,
+        max: data_point.max,
+        bucket_counts: data_point.bucket_counts,
+        explicit_bounds: data_point.explicit_bounds,
+        attributes: attributes_to_map(data_point.attributes),
+        ..base.clone()
+    })
+}
+
+fn has_no_recorded_value(flags: u32) -> bool {
+    flags & (DataPointFlags::NoRecordedValueMask as u32) != 0
+}
+
+fn mean(sum: f64, count: u64) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+fn nanos_to_chrono(nanos: u64) -> DateTime<Utc> {
+    Utc.timestamp_nanos(nanos as i64)
+}
+
+fn optional_nanos_to_chrono(nanos: u64) -> Option<DateTime<Utc>> {
+    if nanos == 0 {
+        None
+    } else {
+        Some(nanos_to_chrono(nanos))
+    }
+}
+
+fn attributes_to_map(attributes: Vec<KeyValue>) -> HashMap<String, String> {
+    attributes
+        .into_iter()
+        .filter(|kv| kv.value.as_ref().is_some_and(|v| v.value.is_some()))
+        .map(|kv| {
+            (
+                kv.key,
+                json_value_to_string(&convert_any_value_to_json_value(kv.value)),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::{
+    mq::{MessageQueue, MessageQueueTrait},
+    opentelemetry::opentelemetry::proto::collector::metrics::v1::{
+        ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+    },
+};
+
+use super::{metric_points_from_otel, QueueMetricsMessage, METRICS_EXCHANGE, METRICS_ROUTING_KEY};
+
+// Number of data points published in a single queue message
+const METRIC_POINTS_PER_MESSAGE: usize = 1000;
+
+pub async fn push_metrics_to_queue(
+    request: ExportMetricsServiceRequest,
+    project_id: Uuid,
+    queue: Arc<MessageQueue>,
+) -> Result<ExportMetricsServiceResponse> {
+    let points = metric_points_from_otel(request);
+
+    for chunk in points.chunks(METRIC_POINTS_PER_MESSAGE) {
+        let message = QueueMetricsMessage {
+            project_id,
+            points: chunk.to_vec(),
+        };
+
+        queue
+            .publish(
+                &serde_json::to_vec(&message)?,
+                METRICS_EXCHANGE,
+                METRICS_ROUTING_KEY,
+            )
+            .await?;
+    }
+
+    Ok(ExportMetricsServiceResponse {
+        partial_success: None,
+    })
+}
@@ -1,29 +1,43 @@
+use chrono::{DateTime, Utc};
 use enum_dispatch::enum_dispatch;
 use lapin::{
     acker::Acker,
     options::{BasicAckOptions, BasicNackOptions, BasicRejectOptions},
 };
+use serde::Deserialize;
+use uuid::Uuid;
 pub mod rabbit;
+pub mod redis_streams;
 pub mod tokio_mpsc;
 
 use rabbit::{RabbitMQ, RabbitMQDelivery, RabbitMQReceiver};
+use redis_streams::{
+    RedisStreamsAcker, RedisStreamsDelivery, RedisStreamsQueue, RedisStreamsReceiver,
+};
 use tokio_mpsc::{TokioMpscDelivery, TokioMpscQueue, TokioMpscReceiver};
 
+pub const DEAD_LETTER_QUEUE: &str = "dead_letter_queue";
+pub const DEAD_LETTER_EXCHANGE: &str = "dead_letter_exchange";
+pub const DEAD_LETTER_ROUTING_KEY: &str = "dead_letter_routing_key";
+
 #[enum_dispatch]
 pub enum MessageQueue {
     Rabbit(RabbitMQ),
+    RedisStreams(RedisStreamsQueue),
     TokioMpsc(TokioMpscQueue),
 }
 
 #[enum_dispatch]
 pub enum MessageQueueReceiver {
     Rabbit(RabbitMQReceiver),
+    RedisStreams(RedisStreamsReceiver),
     TokioMpsc(TokioMpscReceiver),
 }
 
 #[enum_dispatch]
 pub enum MessageQueueDelivery {
     Rabbit(RabbitMQDelivery),
+    RedisStreams(RedisStreamsDelivery),
     TokioMpsc(TokioMpscDelivery),
 }
 
@@ -34,6 +48,7 @@ pub trait MessageQueueReceiverTrait {
 
 pub enum MessageQueueAcker {
     RabbitAcker(Acker),
+    RedisStreamsAcker(RedisStreamsAcker),
     TokioMpscAcker,
 }
 
@@ -44,6 +59,7 @@ impl MessageQueueAcker {
                 Ok(_) => Ok(()),
                 Err(e) => Err(anyhow::anyhow!("Failed to ack message: {}", e)),
             },
+            Self::RedisStreamsAcker(acker) => acker.ack().await,
             Self::TokioMpscAcker => Ok(()),
         }
     }
@@ -61,6 +77,7 @@ impl MessageQueueAcker {
                 Ok(_) => Ok(()),
                 Err(e) => Err(anyhow::anyhow!("Failed to nack message: {}", e)),
             },
+            Self::RedisStreamsAcker(acker) => acker.nack(requeue).await,
             Self::TokioMpscAcker => Ok(()),
         }
     }
@@ -71,6 +88,7 @@ impl MessageQueueAcker {
                 Ok(_) => Ok(()),
                 Err(e) => Err(anyhow::anyhow!("Failed to reject message: {}", e)),
             },
+            Self::RedisStreamsAcker(acker) => acker.nack(requeue).await,
             Self::TokioMpscAcker => Ok(()),
         }
     }
@@ -97,4 +115,117 @@ pub trait MessageQueueTrait {
         exchange: &str,
         routing_key: &str,
     ) -> anyhow::Result<MessageQueueReceiver>;
+
+    /// Publish a message that could not be processed to the dead letter queue
+    async fn dead_letter(
+        &self,
+        message: &[u8],
+        origin: &MessageOrigin,
+        reason: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Get up to `limit` dead letters matching the filter, without removing them
+    async fn get_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        limit: usize,
+    ) -> anyhow::Result<Vec<DeadLetter>>;
+
+    /// Replay or purge the dead letters matching the filter.
+    /// Returns the number of dead letters that were resolved.
+    async fn resolve_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        action: DeadLetterAction,
+    ) -> anyhow::Result<usize>;
+}
+
+/// Queue, exchange and routing key a message was consumed from,
+/// so that it can be replayed from the dead letter queue.
+#[derive(Clone, Debug)]
+pub struct MessageOrigin {
+    pub queue: String,
+    pub exchange: String,
+    pub routing_key: String,
+}
+
+impl MessageOrigin {
+    pub fn new(queue: &str, exchange: &str, routing_key: &str) -> Self {
+        Self {
+            queue: queue.to_string(),
+            exchange: exchange.to_string(),
+            routing_key: routing_key.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    pub id: Uuid,
+    pub origin: MessageOrigin,
+    pub reason: String,
+    pub failed_at: DateTime<Utc>,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetterFilter {
+    /// Only dead letters that were consumed from this queue
+    pub queue: Option<String>,
+    /// Only dead letters with these ids
+    pub ids: Option<Vec<Uuid>>,
+}
+
+impl DeadLetterFilter {
+    pub fn matches(&self, dead_letter: &DeadLetter) -> bool {
+        self.queue
+            .as_ref()
+            .map_or(true, |queue| *queue == dead_letter.origin.queue)
+            && self
+                .ids
+                .as_ref()
+                .map_or(true, |ids| ids.contains(&dead_letter.id))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum DeadLetterAction {
+    /// Publish the message again to the exchange it was originally consumed from
+    Replay,
+    /// Drop the message
+    Purge,
+}
+
+/// Move a delivery that could not be processed to the dead letter queue and ack it.
+///
+/// If the message cannot be dead-lettered, the delivery is rejected.
+pub async fn dead_letter_delivery(
+    queue: &MessageQueue,
+    acker: &MessageQueueAcker,
+    message: &[u8],
+    origin: &MessageOrigin,
+    reason: &str,
+) {
+    match queue.dead_letter(message, origin, reason).await {
+        Ok(_) => {
+            if let Err(e) = acker.ack().await {
+                log::error!(
+                    "Failed to ack dead-lettered MQ delivery ({}): {:?}",
+                    origin.queue,
+                    e
+                );
+            }
+        }
+        Err(e) => {
+            log::error!(
+                "Failed to dead-letter MQ delivery ({}): {:?}",
+                origin.queue,
+                e
+            );
+            if let Err(e) = acker.reject(false).await {
+                log::error!("Failed to reject MQ delivery ({}): {:?}", origin.queue, e);
+            }
+        }
+    }
 }
@@ -1,18 +1,32 @@
-use deadpool::managed::{Manager, Pool, PoolError, RecycleError};
+use chrono::{DateTime, Utc};
+use deadpool::managed::{Manager, Object, Pool, PoolError, RecycleError};
 use futures::StreamExt;
 use lapin::{
     acker::Acker,
-    options::{BasicConsumeOptions, BasicPublishOptions, QueueBindOptions},
-    types::FieldTable,
+    message::Delivery,
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicGetOptions, BasicPublishOptions,
+        QueueBindOptions, QueueDeclareOptions,
+    },
+    types::{AMQPValue, FieldTable, ShortString},
     BasicProperties, Channel, Connection, Consumer,
 };
 use std::sync::Arc;
+use uuid::Uuid;
 
 use super::{
-    MessageQueueAcker, MessageQueueDelivery, MessageQueueDeliveryTrait, MessageQueueReceiver,
-    MessageQueueReceiverTrait, MessageQueueTrait,
+    DeadLetter, DeadLetterAction, DeadLetterFilter, MessageOrigin, MessageQueueAcker,
+    MessageQueueDelivery, MessageQueueDeliveryTrait, MessageQueueReceiver,
+    MessageQueueReceiverTrait, MessageQueueTrait, DEAD_LETTER_EXCHANGE, DEAD_LETTER_QUEUE,
+    DEAD_LETTER_ROUTING_KEY,
 };
 
+const ORIGINAL_QUEUE_HEADER: &str = "x-original-queue";
+const ORIGINAL_EXCHANGE_HEADER: &str = "x-original-exchange";
+const ORIGINAL_ROUTING_KEY_HEADER: &str = "x-original-routing-key";
+const FAILURE_REASON_HEADER: &str = "x-failure-reason";
+const FAILED_AT_HEADER: &str = "x-failed-at";
+
 struct RabbitChannelManager {
     connection: Arc<Connection>,
 }
@@ -99,6 +113,103 @@ impl RabbitMQ {
             publisher_channel_pool: pool,
         }
     }
+
+    async fn get_publisher_channel(&self) -> anyhow::Result<Object<RabbitChannelManager>> {
+        match self.publisher_channel_pool.get().await {
+            Ok(channel) => Ok(channel),
+            Err(PoolError::Backend(e)) => {
+                log::error!("Failed to get channel from pool: {}", e);
+                Err(anyhow::anyhow!("Failed to get channel from pool: {}", e))
+            }
+            Err(e) => {
+                log::error!("Pool error: {}", e);
+                Err(anyhow::anyhow!("Pool error: {}", e))
+            }
+        }
+    }
+}
+
+/// Iterates over the messages in the dead letter queue on a dedicated channel.
+///
+/// Messages are fetched without acking them. Messages that are not acked
+/// are returned to the queue when the scanner is closed.
+struct DeadLetterScanner {
+    channel: Channel,
+    remaining: u32,
+}
+
+impl DeadLetterScanner {
+    async fn new(connection: &Connection) -> anyhow::Result<Self> {
+        let channel = connection.create_channel().await?;
+
+        // Passive declare only reads the current message count
+        let queue = channel
+            .queue_declare(
+                DEAD_LETTER_QUEUE,
+                QueueDeclareOptions {
+                    passive: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+
+        Ok(Self {
+            channel,
+            remaining: queue.message_count(),
+        })
+    }
+
+    async fn next(&mut self) -> anyhow::Result<Option<(DeadLetter, Delivery)>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        let message = self
+            .channel
+            .basic_get(DEAD_LETTER_QUEUE, BasicGetOptions { no_ack: false })
+            .await?;
+
+        Ok(message.map(|message| {
+            let delivery = message.delivery;
+            (dead_letter_from_delivery(&delivery), delivery)
+        }))
+    }
+
+    async fn close(self) {
+        if let Err(e) = self.channel.close(200, "OK").await {
+            log::error!("Failed to close dead letter channel: {:?}", e);
+        }
+    }
+}
+
+fn dead_letter_from_delivery(delivery: &Delivery) -> DeadLetter {
+    let headers = delivery.properties.headers().clone().unwrap_or_default();
+    let header = |name: &str| match headers.inner().get(name) {
+        Some(AMQPValue::LongString(value)) => value.to_string(),
+        Some(AMQPValue::ShortString(value)) => value.to_string(),
+        _ => String::new(),
+    };
+
+    DeadLetter {
+        id: delivery
+            .properties
+            .message_id()
+            .as_ref()
+            .and_then(|id| Uuid::parse_str(id.as_str()).ok())
+            .unwrap_or_default(),
+        origin: MessageOrigin {
+            queue: header(ORIGINAL_QUEUE_HEADER),
+            exchange: header(ORIGINAL_EXCHANGE_HEADER),
+            routing_key: header(ORIGINAL_ROUTING_KEY_HEADER),
+        },
+        reason: header(FAILURE_REASON_HEADER),
+        failed_at: DateTime::parse_from_rfc3339(&header(FAILED_AT_HEADER))
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_default(),
+        payload: delivery.data.clone(),
+    }
 }
 
 impl MessageQueueTrait for RabbitMQ {
@@ -111,17 +222,7 @@ impl MessageQueueTrait for RabbitMQ {
         exchange: &str,
         routing_key: &str,
     ) -> anyhow::Result<()> {
-        let channel = match self.publisher_channel_pool.get().await {
-            Ok(channel) => channel,
-            Err(PoolError::Backend(e)) => {
-                log::error!("Failed to get channel from pool: {}", e);
-                return Err(anyhow::anyhow!("Failed to get channel from pool: {}", e));
-            }
-            Err(e) => {
-                log::error!("Pool error: {}", e);
-                return Err(anyhow::anyhow!("Pool error: {}", e));
-            }
-        };
+        let channel = self.get_publisher_channel().await?;
 
         channel
             .basic_publish(
@@ -166,4 +267,117 @@ impl MessageQueueTrait for RabbitMQ {
 
         Ok(RabbitMQReceiver { consumer }.into())
     }
+
+    /// Dead letters are published to the dead letter exchange explicitly, instead of
+    /// configuring `x-dead-letter-exchange` on the queues, so that the original queue
+    /// and the failure reason can be stored in the message headers.
+    async fn dead_letter(
+        &self,
+        message: &[u8],
+        origin: &MessageOrigin,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        let failed_at = Utc::now().to_rfc3339();
+        let mut headers = FieldTable::default();
+        for (name, value) in [
+            (ORIGINAL_QUEUE_HEADER, origin.queue.as_str()),
+            (ORIGINAL_EXCHANGE_HEADER, origin.exchange.as_str()),
+            (ORIGINAL_ROUTING_KEY_HEADER, origin.routing_key.as_str()),
+            (FAILURE_REASON_HEADER, reason),
+            (FAILED_AT_HEADER, failed_at.as_str()),
+        ] {
+            headers.insert(name.into(), AMQPValue::LongString(value.into()));
+        }
+        let properties = BasicProperties::default()
+            .with_message_id(ShortString::from(Uuid::new_v4().to_string()))
+            .with_headers(headers)
+            // persistent, so that dead letters survive broker restarts
+            .with_delivery_mode(2);
+
+        let channel = self.get_publisher_channel().await?;
+        channel
+            .basic_publish(
+                DEAD_LETTER_EXCHANGE,
+                DEAD_LETTER_ROUTING_KEY,
+                BasicPublishOptions::default(),
+                message,
+                properties,
+            )
+            .await?
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        limit: usize,
+    ) -> anyhow::Result<Vec<DeadLetter>> {
+        let mut dead_letters = Vec::new();
+        let mut scanner = DeadLetterScanner::new(&self.connection).await?;
+
+        while dead_letters.len() < limit {
+            match scanner.next().await {
+                Ok(Some((dead_letter, _))) => {
+                    if filter.matches(&dead_letter) {
+                        dead_letters.push(dead_letter);
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    scanner.close().await;
+                    return Err(e);
+                }
+            }
+        }
+        scanner.close().await;
+
+        Ok(dead_letters)
+    }
+
+    async fn resolve_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        action: DeadLetterAction,
+    ) -> anyhow::Result<usize> {
+        let mut resolved = 0;
+        let mut scanner = DeadLetterScanner::new(&self.connection).await?;
+
+        loop {
+            let (dead_letter, delivery) = match scanner.next().await {
+                Ok(Some(next)) => next,
+                Ok(None) => break,
+                Err(e) => {
+                    scanner.close().await;
+                    return Err(e);
+                }
+            };
+            if !filter.matches(&dead_letter) {
+                continue;
+            }
+            if let DeadLetterAction::Replay = action {
+                // Not acked on failure, so the message stays in the dead letter queue
+                if let Err(e) = self
+                    .publish(
+                        &dead_letter.payload,
+                        &dead_letter.origin.exchange,
+                        &dead_letter.origin.routing_key,
+                    )
+                    .await
+                {
+                    log::error!("Failed to replay dead letter {}: {:?}", dead_letter.id, e);
+                    continue;
+                }
+            }
+            if let Err(e) = delivery.acker.ack(BasicAckOptions::default()).await {
+                log::error!("Failed to ack dead letter {}: {:?}", dead_letter.id, e);
+                continue;
+            }
+            resolved += 1;
+        }
+        scanner.close().await;
+
+        Ok(resolved)
+    }
 }
@@ -0,0 +1,621 @@
+//! Durable message queue on Redis Streams.
+//!
+//! Every exchange is a stream, and every queue is a consumer group on that stream,
+//! so all queues bound to an exchange receive each message, and the receivers of
+//! a queue share its messages. Entries stay pending until they are acked, and pending
+//! entries of consumers that died are reclaimed by the other consumers of the group.
+//!
+//! Streams are only trimmed below the entries that have been acked by all their groups,
+//! so unconsumed entries are never lost. Publishing fails instead if the stream is full.
+
+use std::{collections::HashMap, env, time::Duration};
+
+use chrono::{DateTime, Utc};
+use redis::{
+    aio::MultiplexedConnection,
+    streams::{
+        StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamInfoConsumersReply,
+        StreamInfoGroupsReply, StreamPendingReply, StreamRangeReply, StreamReadOptions,
+        StreamReadReply,
+    },
+    AsyncCommands, Client,
+};
+use tokio::{sync::mpsc, time::Instant};
+use uuid::Uuid;
+
+use super::{
+    DeadLetter, DeadLetterAction, DeadLetterFilter, MessageOrigin, MessageQueueAcker,
+    MessageQueueDelivery, MessageQueueDeliveryTrait, MessageQueueReceiver,
+    MessageQueueReceiverTrait, MessageQueueTrait,
+};
+
+const STREAM_KEY_PREFIX: &str = "mq:";
+const DEAD_LETTER_STREAM: &str = "mq:dead_letters";
+const DATA_FIELD: &str = "data";
+// Requeued entries are only delivered to the group that requeued them
+const REQUEUE_GROUP_FIELD: &str = "requeue_group";
+
+const DEFAULT_MAX_STREAM_LENGTH: usize = 1_000_000;
+// Dead letters are not trimmed, they are only removed when they are resolved
+const MAX_DEAD_LETTERS: usize = 100_000;
+const DEFAULT_RECLAIM_IDLE_MS: usize = 5 * 60 * 1000;
+// How often a receiver looks for pending entries that can be reclaimed, and trims
+// the entries acked by all groups
+const RECLAIM_INTERVAL: Duration = Duration::from_secs(30);
+// Consumers without pending entries are removed from the group after this idle time
+const STALE_CONSUMER_IDLE_MS: usize = 60 * 60 * 1000;
+const READ_COUNT: usize = 100;
+const READ_BLOCK_MS: usize = 1000;
+const DEAD_LETTERS_PAGE_SIZE: usize = 1000;
+
+pub struct RedisStreamsQueue {
+    client: Client,
+    connection: MultiplexedConnection,
+    max_stream_length: usize,
+    reclaim_idle_ms: usize,
+}
+
+/// Receives the entries read by its reader task. Reading in a separate task makes
+/// `receive` cancel-safe: an entry that has been read from the group is never lost
+/// in a dropped read, where it would stay pending until it is reclaimed.
+pub struct RedisStreamsReceiver {
+    connection: MultiplexedConnection,
+    stream: String,
+    group: String,
+    entries: mpsc::Receiver<anyhow::Result<StreamId>>,
+}
+
+/// Reads the entries of a consumer of the group, until its receiver is dropped
+/// or the connection to Redis is lost
+struct RedisStreamsReader {
+    /// Dedicated connection, because blocking reads hold up every other command on it
+    read_connection: MultiplexedConnection,
+    connection: MultiplexedConnection,
+    stream: String,
+    group: String,
+    consumer: String,
+    reclaim_idle_ms: usize,
+    last_reclaim: Option<Instant>,
+    entries: mpsc::Sender<anyhow::Result<StreamId>>,
+}
+
+pub struct RedisStreamsDelivery {
+    acker: RedisStreamsAcker,
+    data: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct RedisStreamsAcker {
+    connection: MultiplexedConnection,
+    stream: String,
+    group: String,
+    id: String,
+}
+
+impl RedisStreamsAcker {
+    pub async fn ack(&self) -> anyhow::Result<()> {
+        let _: usize = self
+            .connection
+            .clone()
+            .xack(&self.stream, &self.group, &[&self.id])
+            .await?;
+        Ok(())
+    }
+
+    /// Requeued entries are added again at the end of the stream, so that they are
+    /// redelivered right away. Other entries are acked and thus dropped.
+    pub async fn nack(&self, requeue: bool) -> anyhow::Result<()> {
+        if !requeue {
+            return self.ack().await;
+        }
+
+        let mut connection = self.connection.clone();
+        let reply: StreamRangeReply = connection
+            .xrange_count(&self.stream, &self.id, &self.id, 1)
+            .await?;
+        let Some(entry) = reply.ids.into_iter().next() else {
+            // The entry has been deleted, there is nothing to requeue
+            return self.ack().await;
+        };
+        let data = entry.get::<Vec<u8>>(DATA_FIELD).unwrap_or_default();
+        let fields: [(&str, &[u8]); 2] = [
+            (DATA_FIELD, &data),
+            (REQUEUE_GROUP_FIELD, self.group.as_bytes()),
+        ];
+        // Add the copy and ack the original atomically, so that the entry is neither
+        // lost nor duplicated
+        let _: (String, usize) = redis::pipe()
+            .atomic()
+            .xadd(&self.stream, "*", &fields)
+            .xack(&self.stream, &self.group, &[&self.id])
+            .query_async(&mut connection)
+            .await?;
+        Ok(())
+    }
+}
+
+impl MessageQueueDeliveryTrait for RedisStreamsDelivery {
+    fn acker(&self) -> MessageQueueAcker {
+        MessageQueueAcker::RedisStreamsAcker(self.acker.clone())
+    }
+
+    fn data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl RedisStreamsReceiver {
+    fn delivery(&self, entry: StreamId) -> RedisStreamsDelivery {
+        RedisStreamsDelivery {
+            acker: RedisStreamsAcker {
+                connection: self.connection.clone(),
+                stream: self.stream.clone(),
+                group: self.group.clone(),
+                id: entry.id.clone(),
+            },
+            data: entry.get::<Vec<u8>>(DATA_FIELD).unwrap_or_default(),
+        }
+    }
+}
+
+impl MessageQueueReceiverTrait for RedisStreamsReceiver {
+    async fn receive(&mut self) -> Option<anyhow::Result<MessageQueueDelivery>> {
+        // The reader task stops when the connection is lost, and the receiver is then
+        // recreated by the consumer
+        let entry = self.entries.recv().await?;
+        Some(entry.map(|entry| self.delivery(entry).into()))
+    }
+}
+
+impl RedisStreamsReader {
+    async fn run(mut self) {
+        while !self.entries.is_closed() {
+            if self.last_reclaim.map_or(true, |last_reclaim| {
+                last_reclaim.elapsed() >= RECLAIM_INTERVAL
+            }) {
+                self.last_reclaim = Some(Instant::now());
+                if let Err(e) = self.trim_acked().await {
+                    log::error!(
+                        "Failed to trim acked entries of stream {}: {:?}",
+                        self.stream,
+                        e
+                    );
+                }
+                match self.reclaim().await {
+                    Ok(claimed) => {
+                        if !self.send(claimed).await {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        log::error!(
+                            "Failed to reclaim pending entries of stream {} ({}): {:?}",
+                            self.stream,
+                            self.group,
+                            e
+                        );
+                    }
+                }
+            }
+
+            let options = StreamReadOptions::default()
+                .group(&self.group, &self.consumer)
+                .count(READ_COUNT)
+                .block(READ_BLOCK_MS);
+            let reply: redis::RedisResult<Option<StreamReadReply>> = self
+                .read_connection
+                .xread_options(&[&self.stream], &[">"], &options)
+                .await;
+            match reply {
+                Ok(Some(reply)) => {
+                    for key in reply.keys {
+                        if !self.send(key.ids).await {
+                            return;
+                        }
+                    }
+                }
+                // Blocking read timed out
+                Ok(None) => {}
+                Err(e) if e.is_connection_dropped() || e.is_io_error() => {
+                    log::error!("Lost connection to Redis ({}): {:?}", self.group, e);
+                    return;
+                }
+                Err(e) => {
+                    let error =
+                        anyhow::anyhow!("Failed to read from stream {}: {}", self.stream, e);
+                    if self.entries.send(Err(error)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns false if the receiver has been dropped, in which case the entries
+    /// stay pending until they are reclaimed
+    async fn send(&mut self, entries: Vec<StreamId>) -> bool {
+        for entry in entries {
+            if entry
+                .get::<String>(REQUEUE_GROUP_FIELD)
+                .is_some_and(|group| group != self.group)
+            {
+                // Entry requeued by another group
+                let result: redis::RedisResult<usize> = self
+                    .connection
+                    .xack(&self.stream, &self.group, &[&entry.id])
+                    .await;
+                if let Err(e) = result {
+                    log::error!(
+                        "Failed to ack entry requeued by another group ({}): {:?}",
+                        self.group,
+                        e
+                    );
+                }
+                continue;
+            }
+            if self.entries.send(Ok(entry)).await.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Claim the entries that have been pending for too long, e.g. because the consumer
+    /// that read them has died, and remove stale consumers from the group
+    async fn reclaim(&mut self) -> anyhow::Result<Vec<StreamId>> {
+        let reply: StreamAutoClaimReply = self
+            .connection
+            .xautoclaim_options(
+                &self.stream,
+                &self.group,
+                &self.consumer,
+                self.reclaim_idle_ms,
+                "0-0",
+                StreamAutoClaimOptions::default().count(READ_COUNT),
+            )
+            .await?;
+        if !reply.claimed.is_empty() {
+            log::info!(
+                "Reclaimed {} pending entries of stream {} ({})",
+                reply.claimed.len(),
+                self.stream,
+                self.group
+            );
+        }
+
+        let info: StreamInfoConsumersReply = self
+            .connection
+            .xinfo_consumers(&self.stream, &self.group)
+            .await?;
+        for consumer in info.consumers {
+            if consumer.name != self.consumer
+                && consumer.pending == 0
+                && consumer.idle > STALE_CONSUMER_IDLE_MS
+            {
+                let _: usize = self
+                    .connection
+                    .xgroup_delconsumer(&self.stream, &self.group, &consumer.name)
+                    .await?;
+            }
+        }
+        Ok(reply.claimed)
+    }
+
+    /// Remove the entries that have been acked by all groups of the stream, i.e. the
+    /// entries before the oldest entry that is pending or not yet delivered in any group
+    async fn trim_acked(&mut self) -> anyhow::Result<()> {
+        let info: StreamInfoGroupsReply = self.connection.xinfo_groups(&self.stream).await?;
+        let mut min_id: Option<StreamEntryId> = None;
+        for group in info.groups {
+            // The pending entries are read after the last delivered id, so every entry
+            // up to that id that is not pending has been acked
+            let pending: StreamPendingReply =
+                self.connection.xpending(&self.stream, &group.name).await?;
+            let group_min_id = match pending {
+                StreamPendingReply::Data(pending) => pending.start_id,
+                StreamPendingReply::Empty => group.last_delivered_id,
+            };
+            let Some(group_min_id) = StreamEntryId::parse(&group_min_id) else {
+                return Ok(());
+            };
+            if min_id.map_or(true, |min_id| group_min_id < min_id) {
+                min_id = Some(group_min_id);
+            }
+        }
+        let Some(min_id) = min_id else {
+            // Without groups, nothing has been consumed
+            return Ok(());
+        };
+
+        // Approximate trimming removes fewer entries, never more
+        let _: usize = redis::cmd("XTRIM")
+            .arg(&self.stream)
+            .arg("MINID")
+            .arg("~")
+            .arg(min_id.to_string())
+            .query_async(&mut self.connection)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Stream entry id, `<milliseconds>-<sequence>`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct StreamEntryId(u64, u64);
+
+impl StreamEntryId {
+    fn parse(id: &str) -> Option<Self> {
+        let (ms, seq) = id.split_once('-')?;
+        Some(Self(ms.parse().ok()?, seq.parse().ok()?))
+    }
+}
+
+impl std::fmt::Display for StreamEntryId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.0, self.1)
+    }
+}
+
+impl RedisStreamsQueue {
+    /// Publishing fails while a stream has REDIS_STREAMS_MAX_LENGTH entries that have not
+    /// been acked by all its groups, and entries that are pending for longer than
+    /// REDIS_STREAMS_RECLAIM_IDLE_MS are redelivered
+    pub async fn new(redis_url: &str) -> anyhow::Result<Self> {
+        let client = Client::open(redis_url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        let max_stream_length = env::var("REDIS_STREAMS_MAX_LENGTH")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_STREAM_LENGTH);
+        let reclaim_idle_ms = env::var("REDIS_STREAMS_RECLAIM_IDLE_MS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_RECLAIM_IDLE_MS);
+
+        Ok(Self {
+            client,
+            connection,
+            max_stream_length,
+            reclaim_idle_ms,
+        })
+    }
+
+    fn stream_key(&self, exchange: &str) -> String {
+        format!("{}{}", STREAM_KEY_PREFIX, exchange)
+    }
+
+    /// Fail rather than trim entries that have not been consumed yet
+    async fn check_stream_length(&self, stream: &str, max_length: usize) -> anyhow::Result<()> {
+        let length: usize = self.connection.clone().xlen(stream).await?;
+        if length >= max_length {
+            log::error!(
+                "Stream {} is full ({} entries), messages are not consumed fast enough",
+                stream,
+                length
+            );
+            return Err(anyhow::anyhow!(
+                "Stream {} has reached its maximum length of {} entries",
+                stream,
+                max_length
+            ));
+        }
+        Ok(())
+    }
+
+    /// Get dead letters page by page, starting after `after` (exclusive)
+    async fn get_dead_letters_page(
+        &self,
+        after: Option<&str>,
+    ) -> anyhow::Result<Vec<(String, DeadLetter)>> {
+        let start = after.map_or("-".to_string(), |id| format!("({}", id));
+        let reply: StreamRangeReply = self
+            .connection
+            .clone()
+            .xrange_count(DEAD_LETTER_STREAM, start, "+", DEAD_LETTERS_PAGE_SIZE)
+            .await?;
+
+        Ok(reply
+            .ids
+            .into_iter()
+            .filter_map(|entry| {
+                let dead_letter = parse_dead_letter(&entry);
+                if dead_letter.is_none() {
+                    log::warn!("Skipping malformed dead letter entry {}", entry.id);
+                }
+                dead_letter.map(|dead_letter| (entry.id, dead_letter))
+            })
+            .collect())
+    }
+}
+
+fn parse_dead_letter(entry: &StreamId) -> Option<DeadLetter> {
+    let failed_at = entry.get::<String>("failed_at")?;
+    Some(DeadLetter {
+        id: entry.get::<String>("id")?.parse().ok()?,
+        origin: MessageOrigin {
+            queue: entry.get("queue")?,
+            exchange: entry.get("exchange")?,
+            routing_key: entry.get("routing_key")?,
+        },
+        reason: entry.get("reason").unwrap_or_default(),
+        failed_at: DateTime::parse_from_rfc3339(&failed_at)
+            .ok()?
+            .with_timezone(&Utc),
+        payload: entry.get(DATA_FIELD).unwrap_or_default(),
+    })
+}
+
+impl MessageQueueTrait for RedisStreamsQueue {
+    async fn publish(
+        &self,
+        message: &[u8],
+        exchange: &str,
+        _routing_key: &str,
+    ) -> anyhow::Result<()> {
+        let stream = self.stream_key(exchange);
+        self.check_stream_length(&stream, self.max_stream_length)
+            .await?;
+        let _: String = self
+            .connection
+            .clone()
+            .xadd(&stream, "*", &[(DATA_FIELD, message)])
+            .await?;
+        Ok(())
+    }
+
+    async fn get_receiver(
+        &self,
+        queue_name: &str,
+        exchange: &str,
+        _routing_key: &str,
+    ) -> anyhow::Result<MessageQueueReceiver> {
+        let stream = self.stream_key(exchange);
+        let result: redis::RedisResult<()> = self
+            .connection
+            .clone()
+            .xgroup_create_mkstream(&stream, queue_name, "0")
+            .await;
+        match result {
+            Ok(_) => {}
+            // The group has already been created by another receiver
+            Err(e) if e.code() == Some("BUSYGROUP") => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let read_connection = self.client.get_multiplexed_async_connection().await?;
+        let (sender, entries) = mpsc::channel(READ_COUNT);
+        let reader = RedisStreamsReader {
+            read_connection,
+            connection: self.connection.clone(),
+            stream: stream.clone(),
+            group: queue_name.to_string(),
+            consumer: format!("{}-{}", queue_name, Uuid::new_v4()),
+            reclaim_idle_ms: self.reclaim_idle_ms,
+            last_reclaim: None,
+            entries: sender,
+        };
+        tokio::spawn(reader.run());
+
+        Ok(RedisStreamsReceiver {
+            connection: self.connection.clone(),
+            stream,
+            group: queue_name.to_string(),
+            entries,
+        }
+        .into())
+    }
+
+    async fn dead_letter(
+        &self,
+        message: &[u8],
+        origin: &MessageOrigin,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        let id = Uuid::new_v4().to_string();
+        let failed_at = Utc::now().to_rfc3339();
+        let fields: [(&str, &[u8]); 7] = [
+            ("id", id.as_bytes()),
+            ("queue", origin.queue.as_bytes()),
+            ("exchange", origin.exchange.as_bytes()),
+            ("routing_key", origin.routing_key.as_bytes()),
+            ("reason", reason.as_bytes()),
+            ("failed_at", failed_at.as_bytes()),
+            (DATA_FIELD, message),
+        ];
+        self.check_stream_length(DEAD_LETTER_STREAM, MAX_DEAD_LETTERS)
+            .await?;
+        let _: String = self
+            .connection
+            .clone()
+            .xadd(DEAD_LETTER_STREAM, "*", &fields)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        limit: usize,
+    ) -> anyhow::Result<Vec<DeadLetter>> {
+        let mut dead_letters = Vec::new();
+        let mut after: Option<String> = None;
+        while dead_letters.len() < limit {
+            let page = self.get_dead_letters_page(after.as_deref()).await?;
+            let Some((last_id, _)) = page.last() else {
+                break;
+            };
+            after = Some(last_id.clone());
+            dead_letters.extend(
+                page.into_iter()
+                    .map(|(_, dead_letter)| dead_letter)
+                    .filter(|dead_letter| filter.matches(dead_letter)),
+            );
+        }
+        dead_letters.truncate(limit);
+        Ok(dead_letters)
+    }
+
+    async fn resolve_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        action: DeadLetterAction,
+    ) -> anyhow::Result<usize> {
+        // Collect first, so that replayed messages that fail again are not resolved twice
+        let mut matching: HashMap<String, DeadLetter> = HashMap::new();
+        let mut after: Option<String> = None;
+        loop {
+            let page = self.get_dead_letters_page(after.as_deref()).await?;
+            let Some((last_id, _)) = page.last() else {
+                break;
+            };
+            after = Some(last_id.clone());
+            matching.extend(
+                page.into_iter()
+                    .filter(|(_, dead_letter)| filter.matches(dead_letter)),
+            );
+        }
+
+        let mut resolved = 0;
+        for (entry_id, dead_letter) in matching {
+            if let DeadLetterAction::Replay = action {
+                if let Err(e) = self
+                    .publish(
+                        &dead_letter.payload,
+                        &dead_letter.origin.exchange,
+                        &dead_letter.origin.routing_key,
+                    )
+                    .await
+                {
+                    log::error!("Failed to replay dead letter {}: {:?}", dead_letter.id, e);
+                    continue;
+                }
+            }
+            let _: usize = self
+                .connection
+                .clone()
+                .xdel(DEAD_LETTER_STREAM, &[&entry_id])
+                .await?;
+            resolved += 1;
+        }
+
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_entry_id_order() {
+        // Ids are compared by their numbers, not as strings
+        let earlier = StreamEntryId::parse("9-5").unwrap();
+        let later = StreamEntryId::parse("10-0").unwrap();
+        assert!(earlier < later);
+        assert!(StreamEntryId::parse("10-0").unwrap() < StreamEntryId::parse("10-1").unwrap());
+        assert_eq!(later.to_string(), "10-0");
+
+        assert!(StreamEntryId::parse("10").is_none());
+        assert!(StreamEntryId::parse("a-0").is_none());
+    }
+}
@@ -1,13 +1,19 @@
 use super::{
-    MessageQueueAcker, MessageQueueDelivery, MessageQueueDeliveryTrait, MessageQueueReceiver,
+    DeadLetter, DeadLetterAction, DeadLetterFilter, MessageOrigin, MessageQueueAcker,
+    MessageQueueDelivery, MessageQueueDeliveryTrait, MessageQueueReceiver,
     MessageQueueReceiverTrait, MessageQueueTrait,
 };
+use chrono::Utc;
 use dashmap::DashMap;
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 use tokio::sync::{
     mpsc::{self, Receiver, Sender},
     Mutex,
 };
+use uuid::Uuid;
+
+// Dead letters are kept in memory, so the oldest ones are dropped above this limit
+const MAX_DEAD_LETTERS: usize = 10_000;
 
 // TODO: Possibly think about how to generalize the inner type with any
 // `T: Clone + Serialize + Deserialize + Send + Sync`
@@ -42,13 +48,29 @@ impl MessageQueueReceiverTrait for TokioMpscReceiver {
 
 pub struct TokioMpscQueue {
     senders: DashMap<String, Arc<Mutex<Vec<Sender<Vec<u8>>>>>>,
+    dead_letters: Mutex<VecDeque<DeadLetter>>,
 }
 
 impl TokioMpscQueue {
     pub fn new() -> Self {
         Self {
             senders: DashMap::new(),
+            dead_letters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    async fn push_dead_letter(&self, dead_letter: DeadLetter) {
+        let mut dead_letters = self.dead_letters.lock().await;
+        if dead_letters.len() >= MAX_DEAD_LETTERS {
+            if let Some(dropped) = dead_letters.pop_front() {
+                log::warn!(
+                    "Dead letter queue is full. Dropping dead letter {} ({})",
+                    dropped.id,
+                    dropped.origin.queue
+                );
+            }
         }
+        dead_letters.push_back(dead_letter);
     }
 
     fn key(&self, exchange: &str, routing_key: &str) -> String {
@@ -116,4 +138,73 @@ impl MessageQueueTrait for TokioMpscQueue {
             .push(sender);
         Ok(tokio_mpsc_receiver.into())
     }
+
+    async fn dead_letter(
+        &self,
+        message: &[u8],
+        origin: &MessageOrigin,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        self.push_dead_letter(DeadLetter {
+            id: Uuid::new_v4(),
+            origin: origin.clone(),
+            reason: reason.to_string(),
+            failed_at: Utc::now(),
+            payload: message.to_vec(),
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn get_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        limit: usize,
+    ) -> anyhow::Result<Vec<DeadLetter>> {
+        Ok(self
+            .dead_letters
+            .lock()
+            .await
+            .iter()
+            .filter(|dead_letter| filter.matches(dead_letter))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn resolve_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        action: DeadLetterAction,
+    ) -> anyhow::Result<usize> {
+        let matching = {
+            let mut dead_letters = self.dead_letters.lock().await;
+            let (matching, rest) = dead_letters
+                .drain(..)
+                .partition::<Vec<_>, _>(|dead_letter| filter.matches(dead_letter));
+            *dead_letters = rest.into();
+            matching
+        };
+
+        let mut resolved = 0;
+        for dead_letter in matching {
+            if let DeadLetterAction::Replay = action {
+                if let Err(e) = self
+                    .publish(
+                        &dead_letter.payload,
+                        &dead_letter.origin.exchange,
+                        &dead_letter.origin.routing_key,
+                    )
+                    .await
+                {
+                    log::error!("Failed to replay dead letter {}: {:?}", dead_letter.id, e);
+                    self.push_dead_letter(dead_letter).await;
+                    continue;
+                }
+            }
+            resolved += 1;
+        }
+
+        Ok(resolved)
+    }
 }
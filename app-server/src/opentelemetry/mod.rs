@@ -2,6 +2,16 @@
 pub mod opentelemetry {
     pub mod proto {
         pub mod collector {
+            pub mod logs {
+                pub mod v1 {
+                    include!("opentelemetry.proto.collector.logs.v1.rs");
+                }
+            }
+            pub mod metrics {
+                pub mod v1 {
+                    include!("opentelemetry.proto.collector.metrics.v1.rs");
+                }
+            }
             pub mod trace {
                 pub mod v1 {
                     include!("opentelemetry.proto.collector.trace.v1.rs");
@@ -13,6 +23,12 @@ pub mod opentelemetry {
 pub mod opentelemetry_proto_common_v1 {
     include!("opentelemetry_proto_common_v1.rs");
 }
+pub mod opentelemetry_proto_logs_v1 {
+    include!("opentelemetry_proto_logs_v1.rs");
+}
+pub mod opentelemetry_proto_metrics_v1 {
+    include!("opentelemetry_proto_metrics_v1.rs");
+}
 pub mod opentelemetry_proto_resource_v1 {
     include!("opentelemetry_proto_resource_v1.rs");
 }
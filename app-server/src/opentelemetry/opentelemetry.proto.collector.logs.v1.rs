@@ -0,0 +1,236 @@
+// This file is @generated by prost-build.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportLogsServiceRequest {
+    /// An array of ResourceLogs.
+    /// For data coming from a single resource this array will typically contain one
+    /// element. Intermediary nodes (such as OpenTelemetry Collector) that receive
+    /// data from multiple origins typically batch the data before forwarding further and
+    /// in that case this array will contain multiple elements.
+    #[prost(message, repeated, tag = "1")]
+    pub resource_logs: ::prost::alloc::vec::Vec<
+        super::super::super::super::super::opentelemetry_proto_logs_v1::ResourceLogs,
+    >,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportLogsServiceResponse {
+    /// The details of a partially successful export request.
+    ///
+    /// If the request is only partially accepted
+    /// (i.e. when the server accepts only parts of the data and rejects the rest)
+    /// the server MUST initialize the `partial_success` field and MUST
+    /// set the `rejected_<signal>` with the number of items it rejected.
+    ///
+    /// Servers MAY also make use of the `partial_success` field to convey
+    /// warnings/suggestions to senders even when the request was fully accepted.
+    /// In such cases, the `rejected_<signal>` MUST have a value of `0` and
+    /// the `error_message` MUST be non-empty.
+    ///
+    /// A `partial_success` message with an empty value (rejected_<signal> = 0 and
+    /// `error_message` = "") is equivalent to it not being set/present. Senders
+    /// SHOULD interpret it the same way as in the full success case.
+    #[prost(message, optional, tag = "1")]
+    pub partial_success: ::core::option::Option<ExportLogsPartialSuccess>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportLogsPartialSuccess {
+    /// The number of rejected log records.
+    ///
+    /// A `rejected_<signal>` field holding a `0` value indicates that the
+    /// request was fully accepted.
+    #[prost(int64, tag = "1")]
+    pub rejected_log_records: i64,
+    /// A developer-facing human-readable message in English. It should be used
+    /// either to explain why the server rejected parts of the data during a partial
+    /// success or to convey warnings/suggestions during a full success. The message
+    /// should offer guidance on how users can address such issues.
+    ///
+    /// error_message is an optional field. An error_message with an empty value
+    /// is equivalent to it not being set.
+    #[prost(string, tag = "2")]
+    pub error_message: ::prost::alloc::string::String,
+}
+/// Generated server implementations.
+pub mod logs_service_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with LogsServiceServer.
+    #[async_trait]
+    pub trait LogsService: std::marker::Send + std::marker::Sync + 'static {
+        /// For performance reasons, it is recommended to keep this RPC
+        /// alive for the entire life of the application.
+        async fn export(
+            &self,
+            request: tonic::Request<super::ExportLogsServiceRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExportLogsServiceResponse>,
+            tonic::Status,
+        >;
+    }
+    /// Service that can be used to push logs between one Application instrumented with
+    /// OpenTelemetry and an collector, or between an collector and a central collector (in this
+    /// case logs are sent/received to/from multiple Applications).
+    #[derive(Debug)]
+    pub struct LogsServiceServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> LogsServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for LogsServiceServer<T>
+    where
+        T: LogsService,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/opentelemetry.proto.collector.logs.v1.LogsService/Export" => {
+                    #[allow(non_camel_case_types)]
+                    struct ExportSvc<T: LogsService>(pub Arc<T>);
+                    impl<
+                        T: LogsService,
+                    > tonic::server::UnaryService<super::ExportLogsServiceRequest>
+                    for ExportSvc<T> {
+                        type Response = super::ExportLogsServiceResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ExportLogsServiceRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as LogsService>::export(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ExportSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(empty_body());
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for LogsServiceServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "opentelemetry.proto.collector.logs.v1.LogsService";
+    impl<T> tonic::server::NamedService for LogsServiceServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
@@ -9,7 +9,7 @@ use crate::{
     semantic_search::SemanticSearch,
 };
 
-use super::{nodes::StreamChunk, runner::PipelineRunner, RunType};
+use super::{nodes::StreamChunk, runner::PipelineRunner, GraphConcurrency, RunType};
 
 pub struct Context {
     pub language_model: Arc<LanguageModelRunner>,
@@ -26,4 +26,7 @@ pub struct Context {
     pub code_executor: Arc<CodeExecutor>,
     pub db: Arc<DB>,
     pub cache: Arc<Cache>,
+    pub concurrency: GraphConcurrency,
+    /// Project the run belongs to, under which its LLM calls are rate limited
+    pub project_id: Uuid,
 }
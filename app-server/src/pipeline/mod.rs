@@ -7,6 +7,7 @@ use thiserror::Error;
 use uuid::Uuid;
 
 use self::nodes::{Node, NodeInput};
+use crate::engine::NodePolicy;
 use crate::language_model::providers::utils::get_required_env_vars_for_model;
 
 pub mod context;
@@ -20,6 +21,11 @@ pub mod utils;
 pub struct Graph {
     pub nodes: HashMap<String, Node>,
     pub pred: HashMap<Uuid, Vec<Uuid>>,
+    /// Retry, timeout and fallback policies of the nodes, by node id
+    #[serde(default)]
+    pub policies: HashMap<Uuid, NodePolicy>,
+    #[serde(default)]
+    pub concurrency: GraphConcurrency,
     #[serde(skip)]
     pub env: HashMap<String, String>,
     #[serde(skip)]
@@ -28,6 +34,35 @@ pub struct Graph {
     pub run_type: RunType,
 }
 
+/// Concurrency limits of a run of the graph
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphConcurrency {
+    /// Number of nodes that run at the same time
+    #[serde(default = "default_max_concurrent_nodes")]
+    pub max_concurrent_nodes: usize,
+    /// Number of subpipeline runs that each Map node of the graph runs at the same time
+    #[serde(default = "default_max_concurrent_map_runs")]
+    pub max_concurrent_map_runs: usize,
+}
+
+fn default_max_concurrent_nodes() -> usize {
+    20
+}
+
+fn default_max_concurrent_map_runs() -> usize {
+    50
+}
+
+impl Default for GraphConcurrency {
+    fn default() -> Self {
+        Self {
+            max_concurrent_nodes: default_max_concurrent_nodes(),
+            max_concurrent_map_runs: default_max_concurrent_map_runs(),
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum GraphError {
     #[error("Graph input is missing: {0}")]
@@ -163,6 +198,12 @@ impl Graph {
                         serde_json::from_value::<Graph>(map_node.runnable_graph.clone()).unwrap();
                     env_vars.extend(subgraph.get_required_env_vars());
                 }
+                Node::Loop(loop_node) => {
+                    // Note: Not efficient, but ok for now
+                    let subgraph =
+                        serde_json::from_value::<Graph>(loop_node.runnable_graph.clone()).unwrap();
+                    env_vars.extend(subgraph.get_required_env_vars());
+                }
                 // Listing nodes explicitly here to avoid missing a node type, when adding new nodes
                 Node::Condition(_)
                 | Node::Extractor(_)
@@ -138,6 +138,7 @@ impl RunnableNode for LLMNode {
             messages.push(ChatMessage {
                 role: String::from("system"),
                 content: ChatMessageContent::Text(prompt.clone()),
+                ..Default::default()
             });
         }
 
@@ -188,6 +189,7 @@ impl RunnableNode for LLMNode {
                     &env_vars,
                     tx.clone(),
                     &node_info,
+                    Some(&context.project_id),
                     db.clone(),
                     cache.clone(),
                 )
@@ -225,6 +227,7 @@ impl RunnableNode for LLMNode {
                     ChatMessage {
                         role: String::from("assistant"),
                         content: ChatMessageContent::Text(response_message.clone()),
+                        ..Default::default()
                     },
                     ChatMessage {
                         role: String::from("user"),
@@ -232,6 +235,7 @@ impl RunnableNode for LLMNode {
                             "Json schema validation failed with error: {}\n\nPlease retry",
                             structured_output.as_ref().err().unwrap().to_string(),
                         )),
+                        ..Default::default()
                     },
                 ]);
             } else {
@@ -281,10 +285,20 @@ impl LLMNode {
         };
 
         if enable_chat_message_output {
+            let message = completion.message();
+            // Tool calls are kept structured in chat message outputs, instead of rendered
+            // as the text of the message
+            let content = if message.text().is_empty() && !message.tool_calls.is_empty() {
+                String::new()
+            } else {
+                result
+            };
             let mut response_chat_messages = input_messages;
             response_chat_messages.push(ChatMessage {
                 role: "assistant".to_string(),
-                content: ChatMessageContent::Text(result),
+                content: ChatMessageContent::Text(content),
+                tool_calls: message.tool_calls.clone(),
+                tool_call_id: None,
             });
 
             RunOutput::Success((response_chat_messages.into(), Some(MetaLog::LLM(meta_log))))
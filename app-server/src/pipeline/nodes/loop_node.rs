@@ -0,0 +1,459 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    engine::{RunOutput, RunnableNode},
+    language_model::{ChatMessageContent, ChatMessageContentPart},
+    pipeline::{
+        context::Context,
+        trace::{MetaLog, RunTraceStats},
+        Graph,
+    },
+};
+
+use super::{utils::map_handles, Handle, Message, Node, NodeInput};
+
+/// Upper bound of `max_iterations`, so that a loop cannot run indefinitely
+pub const MAX_LOOP_ITERATIONS: u32 = 100;
+
+/// Runs its body subpipeline repeatedly, feeding the output of each iteration to the next one,
+/// until the break condition holds or `max_iterations` is reached
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopNode {
+    pub id: Uuid,
+    pub name: String,
+    pub inputs: Vec<Handle>,
+    pub outputs: Vec<Handle>,
+    pub inputs_mappings: HashMap<Uuid, Uuid>,
+    // Names are for displaying these values in the frontend
+    pub pipeline_name: String,
+    #[serde(default)]
+    pub pipeline_id: Option<Uuid>,
+    pub pipeline_version_name: String,
+    // Commit pipeline version id, must be immutable
+    #[serde(default)]
+    pub pipeline_version_id: Option<Uuid>,
+    pub runnable_graph: Value,
+    /// Input node of the body that receives the output of the previous iteration.
+    /// Chat message outputs are appended to the messages of the previous iteration instead.
+    /// The other input nodes of the body receive the same inputs in every iteration
+    pub loop_input_name: String,
+    pub max_iterations: u32,
+    /// If None, the body runs `max_iterations` times
+    #[serde(default)]
+    pub break_condition: Option<LoopBreakCondition>,
+    /// Whether the node outputs the outputs of all the iterations, instead of the last one.
+    /// Chat message outputs are concatenated, other outputs are collected into a list of strings
+    #[serde(default)]
+    pub accumulate_outputs: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum LoopBreakCondition {
+    /// The text of the output contains the value. The text of chat messages is the text of the
+    /// last message
+    OutputContains { value: String },
+    /// The text of the output equals the value, ignoring surrounding whitespace
+    OutputEquals { value: String },
+    /// The output node of the body with this name outputs true. Its value is neither fed back
+    /// nor accumulated, and the body must have exactly one other output node
+    BreakOutput {
+        #[serde(rename = "outputName")]
+        output_name: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopIteration {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Messages of the nodes of the body in this iteration
+    pub messages: HashMap<Uuid, Message>,
+    pub output: NodeInput,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopNodeMetaLog {
+    pub iterations: Vec<LoopIteration>,
+    /// Whether the loop stopped because of its break condition, rather than `max_iterations`
+    pub broke: bool,
+    pub total_token_count: i64,
+    pub approximate_cost: Option<f64>,
+}
+
+impl LoopNode {
+    /// The body must have exactly one output node besides the break output node, whose value
+    /// is the output of the iteration
+    pub fn validate(&self) -> Result<()> {
+        if self.max_iterations == 0 || self.max_iterations > MAX_LOOP_ITERATIONS {
+            return Err(anyhow::anyhow!(
+                "maxIterations must be between 1 and {}",
+                MAX_LOOP_ITERATIONS
+            ));
+        }
+
+        let graph = serde_json::from_value::<Graph>(self.runnable_graph.clone())?;
+        if !graph.get_input_node_names().contains(&self.loop_input_name) {
+            return Err(anyhow::anyhow!(
+                "Loop body has no input node {}",
+                self.loop_input_name
+            ));
+        }
+        let output_names = graph
+            .nodes
+            .values()
+            .filter_map(|node| match node {
+                Node::Output(output_node) => Some(&output_node.name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let break_output_name = match &self.break_condition {
+            Some(LoopBreakCondition::BreakOutput { output_name }) => Some(output_name),
+            _ => None,
+        };
+        if let Some(break_output_name) = break_output_name {
+            if !output_names.contains(&break_output_name) {
+                return Err(anyhow::anyhow!(
+                    "Loop body has no break output node {}",
+                    break_output_name
+                ));
+            }
+        }
+        let value_output_count = output_names
+            .iter()
+            .filter(|name| Some(**name) != break_output_name)
+            .count();
+        if value_output_count != 1 {
+            return Err(anyhow::anyhow!(
+                "Loop body must have exactly one output node besides the break output node"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Output of the iteration, and whether the loop breaks after it
+    fn evaluate_iteration(
+        &self,
+        mut output_values: HashMap<String, NodeInput>,
+    ) -> Result<(NodeInput, bool)> {
+        let break_output = match &self.break_condition {
+            Some(LoopBreakCondition::BreakOutput { output_name }) => {
+                Some(output_values.remove(output_name))
+            }
+            _ => None,
+        };
+        // Output nodes may be skipped by conditional nodes of the body
+        let output = output_values
+            .into_values()
+            .next()
+            .ok_or(anyhow::anyhow!("Loop body did not output a value"))?;
+
+        let broke = match (&self.break_condition, break_output) {
+            (Some(LoopBreakCondition::OutputContains { value }), _) => {
+                output_text(&output).contains(value.as_str())
+            }
+            (Some(LoopBreakCondition::OutputEquals { value }), _) => {
+                output_text(&output).trim() == value.trim()
+            }
+            (_, Some(Some(NodeInput::Boolean(flag)))) => flag,
+            (_, Some(Some(NodeInput::String(flag)))) => flag.trim().eq_ignore_ascii_case("true"),
+            _ => false,
+        };
+        Ok((output, broke))
+    }
+}
+
+/// Text the break condition is evaluated on
+fn output_text(output: &NodeInput) -> String {
+    match output {
+        NodeInput::String(text) => text.clone(),
+        NodeInput::ChatMessageList(messages) => match messages.last().map(|m| &m.content) {
+            Some(ChatMessageContent::Text(text)) => text.clone(),
+            Some(ChatMessageContent::ContentPartList(parts)) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ChatMessageContentPart::Text(text) => Some(text.text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(""),
+            None => String::new(),
+        },
+        NodeInput::ConditionedValue(conditioned_value) => output_text(&conditioned_value.value),
+        NodeInput::Boolean(_) | NodeInput::StringList(_) | NodeInput::Float(_) => {
+            output.clone().into()
+        }
+    }
+}
+
+/// Input of the body's loop input node in the iteration that follows the output
+fn next_loop_input(previous_input: NodeInput, output: NodeInput) -> NodeInput {
+    match (previous_input, output) {
+        (NodeInput::ChatMessageList(mut messages), NodeInput::ChatMessageList(new_messages)) => {
+            messages.extend(new_messages);
+            messages.into()
+        }
+        (_, output) => output,
+    }
+}
+
+fn accumulate_outputs(outputs: Vec<NodeInput>) -> NodeInput {
+    if outputs
+        .iter()
+        .all(|output| matches!(output, NodeInput::ChatMessageList(_)))
+    {
+        outputs
+            .into_iter()
+            .flat_map(|output| match output {
+                NodeInput::ChatMessageList(messages) => messages,
+                _ => vec![],
+            })
+            .collect::<Vec<_>>()
+            .into()
+    } else {
+        outputs.iter().map(output_text).collect::<Vec<_>>().into()
+    }
+}
+
+#[async_trait]
+impl RunnableNode for LoopNode {
+    fn handles_mapping(&self) -> Vec<(Uuid, Handle)> {
+        map_handles(&self.inputs, &self.inputs_mappings)
+    }
+
+    fn output_handle_id(&self) -> Uuid {
+        self.outputs.first().unwrap().id
+    }
+
+    fn node_name(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn node_id(&self) -> Uuid {
+        self.id
+    }
+
+    fn node_type(&self) -> String {
+        "Loop".to_string()
+    }
+
+    async fn run(
+        &self,
+        mut inputs: HashMap<String, NodeInput>,
+        context: Arc<Context>,
+    ) -> Result<RunOutput> {
+        if self.pipeline_version_id.is_none() {
+            return Err(anyhow::anyhow!("Pipeline version id is required"));
+        }
+
+        let graph = serde_json::from_value::<Graph>(self.runnable_graph.clone())?;
+
+        let mut iterations = Vec::new();
+        let mut outputs = Vec::new();
+        let mut broke = false;
+        let mut total_token_count = 0;
+        let mut approximate_cost = Some(0.0);
+
+        for iteration in 0..self.max_iterations {
+            let start_time = Utc::now();
+            let mut iteration_graph = graph.clone();
+            iteration_graph.setup(&inputs, &context.env, &context.metadata, &context.run_type)?;
+            let run_result = context
+                .pipeline_runner
+                .run(iteration_graph, &context.project_id, context.tx.clone())
+                .await;
+
+            let engine_output = match run_result {
+                Ok(engine_output) => engine_output,
+                Err(e) => {
+                    // TODO: Return the partial trace of the failed iteration, once node errors can carry meta logs
+                    return Err(anyhow::anyhow!(
+                        "Loop iteration {} failed: {}",
+                        iteration + 1,
+                        e
+                    ));
+                }
+            };
+
+            let run_stats = RunTraceStats::from_messages(&engine_output.messages);
+            total_token_count += run_stats.total_token_count;
+            approximate_cost = approximate_cost
+                .zip(run_stats.approximate_cost)
+                .map(|(cost, run_cost)| cost + run_cost);
+
+            let (output, iteration_broke) =
+                self.evaluate_iteration(engine_output.output_values())?;
+            iterations.push(LoopIteration {
+                start_time,
+                end_time: Utc::now(),
+                messages: engine_output.messages,
+                output: output.clone(),
+            });
+            outputs.push(output.clone());
+
+            if iteration_broke {
+                broke = true;
+                break;
+            }
+
+            let previous_input = inputs.remove(&self.loop_input_name).ok_or(anyhow::anyhow!(
+                "Loop input {} is missing",
+                self.loop_input_name
+            ))?;
+            inputs.insert(
+                self.loop_input_name.clone(),
+                next_loop_input(previous_input, output),
+            );
+        }
+
+        let output = if self.accumulate_outputs {
+            accumulate_outputs(outputs)
+        } else {
+            // max_iterations is at least 1, so there is at least one output
+            outputs.pop().unwrap()
+        };
+        let meta_log = LoopNodeMetaLog {
+            iterations,
+            broke,
+            total_token_count,
+            approximate_cost,
+        };
+
+        Ok(RunOutput::Success((output, Some(MetaLog::Loop(meta_log)))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language_model::ChatMessage;
+
+    use super::*;
+
+    fn message(role: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: ChatMessageContent::Text(text.to_string()),
+            tool_calls: vec![],
+            tool_call_id: None,
+        }
+    }
+
+    fn loop_node(break_condition: serde_json::Value) -> LoopNode {
+        serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4(),
+            "name": "loop",
+            "inputs": [],
+            "outputs": [],
+            "inputsMappings": {},
+            "pipelineName": "body",
+            "pipelineVersionName": "v1",
+            "runnableGraph": {},
+            "loopInputName": "messages",
+            "maxIterations": 5,
+            "breakCondition": break_condition,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_break_conditions() {
+        let contains = loop_node(serde_json::json!({"type": "OutputContains", "value": "FINAL"}));
+        let output = HashMap::from([(
+            "messages".to_string(),
+            vec![message("user", "FINAL"), message("assistant", "thinking")].into(),
+        )]);
+        assert!(!contains.evaluate_iteration(output).unwrap().1);
+        let output = HashMap::from([(
+            "messages".to_string(),
+            vec![message("assistant", "FINAL answer")].into(),
+        )]);
+        assert!(contains.evaluate_iteration(output).unwrap().1);
+
+        let break_output = loop_node(serde_json::json!({
+            "type": "BreakOutput",
+            "outputName": "done",
+        }));
+        let output = HashMap::from([
+            ("answer".to_string(), "42".to_string().into()),
+            ("done".to_string(), "true".to_string().into()),
+        ]);
+        let (output, broke) = break_output.evaluate_iteration(output).unwrap();
+        assert!(broke);
+        assert!(matches!(output, NodeInput::String(answer) if answer == "42"));
+    }
+
+    fn body_graph(output_names: &[&str]) -> serde_json::Value {
+        let mut nodes = serde_json::Map::new();
+        nodes.insert(
+            "messages".to_string(),
+            serde_json::json!({
+                "type": "Input",
+                "id": Uuid::new_v4(),
+                "name": "messages",
+                "outputs": [],
+                "inputType": "ChatMessageList",
+            }),
+        );
+        for name in output_names {
+            nodes.insert(
+                name.to_string(),
+                serde_json::json!({
+                    "type": "Output",
+                    "id": Uuid::new_v4(),
+                    "name": name,
+                    "inputs": [],
+                    "inputsMappings": {},
+                }),
+            );
+        }
+        serde_json::json!({"nodes": nodes, "pred": {}})
+    }
+
+    #[test]
+    fn test_validate_output_nodes() {
+        let mut contains =
+            loop_node(serde_json::json!({"type": "OutputContains", "value": "FINAL"}));
+        contains.runnable_graph = body_graph(&["answer"]);
+        assert!(contains.validate().is_ok());
+        contains.runnable_graph = body_graph(&["answer", "draft"]);
+        assert!(contains.validate().is_err());
+
+        let mut break_output = loop_node(serde_json::json!({
+            "type": "BreakOutput",
+            "outputName": "done",
+        }));
+        break_output.runnable_graph = body_graph(&["answer", "done"]);
+        assert!(break_output.validate().is_ok());
+        break_output.runnable_graph = body_graph(&["answer"]);
+        assert!(break_output.validate().is_err());
+        break_output.runnable_graph = body_graph(&["answer", "draft", "done"]);
+        assert!(break_output.validate().is_err());
+    }
+
+    #[test]
+    fn test_chat_messages_are_appended() {
+        let input = next_loop_input(
+            vec![message("user", "question")].into(),
+            vec![message("assistant", "call"), message("tool", "result")].into(),
+        );
+        assert!(matches!(&input, NodeInput::ChatMessageList(messages) if messages.len() == 3));
+
+        let accumulated = accumulate_outputs(vec![
+            "first".to_string().into(),
+            "second".to_string().into(),
+        ]);
+        assert!(
+            matches!(accumulated, NodeInput::StringList(outputs) if outputs == ["first", "second"])
+        );
+    }
+}
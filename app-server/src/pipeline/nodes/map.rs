@@ -67,7 +67,7 @@ impl PipelineRunner {
         // ref: https://medium.com/@jaderd/you-should-never-do-bounded-concurrency-like-this-in-rust-851971728cfb
 
         // we limit the number of concurrent calls to avoid hitting the rate limit on the language model
-        let permits = Arc::new(Semaphore::new(50));
+        let permits = Arc::new(Semaphore::new(context.concurrency.max_concurrent_map_runs));
 
         let run_calls = inputs_vec.iter().map(|inputs| {
             let permits = permits.clone();
@@ -90,7 +90,7 @@ impl PipelineRunner {
                         approximate_cost,
                     }
                 } else {
-                    let run_result = self.run(graph, None).await;
+                    let run_result = self.run(graph, &context.project_id, None).await;
 
                     let trace = PipelineRunner::get_trace_from_result(&run_result);
 
@@ -163,7 +163,9 @@ impl RunnableNode for MapNode {
         let mut total_token_count = 0;
         let mut approximate_cost = Some(0.0);
 
-        for batch in input_list.chunks(BATCH_SIZE) {
+        // batches are at least as large as the concurrency, so that they do not limit it further
+        let batch_size = BATCH_SIZE.max(context.concurrency.max_concurrent_map_runs);
+        for batch in input_list.chunks(batch_size) {
             let inputs_vec = batch
                 .iter()
                 .map(|input| {
@@ -21,6 +21,7 @@ mod format_validator;
 pub mod input;
 mod json_extractor;
 pub mod llm;
+pub mod loop_node;
 pub mod map;
 pub mod output;
 mod semantic_search;
@@ -263,6 +264,7 @@ pub enum Node {
     StringTemplate(string_template::StringTemplateNode),
     Subpipeline(subpipeline::SubpipelineNode),
     Map(map::MapNode),
+    Loop(loop_node::LoopNode),
     SemanticSearch(semantic_search::SemanticSearchNode),
     SemanticSwitch(semantic_switch::SemanticSwitchNode),
     Condition(condition::ConditionNode),
@@ -287,6 +289,7 @@ impl Node {
             Self::StringTemplate(node) => node.id,
             Self::Subpipeline(node) => node.id,
             Self::Map(node) => node.id,
+            Self::Loop(node) => node.id,
             Self::SemanticSearch(node) => node.id,
             Self::SemanticSwitch(node) => node.id,
             Self::Condition(node) => node.id,
@@ -312,6 +315,7 @@ impl Node {
             Self::StringTemplate(node) => node.name.as_str(),
             Self::Subpipeline(node) => node.name.as_str(),
             Self::Map(node) => node.name.as_str(),
+            Self::Loop(node) => node.name.as_str(),
             Self::SemanticSearch(node) => node.name.as_str(),
             Self::SemanticSwitch(node) => node.name.as_str(),
             Self::Condition(node) => node.name.as_str(),
@@ -81,7 +81,10 @@ impl RunnableNode for SubpipelineNode {
         let mut graph = serde_json::from_value::<Graph>(self.runnable_graph.clone())?;
         graph.setup(&inputs, &env, &context.metadata, &context.run_type)?;
         // TODO: Add streaming and websocket streaming here so that subpipelines can stream and use external functions.
-        let run_result = context.pipeline_runner.run(graph, context.tx.clone()).await;
+        let run_result = context
+            .pipeline_runner
+            .run(graph, &context.project_id, context.tx.clone())
+            .await;
 
         let trace = PipelineRunner::get_trace_from_result(&run_result);
 
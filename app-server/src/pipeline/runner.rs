@@ -1,10 +1,12 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use crate::{
     api::v1::traces::RabbitMqSpanMessage,
     cache::Cache,
     code_executor::CodeExecutor,
     db::{
+        self,
+        pipelines::{NewPipelineRun, PipelineRunStatus},
         spans::Span,
         trace::{CurrentTraceAndSpan, TraceType},
         DB,
@@ -17,7 +19,7 @@ use crate::{
 use anyhow::Result;
 use itertools::Itertools;
 use serde::Serialize;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{self, Sender};
 use uuid::Uuid;
 
 use crate::{language_model::LanguageModelRunner, semantic_search::SemanticSearch};
@@ -30,6 +32,12 @@ use super::{
     Graph, GraphError, InvalidSchemasError,
 };
 
+/// Interval at which durable runs check whether they were cancelled
+const CANCELLATION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Interval at which durable runs mark themselves as alive, so that long-running nodes do
+/// not make them look interrupted
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Debug)]
 pub struct RunningError {
     pub partial_trace: EngineOutput,
@@ -120,11 +128,12 @@ impl PipelineRunner {
         }
     }
 
-    pub async fn run(
+    fn create_context(
         &self,
-        graph: Graph,
+        graph: &Graph,
+        project_id: &Uuid,
         stream_send: Option<Sender<StreamChunk>>,
-    ) -> Result<EngineOutput, PipelineRunnerError> {
+    ) -> Result<Context, PipelineRunnerError> {
         let missing_env_vars = graph.get_missing_env_vars();
         if !missing_env_vars.is_empty() {
             return Err(PipelineRunnerError::MissingEnvVarsError(
@@ -138,7 +147,7 @@ impl PipelineRunner {
             language_model: self.language_model.clone(),
             semantic_search: self.semantic_search.clone(),
             env: graph.env.clone(),
-            tx: stream_send.clone(),
+            tx: stream_send,
             metadata: graph.metadata.clone(),
             run_type: graph.run_type.clone(),
             pipeline_runner: self.clone(),
@@ -146,8 +155,21 @@ impl PipelineRunner {
             code_executor: self.code_executor.clone(),
             db: self.db.clone(),
             cache: self.cache.clone(),
+            concurrency: graph.concurrency.clone(),
+            project_id: *project_id,
         };
 
+        Ok(context)
+    }
+
+    pub async fn run(
+        &self,
+        graph: Graph,
+        project_id: &Uuid,
+        stream_send: Option<Sender<StreamChunk>>,
+    ) -> Result<EngineOutput, PipelineRunnerError> {
+        let context = self.create_context(&graph, project_id, stream_send.clone())?;
+
         let tasks = parse_graph(graph)?;
 
         let mut engine = Engine::with_tasks_and_context(tasks, context, None, None, None);
@@ -163,34 +185,14 @@ impl PipelineRunner {
     pub async fn run_workshop(
         &self,
         graph: Graph,
+        project_id: &Uuid,
         stream_send: Option<Sender<StreamChunk>>,
         prefilled_messages: Option<Vec<Message>>,
         start_task_id: Option<Uuid>,
         breakpoint_task_ids: Option<Vec<Uuid>>,
         interrupt_recv: tokio::sync::mpsc::Receiver<GraphInterruptMessage>,
     ) -> Result<EngineOutput, PipelineRunnerError> {
-        let missing_env_vars = graph.get_missing_env_vars();
-        if !missing_env_vars.is_empty() {
-            return Err(PipelineRunnerError::MissingEnvVarsError(
-                MissingEnvVarsError { missing_env_vars },
-            ));
-        }
-
-        let validated_schemas = graph.validate_baml_schemas()?;
-
-        let context = Context {
-            language_model: self.language_model.clone(),
-            semantic_search: self.semantic_search.clone(),
-            env: graph.env.clone(),
-            tx: stream_send.clone(),
-            metadata: graph.metadata.clone(),
-            run_type: graph.run_type.clone(),
-            pipeline_runner: self.clone(),
-            baml_schemas: validated_schemas,
-            code_executor: self.code_executor.clone(),
-            db: self.db.clone(),
-            cache: self.cache.clone(),
-        };
+        let context = self.create_context(&graph, project_id, stream_send.clone())?;
 
         let tasks = parse_graph(graph)?;
 
@@ -213,6 +215,140 @@ impl PipelineRunner {
         }
     }
 
+    /// Run the pipeline version durably, i.e. record the run and checkpoint the message of
+    /// every node that completes, so that the run can be resumed with `continue_durable`
+    /// if it fails or is interrupted
+    pub async fn run_durable(
+        &self,
+        graph: Graph,
+        run_id: Uuid,
+        project_id: &Uuid,
+        pipeline_version_id: &Uuid,
+        pipeline_version_name: &str,
+        stream_send: Option<Sender<StreamChunk>>,
+    ) -> Result<EngineOutput, PipelineRunnerError> {
+        let run = NewPipelineRun {
+            id: run_id,
+            project_id: *project_id,
+            pipeline_version_id: *pipeline_version_id,
+            pipeline_version_name: pipeline_version_name.to_string(),
+            status: PipelineRunStatus::RUNNING,
+            graph: serde_json::to_value(&graph)?,
+            metadata: serde_json::to_value(&graph.metadata)?,
+            webhook_url: None,
+            webhook_secret: None,
+        };
+        db::pipelines::create_pipeline_run(&self.db.pool, &run).await?;
+
+        let run_result = self
+            .run_with_checkpoints(graph, run_id, project_id, Vec::new(), stream_send)
+            .await;
+        self.finish_durable_run(&run_id, &run_result).await;
+
+        run_result
+    }
+
+    /// Continue a durable run from the messages it checkpointed, if any.
+    ///
+    /// The run must be marked as running first, i.e. taken from the queue with
+    /// `db::pipelines::start_pipeline_run`, and the graph is the stored graph of the run,
+    /// with the env set again.
+    ///
+    /// Only the messages of the top-level nodes are checkpointed. Map and Subpipeline nodes
+    /// that did not complete are run again from the start, including their inner runs.
+    pub async fn continue_durable(
+        &self,
+        graph: Graph,
+        run_id: Uuid,
+        project_id: &Uuid,
+        stream_send: Option<Sender<StreamChunk>>,
+    ) -> Result<EngineOutput, PipelineRunnerError> {
+        let checkpoint = db::pipelines::get_pipeline_run_messages(&self.db.pool, &run_id).await?;
+
+        let run_result = self
+            .run_with_checkpoints(graph, run_id, project_id, checkpoint, stream_send)
+            .await;
+        self.finish_durable_run(&run_id, &run_result).await;
+
+        run_result
+    }
+
+    async fn run_with_checkpoints(
+        &self,
+        graph: Graph,
+        run_id: Uuid,
+        project_id: &Uuid,
+        checkpoint: Vec<Message>,
+        stream_send: Option<Sender<StreamChunk>>,
+    ) -> Result<EngineOutput, PipelineRunnerError> {
+        let context = self.create_context(&graph, project_id, stream_send.clone())?;
+
+        let tasks = parse_graph(graph)?;
+
+        let mut engine = Engine::with_tasks_and_context(tasks, context, None, None, None);
+        engine.set_checkpoint_run_id(run_id);
+
+        let (interrupt_send, interrupt_recv) = mpsc::channel::<GraphInterruptMessage>(1);
+        let cancellation_watcher =
+            tokio::spawn(watch_cancellation(self.db.clone(), run_id, interrupt_send));
+
+        let run_result = if checkpoint.is_empty() {
+            engine.run(stream_send, Some(interrupt_recv), None).await
+        } else {
+            let start_task_ids = engine.restore_checkpoint(checkpoint);
+            engine
+                .run_from(stream_send, Some(interrupt_recv), start_task_ids)
+                .await
+        };
+        cancellation_watcher.abort();
+
+        run_result.map_err(|errors| {
+            PipelineRunnerError::RunningError(RunningError {
+                partial_trace: errors,
+            })
+        })
+    }
+
+    async fn finish_durable_run(
+        &self,
+        run_id: &Uuid,
+        run_result: &Result<EngineOutput, PipelineRunnerError>,
+    ) {
+        let res = match run_result {
+            Ok(engine_output) => {
+                let outputs = serde_json::to_value(engine_output.output_values()).ok();
+                db::pipelines::finish_pipeline_run(
+                    &self.db.pool,
+                    run_id,
+                    PipelineRunStatus::SUCCEEDED,
+                    outputs.as_ref(),
+                    None,
+                )
+                .await
+            }
+            Err(e) => {
+                let error = match e {
+                    // the error messages of the failed nodes are among the outputs of the partial trace
+                    PipelineRunnerError::RunningError(e) => {
+                        serde_json::to_string(&e.partial_trace.output_values()).unwrap_or_default()
+                    }
+                    _ => e.to_string(),
+                };
+                db::pipelines::finish_pipeline_run(
+                    &self.db.pool,
+                    run_id,
+                    PipelineRunStatus::FAILED,
+                    None,
+                    Some(&error),
+                )
+                .await
+            }
+        };
+        if let Err(e) = res {
+            log::error!("Failed to finish pipeline run {}: {}", run_id, e);
+        }
+    }
+
     /// Write the engine output to the observations (spans) queue
     pub async fn record_observations(
         &self,
@@ -285,3 +421,30 @@ impl PipelineRunner {
         }
     }
 }
+
+/// Cancel the run through its interrupt channel once its status is set to cancelled, and
+/// send heartbeats for the run while it is running
+async fn watch_cancellation(
+    db: Arc<DB>,
+    run_id: Uuid,
+    interrupt_send: Sender<GraphInterruptMessage>,
+) {
+    let mut last_heartbeat = tokio::time::Instant::now();
+    loop {
+        tokio::time::sleep(CANCELLATION_POLL_INTERVAL).await;
+        let status = if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+            last_heartbeat = tokio::time::Instant::now();
+            db::pipelines::heartbeat_pipeline_run(&db.pool, &run_id).await
+        } else {
+            db::pipelines::get_pipeline_run_status(&db.pool, &run_id).await
+        };
+        match status {
+            Ok(Some(PipelineRunStatus::CANCELLED)) => {
+                let _ = interrupt_send.send(GraphInterruptMessage::Cancel).await;
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to get status of pipeline run {}: {}", run_id, e),
+        }
+    }
+}
@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
-use super::nodes::{llm, map, subpipeline, zenguard, Message};
+use super::nodes::{llm, loop_node, map, subpipeline, zenguard, Message};
 
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -22,8 +22,58 @@ pub struct RunTraceStats {
 pub enum MetaLog {
     LLM(llm::LLMNodeMetaLog),
     Zenguard(zenguard::ZenguardNodeMetaLog),
+    // Before Subpipeline, whose fields a loop meta log also has
+    Loop(loop_node::LoopNodeMetaLog),
     Subpipeline(subpipeline::SubpipelineNodeMetaLog),
     Map(map::MapNodeMetaLog),
+    Retry(RetryMetaLog),
+}
+
+/// Meta log of a node that was retried or fell back, according to its policy
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryMetaLog {
+    pub attempts: Vec<NodeAttempt>,
+    /// Whether the node output its fallback, because its last attempt failed
+    pub fell_back: bool,
+    /// Meta log of the last attempt, if it succeeded
+    pub meta_log: Option<Box<MetaLog>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeAttempt {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// None if the attempt succeeded
+    pub error: Option<String>,
+}
+
+impl MetaLog {
+    /// Wrap the meta log of a node in a retry meta log, if it took more than one attempt
+    /// or fell back
+    pub fn with_attempts(
+        meta_log: Option<MetaLog>,
+        attempts: Vec<NodeAttempt>,
+        fell_back: bool,
+    ) -> Option<MetaLog> {
+        if attempts.len() <= 1 && !fell_back {
+            return meta_log;
+        }
+        Some(MetaLog::Retry(RetryMetaLog {
+            attempts,
+            fell_back,
+            meta_log: meta_log.map(Box::new),
+        }))
+    }
+
+    /// Meta log recorded by the node itself
+    pub fn node_meta_log(&self) -> Option<&MetaLog> {
+        match self {
+            MetaLog::Retry(retry_meta) => retry_meta.meta_log.as_deref(),
+            _ => Some(self),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -57,22 +107,28 @@ impl RunTraceStats {
             if message.end_time > latest_end_time {
                 latest_end_time = message.end_time;
             }
-            total_token_count += match &message.meta_log {
+            let meta_log = message
+                .meta_log
+                .as_ref()
+                .and_then(|meta_log| meta_log.node_meta_log());
+            total_token_count += match meta_log {
                 Some(MetaLog::LLM(llm_meta)) => llm_meta.total_token_count,
                 Some(MetaLog::Zenguard(_)) => 0,
                 Some(MetaLog::Subpipeline(subpipeline_meta)) => subpipeline_meta.total_token_count,
                 Some(MetaLog::Map(map_meta)) => map_meta.total_token_count,
-                None => 0,
+                Some(MetaLog::Loop(loop_meta)) => loop_meta.total_token_count,
+                Some(MetaLog::Retry(_)) | None => 0,
             };
 
-            let message_cost = match &message.meta_log {
+            let message_cost = match meta_log {
                 Some(MetaLog::LLM(llm_meta)) => llm_meta.approximate_cost,
                 // TODO: Update Zenguard cost when they become paid, but they are indeed free now
                 // I should've put None, but we care more about LLM prices, so not to make whole `approximate_cost` None because of Zenguard
                 Some(MetaLog::Zenguard(_)) => Some(0.0),
                 Some(MetaLog::Subpipeline(subpipeline_meta)) => subpipeline_meta.approximate_cost,
                 Some(MetaLog::Map(map_meta)) => map_meta.approximate_cost,
-                None => Some(0.0),
+                Some(MetaLog::Loop(loop_meta)) => loop_meta.approximate_cost,
+                Some(MetaLog::Retry(_)) | None => Some(0.0),
             };
             if let Some(cost) = approximate_cost {
                 // add up at least the costs we can
@@ -4,19 +4,29 @@ use uuid::Uuid;
 
 use crate::{
     cache::keys::TARGET_PIPELINE_VERSION_CACHE_KEY,
-    engine::Task,
-    language_model::{ChatMessage, ChatMessageContent, ChatMessageContentPart},
+    engine::{policy::NodeFallback, Task},
+    language_model::ChatMessage,
 };
 
 use super::{nodes::Node, Graph};
 
-pub fn parse_graph(graph: Graph) -> Result<HashMap<Uuid, Task>> {
+/// Upper bound of the concurrency limits of a graph, which are used as semaphore permits
+const MAX_CONCURRENCY_LIMIT: usize = 1000;
+
+pub fn parse_graph(mut graph: Graph) -> Result<HashMap<Uuid, Task>> {
     validate_graph(&graph)?;
 
     let mut tasks: HashMap<Uuid, Task> = graph
         .nodes
         .into_iter()
-        .map(|(_, node)| (node.id(), task_from_node(node)))
+        .map(|(_, node)| {
+            let id = node.id();
+            let mut task = task_from_node(node);
+            if let Some(policy) = graph.policies.remove(&id) {
+                task.policy = policy;
+            }
+            (id, task)
+        })
         .collect();
 
     for (to, from) in graph.pred {
@@ -40,6 +50,56 @@ fn validate_graph(graph: &Graph) -> Result<()> {
             "Graph must contain at least one output node"
         ));
     };
+
+    let concurrency_limits = [
+        graph.concurrency.max_concurrent_nodes,
+        graph.concurrency.max_concurrent_map_runs,
+    ];
+    if concurrency_limits
+        .iter()
+        .any(|limit| !(1..=MAX_CONCURRENCY_LIMIT).contains(limit))
+    {
+        return Err(anyhow::anyhow!(
+            "Concurrency limits must be between 1 and {}",
+            MAX_CONCURRENCY_LIMIT
+        ));
+    }
+
+    for node in graph.nodes.values() {
+        if let Node::Loop(loop_node) = node {
+            loop_node
+                .validate()
+                .map_err(|e| anyhow::anyhow!("Invalid loop node {}: {}", loop_node.name, e))?;
+        }
+    }
+
+    for (node_id, policy) in graph.policies.iter() {
+        if !graph.nodes.values().any(|node| node.id() == *node_id) {
+            return Err(anyhow::anyhow!(
+                "Policy is set for unknown node {}",
+                node_id
+            ));
+        }
+        policy
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid policy of node {}: {}", node_id, e))?;
+        if let Some(NodeFallback::Edge {
+            node_id: fallback_node_id,
+        }) = &policy.fallback
+        {
+            if !graph
+                .pred
+                .get(fallback_node_id)
+                .is_some_and(|pred| pred.contains(node_id))
+            {
+                return Err(anyhow::anyhow!(
+                    "Fallback node {} must be a successor of node {}",
+                    fallback_node_id,
+                    node_id
+                ));
+            }
+        }
+    }
     Ok(())
 }
 
@@ -80,6 +140,7 @@ fn task_from_node(node: Node) -> Task {
             Task::with_action(subpipeline_node.id.clone(), Arc::new(subpipeline_node))
         }
         Node::Map(map_node) => Task::with_action(map_node.id.clone(), Arc::new(map_node)),
+        Node::Loop(loop_node) => Task::with_action(loop_node.id.clone(), Arc::new(loop_node)),
         Node::Zenguard(zenguard_node) => {
             Task::with_action(zenguard_node.id.clone(), Arc::new(zenguard_node))
         }
@@ -99,24 +160,30 @@ pub fn get_target_pipeline_version_cache_key(project_id: &str, pipeline_name: &s
     format!("{TARGET_PIPELINE_VERSION_CACHE_KEY}:{project_id}:{pipeline_name}")
 }
 
+/// Render messages as text, e.g. for evaluators. Tool calls are rendered after the text
+/// of the message, and tool messages reference the tool call they are the result of.
 pub fn render_chat_message_list(messages: Vec<ChatMessage>) -> String {
     messages
         .iter()
-        .map(|message| match message.content {
-            ChatMessageContent::Text(ref text) => {
-                format!("<{}>\n{}\n</{}>", message.role, text, message.role)
+        .map(|message| {
+            let text = message.text();
+            let mut blocks = Vec::new();
+            if !text.is_empty() || message.tool_calls.is_empty() {
+                blocks.push(text);
             }
-            ChatMessageContent::ContentPartList(ref parts) => {
-                let text_message = parts
-                    .iter()
-                    .map(|part| match part {
-                        ChatMessageContentPart::Text(ref text) => text.text.clone(), // TODO: Do it more efficiently than clone
-                        _ => String::new(),
-                    })
-                    .collect::<Vec<String>>()
-                    .join("");
-                format!("<{}>\n{}\n</{}>", message.role, text_message, message.role)
+            for tool_call in &message.tool_calls {
+                blocks.push(format!(
+                    "<tool_call id=\"{}\" name=\"{}\">\n{}\n</tool_call>",
+                    tool_call.id.as_deref().unwrap_or_default(),
+                    tool_call.name,
+                    tool_call.arguments_string(),
+                ));
             }
+            let open_tag = match &message.tool_call_id {
+                Some(tool_call_id) => format!("{} tool_call_id=\"{}\"", message.role, tool_call_id),
+                None => message.role.clone(),
+            };
+            format!("<{}>\n{}\n</{}>", open_tag, blocks.join("\n"), message.role)
         })
         .collect::<Vec<String>>()
         .join("\n\n")
@@ -0,0 +1,184 @@
+//! This module takes queued pipeline runs from the queue and executes them.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::{
+    db::{
+        self,
+        pipelines::{PipelineRun, PipelineRunStatus},
+        DB,
+    },
+    mq::{
+        dead_letter_delivery, MessageOrigin, MessageQueue, MessageQueueDeliveryTrait,
+        MessageQueueReceiverTrait, MessageQueueTrait,
+    },
+    pipeline::{runner::PipelineRunner, Graph, RunType},
+};
+
+use super::{
+    webhook::send_webhook, PipelineJobMessage, PIPELINE_JOBS_EXCHANGE, PIPELINE_JOBS_QUEUE,
+    PIPELINE_JOBS_ROUTING_KEY,
+};
+
+pub async fn process_pipeline_jobs(
+    pipeline_runner: Arc<PipelineRunner>,
+    db: Arc<DB>,
+    queue: Arc<MessageQueue>,
+    client: reqwest::Client,
+) {
+    loop {
+        inner_process_pipeline_jobs(
+            pipeline_runner.clone(),
+            db.clone(),
+            queue.clone(),
+            client.clone(),
+        )
+        .await;
+    }
+}
+
+async fn inner_process_pipeline_jobs(
+    pipeline_runner: Arc<PipelineRunner>,
+    db: Arc<DB>,
+    queue: Arc<MessageQueue>,
+    client: reqwest::Client,
+) {
+    let mut receiver = queue
+        .get_receiver(
+            PIPELINE_JOBS_QUEUE,
+            PIPELINE_JOBS_EXCHANGE,
+            PIPELINE_JOBS_ROUTING_KEY,
+        )
+        .await
+        .unwrap();
+
+    let origin = MessageOrigin::new(
+        PIPELINE_JOBS_QUEUE,
+        PIPELINE_JOBS_EXCHANGE,
+        PIPELINE_JOBS_ROUTING_KEY,
+    );
+
+    while let Some(delivery) = receiver.receive().await {
+        if let Err(e) = delivery {
+            log::error!("Failed to receive message from queue: {:?}", e);
+            continue;
+        }
+        let delivery = delivery.unwrap();
+        let acker = delivery.acker();
+        let payload = delivery.data();
+        let message = match serde_json::from_slice::<PipelineJobMessage>(&payload) {
+            Ok(message) => message,
+            Err(e) => {
+                log::error!("Failed to deserialize message from queue: {:?}", e);
+                let reason = format!("Failed to deserialize message: {}", e);
+                dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
+                continue;
+            }
+        };
+
+        let run = match db::pipelines::start_pipeline_run(&db.pool, &message.run_id).await {
+            Ok(run) => run,
+            Err(e) => {
+                log::error!("Failed to start pipeline run {}: {:?}", message.run_id, e);
+                let reason = format!("Failed to start pipeline run: {:?}", e);
+                dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
+                continue;
+            }
+        };
+
+        // The run is tracked in the database from here on, and can be resumed through the API
+        // if it is interrupted, so the delivery is acked before the run, which can take longer
+        // than the delivery is allowed to stay unacked
+        if let Err(e) = acker.ack().await {
+            log::error!("Failed to ack MQ delivery (pipeline jobs): {:?}", e);
+        }
+
+        // e.g. the run was cancelled while it was queued
+        let Some(run) = run else {
+            continue;
+        };
+
+        run_pipeline_job(&pipeline_runner, &db, &client, run, message).await;
+    }
+}
+
+async fn run_pipeline_job(
+    pipeline_runner: &PipelineRunner,
+    db: &DB,
+    client: &reqwest::Client,
+    run: PipelineRun,
+    message: PipelineJobMessage,
+) {
+    let run_id = run.id;
+    let project_id = run.project_id;
+
+    // the runner finishes the runs it executes, so only the runs it could not execute are failed here
+    if let Err(e) = execute_pipeline_job(pipeline_runner, run, message).await {
+        log::error!("Failed to execute pipeline run {}: {:?}", run_id, e);
+        let error = e.to_string();
+        if let Err(e) = db::pipelines::finish_pipeline_run(
+            &db.pool,
+            &run_id,
+            PipelineRunStatus::FAILED,
+            None,
+            Some(&error),
+        )
+        .await
+        {
+            log::error!("Failed to finish pipeline run {}: {:?}", run_id, e);
+        }
+    }
+
+    let run = match db::pipelines::get_pipeline_run(&db.pool, &project_id, &run_id).await {
+        Ok(Some(run)) => run,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Failed to get pipeline run {}: {:?}", run_id, e);
+            return;
+        }
+    };
+    // the webhook is retried for minutes if it fails, so it is called in the background
+    // rather than holding up the worker
+    let client = client.clone();
+    tokio::spawn(async move {
+        if let Err(e) = send_webhook(&client, &run).await {
+            log::error!("Failed to call webhook of pipeline run {}: {:?}", run_id, e);
+        }
+    });
+}
+
+async fn execute_pipeline_job(
+    pipeline_runner: &PipelineRunner,
+    run: PipelineRun,
+    message: PipelineJobMessage,
+) -> Result<()> {
+    let mut graph = serde_json::from_value::<Graph>(run.graph)?;
+    graph.env = message.env;
+    graph.metadata = serde_json::from_value(run.metadata).unwrap_or_default();
+    graph.run_type = RunType::Endpoint;
+
+    let run_result = pipeline_runner
+        .continue_durable(graph, run.id, &run.project_id, None)
+        .await;
+
+    if let Err(e) = pipeline_runner
+        .record_observations(
+            &run_result,
+            &run.project_id,
+            &run.pipeline_version_name,
+            message.current_trace_and_span,
+            None,
+        )
+        .await
+    {
+        log::error!(
+            "Failed to record observations of pipeline run {}: {:?}",
+            run.id,
+            e
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,29 @@
+//! Pipeline runs that are submitted through the API and executed by the pipeline jobs
+//! workers, instead of the web workers.
+//!
+//! A submitted run is stored as a queued durable run, so that it can be polled, cancelled
+//! and resumed like any other durable run, and the queue message references it by id.
+//! The env of the run is carried by the message, because it is never stored.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::trace::CurrentTraceAndSpan;
+
+pub mod consumer;
+pub mod producer;
+pub mod webhook;
+
+pub const PIPELINE_JOBS_QUEUE: &str = "pipeline_jobs_queue";
+pub const PIPELINE_JOBS_EXCHANGE: &str = "pipeline_jobs_exchange";
+pub const PIPELINE_JOBS_ROUTING_KEY: &str = "pipeline_jobs_routing_key";
+
+#[derive(Serialize, Deserialize)]
+pub struct PipelineJobMessage {
+    pub run_id: Uuid,
+    pub project_id: Uuid,
+    pub env: HashMap<String, String>,
+    pub current_trace_and_span: Option<CurrentTraceAndSpan>,
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+
+use crate::{
+    db::{
+        self,
+        pipelines::{NewPipelineRun, PipelineRunStatus},
+        trace::CurrentTraceAndSpan,
+        DB,
+    },
+    mq::{MessageQueue, MessageQueueTrait},
+};
+
+use super::{PipelineJobMessage, PIPELINE_JOBS_EXCHANGE, PIPELINE_JOBS_ROUTING_KEY};
+
+/// Store the queued run and publish it to the pipeline jobs queue.
+///
+/// If the run cannot be published, it is marked as failed.
+pub async fn push_pipeline_job_to_queue(
+    db: Arc<DB>,
+    queue: Arc<MessageQueue>,
+    run: NewPipelineRun,
+    env: HashMap<String, String>,
+    current_trace_and_span: Option<CurrentTraceAndSpan>,
+) -> Result<()> {
+    db::pipelines::create_pipeline_run(&db.pool, &run).await?;
+
+    let message = PipelineJobMessage {
+        run_id: run.id,
+        project_id: run.project_id,
+        env,
+        current_trace_and_span,
+    };
+    publish_pipeline_job(db, queue, message).await
+}
+
+/// Publish a run that is already stored as queued, e.g. a resumed run, to the pipeline
+/// jobs queue.
+///
+/// If the run cannot be published, it is marked as failed.
+pub async fn publish_pipeline_job(
+    db: Arc<DB>,
+    queue: Arc<MessageQueue>,
+    message: PipelineJobMessage,
+) -> Result<()> {
+    let res = queue
+        .publish(
+            &serde_json::to_vec(&message)?,
+            PIPELINE_JOBS_EXCHANGE,
+            PIPELINE_JOBS_ROUTING_KEY,
+        )
+        .await;
+
+    if let Err(e) = res {
+        let error = format!("Failed to queue the run: {}", e);
+        db::pipelines::finish_pipeline_run(
+            &db.pool,
+            &message.run_id,
+            PipelineRunStatus::FAILED,
+            None,
+            Some(&error),
+        )
+        .await?;
+        return Err(e);
+    }
+
+    Ok(())
+}
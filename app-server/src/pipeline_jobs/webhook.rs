@@ -0,0 +1,228 @@
+//! Signed webhook calls that notify the caller when a submitted pipeline run finishes.
+//!
+//! The body of the call is the run, as returned by the run status endpoint. The
+//! `X-Laminar-Signature` header is `sha256=` followed by the hex encoded HMAC-SHA256 of
+//! `{timestamp}.{body}` with the webhook secret of the run, where the timestamp is the
+//! `X-Laminar-Timestamp` header, in seconds since the epoch. Receivers should reject calls
+//! with old timestamps, so that calls cannot be replayed.
+//!
+//! Webhooks are only called on public addresses, so that they cannot be used to reach the
+//! internal network of the app server.
+
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use backoff::ExponentialBackoffBuilder;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect, StatusCode,
+};
+use sha2::Sha256;
+use url::{Host, Url};
+
+use crate::db::pipelines::PipelineRun;
+
+pub const SIGNATURE_HEADER: &str = "X-Laminar-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Laminar-Timestamp";
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Check that the URL is an HTTP or HTTPS URL whose host is not a local or private address.
+///
+/// The addresses that domains resolve to are checked when the webhook is called, by the
+/// resolver of `webhook_client`.
+pub fn validate_webhook_url(url: &str) -> Result<()> {
+    let url = Url::parse(url)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow::anyhow!("Webhook URL must be an HTTP or HTTPS URL"));
+    }
+    let is_public_host = match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        Some(Host::Ipv4(ip)) => is_public_ip(&IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public_ip(&IpAddr::V6(ip)),
+        None => false,
+    };
+    if !is_public_host {
+        return Err(anyhow::anyhow!("Webhook URL must have a public host"));
+    }
+    Ok(())
+}
+
+/// Whether the address is reachable on the public internet, i.e. is not a loopback,
+/// private, link-local, unspecified or otherwise reserved address
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                // "this network", 0.0.0.0/8
+                || a == 0
+                // shared address space, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(ip));
+            }
+            let first_segment = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local, fc00::/7
+                || (first_segment & 0xfe00) == 0xfc00
+                // link-local, fe80::/10
+                || (first_segment & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Resolver that fails for hosts that resolve to addresses that are not public. Checking
+/// the addresses the connection is made to, rather than the addresses the host resolved to
+/// when it was validated, prevents DNS rebinding.
+struct PublicAddrResolver;
+
+impl Resolve for PublicAddrResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .collect::<Vec<_>>();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(&addr.ip())) {
+                return Err(format!(
+                    "Webhook host {} resolves to non-public address {}",
+                    host,
+                    addr.ip()
+                )
+                .into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(addrs)
+        })
+    }
+}
+
+/// Client to call webhooks with, which only connects to public addresses and does not
+/// follow redirects, as they could point to other addresses
+pub fn webhook_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .dns_resolver(Arc::new(PublicAddrResolver))
+        .redirect(redirect::Policy::none())
+        .build()
+        .expect("Failed to build webhook client")
+}
+
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Call the webhook of the run, if it has one. The client must be a `webhook_client`.
+///
+/// Failed requests, rate limits and server errors are retried, other responses that are
+/// not successful fail the call right away.
+pub async fn send_webhook(client: &reqwest::Client, run: &PipelineRun) -> Result<()> {
+    let (Some(url), Some(secret)) = (&run.webhook_url, &run.webhook_secret) else {
+        return Ok(());
+    };
+    // IP addresses in the URL are not resolved, so they are checked here
+    validate_webhook_url(url)?;
+    let body = serde_json::to_vec(run)?;
+
+    let send = || async {
+        let timestamp = Utc::now().timestamp();
+        let response = client
+            .post(url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .header(CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, sign(secret, timestamp, &body))
+            .body(body.clone())
+            .send()
+            .await
+            .map_err(|e| backoff::Error::transient(anyhow::Error::from(e)))?;
+
+        let status = response.status();
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(backoff::Error::transient(anyhow::anyhow!(
+                "Webhook responded with {}",
+                status
+            )));
+        }
+        if !status.is_success() {
+            return Err(backoff::Error::permanent(anyhow::anyhow!(
+                "Webhook responded with {}",
+                status
+            )));
+        }
+
+        Ok::<(), backoff::Error<anyhow::Error>>(())
+    };
+    // Starting with 1 second delay, delay multiplies by random factor between 1 and 2
+    // up to 1 minute and until the total elapsed time is 5 minutes
+    // https://docs.rs/backoff/latest/backoff/default/index.html
+    let exponential_backoff = ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(1000))
+        .with_multiplier(1.5)
+        .with_randomization_factor(0.5)
+        .with_max_interval(Duration::from_secs(60))
+        .with_max_elapsed_time(Some(Duration::from_secs(5 * 60)))
+        .build();
+
+    backoff::future::retry(exponential_backoff, send).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("whsec_test", 1700000000, br#"{"runId":"1"}"#),
+            "sha256=87f0a12b4b55c0a9cbf7921f378c1031f58ff0017df7845575cdf2110ef2157f"
+        );
+    }
+
+    #[test]
+    fn test_validate_webhook_url() {
+        assert!(validate_webhook_url("https://example.com/hooks/laminar").is_ok());
+        assert!(validate_webhook_url("ftp://example.com/hooks").is_err());
+        assert!(validate_webhook_url("not a url").is_err());
+        assert!(validate_webhook_url("http://localhost:8000/hooks").is_err());
+        assert!(validate_webhook_url("http://api.localhost/hooks").is_err());
+        assert!(validate_webhook_url("http://127.0.0.1/hooks").is_err());
+        assert!(validate_webhook_url("http://2130706433/hooks").is_err());
+        assert!(validate_webhook_url("http://10.0.0.1/hooks").is_err());
+        assert!(validate_webhook_url("http://169.254.169.254/latest/meta-data").is_err());
+        assert!(validate_webhook_url("http://0.0.0.0/hooks").is_err());
+        assert!(validate_webhook_url("http://[::1]/hooks").is_err());
+        assert!(validate_webhook_url("http://[::ffff:192.168.0.1]/hooks").is_err());
+        assert!(validate_webhook_url("http://[fd00::1]/hooks").is_err());
+        assert!(validate_webhook_url("https://93.184.215.14/hooks").is_ok());
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        assert!(is_public_ip(&"8.8.8.8".parse().unwrap()));
+        assert!(is_public_ip(&"2606:4700:4700::1111".parse().unwrap()));
+        assert!(!is_public_ip(&"172.16.0.1".parse().unwrap()));
+        assert!(!is_public_ip(&"100.64.0.1".parse().unwrap()));
+        assert!(!is_public_ip(&"fe80::1".parse().unwrap()));
+        assert!(!is_public_ip(&"::".parse().unwrap()));
+    }
+}
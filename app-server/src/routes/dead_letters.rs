@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use actix_web::{get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::ResponseResult;
+use crate::mq::{DeadLetter, DeadLetterAction, DeadLetterFilter, MessageQueue, MessageQueueTrait};
+
+const DEFAULT_DEAD_LETTERS_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetDeadLettersParams {
+    queue: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeadLetterResponse {
+    id: Uuid,
+    queue: String,
+    exchange: String,
+    routing_key: String,
+    reason: String,
+    failed_at: DateTime<Utc>,
+    /// Message payload if it is valid JSON, null otherwise
+    payload: Value,
+    payload_size: usize,
+}
+
+impl From<DeadLetter> for DeadLetterResponse {
+    fn from(dead_letter: DeadLetter) -> Self {
+        Self {
+            id: dead_letter.id,
+            queue: dead_letter.origin.queue,
+            exchange: dead_letter.origin.exchange,
+            routing_key: dead_letter.origin.routing_key,
+            reason: dead_letter.reason,
+            failed_at: dead_letter.failed_at,
+            payload: serde_json::from_slice(&dead_letter.payload).unwrap_or(Value::Null),
+            payload_size: dead_letter.payload.len(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResolveDeadLettersResponse {
+    resolved: usize,
+}
+
+/// Inspect messages that could not be processed by the queue consumers
+#[get("dead-letters")]
+pub async fn get_dead_letters(
+    message_queue: web::Data<Arc<MessageQueue>>,
+    params: web::Query<GetDeadLettersParams>,
+) -> ResponseResult {
+    let params = params.into_inner();
+    let filter = DeadLetterFilter {
+        queue: params.queue,
+        ids: None,
+    };
+    let dead_letters = message_queue
+        .get_dead_letters(&filter, params.limit.unwrap_or(DEFAULT_DEAD_LETTERS_LIMIT))
+        .await?
+        .into_iter()
+        .map(DeadLetterResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(dead_letters))
+}
+
+/// Publish dead letters back to the queues they were consumed from
+#[post("dead-letters/replay")]
+pub async fn replay_dead_letters(
+    message_queue: web::Data<Arc<MessageQueue>>,
+    filter: web::Json<DeadLetterFilter>,
+) -> ResponseResult {
+    let resolved = message_queue
+        .resolve_dead_letters(&filter, DeadLetterAction::Replay)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ResolveDeadLettersResponse { resolved }))
+}
+
+/// Drop dead letters
+#[post("dead-letters/purge")]
+pub async fn purge_dead_letters(
+    message_queue: web::Data<Arc<MessageQueue>>,
+    filter: web::Json<DeadLetterFilter>,
+) -> ResponseResult {
+    let resolved = message_queue
+        .resolve_dead_letters(&filter, DeadLetterAction::Purge)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ResolveDeadLettersResponse { resolved }))
+}
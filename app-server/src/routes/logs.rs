@@ -0,0 +1,44 @@
+use super::ResponseResult;
+use crate::ch::{self, logs::LogsFilter};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use uuid::Uuid;
+
+const DEFAULT_LOGS_LIMIT: u64 = 1000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetLogsParams {
+    trace_id: Option<Uuid>,
+    span_id: Option<Uuid>,
+    /// Minimum OpenTelemetry severity number, e.g. 9 for INFO and 17 for ERROR
+    min_severity: Option<u8>,
+    /// Full-text search on the log body
+    search: Option<String>,
+    limit: Option<u64>,
+    #[serde(default)]
+    offset: u64,
+}
+
+/// Get the logs of a project, e.g. the logs emitted inside a span
+#[get("logs")]
+pub async fn get_logs(
+    path: web::Path<Uuid>,
+    clickhouse: web::Data<clickhouse::Client>,
+    params: web::Query<GetLogsParams>,
+) -> ResponseResult {
+    let project_id = path.into_inner();
+    let params = params.into_inner();
+
+    let filter = LogsFilter {
+        trace_id: params.trace_id,
+        span_id: params.span_id,
+        min_severity: params.min_severity,
+        search: params.search.filter(|s| !s.is_empty()),
+        limit: params.limit.unwrap_or(DEFAULT_LOGS_LIMIT),
+        offset: params.offset,
+    };
+    let logs = ch::logs::get_logs(&clickhouse, project_id, filter).await?;
+
+    Ok(HttpResponse::Ok().json(logs))
+}
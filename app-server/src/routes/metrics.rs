@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use super::{GetMetricsQueryParams, ResponseResult};
+use crate::ch::utils::get_bounds;
+use crate::{
+    ch::{self, metrics::MetricField},
+    db::modifiers::{DateRange, RelativeDateInterval},
+};
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetMetricValuesParams {
+    /// Name of the OpenTelemetry metric
+    name: String,
+    #[serde(default)]
+    field: MetricField,
+    /// Only include data points with these attribute values
+    #[serde(default)]
+    attributes: HashMap<String, String>,
+    #[serde(flatten)]
+    base_params: GetMetricsQueryParams,
+}
+
+/// Get the names of the OpenTelemetry metrics reported in the project
+#[get("metrics/names")]
+pub async fn get_metric_names(
+    params: web::Path<Uuid>,
+    clickhouse: web::Data<clickhouse::Client>,
+) -> ResponseResult {
+    let project_id = params.into_inner();
+    let names = ch::metrics::get_metric_names(&clickhouse, project_id).await?;
+
+    Ok(HttpResponse::Ok().json(names))
+}
+
+/// Get aggregated values of an OpenTelemetry metric over time
+#[post("metrics")]
+pub async fn get_metric_values(
+    params: web::Path<Uuid>,
+    clickhouse: web::Data<clickhouse::Client>,
+    req: web::Json<GetMetricValuesParams>,
+) -> ResponseResult {
+    let project_id = params.into_inner();
+    let clickhouse = clickhouse.into_inner().as_ref().clone();
+    let req = req.into_inner();
+    let aggregation = req.base_params.aggregation;
+    let group_by_interval = req.base_params.group_by_interval;
+
+    // Same as for trace metrics, default to all time
+    let defaulted_range =
+        req.base_params
+            .date_range
+            .unwrap_or(DateRange::Relative(RelativeDateInterval {
+                past_hours: "all".to_string(),
+            }));
+
+    let values = match defaulted_range {
+        DateRange::Relative(interval) if interval.past_hours == "all" => {
+            let (start_time, end_time) =
+                get_bounds(&clickhouse, &project_id, "metrics", "timestamp").await?;
+            ch::metrics::get_metric_values_absolute(
+                &clickhouse,
+                project_id,
+                &req.name,
+                req.field,
+                &req.attributes,
+                group_by_interval,
+                start_time,
+                end_time,
+                aggregation,
+            )
+            .await?
+        }
+        DateRange::Relative(interval) => {
+            let past_hours = interval
+                .past_hours
+                .parse::<i64>()
+                .map_err(|e| anyhow::anyhow!("Failed to parse past_hours as i64: {}", e))?;
+            ch::metrics::get_metric_values_relative(
+                &clickhouse,
+                project_id,
+                &req.name,
+                req.field,
+                &req.attributes,
+                group_by_interval,
+                past_hours,
+                aggregation,
+            )
+            .await?
+        }
+        DateRange::Absolute(interval) => {
+            ch::metrics::get_metric_values_absolute(
+                &clickhouse,
+                project_id,
+                &req.name,
+                req.field,
+                &req.attributes,
+                group_by_interval,
+                interval.start_date,
+                interval.end_date,
+                aggregation,
+            )
+            .await?
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(values))
+}
@@ -1,14 +1,20 @@
 pub mod api_keys;
 pub mod auth;
 pub mod datasets;
+pub mod dead_letters;
 pub mod error;
 pub mod evaluations;
 pub mod labels;
 pub mod limits;
+pub mod logs;
+pub mod metrics;
+pub mod payloads;
 pub mod pipelines;
+pub mod prices;
 pub mod probes;
 pub mod projects;
 pub mod provider_api_keys;
+pub mod redaction_rules;
 pub mod traces;
 pub mod types;
 pub mod workspace;
@@ -0,0 +1,165 @@
+use std::{env, sync::Arc, time::Duration};
+
+use actix_web::{get, put, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{error::Error, ResponseResult};
+use crate::{
+    db::{self, projects::PayloadSettings, DB},
+    storage::{get_key, get_url, PayloadHeaders, Storage, StorageTrait},
+};
+
+const DEFAULT_PAYLOAD_URL_EXPIRY_SECONDS: i32 = 3600;
+/// Presigned S3 urls can be valid for at most 7 days
+const MAX_PAYLOAD_URL_EXPIRY_SECONDS: i32 = 7 * 24 * 3600;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPayloadParams {
+    /// `image` or `raw` to return the payload inline, otherwise it is returned as an attachment
+    pub payload_type: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PayloadUrlResponse {
+    url: String,
+    /// If true, the url points directly to the storage and expires at `expires_at`.
+    /// Otherwise, the payload must be fetched through the payload endpoint.
+    presigned: bool,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Get a payload stored for a span: its input or output, or media from its messages
+#[get("payloads/{payload_id}")]
+pub async fn get_payload(
+    path: web::Path<(Uuid, String)>,
+    storage: web::Data<Arc<Storage>>,
+    params: web::Query<GetPayloadParams>,
+) -> ResponseResult {
+    let (project_id, payload_id) = path.into_inner();
+    let params = params.into_inner();
+
+    payload_response(
+        &storage,
+        &project_id,
+        &payload_id,
+        params.payload_type.as_deref(),
+    )
+    .await
+}
+
+/// Get a url to load the payload directly from the storage, if the storage supports it
+#[get("payloads/{payload_id}/url")]
+pub async fn get_payload_url(
+    path: web::Path<(Uuid, String)>,
+    db: web::Data<DB>,
+    storage: web::Data<Arc<Storage>>,
+    params: web::Query<GetPayloadParams>,
+) -> ResponseResult {
+    let (project_id, payload_id) = path.into_inner();
+    let params = params.into_inner();
+    validate_payload_id(&payload_id)?;
+
+    let settings = db::projects::get_payload_settings(&db.pool, &project_id).await?;
+    let expiry_seconds = settings
+        .payload_url_expiry_seconds
+        .unwrap_or_else(default_payload_url_expiry_seconds);
+
+    let key = get_key(&project_id, &payload_id);
+    let headers = PayloadHeaders::new(&payload_id, params.payload_type.as_deref());
+    let presigned_url = storage
+        .get_presigned_url(&key, Duration::from_secs(expiry_seconds as u64), &headers)
+        .await?;
+
+    let response = match presigned_url {
+        Some(url) => PayloadUrlResponse {
+            url,
+            presigned: true,
+            expires_at: Some(Utc::now() + chrono::Duration::seconds(expiry_seconds as i64)),
+        },
+        None => PayloadUrlResponse {
+            url: get_url(&key),
+            presigned: false,
+            expires_at: None,
+        },
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("payload-settings")]
+pub async fn get_payload_settings(
+    project_id: web::Path<Uuid>,
+    db: web::Data<DB>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let settings = db::projects::get_payload_settings(&db.pool, &project_id).await?;
+
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+#[put("payload-settings")]
+pub async fn update_payload_settings(
+    project_id: web::Path<Uuid>,
+    db: web::Data<DB>,
+    settings: web::Json<PayloadSettings>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let settings = settings.into_inner();
+
+    if let Some(expiry_seconds) = settings.payload_url_expiry_seconds {
+        if expiry_seconds <= 0 || expiry_seconds > MAX_PAYLOAD_URL_EXPIRY_SECONDS {
+            return Err(Error::invalid_request(Some(&format!(
+                "Payload url expiry must be between 1 and {} seconds",
+                MAX_PAYLOAD_URL_EXPIRY_SECONDS
+            ))));
+        }
+    }
+    db::projects::update_payload_settings(&db.pool, &project_id, &settings).await?;
+
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+pub async fn payload_response(
+    storage: &Storage,
+    project_id: &Uuid,
+    payload_id: &str,
+    payload_type: Option<&str>,
+) -> ResponseResult {
+    validate_payload_id(payload_id)?;
+
+    let key = get_key(project_id, payload_id);
+    if !storage.exists(&key).await? {
+        return Ok(HttpResponse::NotFound().body(format!("Payload not found: {}", payload_id)));
+    }
+    let data = storage.get_stream(&key).await?;
+
+    let headers = PayloadHeaders::new(payload_id, payload_type);
+    let mut response = HttpResponse::Ok();
+    if let Some(content_type) = headers.content_type {
+        response.content_type(content_type);
+    }
+    if let Some(content_disposition) = headers.content_disposition {
+        response.insert_header(("Content-Disposition", content_disposition));
+    }
+
+    Ok(response.streaming(data))
+}
+
+fn validate_payload_id(payload_id: &str) -> Result<(), Error> {
+    if payload_id.is_empty() || payload_id.contains('/') || payload_id.starts_with('.') {
+        return Err(Error::invalid_request(Some("Invalid payload id")));
+    }
+    Ok(())
+}
+
+fn default_payload_url_expiry_seconds() -> i32 {
+    env::var("PAYLOAD_URL_EXPIRY_SECONDS")
+        .ok()
+        .and_then(|v| v.parse::<i32>().ok())
+        .filter(|v| *v > 0 && *v <= MAX_PAYLOAD_URL_EXPIRY_SECONDS)
+        .unwrap_or(DEFAULT_PAYLOAD_URL_EXPIRY_SECONDS)
+}
@@ -118,6 +118,7 @@ async fn run_pipeline_graph(
             let run_result = pipeline_runner
                 .run_workshop(
                     graph,
+                    &project_id,
                     Some(tx.clone()),
                     prefilled_messages,
                     start_task_id,
@@ -186,6 +187,7 @@ async fn run_pipeline_graph(
         let run_result = pipeline_runner
             .run_workshop(
                 graph,
+                &project_id,
                 None, // Don't pass tx when streaming is disabled
                 prefilled_messages,
                 start_task_id,
@@ -0,0 +1,242 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{error::Error, ResponseResult};
+use crate::{
+    cache::{
+        keys::{MODEL_NAME_RULES_CACHE_KEY, PROJECT_LLM_PRICES_CACHE_KEY},
+        Cache, CacheTrait,
+    },
+    db::{self, model_name_rules::ModelNameRuleFields, prices::ProjectPriceFields, DB},
+    language_model::costs::model_names::validate_rule,
+    traces::cost_backfill::run_cost_backfill,
+};
+
+fn validate_price(fields: &ProjectPriceFields) -> Result<(), Error> {
+    if fields.provider.is_empty() || fields.model.is_empty() {
+        return Err(Error::invalid_request(Some(
+            "Provider and model must not be empty",
+        )));
+    }
+    let prices = [
+        Some(fields.input_price_per_million),
+        Some(fields.output_price_per_million),
+        fields.input_cached_price_per_million,
+    ];
+    if prices.iter().flatten().any(|price| *price < 0.0) || !fields.additional_prices.is_object() {
+        return Err(Error::invalid_request(Some(
+            "Prices must be non-negative, and additional prices must be an object",
+        )));
+    }
+    if let (Some(valid_from), Some(valid_to)) = (fields.valid_from, fields.valid_to) {
+        if valid_from >= valid_to {
+            return Err(Error::invalid_request(Some(
+                "validFrom must be earlier than validTo",
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[get("prices")]
+pub async fn get_project_prices(project_id: web::Path<Uuid>, db: web::Data<DB>) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let prices = db::prices::get_project_prices(&db.pool, &project_id).await?;
+
+    Ok(HttpResponse::Ok().json(prices))
+}
+
+#[post("prices")]
+pub async fn create_project_price(
+    project_id: web::Path<Uuid>,
+    req: web::Json<ProjectPriceFields>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let fields = req.into_inner();
+    validate_price(&fields)?;
+
+    let price = db::prices::create_project_price(&db.pool, &project_id, &fields).await?;
+    let _ = cache
+        .remove(&format!("{PROJECT_LLM_PRICES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().json(price))
+}
+
+#[put("prices/{price_id}")]
+pub async fn update_project_price(
+    path: web::Path<(Uuid, Uuid)>,
+    req: web::Json<ProjectPriceFields>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let (project_id, price_id) = path.into_inner();
+    let fields = req.into_inner();
+    validate_price(&fields)?;
+
+    let Some(price) =
+        db::prices::update_project_price(&db.pool, &project_id, &price_id, &fields).await?
+    else {
+        return Ok(HttpResponse::NotFound().body(format!("Price not found: {}", price_id)));
+    };
+    let _ = cache
+        .remove(&format!("{PROJECT_LLM_PRICES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().json(price))
+}
+
+#[delete("prices/{price_id}")]
+pub async fn delete_project_price(
+    path: web::Path<(Uuid, Uuid)>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let (project_id, price_id) = path.into_inner();
+
+    db::prices::delete_project_price(&db.pool, &project_id, &price_id).await?;
+    let _ = cache
+        .remove(&format!("{PROJECT_LLM_PRICES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("model-name-rules")]
+pub async fn get_model_name_rules(
+    project_id: web::Path<Uuid>,
+    db: web::Data<DB>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let rules = db::model_name_rules::get_model_name_rules(&db.pool, &project_id).await?;
+
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+#[post("model-name-rules")]
+pub async fn create_model_name_rule(
+    project_id: web::Path<Uuid>,
+    req: web::Json<ModelNameRuleFields>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let fields = req.into_inner();
+    validate_rule(&fields.rule_type, &fields.pattern)
+        .map_err(|e| Error::invalid_request(Some(&e.to_string())))?;
+
+    let rule = db::model_name_rules::create_model_name_rule(&db.pool, &project_id, &fields).await?;
+    let _ = cache
+        .remove(&format!("{MODEL_NAME_RULES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[put("model-name-rules/{rule_id}")]
+pub async fn update_model_name_rule(
+    path: web::Path<(Uuid, Uuid)>,
+    req: web::Json<ModelNameRuleFields>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let (project_id, rule_id) = path.into_inner();
+    let fields = req.into_inner();
+    validate_rule(&fields.rule_type, &fields.pattern)
+        .map_err(|e| Error::invalid_request(Some(&e.to_string())))?;
+
+    let Some(rule) =
+        db::model_name_rules::update_model_name_rule(&db.pool, &project_id, &rule_id, &fields)
+            .await?
+    else {
+        return Ok(HttpResponse::NotFound().body(format!("Model name rule not found: {}", rule_id)));
+    };
+    let _ = cache
+        .remove(&format!("{MODEL_NAME_RULES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[delete("model-name-rules/{rule_id}")]
+pub async fn delete_model_name_rule(
+    path: web::Path<(Uuid, Uuid)>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let (project_id, rule_id) = path.into_inner();
+
+    db::model_name_rules::delete_model_name_rule(&db.pool, &project_id, &rule_id).await?;
+    let _ = cache
+        .remove(&format!("{MODEL_NAME_RULES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackfillCostsRequest {
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+}
+
+/// Start recalculating the costs of the LLM spans that started in the time range, and of
+/// their traces, with the current prices and model name rules.
+///
+/// The backfill runs in the background. The response is the backfill, whose progress can be
+/// polled by its id.
+#[post("prices/backfill")]
+pub async fn backfill_costs(
+    project_id: web::Path<Uuid>,
+    req: web::Json<BackfillCostsRequest>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+    clickhouse: web::Data<clickhouse::Client>,
+    runtime_handle: web::Data<tokio::runtime::Handle>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let req = req.into_inner();
+    if req.start_time >= req.end_time {
+        return Err(Error::invalid_request(Some(
+            "startTime must be earlier than endTime",
+        )));
+    }
+
+    let backfill = db::cost_backfills::create_cost_backfill(
+        &db.pool,
+        &project_id,
+        req.start_time,
+        req.end_time,
+    )
+    .await?;
+
+    let clickhouse = clickhouse.into_inner().as_ref().clone();
+    runtime_handle.spawn(run_cost_backfill(
+        db.into_inner(),
+        cache.into_inner(),
+        clickhouse,
+        backfill.clone(),
+    ));
+
+    Ok(HttpResponse::Accepted().json(backfill))
+}
+
+#[get("prices/backfill/{backfill_id}")]
+pub async fn get_cost_backfill(path: web::Path<(Uuid, Uuid)>, db: web::Data<DB>) -> ResponseResult {
+    let (project_id, backfill_id) = path.into_inner();
+
+    let Some(backfill) =
+        db::cost_backfills::get_cost_backfill(&db.pool, &project_id, &backfill_id).await?
+    else {
+        return Ok(
+            HttpResponse::NotFound().body(format!("Cost backfill not found: {}", backfill_id))
+        );
+    };
+
+    Ok(HttpResponse::Ok().json(backfill))
+}
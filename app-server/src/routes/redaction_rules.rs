@@ -0,0 +1,93 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{error::Error, ResponseResult};
+use crate::{
+    cache::{keys::REDACTION_RULES_CACHE_KEY, Cache, CacheTrait},
+    db::{self, redaction_rules::RedactionRuleFields, DB},
+    traces::redaction::{detector_names, validate_rule},
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RedactionDetectorsResponse {
+    detectors: Vec<&'static str>,
+}
+
+#[get("redaction-rules")]
+pub async fn get_redaction_rules(project_id: web::Path<Uuid>, db: web::Data<DB>) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let rules = db::redaction_rules::get_redaction_rules(&db.pool, &project_id).await?;
+
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+/// Names of the built-in detectors, to be used as the pattern of DETECTOR rules
+#[get("redaction-rules/detectors")]
+pub async fn get_redaction_detectors() -> ResponseResult {
+    Ok(HttpResponse::Ok().json(RedactionDetectorsResponse {
+        detectors: detector_names(),
+    }))
+}
+
+#[post("redaction-rules")]
+pub async fn create_redaction_rule(
+    project_id: web::Path<Uuid>,
+    req: web::Json<RedactionRuleFields>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let fields = req.into_inner();
+    validate_rule(&fields.rule_type, &fields.pattern)
+        .map_err(|e| Error::invalid_request(Some(&e.to_string())))?;
+
+    let rule = db::redaction_rules::create_redaction_rule(&db.pool, &project_id, &fields).await?;
+    let _ = cache
+        .remove(&format!("{REDACTION_RULES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[put("redaction-rules/{rule_id}")]
+pub async fn update_redaction_rule(
+    path: web::Path<(Uuid, Uuid)>,
+    req: web::Json<RedactionRuleFields>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let (project_id, rule_id) = path.into_inner();
+    let fields = req.into_inner();
+    validate_rule(&fields.rule_type, &fields.pattern)
+        .map_err(|e| Error::invalid_request(Some(&e.to_string())))?;
+
+    let Some(rule) =
+        db::redaction_rules::update_redaction_rule(&db.pool, &project_id, &rule_id, &fields)
+            .await?
+    else {
+        return Ok(HttpResponse::NotFound().body(format!("Redaction rule not found: {}", rule_id)));
+    };
+    let _ = cache
+        .remove(&format!("{REDACTION_RULES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[delete("redaction-rules/{rule_id}")]
+pub async fn delete_redaction_rule(
+    path: web::Path<(Uuid, Uuid)>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+) -> ResponseResult {
+    let (project_id, rule_id) = path.into_inner();
+
+    db::redaction_rules::delete_redaction_rule(&db.pool, &project_id, &rule_id).await?;
+    let _ = cache
+        .remove(&format!("{REDACTION_RULES_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().finish())
+}
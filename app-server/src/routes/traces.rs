@@ -1,12 +1,26 @@
-use super::{GetMetricsQueryParams, ResponseResult};
+use super::{error::Error, GetMetricsQueryParams, ResponseResult};
 use crate::ch::utils::get_bounds;
 use crate::{
-    ch::{self, modifiers::GroupByInterval, Aggregation},
-    db::modifiers::{DateRange, RelativeDateInterval},
+    cache::{keys::SAMPLING_POLICY_CACHE_KEY, Cache, CacheTrait},
+    ch::{
+        self,
+        modifiers::GroupByInterval,
+        spans::{AttributeFilter, SpanFilters},
+        Aggregation,
+    },
+    db::{
+        self,
+        events::TraceEvent,
+        modifiers::{DateRange, RelativeDateInterval},
+        projects::SamplingPolicy,
+        spans::ErrorSpan,
+        DB,
+    },
 };
-use actix_web::{post, web, HttpResponse};
-use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use actix_web::{get, post, put, web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
 #[derive(Deserialize)]
@@ -18,10 +32,32 @@ enum TraceMetric {
     CostUsd,
 }
 
+impl TraceMetric {
+    /// Value of the metric for a single trace
+    fn ch_metric(&self) -> &'static str {
+        match self {
+            TraceMetric::TraceCount => ch::spans::TRACE_COUNT_METRIC,
+            TraceMetric::TraceLatencySeconds => ch::spans::TRACE_LATENCY_SECONDS_METRIC,
+            TraceMetric::TotalTokenCount => ch::spans::TOTAL_TOKEN_COUNT_METRIC,
+            TraceMetric::CostUsd => ch::spans::COST_USD_METRIC,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GetTraceMetricsParams {
     metric: TraceMetric,
+    /// Only include spans of this service, i.e. with this `service.name` resource attribute
+    #[serde(default)]
+    service_name: Option<String>,
+    /// Only include spans with these attribute values, e.g. `metadata.customer_id`
+    #[serde(default)]
+    attribute_filters: Vec<AttributeFilter>,
+    /// Return the values for each value of this span attribute, e.g. the cost per
+    /// `metadata.customer_id`
+    #[serde(default)]
+    group_by_attribute: Option<String>,
     #[serde(flatten)]
     base_params: GetMetricsQueryParams,
 }
@@ -40,6 +76,23 @@ pub async fn get_traces_metrics(
     let aggregation = req.base_params.aggregation;
     let date_range = req.base_params.date_range.as_ref();
     let group_by_interval = req.base_params.group_by_interval;
+    let filters = SpanFilters {
+        service_name: req.service_name,
+        attributes: req.attribute_filters,
+    };
+    let group_by_attribute = req.group_by_attribute;
+
+    // Each trace is counted once, so only the total count is meaningful
+    if group_by_attribute.is_some()
+        && matches!(metric, TraceMetric::TraceCount)
+        && !matches!(aggregation, Aggregation::Total)
+    {
+        return Err(anyhow::anyhow!(
+            "{} grouping is not supported for traceCount metric",
+            aggregation.to_string()
+        )
+        .into());
+    }
 
     // We expect the frontend to always provide a date range.
     // However, for smooth UX we default this to all time.
@@ -55,6 +108,21 @@ pub async fn get_traces_metrics(
             if interval.past_hours == "all" {
                 let (start_time, end_time) =
                     get_bounds(&clickhouse, &project_id, "spans", "start_time").await?;
+                if let Some(attribute) = group_by_attribute {
+                    let values = ch::spans::get_trace_metrics_by_attribute_absolute(
+                        clickhouse,
+                        group_by_interval,
+                        project_id,
+                        start_time,
+                        end_time,
+                        aggregation,
+                        &filters,
+                        &attribute,
+                        metric.ch_metric(),
+                    )
+                    .await?;
+                    return Ok(HttpResponse::Ok().json(values));
+                }
                 return get_metrics_absolute_time(
                     clickhouse.clone(),
                     metric,
@@ -63,6 +131,7 @@ pub async fn get_traces_metrics(
                     end_time,
                     group_by_interval,
                     aggregation,
+                    &filters,
                 )
                 .await;
             } else {
@@ -70,6 +139,20 @@ pub async fn get_traces_metrics(
                     .past_hours
                     .parse::<i64>()
                     .map_err(|e| anyhow::anyhow!("Failed to parse past_hours as i64: {}", e))?;
+                if let Some(attribute) = group_by_attribute {
+                    let values = ch::spans::get_trace_metrics_by_attribute_relative(
+                        clickhouse,
+                        group_by_interval,
+                        project_id,
+                        past_hours,
+                        aggregation,
+                        &filters,
+                        &attribute,
+                        metric.ch_metric(),
+                    )
+                    .await?;
+                    return Ok(HttpResponse::Ok().json(values));
+                }
                 get_metrics_relative_time(
                     clickhouse.clone(),
                     metric,
@@ -77,11 +160,27 @@ pub async fn get_traces_metrics(
                     past_hours,
                     group_by_interval,
                     aggregation,
+                    &filters,
                 )
                 .await
             }
         }
         DateRange::Absolute(interval) => {
+            if let Some(attribute) = group_by_attribute {
+                let values = ch::spans::get_trace_metrics_by_attribute_absolute(
+                    clickhouse,
+                    group_by_interval,
+                    project_id,
+                    interval.start_date,
+                    interval.end_date,
+                    aggregation,
+                    &filters,
+                    &attribute,
+                    metric.ch_metric(),
+                )
+                .await?;
+                return Ok(HttpResponse::Ok().json(values));
+            }
             get_metrics_absolute_time(
                 clickhouse.clone(),
                 metric,
@@ -90,6 +189,7 @@ pub async fn get_traces_metrics(
                 interval.end_date,
                 group_by_interval,
                 aggregation,
+                &filters,
             )
             .await
         }
@@ -103,6 +203,7 @@ async fn get_metrics_relative_time(
     past_hours: i64,
     group_by_interval: GroupByInterval,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> ResponseResult {
     match metric {
         TraceMetric::TraceCount => match aggregation {
@@ -112,6 +213,7 @@ async fn get_metrics_relative_time(
                     group_by_interval,
                     project_id,
                     past_hours,
+                    filters,
                 )
                 .await?;
 
@@ -132,6 +234,7 @@ async fn get_metrics_relative_time(
                 project_id,
                 past_hours,
                 aggregation,
+                filters,
             )
             .await?;
 
@@ -145,6 +248,7 @@ async fn get_metrics_relative_time(
                     project_id,
                     past_hours,
                     aggregation,
+                    filters,
                 )
                 .await?;
 
@@ -166,6 +270,7 @@ async fn get_metrics_relative_time(
                     project_id,
                     past_hours,
                     aggregation,
+                    filters,
                 )
                 .await?;
 
@@ -190,6 +295,7 @@ async fn get_metrics_absolute_time(
     end_time: DateTime<Utc>,
     group_by_interval: GroupByInterval,
     aggregation: Aggregation,
+    filters: &SpanFilters,
 ) -> ResponseResult {
     match metric {
         TraceMetric::TraceCount => {
@@ -200,6 +306,7 @@ async fn get_metrics_absolute_time(
                 start_time,
                 end_time,
                 aggregation,
+                filters,
             )
             .await?;
 
@@ -213,6 +320,7 @@ async fn get_metrics_absolute_time(
                 start_time,
                 end_time,
                 aggregation,
+                filters,
             )
             .await?;
 
@@ -226,6 +334,7 @@ async fn get_metrics_absolute_time(
                 start_time,
                 end_time,
                 aggregation,
+                filters,
             )
             .await?;
 
@@ -239,6 +348,7 @@ async fn get_metrics_absolute_time(
                 start_time,
                 end_time,
                 aggregation,
+                filters,
             )
             .await?;
 
@@ -246,3 +356,183 @@ async fn get_metrics_absolute_time(
         }
     }
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetTraceTimelineParams {
+    #[serde(default)]
+    exceptions_only: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EventException {
+    r#type: Option<String>,
+    message: Option<String>,
+    stacktrace: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TimelineEvent {
+    id: Uuid,
+    span_id: Uuid,
+    span_name: String,
+    timestamp: DateTime<Utc>,
+    name: String,
+    attributes: Value,
+    /// Parsed from the OpenTelemetry `exception.*` attributes
+    exception: Option<EventException>,
+}
+
+impl From<TraceEvent> for TimelineEvent {
+    fn from(event: TraceEvent) -> Self {
+        let attribute = |key: &str| {
+            event
+                .attributes
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(String::from)
+        };
+        let exception = if event.name == db::events::EXCEPTION_EVENT_NAME {
+            Some(EventException {
+                r#type: attribute("exception.type"),
+                message: attribute("exception.message"),
+                stacktrace: attribute("exception.stacktrace"),
+            })
+        } else {
+            None
+        };
+
+        Self {
+            id: event.id,
+            span_id: event.span_id,
+            span_name: event.span_name,
+            timestamp: event.timestamp,
+            name: event.name,
+            attributes: event.attributes,
+            exception,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TimelineErrorSpan {
+    span_id: Uuid,
+    name: String,
+    status_message: Option<String>,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+}
+
+impl From<ErrorSpan> for TimelineErrorSpan {
+    fn from(span: ErrorSpan) -> Self {
+        Self {
+            span_id: span.span_id,
+            name: span.name,
+            status_message: span.status_message,
+            start_time: span.start_time,
+            end_time: span.end_time,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceTimeline {
+    events: Vec<TimelineEvent>,
+    error_spans: Vec<TimelineErrorSpan>,
+}
+
+/// Get the events, including exceptions, and the failed spans of a trace in chronological order
+#[get("traces/{trace_id}/timeline")]
+pub async fn get_trace_timeline(
+    path: web::Path<(Uuid, Uuid)>,
+    db: web::Data<DB>,
+    params: web::Query<GetTraceTimelineParams>,
+) -> ResponseResult {
+    let (project_id, trace_id) = path.into_inner();
+
+    let events =
+        db::events::get_trace_events(&db.pool, project_id, trace_id, params.exceptions_only)
+            .await?;
+    let error_spans = db::spans::get_trace_error_spans(&db.pool, project_id, trace_id).await?;
+
+    Ok(HttpResponse::Ok().json(TraceTimeline {
+        events: events.into_iter().map(TimelineEvent::from).collect(),
+        error_spans: error_spans
+            .into_iter()
+            .map(TimelineErrorSpan::from)
+            .collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetErrorRatesParams {
+    #[serde(default, flatten)]
+    date_range: Option<DateRange>,
+}
+
+/// Get the share of spans with the error status for each span path
+#[post("spans/error-rates")]
+pub async fn get_span_error_rates(
+    params: web::Path<Uuid>,
+    clickhouse: web::Data<clickhouse::Client>,
+    req: web::Json<GetErrorRatesParams>,
+) -> ResponseResult {
+    let project_id = params.into_inner();
+    let date_range = req.into_inner().date_range.unwrap_or_default();
+
+    let (start_time, end_time) = match date_range {
+        DateRange::Relative(interval) if interval.past_hours == "all" => {
+            get_bounds(&clickhouse, &project_id, "spans", "start_time").await?
+        }
+        DateRange::Relative(interval) => {
+            let past_hours = interval
+                .past_hours
+                .parse::<i64>()
+                .map_err(|e| anyhow::anyhow!("Failed to parse past_hours as i64: {}", e))?;
+            let end_time = Utc::now();
+            (end_time - Duration::hours(past_hours), end_time)
+        }
+        DateRange::Absolute(interval) => (interval.start_date, interval.end_date),
+    };
+
+    let error_rates =
+        ch::spans::get_error_rates_by_path(&clickhouse, project_id, start_time, end_time).await?;
+
+    Ok(HttpResponse::Ok().json(error_rates))
+}
+
+#[get("sampling-policy")]
+pub async fn get_sampling_policy(project_id: web::Path<Uuid>, db: web::Data<DB>) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let policy = db::projects::get_sampling_policy(&db.pool, &project_id).await?;
+
+    Ok(HttpResponse::Ok().json(policy))
+}
+
+/// Set the server-side sampling policy of the project traces. Spans dropped by the
+/// policy are not stored and do not count towards the usage limits.
+#[put("sampling-policy")]
+pub async fn update_sampling_policy(
+    project_id: web::Path<Uuid>,
+    db: web::Data<DB>,
+    cache: web::Data<Cache>,
+    policy: web::Json<SamplingPolicy>,
+) -> ResponseResult {
+    let project_id = project_id.into_inner();
+    let policy = policy.into_inner();
+    policy
+        .validate()
+        .map_err(|e| Error::invalid_request(Some(&e)))?;
+
+    db::projects::update_sampling_policy(&db.pool, &project_id, &policy).await?;
+    let _ = cache
+        .remove(&format!("{SAMPLING_POLICY_CACHE_KEY}:{project_id}"))
+        .await;
+
+    Ok(HttpResponse::Ok().json(policy))
+}
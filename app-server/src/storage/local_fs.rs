@@ -0,0 +1,94 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use bytes::BytesMut;
+use tokio::io::AsyncReadExt;
+
+use super::{ByteStream, PayloadHeaders};
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stores payloads as files under a local directory, for self-hosted deployments without S3
+pub struct LocalFsStorage {
+    root: PathBuf,
+}
+
+impl LocalFsStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn get_path(&self, key: &str) -> Result<PathBuf> {
+        let key_path = Path::new(key);
+        // Keys are relative paths, make sure they can't escape the root directory
+        if key_path.as_os_str().is_empty()
+            || !key_path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow::anyhow!("Invalid storage key: {}", key));
+        }
+        Ok(self.root.join(key_path))
+    }
+}
+
+#[async_trait::async_trait]
+impl super::StorageTrait for LocalFsStorage {
+    async fn store(&self, data: Vec<u8>, key: &str) -> Result<String> {
+        let path = self.get_path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, data).await?;
+
+        Ok(super::get_url(key))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let path = self.get_path(key)?;
+        Ok(tokio::fs::read(&path).await?)
+    }
+
+    async fn get_stream(&self, key: &str) -> Result<ByteStream> {
+        let path = self.get_path(key)?;
+        let file = tokio::fs::File::open(&path).await?;
+        // The stream ends after the end of the file or the first read error
+        let stream = futures_util::stream::unfold(Some(file), |file| async move {
+            let mut file = file?;
+            let mut chunk = BytesMut::with_capacity(READ_CHUNK_SIZE);
+            match file.read_buf(&mut chunk).await {
+                Ok(0) => None,
+                Ok(_) => Some((Ok(chunk.freeze()), Some(file))),
+                Err(e) => Some((Err(e.into()), None)),
+            }
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.get_path(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let path = self.get_path(key)?;
+        Ok(tokio::fs::try_exists(&path).await?)
+    }
+
+    async fn get_presigned_url(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+        _headers: &PayloadHeaders,
+    ) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use anyhow::Result;
 
+use super::{ByteStream, PayloadHeaders};
+
 pub struct MockStorage;
 
 #[async_trait::async_trait]
@@ -7,4 +11,29 @@ impl super::StorageTrait for MockStorage {
     async fn store(&self, _data: Vec<u8>, _key: &str) -> Result<String> {
         Ok("mock".to_string())
     }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        Err(anyhow::anyhow!("Mock storage does not store {}", key))
+    }
+
+    async fn get_stream(&self, key: &str) -> Result<ByteStream> {
+        Err(anyhow::anyhow!("Mock storage does not store {}", key))
+    }
+
+    async fn delete(&self, _key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn exists(&self, _key: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn get_presigned_url(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+        _headers: &PayloadHeaders,
+    ) -> Result<Option<String>> {
+        Ok(None)
+    }
 }
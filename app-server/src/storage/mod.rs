@@ -1,25 +1,76 @@
+use std::{pin::Pin, time::Duration};
+
 use anyhow::Result;
 use async_trait::async_trait;
 use base64::{prelude::BASE64_STANDARD, Engine};
+use bytes::Bytes;
 use enum_dispatch::enum_dispatch;
+use futures_core::Stream;
 use uuid::Uuid;
 
+pub mod local_fs;
 pub mod mock;
 pub mod s3;
 
+use local_fs::LocalFsStorage;
 use mock::MockStorage;
 use s3::S3Storage;
 
+/// Chunks of a stored object, read as they are consumed
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>;
+
 #[enum_dispatch]
 pub enum Storage {
     Mock(MockStorage),
     S3(S3Storage),
+    LocalFs(LocalFsStorage),
 }
 
 #[async_trait]
 #[enum_dispatch(Storage)]
 pub trait StorageTrait {
+    /// Store the data under the key and return the url to get it from
     async fn store(&self, data: Vec<u8>, key: &str) -> Result<String>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+    /// Same as `get`, but without reading the whole object into memory
+    async fn get_stream(&self, key: &str) -> Result<ByteStream>;
+    async fn delete(&self, key: &str) -> Result<()>;
+    async fn exists(&self, key: &str) -> Result<bool>;
+    /// Url to get the data directly from the storage, if the storage supports it
+    async fn get_presigned_url(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        headers: &PayloadHeaders,
+    ) -> Result<Option<String>>;
+}
+
+/// Headers to serve a stored payload with
+pub struct PayloadHeaders {
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+}
+
+impl PayloadHeaders {
+    /// `image` and `raw` payload types are served inline, other payloads as attachments
+    pub fn new(payload_id: &str, payload_type: Option<&str>) -> Self {
+        match payload_type {
+            Some("image") | Some("raw") => Self {
+                content_type: None,
+                content_disposition: None,
+            },
+            _ => Self {
+                // the key is set to *.pdf, if we know from the LLM provider
+                // that the media-type is application/pdf
+                content_type: Some(if payload_id.ends_with(".pdf") {
+                    "application/pdf".to_string()
+                } else {
+                    "application/octet-stream".to_string()
+                }),
+                content_disposition: Some(format!("attachment; filename=\"{}\"", payload_id)),
+            },
+        }
+    }
 }
 
 pub fn create_key(project_id: &Uuid, file_extension: &Option<String>) -> String {
@@ -33,6 +84,20 @@ pub fn create_key(project_id: &Uuid, file_extension: &Option<String>) -> String
     )
 }
 
+pub fn get_key(project_id: &Uuid, payload_id: &str) -> String {
+    format!("project/{project_id}/{payload_id}")
+}
+
+/// Url of the payload stored under the key, as served by the frontend
+pub fn get_url(key: &str) -> String {
+    let parts = key
+        .strip_prefix("project/")
+        .unwrap()
+        .split("/")
+        .collect::<Vec<&str>>();
+    format!("/api/projects/{}/payloads/{}", parts[0], parts[1])
+}
+
 pub fn base64_to_bytes(base64: &str) -> Result<Vec<u8>> {
     BASE64_STANDARD
         .decode(base64.as_bytes())
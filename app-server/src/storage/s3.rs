@@ -1,24 +1,80 @@
+use std::{env, time::Duration};
+
 use anyhow::Result;
-use aws_sdk_s3::Client;
+use aws_config::SdkConfig;
+use aws_sdk_s3::{
+    config::{Credentials, Region},
+    presigning::PresigningConfig,
+    Client,
+};
+
+use super::{ByteStream, PayloadHeaders};
 
 #[derive(Clone)]
 pub struct S3Storage {
     client: Client,
     bucket: String,
+    /// Prefix of all object keys in the bucket, e.g. to share the bucket with other data
+    key_prefix: String,
 }
 
 impl S3Storage {
-    pub fn new(client: Client, bucket: String) -> Self {
-        Self { client, bucket }
+    pub fn new(client: Client, bucket: String, key_prefix: String) -> Self {
+        Self {
+            client,
+            bucket,
+            key_prefix,
+        }
     }
 
-    fn get_url(&self, key: &str) -> String {
-        let parts = key
-            .strip_prefix("project/")
-            .unwrap()
-            .split("/")
-            .collect::<Vec<&str>>();
-        format!("/api/projects/{}/payloads/{}", parts[0], parts[1])
+    /// Create the storage from the shared AWS config, overridden with the S3_* env vars,
+    /// so that S3-compatible stores, such as MinIO, can be used
+    pub fn from_env(aws_sdk_config: &SdkConfig) -> Self {
+        let mut config = aws_sdk_s3::config::Builder::from(aws_sdk_config);
+        if let Ok(endpoint_url) = env::var("S3_ENDPOINT_URL") {
+            config = config.endpoint_url(endpoint_url);
+        }
+        if let Ok(region) = env::var("S3_REGION") {
+            config = config.region(Region::new(region));
+        }
+        if env::var("S3_FORCE_PATH_STYLE")
+            .map(|v| v.trim().to_lowercase() == "true")
+            .unwrap_or(false)
+        {
+            config = config.force_path_style(true);
+        }
+        // Credentials of the S3-compatible store, if they are different from the AWS credentials
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            env::var("S3_ACCESS_KEY_ID"),
+            env::var("S3_SECRET_ACCESS_KEY"),
+        ) {
+            config = config.credentials_provider(Credentials::new(
+                access_key_id,
+                secret_access_key,
+                None,
+                None,
+                "s3-storage-env",
+            ));
+        }
+
+        let bucket =
+            env::var("S3_TRACE_PAYLOADS_BUCKET").expect("S3_TRACE_PAYLOADS_BUCKET must be set");
+        let key_prefix = env::var("S3_KEY_PREFIX")
+            .map(|prefix| {
+                let prefix = prefix.trim_matches('/');
+                if prefix.is_empty() {
+                    String::new()
+                } else {
+                    format!("{prefix}/")
+                }
+            })
+            .unwrap_or_default();
+
+        Self::new(Client::from_conf(config.build()), bucket, key_prefix)
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        format!("{}{}", self.key_prefix, key)
     }
 }
 
@@ -31,11 +87,91 @@ impl super::StorageTrait for S3Storage {
         self.client
             .put_object()
             .bucket(&self.bucket)
-            .key(key)
+            .key(self.object_key(key))
             .body(data.into())
             .send()
             .await?;
 
-        Ok(self.get_url(key))
+        Ok(super::get_url(key))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        let data = object.body.collect().await?;
+
+        Ok(data.into_bytes().to_vec())
+    }
+
+    async fn get_stream(&self, key: &str) -> Result<ByteStream> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        let stream = futures_util::stream::unfold(object.body, |mut body| async move {
+            body.next()
+                .await
+                .map(|chunk| (chunk.map_err(anyhow::Error::from), body))
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                if e.as_service_error().map_or(false, |e| e.is_not_found()) {
+                    Ok(false)
+                } else {
+                    Err(e.into())
+                }
+            }
+        }
+    }
+
+    async fn get_presigned_url(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        headers: &PayloadHeaders,
+    ) -> Result<Option<String>> {
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .set_response_content_type(headers.content_type.clone())
+            .set_response_content_disposition(headers.content_disposition.clone())
+            .presigned(PresigningConfig::expires_in(expires_in)?)
+            .await?;
+
+        Ok(Some(request.uri().to_string()))
     }
 }
@@ -87,4 +87,57 @@ impl TraceAttributes {
     pub fn set_top_span_id(&mut self, top_span_id: Uuid) {
         self.top_span_id = Some(top_span_id);
     }
+
+    /// Remove the token counts and costs, e.g. of a span that was already counted towards
+    /// its trace
+    pub fn clear_usage(&mut self) {
+        self.input_token_count = None;
+        self.output_token_count = None;
+        self.total_token_count = None;
+        self.input_cost = None;
+        self.output_cost = None;
+        self.cost = None;
+    }
+
+    /// Merge the attributes of a later span of the same trace, the same way
+    /// consecutive upserts of both attributes would be merged in the database
+    pub fn merge(&mut self, other: TraceAttributes) {
+        if let Some(tokens) = other.input_token_count {
+            self.add_input_tokens(tokens);
+        }
+        if let Some(tokens) = other.output_token_count {
+            self.add_output_tokens(tokens);
+        }
+        if let Some(tokens) = other.total_token_count {
+            self.add_total_tokens(tokens);
+        }
+        if let Some(cost) = other.input_cost {
+            self.add_input_cost(cost);
+        }
+        if let Some(cost) = other.output_cost {
+            self.add_output_cost(cost);
+        }
+        if let Some(cost) = other.cost {
+            self.add_total_cost(cost);
+        }
+        if let Some(start_time) = other.start_time {
+            self.update_start_time(start_time);
+        }
+        if let Some(end_time) = other.end_time {
+            self.update_end_time(end_time);
+        }
+        if self.session_id.is_none() {
+            self.session_id = other.session_id;
+        }
+        if other.trace_type.is_some() {
+            self.trace_type = other.trace_type;
+        }
+        self.metadata = other.metadata;
+        if other.has_browser_session.is_some() {
+            self.has_browser_session = other.has_browser_session;
+        }
+        if self.top_span_id.is_none() {
+            self.top_span_id = other.top_span_id;
+        }
+    }
 }
@@ -0,0 +1,207 @@
+//! Accumulates span messages, so that they are written with multi-row inserts to
+//! the database and a single insert per table to Clickhouse. MQ deliveries are only
+//! acked after the whole batch has been written.
+
+use std::{env, future::Future, sync::Arc, time::Duration};
+
+use backoff::ExponentialBackoffBuilder;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use super::{attributes::TraceAttributes, record_spans_and_events};
+use crate::{
+    ch::{self, events::CHEvent, labels::CHLabel, spans::CHSpan},
+    db::{events::Event, spans::Span, DB},
+    mq::{dead_letter_delivery, MessageOrigin, MessageQueue, MessageQueueAcker},
+};
+
+const DEFAULT_SPANS_BATCH_SIZE: usize = 100;
+const DEFAULT_SPANS_BATCH_MAX_WAIT_MS: u64 = 1000;
+
+#[derive(Default)]
+struct ClickhouseRows {
+    spans: Vec<CHSpan>,
+    events: Vec<CHEvent>,
+    labels: Vec<CHLabel>,
+}
+
+impl ClickhouseRows {
+    fn clear(&mut self) {
+        self.spans.clear();
+        self.events.clear();
+        self.labels.clear();
+    }
+
+    /// Insert the rows that are not in Clickhouse yet. The tables are plain MergeTree
+    /// tables, so rows that were inserted before, e.g. by an earlier attempt or by a batch
+    /// whose deliveries are replayed, are skipped rather than inserted twice.
+    async fn insert(&self, clickhouse: &clickhouse::Client) -> anyhow::Result<()> {
+        insert_with_backoff("spans", || async {
+            let existing = ch::spans::get_existing_span_ids(clickhouse, &self.spans).await?;
+            let spans = self
+                .spans
+                .iter()
+                .filter(|span| !existing.contains(&span.span_id))
+                .collect::<Vec<_>>();
+            ch::spans::insert_spans(clickhouse, &spans).await
+        })
+        .await?;
+        insert_with_backoff("events", || async {
+            let existing = ch::events::get_existing_event_ids(clickhouse, &self.events).await?;
+            let events = self
+                .events
+                .iter()
+                .filter(|event| !existing.contains(&event.id))
+                .collect::<Vec<_>>();
+            ch::events::insert_events(clickhouse, &events).await
+        })
+        .await?;
+        insert_with_backoff("labels", || async {
+            let existing = ch::labels::get_existing_code_labels(clickhouse, &self.labels).await?;
+            let labels = self
+                .labels
+                .iter()
+                .filter(|label| !existing.contains(&(label.class_id, label.span_id)))
+                .collect::<Vec<_>>();
+            ch::labels::insert_labels(clickhouse, &labels).await
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// A span message that has been prepared, but not written yet
+pub struct PendingSpan {
+    pub acker: MessageQueueAcker,
+    pub payload: Vec<u8>,
+    pub project_id: Uuid,
+    pub span: Span,
+    pub events: Vec<Event>,
+    pub trace_attributes: TraceAttributes,
+}
+
+pub struct SpanBatch {
+    pending: Vec<PendingSpan>,
+    rows: ClickhouseRows,
+    max_size: usize,
+    max_wait: Duration,
+    deadline: Option<Instant>,
+}
+
+impl SpanBatch {
+    pub fn new(max_size: usize, max_wait: Duration) -> Self {
+        Self {
+            pending: Vec::with_capacity(max_size),
+            rows: ClickhouseRows::default(),
+            max_size: max_size.max(1),
+            max_wait,
+            deadline: None,
+        }
+    }
+
+    /// Batch size is configured with SPANS_BATCH_SIZE (number of span messages)
+    /// and SPANS_BATCH_MAX_WAIT_MS (time since the first message of the batch)
+    pub fn from_env() -> Self {
+        let max_size = env::var("SPANS_BATCH_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_SPANS_BATCH_SIZE);
+        let max_wait_ms = env::var("SPANS_BATCH_MAX_WAIT_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SPANS_BATCH_MAX_WAIT_MS);
+        Self::new(max_size, Duration::from_millis(max_wait_ms))
+    }
+
+    pub fn push(&mut self, pending: PendingSpan, ch_span: CHSpan) {
+        if self.pending.is_empty() {
+            self.deadline = Some(Instant::now() + self.max_wait);
+        }
+        self.pending.push(pending);
+        self.rows.spans.push(ch_span);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.pending.len() >= self.max_size
+    }
+
+    /// Time by which the batch must be flushed, if it is not empty
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Write the batch to the database and Clickhouse, ack the deliveries on success
+    /// and dead-letter them otherwise.
+    ///
+    /// Returns the spans that have been written.
+    pub async fn flush(
+        &mut self,
+        db: Arc<DB>,
+        clickhouse: &clickhouse::Client,
+        queue: &MessageQueue,
+        origin: &MessageOrigin,
+    ) -> Vec<PendingSpan> {
+        self.deadline = None;
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        let result = match record_spans_and_events(&pending, db).await {
+            Ok((events, labels)) => {
+                self.rows.events = events;
+                self.rows.labels = labels;
+                self.rows.insert(clickhouse).await
+            }
+            Err(e) => Err(e),
+        };
+        self.rows.clear();
+
+        match result {
+            Ok(_) => {
+                for span in pending.iter() {
+                    if let Err(e) = span.acker.ack().await {
+                        log::error!("Failed to ack MQ delivery (span): {:?}", e);
+                    }
+                }
+                pending
+            }
+            Err(e) => {
+                log::error!("Failed to write batch of {} spans: {:?}", pending.len(), e);
+                let reason = format!("Failed to write span batch: {:#}", e);
+                for span in pending {
+                    dead_letter_delivery(queue, &span.acker, &span.payload, origin, &reason).await;
+                }
+                Vec::new()
+            }
+        }
+    }
+}
+
+async fn insert_with_backoff<F, Fut>(table: &str, insert: F) -> anyhow::Result<()>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let operation = || async {
+        insert().await.map_err(|e| {
+            log::error!(
+                "Failed attempt to insert {} batch. Will retry according to backoff policy. Error: {:?}",
+                table,
+                e
+            );
+            backoff::Error::transient(e)
+        })
+    };
+    // Starting with 0.5 second delay, delay multiplies by random factor between 1 and 2
+    // up to 10 seconds and until the total elapsed time is 1 minute
+    // https://docs.rs/backoff/latest/backoff/default/index.html
+    let exponential_backoff = ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(500))
+        .with_multiplier(1.5)
+        .with_randomization_factor(0.5)
+        .with_max_interval(Duration::from_secs(10))
+        .with_max_elapsed_time(Some(Duration::from_secs(60)))
+        .build();
+    backoff::future::retry(exponential_backoff, operation).await
+}
@@ -1,18 +1,27 @@
 //! This module reads spans from RabbitMQ and processes them: writes to DB,
-//! clickhouse, and semantic search.
+//! clickhouse in batches, and semantic search.
 
 use std::sync::Arc;
 
+use tokio::time::Instant;
+
 use super::{
-    process_label_classes, process_spans_and_events, OBSERVATIONS_EXCHANGE, OBSERVATIONS_QUEUE,
-    OBSERVATIONS_ROUTING_KEY,
+    batch::{PendingSpan, SpanBatch},
+    prepare_span, process_label_classes,
+    redaction::{get_project_redaction_rules, project_hash_key, Redactor},
+    sampling::{get_sampling_policy, SamplingDecision, TraceBuffer, TraceDecisions},
+    OBSERVATIONS_EXCHANGE, OBSERVATIONS_QUEUE, OBSERVATIONS_ROUTING_KEY,
 };
 use crate::{
     api::v1::traces::RabbitMqSpanMessage,
     cache::Cache,
-    db::{spans::Span, DB},
+    ch::spans::CHSpan,
+    db::{projects::SamplingPolicy, spans::Span, DB},
     features::{is_feature_enabled, Feature},
-    mq::{MessageQueue, MessageQueueDeliveryTrait, MessageQueueReceiverTrait, MessageQueueTrait},
+    mq::{
+        dead_letter_delivery, MessageOrigin, MessageQueue, MessageQueueAcker,
+        MessageQueueDeliveryTrait, MessageQueueReceiverTrait, MessageQueueTrait,
+    },
     pipeline::runner::PipelineRunner,
     storage::Storage,
 };
@@ -21,6 +30,7 @@ pub async fn process_queue_spans(
     pipeline_runner: Arc<PipelineRunner>,
     db: Arc<DB>,
     cache: Arc<Cache>,
+    trace_decisions: Arc<TraceDecisions>,
     queue: Arc<MessageQueue>,
     clickhouse: clickhouse::Client,
     storage: Arc<Storage>,
@@ -30,6 +40,7 @@ pub async fn process_queue_spans(
             pipeline_runner.clone(),
             db.clone(),
             cache.clone(),
+            trace_decisions.clone(),
             queue.clone(),
             clickhouse.clone(),
             storage.clone(),
@@ -43,6 +54,7 @@ async fn inner_process_queue_spans(
     pipeline_runner: Arc<PipelineRunner>,
     db: Arc<DB>,
     cache: Arc<Cache>,
+    trace_decisions: Arc<TraceDecisions>,
     queue: Arc<MessageQueue>,
     clickhouse: clickhouse::Client,
     storage: Arc<Storage>,
@@ -57,24 +69,81 @@ async fn inner_process_queue_spans(
         .await
         .unwrap();
 
+    let origin = MessageOrigin::new(
+        OBSERVATIONS_QUEUE,
+        OBSERVATIONS_EXCHANGE,
+        OBSERVATIONS_ROUTING_KEY,
+    );
+
+    let mut batch = SpanBatch::from_env();
+    // Spans waiting for a tail sampling decision. If the queue connection is lost,
+    // their deliveries are redelivered, as they have not been acked.
+    let mut trace_buffer = TraceBuffer::from_env();
+
     log::info!("Started processing spans from queue");
 
-    while let Some(delivery) = receiver.receive().await {
+    loop {
+        for (trace_id, spans) in trace_buffer.take_expired() {
+            // The top span has not arrived in time. Unless another consumer has dropped
+            // the trace, it is kept, rather than dropping the rest of a long trace or
+            // keeping an errored top span alone.
+            match trace_decisions.get(&trace_id).await {
+                Some(false) => ack_dropped_spans(spans).await,
+                Some(true) | None => {
+                    trace_decisions.set(&trace_id, true).await;
+                    for (pending, ch_span) in spans {
+                        push_kept_span(&mut batch, pending, ch_span, &storage).await;
+                    }
+                }
+            }
+        }
+        if batch.is_full()
+            || batch
+                .deadline()
+                .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            flush_batch(
+                &mut batch,
+                &queue,
+                &origin,
+                db.clone(),
+                clickhouse.clone(),
+                pipeline_runner.clone(),
+            )
+            .await;
+        }
+
+        let deadline = match (batch.deadline(), trace_buffer.deadline()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let delivery = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, receiver.receive()).await {
+                Ok(delivery) => delivery,
+                Err(_) => continue,
+            },
+            None => receiver.receive().await,
+        };
+        let Some(delivery) = delivery else {
+            break;
+        };
+
         if let Err(e) = delivery {
             log::error!("Failed to receive message from queue: {:?}", e);
             continue;
         }
         let delivery = delivery.unwrap();
         let acker = delivery.acker();
-        let rabbitmq_span_message =
-            match serde_json::from_slice::<RabbitMqSpanMessage>(&delivery.data()) {
-                Ok(rabbitmq_span_message) => rabbitmq_span_message,
-                Err(e) => {
-                    log::error!("Failed to deserialize span message: {:?}", e);
-                    let _ = acker.reject(false).await;
-                    continue;
-                }
-            };
+        let payload = delivery.data();
+        let rabbitmq_span_message = match serde_json::from_slice::<RabbitMqSpanMessage>(&payload) {
+            Ok(rabbitmq_span_message) => rabbitmq_span_message,
+            Err(e) => {
+                log::error!("Failed to deserialize span message: {:?}", e);
+                let reason = format!("Failed to deserialize span message: {}", e);
+                dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
+                continue;
+            }
+        };
 
         if is_feature_enabled(Feature::UsageLimit) {
             match super::limits::update_workspace_limit_exceeded_by_project_id(
@@ -103,44 +172,183 @@ async fn inner_process_queue_spans(
             }
         }
 
+        let project_id = rabbitmq_span_message.project_id;
         let mut span: Span = rabbitmq_span_message.span;
+        let mut events = rabbitmq_span_message.events;
 
-        if is_feature_enabled(Feature::Storage) {
-            if let Err(e) = span
-                .store_payloads(&rabbitmq_span_message.project_id, storage.clone())
-                .await
-            {
-                log::error!(
-                    "Failed to store input images. span_id [{}], project_id [{}]: {:?}",
-                    span.span_id,
-                    rabbitmq_span_message.project_id,
-                    e
-                );
-            }
+        // Redaction must happen before the payloads are stored. If the rules cannot be
+        // read, the message is dead-lettered rather than written unredacted.
+        let redaction_rules =
+            match get_project_redaction_rules(db.clone(), cache.clone(), &project_id).await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    log::error!(
+                        "Failed to get redaction rules. project_id [{}]: {:?}",
+                        project_id,
+                        e
+                    );
+                    let reason = format!("Failed to get redaction rules: {:#}", e);
+                    dead_letter_delivery(&queue, &acker, &payload, &origin, &reason).await;
+                    continue;
+                }
+            };
+        let redactor = Redactor::new(&redaction_rules, project_hash_key(&project_id));
+        if !redactor.is_empty() {
+            redactor.redact_span(&mut span, &mut events);
+        }
+
+        let sampling_policy =
+            match get_sampling_policy(db.clone(), cache.clone(), &project_id).await {
+                Ok(policy) => policy,
+                Err(e) => {
+                    log::error!(
+                        "Failed to get sampling policy, keeping the span. project_id [{}]: {:?}",
+                        project_id,
+                        e
+                    );
+                    SamplingPolicy::default()
+                }
+            };
+        let head_sampled = sampling_policy.head_keep(&span);
+        if !head_sampled && !sampling_policy.has_tail_rules() {
+            ack_dropped_span(&acker).await;
+            continue;
         }
 
-        let events = rabbitmq_span_message.events;
+        let (trace_attributes, ch_span) =
+            prepare_span(&mut span, &project_id, db.clone(), cache.clone()).await;
 
-        process_spans_and_events(
-            &mut span,
-            events,
-            &rabbitmq_span_message.project_id,
-            db.clone(),
-            clickhouse.clone(),
-            cache.clone(),
+        let trace_id = span.trace_id;
+        let decision = if head_sampled {
+            SamplingDecision::Keep
+        } else {
+            sampling_policy.tail_decide(&span, &events, &ch_span)
+        };
+        let pending = PendingSpan {
             acker,
-        )
-        .await;
+            payload,
+            project_id,
+            span,
+            events,
+            trace_attributes,
+        };
+
+        match decision {
+            SamplingDecision::Keep => {
+                if !head_sampled {
+                    trace_decisions.set(&trace_id, true).await;
+                    for (buffered, ch_span) in trace_buffer.take(&trace_id) {
+                        push_kept_span(&mut batch, buffered, ch_span, &storage).await;
+                    }
+                }
+                push_kept_span(&mut batch, pending, ch_span, &storage).await;
+            }
+            SamplingDecision::Drop | SamplingDecision::Defer => {
+                match trace_decisions.get(&trace_id).await {
+                    Some(true) => {
+                        for (buffered, ch_span) in trace_buffer.take(&trace_id) {
+                            push_kept_span(&mut batch, buffered, ch_span, &storage).await;
+                        }
+                        push_kept_span(&mut batch, pending, ch_span, &storage).await;
+                    }
+                    Some(false) => {
+                        ack_dropped_spans(trace_buffer.take(&trace_id)).await;
+                        ack_dropped_span(&pending.acker).await;
+                    }
+                    None if decision == SamplingDecision::Drop => {
+                        trace_decisions.set(&trace_id, false).await;
+                        ack_dropped_spans(trace_buffer.take(&trace_id)).await;
+                        ack_dropped_span(&pending.acker).await;
+                    }
+                    None => trace_buffer.push(pending, ch_span),
+                }
+            }
+        }
+
+        if batch.is_full() {
+            flush_batch(
+                &mut batch,
+                &queue,
+                &origin,
+                db.clone(),
+                clickhouse.clone(),
+                pipeline_runner.clone(),
+            )
+            .await;
+        }
+    }
+
+    flush_batch(
+        &mut batch,
+        &queue,
+        &origin,
+        db.clone(),
+        clickhouse.clone(),
+        pipeline_runner.clone(),
+    )
+    .await;
+
+    log::warn!("Queue closed connection. Shutting down span listener");
+}
+
+/// Store the payloads of a span that is kept by sampling and add it to the batch. Payloads
+/// are only stored once the span is kept, so that dropped spans leave nothing in storage.
+async fn push_kept_span(
+    batch: &mut SpanBatch,
+    mut pending: PendingSpan,
+    mut ch_span: CHSpan,
+    storage: &Arc<Storage>,
+) {
+    if is_feature_enabled(Feature::Storage) {
+        if let Err(e) = pending
+            .span
+            .store_payloads(&pending.project_id, storage.clone())
+            .await
+        {
+            log::error!(
+                "Failed to store input images. span_id [{}], project_id [{}]: {:?}",
+                pending.span.span_id,
+                pending.project_id,
+                e
+            );
+        }
+        ch_span.set_payloads(&pending.span);
+    }
+    batch.push(pending, ch_span);
+}
+
+/// Spans dropped by sampling are acked without being written, so that they are not
+/// counted in the project usage
+async fn ack_dropped_span(acker: &MessageQueueAcker) {
+    if let Err(e) = acker.ack().await {
+        log::error!("Failed to ack MQ delivery (dropped span): {:?}", e);
+    }
+}
 
+async fn ack_dropped_spans(spans: Vec<(PendingSpan, CHSpan)>) {
+    for (pending, _) in spans {
+        ack_dropped_span(&pending.acker).await;
+    }
+}
+
+/// Write the batch and run the label classes registered for the written spans
+async fn flush_batch(
+    batch: &mut SpanBatch,
+    queue: &MessageQueue,
+    origin: &MessageOrigin,
+    db: Arc<DB>,
+    clickhouse: clickhouse::Client,
+    pipeline_runner: Arc<PipelineRunner>,
+) {
+    let written = batch.flush(db.clone(), &clickhouse, queue, origin).await;
+    for pending in written {
         process_label_classes(
-            &span,
-            &rabbitmq_span_message.project_id,
+            &pending.span,
+            &pending.project_id,
             db.clone(),
             clickhouse.clone(),
             pipeline_runner.clone(),
         )
         .await;
     }
-
-    log::warn!("Queue closed connection. Shutting down span listener");
 }
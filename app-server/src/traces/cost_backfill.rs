@@ -0,0 +1,147 @@
+//! Recalculate the costs of the LLM spans that were already recorded, e.g. after the
+//! price overrides or model name rules of the project changed.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{
+    spans::{SpanAttributes, SpanUsage},
+    utils::get_llm_usage_for_span,
+};
+use crate::{
+    cache::Cache,
+    ch,
+    db::{
+        cost_backfills::{CostBackfill, CostBackfillStatus},
+        DB,
+    },
+};
+
+const BACKFILL_BATCH_SIZE: i64 = 500;
+/// Number of spans updated by one ClickHouse mutation. Every mutation rewrites the data
+/// parts it touches, so the costs of several Postgres batches are updated at once.
+const CH_MUTATION_BATCH_SIZE: usize = 5000;
+
+/// Run the cost backfill to completion, and record its outcome in the backfill row.
+///
+/// Meant to be spawned in the background, as large time ranges take long to backfill.
+pub async fn run_cost_backfill(
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    clickhouse: clickhouse::Client,
+    backfill: CostBackfill,
+) {
+    let mut span_count = 0;
+    let result =
+        backfill_span_costs(db.clone(), cache, clickhouse, &backfill, &mut span_count).await;
+
+    let (status, error) = match result {
+        Ok(()) => (CostBackfillStatus::SUCCEEDED, None),
+        Err(e) => {
+            log::error!("Cost backfill {} failed: {:?}", backfill.id, e);
+            (CostBackfillStatus::FAILED, Some(e.to_string()))
+        }
+    };
+    if let Err(e) = crate::db::cost_backfills::finish_cost_backfill(
+        &db.pool,
+        &backfill.id,
+        status,
+        span_count,
+        error,
+    )
+    .await
+    {
+        log::error!("Failed to finish cost backfill {}: {:?}", backfill.id, e);
+    }
+}
+
+/// Recalculate the costs of the LLM spans of the project that started in
+/// `[start_time, end_time)` of the backfill, and of their traces.
+///
+/// `span_count` is the number of spans whose costs were recalculated so far, and is
+/// reported as the progress of the backfill after every ClickHouse mutation.
+async fn backfill_span_costs(
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    clickhouse: clickhouse::Client,
+    backfill: &CostBackfill,
+    span_count: &mut i64,
+) -> Result<()> {
+    let project_id = backfill.project_id;
+    let mut after = None;
+    let mut pending_usages: Vec<(Uuid, SpanUsage)> = Vec::new();
+
+    loop {
+        let spans = crate::db::spans::get_llm_spans_attributes(
+            &db.pool,
+            &project_id,
+            backfill.start_time,
+            backfill.end_time,
+            after,
+            BACKFILL_BATCH_SIZE,
+        )
+        .await?;
+        let batch_len = spans.len();
+        let Some(last_span) = spans.last() else {
+            break;
+        };
+        after = Some((last_span.start_time, last_span.span_id));
+
+        let mut span_attributes = Vec::with_capacity(spans.len());
+        let mut trace_ids = Vec::new();
+        for span in spans {
+            let attributes = serde_json::from_value::<HashMap<String, Value>>(span.attributes)
+                .unwrap_or_default();
+            let mut attributes = SpanAttributes::new(attributes);
+            let usage = get_llm_usage_for_span(
+                &mut attributes,
+                db.clone(),
+                cache.clone(),
+                &project_id,
+                &span.start_time,
+            )
+            .await;
+            attributes.set_usage(&usage);
+
+            span_attributes.push((span.span_id, serde_json::to_value(&attributes.attributes)?));
+            pending_usages.push((span.span_id, usage));
+            if !trace_ids.contains(&span.trace_id) {
+                trace_ids.push(span.trace_id);
+            }
+        }
+
+        crate::db::spans::update_spans_attributes(&db.pool, &project_id, &span_attributes).await?;
+        crate::db::trace::update_traces_usage_from_spans(&db.pool, &project_id, &trace_ids).await?;
+
+        if pending_usages.len() >= CH_MUTATION_BATCH_SIZE {
+            flush_span_costs(&db, &clickhouse, backfill, &mut pending_usages, span_count).await?;
+        }
+        if batch_len < BACKFILL_BATCH_SIZE as usize {
+            break;
+        }
+    }
+
+    flush_span_costs(&db, &clickhouse, backfill, &mut pending_usages, span_count).await
+}
+
+/// Update the costs of the pending spans in ClickHouse with a single mutation
+async fn flush_span_costs(
+    db: &DB,
+    clickhouse: &clickhouse::Client,
+    backfill: &CostBackfill,
+    pending_usages: &mut Vec<(Uuid, SpanUsage)>,
+    span_count: &mut i64,
+) -> Result<()> {
+    if pending_usages.is_empty() {
+        return Ok(());
+    }
+    ch::spans::update_spans_costs(clickhouse, backfill.project_id, pending_usages).await?;
+
+    *span_count += pending_usages.len() as i64;
+    pending_usages.clear();
+    crate::db::cost_backfills::update_cost_backfill_progress(&db.pool, &backfill.id, *span_count)
+        .await
+}
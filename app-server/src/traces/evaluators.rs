@@ -70,14 +70,17 @@ pub async fn run_evaluator(
     let run_type = RunType::AutoLabel;
     graph.setup(&inputs, &env, &HashMap::new(), &run_type)?;
 
-    let run_result = pipeline_runner.run(graph, None).await.map_err(|e| {
-        anyhow::anyhow!(
-            "Failed to run pipeline for autolabeling: {} ({}): {}",
-            label_class.name,
-            label_class.id,
-            e
-        )
-    })?;
+    let run_result = pipeline_runner
+        .run(graph, &project_id, None)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to run pipeline for autolabeling: {} ({}): {}",
+                label_class.name,
+                label_class.id,
+                e
+            )
+        })?;
 
     let outputs = run_result.output_values();
     let output_str: String = outputs
@@ -1,21 +1,31 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Result;
+use uuid::Uuid;
 
 use crate::{
-    ch::{self, events::CHEvent},
+    ch::events::CHEvent,
     db::{self, events::Event, DB},
 };
 
+/// Record events to the database and return the rows to be inserted into Clickhouse,
+/// and the number of events that had not been recorded before, by project.
+///
+/// Rows are returned for all the events, because the Clickhouse insert of events that
+/// were recorded before may have failed. Rows that are already in Clickhouse are skipped
+/// when the batch is inserted.
 pub async fn record_events(
     db: Arc<DB>,
-    clickhouse: clickhouse::Client,
     event_payloads: Vec<Event>,
-) -> Result<()> {
-    db::events::insert_events(&db.pool, &event_payloads).await?;
+) -> Result<(Vec<CHEvent>, HashMap<Uuid, i64>)> {
+    if event_payloads.is_empty() {
+        return Ok((Vec::new(), HashMap::new()));
+    }
+
+    let inserted_events = db::events::insert_events(&db.pool, &event_payloads).await?;
     let ch_events = event_payloads
         .iter()
         .map(|e| CHEvent::from_db_event(e))
         .collect::<Vec<CHEvent>>();
-    ch::events::insert_events(clickhouse, ch_events).await
+    Ok((ch_events, inserted_events))
 }
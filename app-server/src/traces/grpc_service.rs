@@ -14,7 +14,10 @@ use crate::{
 };
 use tonic::{Request, Response, Status};
 
-use super::{limits::get_workspace_limit_exceeded_by_project_id, producer::push_spans_to_queue};
+use super::{
+    limits::get_workspace_limit_exceeded_by_project_id,
+    producer::{push_spans_to_queue, reject_all_spans},
+};
 
 pub struct ProcessTracesService {
     db: Arc<DB>,
@@ -54,7 +57,7 @@ impl TraceService for ProcessTracesService {
 
             // TODO: do the same for events
             if limits_exceeded.spans {
-                return Err(Status::resource_exhausted("Workspace span limit exceeded"));
+                return Ok(Response::new(reject_all_spans(&request)));
             }
         }
 
@@ -62,14 +65,15 @@ impl TraceService for ProcessTracesService {
             .await
             .map_err(|e| {
                 log::error!("Failed to process traces: {:?}", e);
-                Status::internal("Failed to process traces")
+                // UNAVAILABLE is retryable by OTLP exporters
+                Status::unavailable("Failed to process traces")
             })?;
 
         Ok(Response::new(response))
     }
 }
 
-async fn authenticate_request(
+pub async fn authenticate_request(
     metadata: &tonic::metadata::MetadataMap,
     pool: &PgPool,
     cache: Arc<Cache>,
@@ -0,0 +1,222 @@
+//! The OpenTelemetry GenAI semantic conventions record the messages of LLM calls as
+//! events, e.g. `gen_ai.user.message` and `gen_ai.choice`, instead of attributes.
+//! https://opentelemetry.io/docs/specs/semconv/gen-ai/gen-ai-events/
+//!
+//! The event body is either in the event attributes, or serialized in the
+//! `gen_ai.event.content` attribute. Earlier versions of the conventions recorded the
+//! whole prompt and completion in `gen_ai.content.prompt` and `gen_ai.content.completion`
+//! events instead.
+
+use serde_json::{json, Map, Value};
+
+use super::{
+    completion_output, json_or_string, openllmetry::chat_message_content_from_string,
+    parse_tool_call_arguments, tool_call_block, InstrumentationAdapterTrait, InstrumentedSpan,
+};
+use crate::{
+    db::{spans::Span, utils::convert_any_value_to_json_value},
+    language_model::{
+        ChatMessage, ChatMessageContent, ChatMessageContentPart, ChatMessageToolCall,
+        InstrumentationChatMessageContentPart,
+    },
+    opentelemetry::opentelemetry_proto_trace_v1::span::Event,
+};
+
+/// Message events and the default role of their messages
+const MESSAGE_EVENTS: &[(&str, &str)] = &[
+    ("gen_ai.system.message", "system"),
+    ("gen_ai.user.message", "user"),
+    ("gen_ai.assistant.message", "assistant"),
+    ("gen_ai.tool.message", "tool"),
+];
+const CHOICE_EVENT: &str = "gen_ai.choice";
+const EVENT_CONTENT: &str = "gen_ai.event.content";
+
+const PROMPT_EVENT: &str = "gen_ai.content.prompt";
+const PROMPT_ATTRIBUTE: &str = "gen_ai.prompt";
+const COMPLETION_EVENT: &str = "gen_ai.content.completion";
+const COMPLETION_ATTRIBUTE: &str = "gen_ai.completion";
+
+pub struct GenAiEventsAdapter;
+
+impl InstrumentationAdapterTrait for GenAiEventsAdapter {
+    fn matches(&self, _span: &Span, otel_span: &InstrumentedSpan) -> bool {
+        otel_span.events.iter().any(|event| {
+            message_role(&event.name).is_some()
+                || event.name == CHOICE_EVENT
+                || event.name == PROMPT_EVENT
+                || event.name == COMPLETION_EVENT
+        })
+    }
+
+    /// Only fills in the input and output that are not set by the other adapters
+    fn apply(&self, span: &mut Span, otel_span: &InstrumentedSpan) {
+        let mut input_messages = Vec::new();
+        let mut legacy_input = None;
+        let mut output = None;
+        let mut legacy_output = None;
+
+        for event in otel_span.events {
+            if let Some(default_role) = message_role(&event.name) {
+                input_messages.push(chat_message(&event_body(event), default_role));
+            } else if event.name == CHOICE_EVENT {
+                let body = event_body(event);
+                let index = body.get("index").and_then(|index| index.as_i64());
+                // Only the first choice is recorded as the output, like for OpenLLMetry
+                if output.is_none() && index.unwrap_or_default() == 0 {
+                    output = choice_output(&body);
+                }
+            } else if event.name == PROMPT_EVENT {
+                if let Some(Value::String(s)) = event_body(event).get(PROMPT_ATTRIBUTE) {
+                    legacy_input = Some(json_or_string(s));
+                }
+            } else if event.name == COMPLETION_EVENT {
+                if let Some(Value::String(s)) = event_body(event).get(COMPLETION_ATTRIBUTE) {
+                    legacy_output = Some(json_or_string(s));
+                }
+            }
+        }
+
+        if span.input.is_none() {
+            span.input = if input_messages.is_empty() {
+                legacy_input
+            } else {
+                Some(json!(input_messages))
+            };
+        }
+        if span.output.is_none() {
+            span.output = output.or(legacy_output);
+        }
+    }
+}
+
+fn message_role(event_name: &str) -> Option<&'static str> {
+    MESSAGE_EVENTS
+        .iter()
+        .find(|(name, _)| *name == event_name)
+        .map(|(_, role)| *role)
+}
+
+fn event_body(event: &Event) -> Map<String, Value> {
+    let attributes = event
+        .attributes
+        .iter()
+        .filter(|kv| kv.value.is_some())
+        .map(|kv| {
+            (
+                kv.key.clone(),
+                convert_any_value_to_json_value(kv.value.clone()),
+            )
+        })
+        .collect::<Map<String, Value>>();
+
+    if let Some(Value::String(content)) = attributes.get(EVENT_CONTENT) {
+        if let Ok(Value::Object(body)) = serde_json::from_str::<Value>(content) {
+            return body;
+        }
+    }
+    attributes
+}
+
+/// Nested values can be recorded as serialized JSON
+fn as_object(value: &Value) -> Option<Map<String, Value>> {
+    match value {
+        Value::Object(map) => Some(map.clone()),
+        Value::String(s) => match serde_json::from_str::<Value>(s) {
+            Ok(Value::Object(map)) => Some(map),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn as_array(value: &Value) -> Option<Vec<Value>> {
+    match value {
+        Value::Array(values) => Some(values.clone()),
+        Value::String(s) => match serde_json::from_str::<Value>(s) {
+            Ok(Value::Array(values)) => Some(values),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn chat_message(body: &Map<String, Value>, default_role: &str) -> ChatMessage {
+    let role = body
+        .get("role")
+        .and_then(|role| role.as_str())
+        .unwrap_or(default_role)
+        .to_string();
+
+    let content = match body.get("content") {
+        Some(Value::String(s)) => chat_message_content_from_string(s.clone()),
+        Some(content) => {
+            match serde_json::from_value::<Vec<InstrumentationChatMessageContentPart>>(
+                content.clone(),
+            ) {
+                Ok(parts) => ChatMessageContent::ContentPartList(
+                    parts
+                        .into_iter()
+                        .map(ChatMessageContentPart::from_instrumentation_content_part)
+                        .collect(),
+                ),
+                Err(_) => ChatMessageContent::Text(content.to_string()),
+            }
+        }
+        // Assistant messages that only call tools have no content
+        None => ChatMessageContent::Text(String::new()),
+    };
+
+    // Tool calls are in the OpenAI format, with the arguments nested in `function`
+    let tool_calls = body
+        .get("tool_calls")
+        .and_then(as_array)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|tool_call| serde_json::from_value::<ChatMessageToolCall>(tool_call).ok())
+        .collect();
+    // Tool messages record the id of the tool call they answer in `id`
+    let tool_call_id = if role == "tool" {
+        body.get("id").and_then(|id| id.as_str()).map(String::from)
+    } else {
+        None
+    };
+
+    ChatMessage {
+        role,
+        content,
+        tool_calls,
+        tool_call_id,
+    }
+}
+
+fn choice_output(body: &Map<String, Value>) -> Option<Value> {
+    let message = body
+        .get("message")
+        .and_then(as_object)
+        .unwrap_or_else(|| body.clone());
+
+    let text = match message.get("content") {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Null) | None => None,
+        Some(content) => Some(content.to_string()),
+    };
+    let tool_calls = message
+        .get("tool_calls")
+        .and_then(as_array)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|tool_call| {
+            let function = tool_call.get("function")?;
+            let name = function.get("name")?.as_str()?.to_string();
+            let id = tool_call
+                .get("id")
+                .and_then(|id| id.as_str())
+                .map(String::from);
+            let arguments = parse_tool_call_arguments(function.get("arguments"));
+            Some(tool_call_block(name, id, arguments))
+        })
+        .collect::<Vec<_>>();
+
+    completion_output(text, tool_calls)
+}
@@ -0,0 +1,46 @@
+//! LiteLLM records the messages of LLM spans like OpenLLMetry, but under the
+//! `SpanAttributes.LLM_PROMPTS` and `SpanAttributes.LLM_COMPLETIONS` prefixes.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::json;
+
+use super::{
+    openllmetry::{input_chat_messages_from_prompt_content, output_from_completion_content},
+    InstrumentationAdapterTrait, InstrumentedSpan,
+};
+use crate::db::spans::{Span, SpanType};
+
+lazy_static! {
+    static ref CONSUMED_ATTRIBUTES: Regex =
+        Regex::new(r"SpanAttributes\.LLM_(PROMPTS|COMPLETIONS)\.\d+\.(content|role)").unwrap();
+}
+
+pub struct LiteLlmAdapter;
+
+impl InstrumentationAdapterTrait for LiteLlmAdapter {
+    fn matches(&self, span: &Span, otel_span: &InstrumentedSpan) -> bool {
+        span.span_type == SpanType::LLM
+            && otel_span
+                .attributes
+                .contains_key("SpanAttributes.LLM_PROMPTS.0.content")
+    }
+
+    fn apply(&self, span: &mut Span, otel_span: &InstrumentedSpan) {
+        let input_messages = input_chat_messages_from_prompt_content(
+            otel_span.attributes,
+            "SpanAttributes.LLM_PROMPTS",
+        );
+        span.input = Some(json!(input_messages));
+        span.output = output_from_completion_content(
+            otel_span.attributes,
+            "SpanAttributes.LLM_COMPLETIONS",
+            "function_call",
+            false,
+        );
+    }
+
+    fn consumes_attribute(&self, attribute: &str) -> bool {
+        CONSUMED_ATTRIBUTES.is_match(attribute)
+    }
+}
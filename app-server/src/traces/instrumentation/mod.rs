@@ -0,0 +1,255 @@
+//! Instrumentation libraries record LLM calls in different formats. Each adapter claims
+//! the spans recorded by one library, by instrumentation scope or by attributes, and
+//! maps them to the span input, output and usage.
+//!
+//! To support a new library, add an adapter module and register it in [`ADAPTERS`].
+
+use enum_dispatch::enum_dispatch;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    db::spans::Span, language_model::ChatMessage,
+    opentelemetry::opentelemetry_proto_trace_v1::span::Event,
+};
+
+use gen_ai_events::GenAiEventsAdapter;
+use litellm::LiteLlmAdapter;
+use openinference::OpenInferenceAdapter;
+use openllmetry::OpenLLMetryAdapter;
+use traceloop::TraceloopAdapter;
+use vercel_ai::VercelAiAdapter;
+
+pub mod gen_ai_events;
+pub mod litellm;
+pub mod openinference;
+pub mod openllmetry;
+pub mod traceloop;
+pub mod vercel_ai;
+
+lazy_static! {
+    /// Adapters in the order they are applied. All adapters that match a span are
+    /// applied, so the ones that only fill in missing input and output come last.
+    static ref ADAPTERS: Vec<InstrumentationAdapter> = vec![
+        OpenInferenceAdapter.into(),
+        OpenLLMetryAdapter.into(),
+        LiteLlmAdapter.into(),
+        VercelAiAdapter.into(),
+        GenAiEventsAdapter.into(),
+        TraceloopAdapter.into(),
+    ];
+}
+
+/// The parts of the OpenTelemetry span that are not kept on the span as is
+pub struct InstrumentedSpan<'a> {
+    pub scope_name: Option<&'a str>,
+    /// All attributes, including the ones that are not kept on the span
+    pub attributes: &'a Map<String, Value>,
+    pub events: &'a [Event],
+}
+
+#[enum_dispatch]
+pub trait InstrumentationAdapterTrait {
+    /// Whether the span was recorded by the instrumentation library of the adapter
+    fn matches(&self, span: &Span, otel_span: &InstrumentedSpan) -> bool;
+
+    /// Set the input and output of the span, and its type and usage attributes if the
+    /// library records them differently
+    fn apply(&self, span: &mut Span, otel_span: &InstrumentedSpan);
+
+    /// Attributes that the adapter maps to the span input or output. They are not
+    /// kept on the span, so that the payloads are not stored twice.
+    fn consumes_attribute(&self, _attribute: &str) -> bool {
+        false
+    }
+}
+
+#[enum_dispatch(InstrumentationAdapterTrait)]
+pub enum InstrumentationAdapter {
+    OpenInference(OpenInferenceAdapter),
+    OpenLLMetry(OpenLLMetryAdapter),
+    LiteLlm(LiteLlmAdapter),
+    VercelAi(VercelAiAdapter),
+    GenAiEvents(GenAiEventsAdapter),
+    Traceloop(TraceloopAdapter),
+}
+
+/// Apply the adapters that match the span, and remove the attributes they consume from
+/// the span. Spans that no adapter matches keep all their attributes.
+pub fn apply_adapters(span: &mut Span, otel_span: &InstrumentedSpan) {
+    for adapter in ADAPTERS.iter() {
+        if adapter.matches(span, otel_span) {
+            adapter.apply(span, otel_span);
+            if let Value::Object(attributes) = &mut span.attributes {
+                attributes.retain(|key, _| !adapter.consumes_attribute(key));
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ToolCall {
+    name: String,
+    id: Option<String>,
+    arguments: Option<Value>,
+    #[serde(rename = "type")]
+    content_block_type: String,
+}
+
+#[derive(Serialize)]
+struct TextBlock {
+    content: String,
+    #[serde(rename = "type")]
+    content_block_type: String,
+}
+
+/// Tool call arguments are usually serialized JSON objects
+fn parse_tool_call_arguments(arguments: Option<&Value>) -> Option<Value> {
+    match arguments {
+        Some(Value::String(s)) => Some(
+            serde_json::from_str::<Map<String, Value>>(s)
+                .map(Value::Object)
+                .unwrap_or_else(|_| Value::String(s.clone())),
+        ),
+        _ => arguments.cloned(),
+    }
+}
+
+fn tool_call_block(name: String, id: Option<String>, arguments: Option<Value>) -> Value {
+    serde_json::to_value(ToolCall {
+        name,
+        id,
+        arguments,
+        content_block_type: "tool_call".to_string(),
+    })
+    .unwrap()
+}
+
+/// The output of an LLM span is its text if it did not call tools, otherwise the list of
+/// its text and tool call blocks
+fn completion_output(text: Option<String>, tool_calls: Vec<Value>) -> Option<Value> {
+    if tool_calls.is_empty() {
+        return text.map(Value::String);
+    }
+    let mut blocks = match text {
+        Some(content) => vec![serde_json::to_value(TextBlock {
+            content,
+            content_block_type: "text".to_string(),
+        })
+        .unwrap()],
+        None => vec![],
+    };
+    blocks.extend(tool_calls);
+    Some(Value::Array(blocks))
+}
+
+/// Output of an LLM span that is recorded as an assistant message
+pub fn chat_message_output(message: &ChatMessage) -> Option<Value> {
+    let text = message.text();
+    let tool_calls = message
+        .tool_calls
+        .iter()
+        .map(|tool_call| {
+            tool_call_block(
+                tool_call.name.clone(),
+                tool_call.id.clone(),
+                Some(tool_call.arguments.clone()),
+            )
+        })
+        .collect();
+    completion_output((!text.is_empty()).then_some(text), tool_calls)
+}
+
+/// Parse a string attribute as JSON, or keep it as a string
+fn json_or_string(s: &str) -> Value {
+    serde_json::from_str::<Value>(s).unwrap_or_else(|_| Value::String(s.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::traces::otlp_json::decode_export_trace_service_request;
+
+    use super::*;
+
+    /// A fixture is an OTLP/JSON export request with a single span, and the expected
+    /// input, output, type and attributes of the span
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Fixture {
+        request: Value,
+        input: Option<Value>,
+        output: Option<Value>,
+        span_type: Option<Value>,
+        /// Attributes that must be set on the span
+        #[serde(default)]
+        attributes: Map<String, Value>,
+        /// Attributes that must not be kept on the span
+        #[serde(default)]
+        dropped_attributes: Vec<String>,
+    }
+
+    fn check_fixture(fixture: &str) {
+        let fixture = serde_json::from_str::<Fixture>(fixture).unwrap();
+        let body = serde_json::to_vec(&fixture.request).unwrap();
+        let request = decode_export_trace_service_request(&body).unwrap();
+        let scope_span = &request.resource_spans[0].scope_spans[0];
+        let otel_span = scope_span.spans[0].clone();
+
+        let span = Span::from_otel_span(otel_span, scope_span.scope.as_ref());
+
+        assert_eq!(span.input, fixture.input);
+        assert_eq!(span.output, fixture.output);
+        if let Some(span_type) = fixture.span_type {
+            assert_eq!(serde_json::to_value(&span.span_type).unwrap(), span_type);
+        }
+        for (key, value) in fixture.attributes {
+            assert_eq!(span.attributes.get(&key), Some(&value), "attribute {key}");
+        }
+        for key in fixture.dropped_attributes {
+            assert!(span.attributes.get(&key).is_none(), "attribute {key}");
+        }
+    }
+
+    #[test]
+    fn test_openllmetry() {
+        check_fixture(include_str!("fixtures/openllmetry.json"));
+    }
+
+    #[test]
+    fn test_litellm() {
+        check_fixture(include_str!("fixtures/litellm.json"));
+    }
+
+    #[test]
+    fn test_vercel_ai() {
+        check_fixture(include_str!("fixtures/vercel_ai.json"));
+    }
+
+    #[test]
+    fn test_traceloop() {
+        check_fixture(include_str!("fixtures/traceloop.json"));
+    }
+
+    #[test]
+    fn test_openinference() {
+        check_fixture(include_str!("fixtures/openinference.json"));
+    }
+
+    #[test]
+    fn test_openinference_tool_calls() {
+        check_fixture(include_str!("fixtures/openinference_tool_calls.json"));
+    }
+
+    #[test]
+    fn test_gen_ai_events() {
+        check_fixture(include_str!("fixtures/gen_ai_events.json"));
+    }
+
+    #[test]
+    fn test_unmatched_span_keeps_attributes() {
+        check_fixture(include_str!("fixtures/unmatched.json"));
+    }
+}
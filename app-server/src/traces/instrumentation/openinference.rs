@@ -0,0 +1,236 @@
+//! OpenInference (Arize) records the span kind in `openinference.span.kind`, the
+//! payloads in `input.value` and `output.value`, and the messages of LLM spans as
+//! indexed attributes, e.g. `llm.input_messages.0.message.content`.
+//! https://github.com/Arize-ai/openinference/blob/main/spec/semantic_conventions.md
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+use super::{
+    completion_output, json_or_string, parse_tool_call_arguments, tool_call_block,
+    InstrumentationAdapterTrait, InstrumentedSpan,
+};
+use crate::{
+    db::spans::{Span, SpanType},
+    language_model::{
+        ChatMessage, ChatMessageContent, ChatMessageContentPart, ChatMessageImageUrl,
+        ChatMessageText, ChatMessageToolCall,
+    },
+    traces::span_attributes::{
+        GEN_AI_CACHE_CREATION_INPUT_TOKENS, GEN_AI_CACHE_READ_INPUT_TOKENS, GEN_AI_INPUT_TOKENS,
+        GEN_AI_OUTPUT_TOKENS, GEN_AI_REASONING_TOKENS, GEN_AI_REQUEST_MODEL, GEN_AI_RESPONSE_MODEL,
+        GEN_AI_SYSTEM, GEN_AI_TOTAL_TOKENS, SPAN_TYPE,
+    },
+};
+
+const SPAN_KIND: &str = "openinference.span.kind";
+const SCOPE_PREFIX: &str = "openinference.instrumentation";
+const INPUT_VALUE: &str = "input.value";
+const OUTPUT_VALUE: &str = "output.value";
+const INPUT_MESSAGES: &str = "llm.input_messages";
+const OUTPUT_MESSAGES: &str = "llm.output_messages";
+
+/// OpenInference usage and model attributes, and the attributes they map to
+const USAGE_ATTRIBUTES: &[(&str, &str)] = &[
+    ("llm.token_count.prompt", GEN_AI_INPUT_TOKENS),
+    ("llm.token_count.completion", GEN_AI_OUTPUT_TOKENS),
+    ("llm.token_count.total", GEN_AI_TOTAL_TOKENS),
+    (
+        "llm.token_count.prompt_details.cache_read",
+        GEN_AI_CACHE_READ_INPUT_TOKENS,
+    ),
+    (
+        "llm.token_count.prompt_details.cache_write",
+        GEN_AI_CACHE_CREATION_INPUT_TOKENS,
+    ),
+    (
+        "llm.token_count.completion_details.reasoning",
+        GEN_AI_REASONING_TOKENS,
+    ),
+    ("llm.model_name", GEN_AI_REQUEST_MODEL),
+    ("llm.model_name", GEN_AI_RESPONSE_MODEL),
+    ("llm.provider", GEN_AI_SYSTEM),
+    ("llm.system", GEN_AI_SYSTEM),
+];
+
+lazy_static! {
+    static ref CONSUMED_ATTRIBUTES: Regex =
+        Regex::new(r"^llm\.(input|output)_messages\.\d+\.").unwrap();
+}
+
+pub struct OpenInferenceAdapter;
+
+impl InstrumentationAdapterTrait for OpenInferenceAdapter {
+    fn matches(&self, _span: &Span, otel_span: &InstrumentedSpan) -> bool {
+        otel_span.attributes.contains_key(SPAN_KIND)
+            || otel_span
+                .scope_name
+                .is_some_and(|name| name.starts_with(SCOPE_PREFIX))
+    }
+
+    fn apply(&self, span: &mut Span, otel_span: &InstrumentedSpan) {
+        let attributes = otel_span.attributes;
+
+        if !attributes.contains_key(SPAN_TYPE) {
+            match attributes.get(SPAN_KIND).and_then(|kind| kind.as_str()) {
+                Some("LLM") => span.span_type = SpanType::LLM,
+                Some("TOOL") => span.span_type = SpanType::TOOL,
+                Some(_) => span.span_type = SpanType::DEFAULT,
+                None => {}
+            }
+        }
+
+        let input_messages = input_messages(attributes);
+        span.input = if !input_messages.is_empty() {
+            Some(json!(input_messages))
+        } else {
+            string_attribute(attributes, INPUT_VALUE).map(|s| json_or_string(&s))
+        };
+        span.output = if attributes.contains_key(&message_attribute(OUTPUT_MESSAGES, 0, "role")) {
+            output_message(attributes)
+        } else {
+            string_attribute(attributes, OUTPUT_VALUE).map(|s| json_or_string(&s))
+        };
+
+        if let Some(span_attributes) = span.attributes.as_object_mut() {
+            for (key, mapped_key) in USAGE_ATTRIBUTES {
+                if let Some(value) = attributes.get(*key) {
+                    if !span_attributes.contains_key(*mapped_key) {
+                        span_attributes.insert(mapped_key.to_string(), value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn consumes_attribute(&self, attribute: &str) -> bool {
+        attribute == INPUT_VALUE
+            || attribute == OUTPUT_VALUE
+            || CONSUMED_ATTRIBUTES.is_match(attribute)
+    }
+}
+
+fn message_attribute(prefix: &str, index: usize, attribute: &str) -> String {
+    format!("{prefix}.{index}.message.{attribute}")
+}
+
+fn string_attribute(attributes: &Map<String, Value>, key: &str) -> Option<String> {
+    match attributes.get(key) {
+        Some(Value::String(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn input_messages(attributes: &Map<String, Value>) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+    let mut i = 0;
+    while let Some(role) =
+        string_attribute(attributes, &message_attribute(INPUT_MESSAGES, i, "role"))
+    {
+        messages.push(ChatMessage {
+            role,
+            content: message_content(attributes, INPUT_MESSAGES, i),
+            tool_calls: message_tool_calls(attributes, INPUT_MESSAGES, i)
+                .into_iter()
+                .map(|(name, id, arguments)| ChatMessageToolCall {
+                    id,
+                    name,
+                    arguments: arguments.unwrap_or_default(),
+                })
+                .collect(),
+            tool_call_id: string_attribute(
+                attributes,
+                &message_attribute(INPUT_MESSAGES, i, "tool_call_id"),
+            ),
+        });
+        i += 1;
+    }
+    messages
+}
+
+/// Content is either in `message.content`, or a list of parts in `message.contents`
+fn message_content(
+    attributes: &Map<String, Value>,
+    prefix: &str,
+    index: usize,
+) -> ChatMessageContent {
+    if let Some(content) =
+        string_attribute(attributes, &message_attribute(prefix, index, "content"))
+    {
+        return ChatMessageContent::Text(content);
+    }
+
+    let mut parts = Vec::new();
+    let mut j = 0;
+    loop {
+        let part_prefix =
+            message_attribute(prefix, index, &format!("contents.{j}.message_content"));
+        let Some(part_type) = string_attribute(attributes, &format!("{part_prefix}.type")) else {
+            break;
+        };
+        match part_type.as_str() {
+            "text" => {
+                if let Some(text) = string_attribute(attributes, &format!("{part_prefix}.text")) {
+                    parts.push(ChatMessageContentPart::Text(ChatMessageText { text }));
+                }
+            }
+            "image" => {
+                if let Some(url) =
+                    string_attribute(attributes, &format!("{part_prefix}.image.image.url"))
+                {
+                    parts.push(ChatMessageContentPart::ImageUrl(ChatMessageImageUrl {
+                        url,
+                        detail: None,
+                    }));
+                }
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    if parts.is_empty() {
+        ChatMessageContent::Text(String::new())
+    } else {
+        ChatMessageContent::ContentPartList(parts)
+    }
+}
+
+fn output_message(attributes: &Map<String, Value>) -> Option<Value> {
+    let text = string_attribute(
+        attributes,
+        &message_attribute(OUTPUT_MESSAGES, 0, "content"),
+    );
+
+    let tool_calls = message_tool_calls(attributes, OUTPUT_MESSAGES, 0)
+        .into_iter()
+        .map(|(name, id, arguments)| tool_call_block(name, id, arguments))
+        .collect();
+
+    completion_output(text, tool_calls)
+}
+
+/// Name, id and arguments of the tool calls of a message
+fn message_tool_calls(
+    attributes: &Map<String, Value>,
+    prefix: &str,
+    index: usize,
+) -> Vec<(String, Option<String>, Option<Value>)> {
+    let mut tool_calls = Vec::new();
+    let mut j = 0;
+    loop {
+        let tool_call_prefix =
+            message_attribute(prefix, index, &format!("tool_calls.{j}.tool_call"));
+        let Some(name) = string_attribute(attributes, &format!("{tool_call_prefix}.function.name"))
+        else {
+            break;
+        };
+        let id = string_attribute(attributes, &format!("{tool_call_prefix}.id"));
+        let arguments = parse_tool_call_arguments(
+            attributes.get(&format!("{tool_call_prefix}.function.arguments")),
+        );
+        tool_calls.push((name, id, arguments));
+        j += 1;
+    }
+    tool_calls
+}
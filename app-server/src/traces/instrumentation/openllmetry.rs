@@ -0,0 +1,213 @@
+//! OpenLLMetry records the messages of LLM spans as indexed attributes, e.g.
+//! `gen_ai.prompt.0.role` and `gen_ai.prompt.0.content`.
+//! https://github.com/traceloop/openllmetry
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+use super::{
+    completion_output, parse_tool_call_arguments, tool_call_block, InstrumentationAdapterTrait,
+    InstrumentedSpan,
+};
+use crate::{
+    db::spans::{Span, SpanType},
+    language_model::{
+        ChatMessage, ChatMessageContent, ChatMessageContentPart, ChatMessageToolCall,
+        InstrumentationChatMessageContentPart,
+    },
+};
+
+lazy_static! {
+    static ref CONSUMED_ATTRIBUTES: Regex = Regex::new(
+        r"gen_ai\.(prompt|completion)\.\d+\.(content|role)|gen_ai\.prompt\.\d+\.tool_call"
+    )
+    .unwrap();
+}
+
+pub struct OpenLLMetryAdapter;
+
+impl InstrumentationAdapterTrait for OpenLLMetryAdapter {
+    fn matches(&self, span: &Span, otel_span: &InstrumentedSpan) -> bool {
+        span.span_type == SpanType::LLM
+            && otel_span.attributes.contains_key("gen_ai.prompt.0.content")
+    }
+
+    fn apply(&self, span: &mut Span, otel_span: &InstrumentedSpan) {
+        let input_messages =
+            input_chat_messages_from_prompt_content(otel_span.attributes, "gen_ai.prompt");
+        span.input = Some(json!(input_messages));
+        span.output = output_from_completion_content(
+            otel_span.attributes,
+            "gen_ai.completion",
+            "tool_calls",
+            true,
+        );
+    }
+
+    fn consumes_attribute(&self, attribute: &str) -> bool {
+        CONSUMED_ATTRIBUTES.is_match(attribute)
+    }
+}
+
+/// Also used for the LiteLLM attributes, which follow the same layout
+pub(super) fn input_chat_messages_from_prompt_content(
+    attributes: &Map<String, Value>,
+    prefix: &str,
+) -> Vec<ChatMessage> {
+    let mut input_messages: Vec<ChatMessage> = vec![];
+
+    let mut i = 0;
+    // Assistant messages that only call tools have no content
+    while attributes.contains_key(format!("{prefix}.{i}.content").as_str())
+        || attributes.contains_key(format!("{prefix}.{i}.tool_calls.0.name").as_str())
+    {
+        // TODO: handle case where content is not a string, e.g. LangChain tool messages
+        let content = if let Some(Value::String(s)) =
+            attributes.get(format!("{prefix}.{i}.content").as_str())
+        {
+            s.clone()
+        } else {
+            "".to_string()
+        };
+
+        let role =
+            if let Some(Value::String(s)) = attributes.get(format!("{prefix}.{i}.role").as_str()) {
+                s.clone()
+            } else {
+                "user".to_string()
+            };
+
+        let tool_call_id = match attributes.get(format!("{prefix}.{i}.tool_call_id").as_str()) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
+        };
+
+        input_messages.push(ChatMessage {
+            role,
+            content: chat_message_content_from_string(content),
+            tool_calls: prompt_tool_calls(attributes, &format!("{prefix}.{i}")),
+            tool_call_id,
+        });
+        i += 1;
+    }
+
+    input_messages
+}
+
+/// Tool calls of an assistant message in the prompt, e.g. `gen_ai.prompt.1.tool_calls.0.name`
+fn prompt_tool_calls(
+    attributes: &Map<String, Value>,
+    message_prefix: &str,
+) -> Vec<ChatMessageToolCall> {
+    let mut tool_calls = Vec::new();
+    let mut j = 0;
+    while let Some(Value::String(name)) =
+        attributes.get(format!("{message_prefix}.tool_calls.{j}.name").as_str())
+    {
+        let id = attributes
+            .get(format!("{message_prefix}.tool_calls.{j}.id").as_str())
+            .and_then(|id| id.as_str())
+            .map(String::from);
+        let arguments = parse_tool_call_arguments(
+            attributes.get(format!("{message_prefix}.tool_calls.{j}.arguments").as_str()),
+        );
+        tool_calls.push(ChatMessageToolCall {
+            id,
+            name: name.clone(),
+            arguments: arguments.unwrap_or_default(),
+        });
+        j += 1;
+    }
+    tool_calls
+}
+
+/// Message content is either text, or a serialized list of content parts
+pub(super) fn chat_message_content_from_string(content: String) -> ChatMessageContent {
+    match serde_json::from_str::<Vec<InstrumentationChatMessageContentPart>>(&content) {
+        Ok(otel_parts) => ChatMessageContent::ContentPartList(
+            otel_parts
+                .into_iter()
+                .map(ChatMessageContentPart::from_instrumentation_content_part)
+                .collect(),
+        ),
+        Err(_) => ChatMessageContent::Text(content),
+    }
+}
+
+fn tool_call_attribute(
+    prefix: &str,
+    tool_call_attribute_name: &str,
+    use_index_in_tools: bool,
+    index: usize,
+    attribute: &str,
+) -> String {
+    if use_index_in_tools {
+        format!("{prefix}.0.{tool_call_attribute_name}.{index}.{attribute}")
+    } else {
+        format!("{prefix}.0.{tool_call_attribute_name}.{attribute}")
+    }
+}
+
+pub(super) fn output_from_completion_content(
+    attributes: &Map<String, Value>,
+    prefix: &str,
+    tool_call_attribute_name: &str,
+    use_index_in_tools: bool,
+) -> Option<Value> {
+    let text_msg = match attributes.get(format!("{prefix}.0.content").as_str()) {
+        Some(Value::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+
+    let mut tool_calls = Vec::new();
+    let mut i = 0;
+
+    while let Some(Value::String(tool_call_name)) = attributes.get(
+        tool_call_attribute(
+            prefix,
+            tool_call_attribute_name,
+            use_index_in_tools,
+            i,
+            "name",
+        )
+        .as_str(),
+    ) {
+        let tool_call_id = attributes
+            .get(
+                tool_call_attribute(
+                    prefix,
+                    tool_call_attribute_name,
+                    use_index_in_tools,
+                    i,
+                    "id",
+                )
+                .as_str(),
+            )
+            .and_then(|id| id.as_str())
+            .map(String::from);
+        let tool_call_arguments = parse_tool_call_arguments(
+            attributes.get(
+                tool_call_attribute(
+                    prefix,
+                    tool_call_attribute_name,
+                    use_index_in_tools,
+                    i,
+                    "arguments",
+                )
+                .as_str(),
+            ),
+        );
+        tool_calls.push(tool_call_block(
+            tool_call_name.clone(),
+            tool_call_id,
+            tool_call_arguments,
+        ));
+        i += 1;
+        if !use_index_in_tools {
+            break;
+        }
+    }
+
+    completion_output(text_msg, tool_calls)
+}
@@ -0,0 +1,32 @@
+//! Traceloop hard-codes `traceloop.entity.input` and `traceloop.entity.output` to
+//! LangChain auto-instrumented spans, including some of the deeply nested ones.
+
+use super::{InstrumentationAdapterTrait, InstrumentedSpan};
+use crate::db::spans::Span;
+
+const ENTITY_INPUT: &str = "traceloop.entity.input";
+const ENTITY_OUTPUT: &str = "traceloop.entity.output";
+const ENTITY_PATH: &str = "traceloop.entity.path";
+
+pub struct TraceloopAdapter;
+
+impl InstrumentationAdapterTrait for TraceloopAdapter {
+    fn matches(&self, _span: &Span, otel_span: &InstrumentedSpan) -> bool {
+        otel_span.attributes.contains_key(ENTITY_INPUT)
+            || otel_span.attributes.contains_key(ENTITY_OUTPUT)
+    }
+
+    /// Only fills in the input and output that are not set by the other adapters
+    fn apply(&self, span: &mut Span, otel_span: &InstrumentedSpan) {
+        if span.input.is_none() {
+            span.input = otel_span.attributes.get(ENTITY_INPUT).cloned();
+        }
+        if span.output.is_none() {
+            span.output = otel_span.attributes.get(ENTITY_OUTPUT).cloned();
+        }
+    }
+
+    fn consumes_attribute(&self, attribute: &str) -> bool {
+        attribute == ENTITY_INPUT || attribute == ENTITY_OUTPUT || attribute == ENTITY_PATH
+    }
+}
@@ -0,0 +1,121 @@
+//! Vercel AI SDK records the messages of the "raw" LLM spans, e.g.
+//! `ai.generateText.doGenerate`, in `ai.prompt.messages`. These are wrapped in spans,
+//! e.g. `ai.generateText`, which are not LLM spans, but have the prompt in `ai.prompt`.
+//! https://sdk.vercel.ai/docs/ai-sdk-core/telemetry
+
+use serde_json::{json, Value};
+
+use super::{json_or_string, InstrumentationAdapterTrait, InstrumentedSpan};
+use crate::{
+    db::spans::{Span, SpanType},
+    language_model::{
+        ChatMessage, ChatMessageContent, ChatMessageContentPart, ChatMessageToolCall,
+    },
+};
+
+const PROMPT_MESSAGES: &str = "ai.prompt.messages";
+const PROMPT: &str = "ai.prompt";
+const RESPONSE_TEXT: &str = "ai.response.text";
+const RESPONSE_OBJECT: &str = "ai.response.object";
+
+pub struct VercelAiAdapter;
+
+impl InstrumentationAdapterTrait for VercelAiAdapter {
+    fn matches(&self, _span: &Span, otel_span: &InstrumentedSpan) -> bool {
+        [PROMPT_MESSAGES, PROMPT, RESPONSE_TEXT, RESPONSE_OBJECT]
+            .iter()
+            .any(|key| otel_span.attributes.contains_key(*key))
+    }
+
+    fn apply(&self, span: &mut Span, otel_span: &InstrumentedSpan) {
+        let attributes = otel_span.attributes;
+        if span.span_type == SpanType::LLM {
+            if let Some(Value::String(s)) = attributes.get(PROMPT_MESSAGES) {
+                if let Ok(messages) = serde_json::from_str::<Vec<Value>>(s) {
+                    let input_messages = messages
+                        .into_iter()
+                        .map(chat_messages)
+                        .collect::<Option<Vec<_>>>();
+                    if let Some(input_messages) = input_messages {
+                        span.input = Some(json!(input_messages.concat()));
+                    }
+                }
+            }
+        }
+
+        if let Some(Value::String(s)) = attributes.get(PROMPT) {
+            span.input = Some(json_or_string(s));
+        }
+        if let Some(Value::String(s)) = attributes.get(RESPONSE_TEXT) {
+            span.output = Some(json_or_string(s));
+        } else if let Some(Value::String(s)) = attributes.get(RESPONSE_OBJECT) {
+            span.output = Some(json_or_string(s));
+        }
+    }
+
+    fn consumes_attribute(&self, attribute: &str) -> bool {
+        attribute == PROMPT_MESSAGES
+    }
+}
+
+/// The AI SDK records tool calls as `tool-call` content parts of assistant messages, and
+/// tool results as `tool-result` parts of a single tool message. Each tool result becomes
+/// a separate tool message.
+fn chat_messages(message: Value) -> Option<Vec<ChatMessage>> {
+    let role = message.get("role")?.as_str()?.to_string();
+    let Some(Value::Array(parts)) = message.get("content") else {
+        return serde_json::from_value::<ChatMessage>(message)
+            .ok()
+            .map(|m| vec![m]);
+    };
+
+    let mut content_parts = Vec::new();
+    let mut tool_calls = Vec::new();
+    let mut tool_results = Vec::new();
+    for part in parts {
+        match part.get("type").and_then(|t| t.as_str()) {
+            Some("tool-call") => tool_calls.push(ChatMessageToolCall {
+                id: part
+                    .get("toolCallId")
+                    .and_then(|id| id.as_str())
+                    .map(String::from),
+                name: part.get("toolName")?.as_str()?.to_string(),
+                arguments: ChatMessageToolCall::parse_arguments(
+                    part.get("args").cloned().unwrap_or_default(),
+                ),
+            }),
+            Some("tool-result") => tool_results.push(ChatMessage {
+                role: "tool".to_string(),
+                content: ChatMessageContent::Text(match part.get("result") {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(result) => result.to_string(),
+                    None => String::new(),
+                }),
+                tool_call_id: part
+                    .get("toolCallId")
+                    .and_then(|id| id.as_str())
+                    .map(String::from),
+                ..Default::default()
+            }),
+            _ => content_parts
+                .push(serde_json::from_value::<ChatMessageContentPart>(part.clone()).ok()?),
+        }
+    }
+
+    if !tool_results.is_empty() && content_parts.is_empty() && tool_calls.is_empty() {
+        return Some(tool_results);
+    }
+    let content = if content_parts.is_empty() {
+        ChatMessageContent::Text(String::new())
+    } else {
+        ChatMessageContent::ContentPartList(content_parts)
+    };
+    let mut messages = vec![ChatMessage {
+        role,
+        content,
+        tool_calls,
+        tool_call_id: None,
+    }];
+    messages.extend(tool_results);
+    Some(messages)
+}
@@ -1,31 +1,44 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use uuid::Uuid;
 
 use crate::{
     cache::Cache,
-    ch::{self, spans::CHSpan},
+    ch::{events::CHEvent, labels::CHLabel, spans::CHSpan},
     db::{
         events::Event, labels::get_registered_label_classes_for_path, spans::Span,
         stats::add_spans_and_events_to_project_usage_stats, DB,
     },
-    mq::MessageQueueAcker,
     pipeline::runner::PipelineRunner,
     traces::{
+        attributes::TraceAttributes,
+        batch::PendingSpan,
         evaluators::run_evaluator,
         events::record_events,
-        utils::{get_llm_usage_for_span, record_labels_to_db_and_ch, record_span_to_db},
+        utils::{
+            get_llm_usage_for_span, prepare_span_for_recording, record_labels_to_db,
+            record_spans_to_db,
+        },
     },
 };
 
 pub mod attributes;
+pub mod batch;
 pub mod consumer;
+pub mod cost_backfill;
 pub mod evaluators;
 pub mod events;
 pub mod grpc_service;
+pub mod instrumentation;
 pub mod limits;
+pub mod otlp_json;
 
 pub mod producer;
+pub mod redaction;
+pub mod sampling;
 pub mod span_attributes;
 pub mod spans;
 pub mod utils;
@@ -34,74 +47,105 @@ pub const OBSERVATIONS_QUEUE: &str = "observations_queue";
 pub const OBSERVATIONS_EXCHANGE: &str = "observations_exchange";
 pub const OBSERVATIONS_ROUTING_KEY: &str = "observations_routing_key";
 
-pub async fn process_spans_and_events(
+/// Compute the usage of the span and fill in its path and parent, so that it can be
+/// recorded as part of a batch.
+///
+/// Returns the attributes the span contributes to its trace and its Clickhouse row.
+pub async fn prepare_span(
     span: &mut Span,
-    events: Vec<Event>,
     project_id: &Uuid,
     db: Arc<DB>,
-    clickhouse: clickhouse::Client,
     cache: Arc<Cache>,
-    acker: MessageQueueAcker,
-) {
-    let span_usage =
-        get_llm_usage_for_span(&mut span.get_attributes(), db.clone(), cache.clone()).await;
+) -> (TraceAttributes, CHSpan) {
+    let span_usage = get_llm_usage_for_span(
+        &mut span.get_attributes(),
+        db,
+        cache,
+        project_id,
+        &span.start_time,
+    )
+    .await;
+    let trace_attributes = prepare_span_for_recording(&span_usage, span);
+    let ch_span = CHSpan::from_db_span(span, span_usage, *project_id);
+    (trace_attributes, ch_span)
+}
 
-    match record_span_to_db(db.clone(), &span_usage, &project_id, span).await {
-        Ok(_) => {
-            let _ = acker.ack().await.map_err(|e| {
-                log::error!("Failed to ack MQ delivery (span): {:?}", e);
-            });
+/// Record a batch of spans and their events and labels to the database and return
+/// the event and label rows to be inserted into Clickhouse.
+///
+/// Returns an error only if the spans themselves could not be recorded. Recording the
+/// same span messages again does not count them twice: spans and labels are upserted,
+/// events that were already recorded are skipped, and only newly recorded spans add to
+/// the usage of their traces and, along with newly recorded events, of their projects.
+/// The returned rows include the rows of spans and events that were recorded before, and
+/// the batch skips the ones that are already in Clickhouse, so that a batch whose
+/// Clickhouse insert failed can be replayed.
+pub async fn record_spans_and_events(
+    spans: &[PendingSpan],
+    db: Arc<DB>,
+) -> anyhow::Result<(Vec<CHEvent>, Vec<CHLabel>)> {
+    let records = spans
+        .iter()
+        .map(|pending| (pending.project_id, &pending.span, &pending.trace_attributes))
+        .collect::<Vec<_>>();
+    let inserted_spans = match record_spans_to_db(db.clone(), &records).await {
+        Ok(inserted_spans) => inserted_spans,
+        Err(e) => {
+            log::error!("Failed to record batch of {} spans: {:?}", spans.len(), e);
+            return Err(e);
         }
+    };
+
+    let events = spans
+        .iter()
+        .flat_map(|pending| pending.events.iter().cloned())
+        .collect::<Vec<Event>>();
+    let (ch_events, inserted_events) = match record_events(db.clone(), events).await {
+        Ok(recorded) => recorded,
         Err(e) => {
+            log::error!("Failed to record events: {:?}", e);
+            (Vec::new(), HashMap::new())
+        }
+    };
+
+    let project_ids = inserted_spans
+        .keys()
+        .chain(inserted_events.keys())
+        .collect::<HashSet<_>>();
+    for project_id in project_ids {
+        let spans_count = inserted_spans.get(project_id).copied().unwrap_or_default();
+        let events_count = inserted_events.get(project_id).copied().unwrap_or_default();
+        if let Err(e) = add_spans_and_events_to_project_usage_stats(
+            &db.pool,
+            project_id,
+            spans_count,
+            events_count,
+        )
+        .await
+        {
             log::error!(
-                "Failed to record span. span_id [{}], project_id [{}]: {:?}",
-                span.span_id,
-                project_id,
+                "Failed to add spans and events to project usage stats: {:?}",
                 e
             );
-            // TODO: Implement proper nacks and DLX
-            let _ = acker.reject(false).await.map_err(|e| {
-                log::error!("Failed to reject MQ delivery (span): {:?}", e);
-            });
         }
     }
 
-    let events_count = events.len() as i64;
-
-    if let Err(e) =
-        add_spans_and_events_to_project_usage_stats(&db.pool, &project_id, 1, events_count).await
-    {
-        log::error!(
-            "Failed to add spans and events to project usage stats: {:?}",
-            e
-        );
-    }
-
-    if let Err(e) = record_events(db.clone(), clickhouse.clone(), events).await {
-        log::error!("Failed to record events: {:?}", e);
-    }
-
-    if let Err(e) =
-        record_labels_to_db_and_ch(db.clone(), clickhouse.clone(), &span, &project_id).await
-    {
-        log::error!(
-            "Failed to record labels to DB. span_id [{}], project_id [{}]: {:?}",
-            span.span_id,
-            project_id,
-            e
-        );
+    let mut ch_labels = Vec::new();
+    for pending in spans {
+        match record_labels_to_db(db.clone(), &pending.span, &pending.project_id).await {
+            Ok(labels) => ch_labels.extend(labels),
+            Err(e) => {
+                log::error!(
+                    "Failed to record labels to DB. span_id [{}], project_id [{}]: {:?}",
+                    pending.span.span_id,
+                    pending.project_id,
+                    e
+                );
+            }
+        }
     }
 
-    let ch_span = CHSpan::from_db_span(span, span_usage, *project_id);
-
-    if let Err(e) = ch::spans::insert_span(clickhouse.clone(), &ch_span).await {
-        log::error!(
-            "Failed to insert span into Clickhouse. span_id [{}], project_id [{}]: {:?}",
-            span.span_id,
-            project_id,
-            e
-        );
-    }
+    Ok((ch_events, ch_labels))
 }
 
 pub async fn process_label_classes(
@@ -0,0 +1,497 @@
+//! OTLP/HTTP JSON encoding of trace exports.
+//!
+//! The JSON mapping differs from the canonical proto3 JSON mapping in a few places:
+//! trace and span ids are hex-encoded instead of base64, field names are camelCase,
+//! 64-bit integers may be sent either as numbers or as strings, and enums may be sent
+//! either as integers or as their string names.
+//!
+//! https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding
+//!
+//! The structs here mirror the protobuf types and are converted into them, so that the
+//! rest of the ingestion pipeline is agnostic of the wire encoding.
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::Result;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::opentelemetry::{
+    opentelemetry::proto::collector::trace::v1::{
+        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    },
+    opentelemetry_proto_common_v1::{
+        any_value, AnyValue, ArrayValue, InstrumentationScope, KeyValue, KeyValueList,
+    },
+    opentelemetry_proto_resource_v1::Resource,
+    opentelemetry_proto_trace_v1::{
+        span::{Event, Link, SpanKind},
+        status::StatusCode,
+        ResourceSpans, ScopeSpans, Span, Status,
+    },
+};
+
+pub fn decode_export_trace_service_request(body: &[u8]) -> Result<ExportTraceServiceRequest> {
+    let request = serde_json::from_slice::<JsonExportTraceServiceRequest>(body)?;
+    Ok(request.into())
+}
+
+pub fn encode_export_trace_service_response(
+    response: &ExportTraceServiceResponse,
+) -> serde_json::Value {
+    match &response.partial_success {
+        Some(partial_success) => serde_json::json!({
+            "partialSuccess": {
+                "rejectedSpans": partial_success.rejected_spans.to_string(),
+                "errorMessage": partial_success.error_message,
+            }
+        }),
+        None => serde_json::json!({}),
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonExportTraceServiceRequest {
+    resource_spans: Vec<JsonResourceSpans>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonResourceSpans {
+    resource: Option<JsonResource>,
+    scope_spans: Vec<JsonScopeSpans>,
+    schema_url: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonScopeSpans {
+    scope: Option<JsonInstrumentationScope>,
+    spans: Vec<JsonSpan>,
+    schema_url: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JsonResource {
+    attributes: Vec<JsonKeyValue>,
+    dropped_attributes_count: u32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JsonInstrumentationScope {
+    name: String,
+    version: String,
+    attributes: Vec<JsonKeyValue>,
+    dropped_attributes_count: u32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonSpan {
+    #[serde(deserialize_with = "deserialize_hex_bytes")]
+    trace_id: Vec<u8>,
+    #[serde(deserialize_with = "deserialize_hex_bytes")]
+    span_id: Vec<u8>,
+    trace_state: String,
+    #[serde(deserialize_with = "deserialize_hex_bytes")]
+    parent_span_id: Vec<u8>,
+    flags: u32,
+    name: String,
+    #[serde(deserialize_with = "deserialize_span_kind")]
+    kind: i32,
+    #[serde(deserialize_with = "deserialize_number")]
+    start_time_unix_nano: u64,
+    #[serde(deserialize_with = "deserialize_number")]
+    end_time_unix_nano: u64,
+    attributes: Vec<JsonKeyValue>,
+    dropped_attributes_count: u32,
+    events: Vec<JsonEvent>,
+    dropped_events_count: u32,
+    links: Vec<JsonLink>,
+    dropped_links_count: u32,
+    status: Option<JsonStatus>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonEvent {
+    #[serde(deserialize_with = "deserialize_number")]
+    time_unix_nano: u64,
+    name: String,
+    attributes: Vec<JsonKeyValue>,
+    dropped_attributes_count: u32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonLink {
+    #[serde(deserialize_with = "deserialize_hex_bytes")]
+    trace_id: Vec<u8>,
+    #[serde(deserialize_with = "deserialize_hex_bytes")]
+    span_id: Vec<u8>,
+    trace_state: String,
+    attributes: Vec<JsonKeyValue>,
+    dropped_attributes_count: u32,
+    flags: u32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonStatus {
+    message: String,
+    #[serde(deserialize_with = "deserialize_status_code")]
+    code: i32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JsonKeyValue {
+    key: String,
+    value: Option<JsonAnyValue>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JsonAnyValue {
+    string_value: Option<String>,
+    bool_value: Option<bool>,
+    #[serde(deserialize_with = "deserialize_optional_number")]
+    int_value: Option<i64>,
+    #[serde(deserialize_with = "deserialize_optional_number")]
+    double_value: Option<f64>,
+    array_value: Option<JsonArrayValue>,
+    kvlist_value: Option<JsonKeyValueList>,
+    #[serde(deserialize_with = "deserialize_base64_bytes")]
+    bytes_value: Option<Vec<u8>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonArrayValue {
+    values: Vec<JsonAnyValue>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonKeyValueList {
+    values: Vec<JsonKeyValue>,
+}
+
+impl From<JsonExportTraceServiceRequest> for ExportTraceServiceRequest {
+    fn from(request: JsonExportTraceServiceRequest) -> Self {
+        Self {
+            resource_spans: request.resource_spans.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<JsonResourceSpans> for ResourceSpans {
+    fn from(resource_spans: JsonResourceSpans) -> Self {
+        Self {
+            resource: resource_spans.resource.map(Into::into),
+            scope_spans: resource_spans
+                .scope_spans
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            schema_url: resource_spans.schema_url,
+        }
+    }
+}
+
+impl From<JsonScopeSpans> for ScopeSpans {
+    fn from(scope_spans: JsonScopeSpans) -> Self {
+        Self {
+            scope: scope_spans.scope.map(Into::into),
+            spans: scope_spans.spans.into_iter().map(Into::into).collect(),
+            schema_url: scope_spans.schema_url,
+        }
+    }
+}
+
+impl From<JsonResource> for Resource {
+    fn from(resource: JsonResource) -> Self {
+        Self {
+            attributes: resource.attributes.into_iter().map(Into::into).collect(),
+            dropped_attributes_count: resource.dropped_attributes_count,
+        }
+    }
+}
+
+impl From<JsonInstrumentationScope> for InstrumentationScope {
+    fn from(scope: JsonInstrumentationScope) -> Self {
+        Self {
+            name: scope.name,
+            version: scope.version,
+            attributes: scope.attributes.into_iter().map(Into::into).collect(),
+            dropped_attributes_count: scope.dropped_attributes_count,
+        }
+    }
+}
+
+impl From<JsonSpan> for Span {
+    fn from(span: JsonSpan) -> Self {
+        Self {
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            trace_state: span.trace_state,
+            parent_span_id: span.parent_span_id,
+            flags: span.flags,
+            name: span.name,
+            kind: span.kind,
+            start_time_unix_nano: span.start_time_unix_nano,
+            end_time_unix_nano: span.end_time_unix_nano,
+            attributes: span.attributes.into_iter().map(Into::into).collect(),
+            dropped_attributes_count: span.dropped_attributes_count,
+            events: span.events.into_iter().map(Into::into).collect(),
+            dropped_events_count: span.dropped_events_count,
+            links: span.links.into_iter().map(Into::into).collect(),
+            dropped_links_count: span.dropped_links_count,
+            status: span.status.map(Into::into),
+        }
+    }
+}
+
+impl From<JsonEvent> for Event {
+    fn from(event: JsonEvent) -> Self {
+        Self {
+            time_unix_nano: event.time_unix_nano,
+            name: event.name,
+            attributes: event.attributes.into_iter().map(Into::into).collect(),
+            dropped_attributes_count: event.dropped_attributes_count,
+        }
+    }
+}
+
+impl From<JsonLink> for Link {
+    fn from(link: JsonLink) -> Self {
+        Self {
+            trace_id: link.trace_id,
+            span_id: link.span_id,
+            trace_state: link.trace_state,
+            attributes: link.attributes.into_iter().map(Into::into).collect(),
+            dropped_attributes_count: link.dropped_attributes_count,
+            flags: link.flags,
+        }
+    }
+}
+
+impl From<JsonStatus> for Status {
+    fn from(status: JsonStatus) -> Self {
+        Self {
+            message: status.message,
+            code: status.code,
+        }
+    }
+}
+
+impl From<JsonKeyValue> for KeyValue {
+    fn from(key_value: JsonKeyValue) -> Self {
+        Self {
+            key: key_value.key,
+            value: key_value.value.map(Into::into),
+        }
+    }
+}
+
+impl From<JsonAnyValue> for AnyValue {
+    fn from(any_value: JsonAnyValue) -> Self {
+        let value = if let Some(s) = any_value.string_value {
+            Some(any_value::Value::StringValue(s))
+        } else if let Some(b) = any_value.bool_value {
+            Some(any_value::Value::BoolValue(b))
+        } else if let Some(i) = any_value.int_value {
+            Some(any_value::Value::IntValue(i))
+        } else if let Some(d) = any_value.double_value {
+            Some(any_value::Value::DoubleValue(d))
+        } else if let Some(array) = any_value.array_value {
+            Some(any_value::Value::ArrayValue(ArrayValue {
+                values: array.values.into_iter().map(Into::into).collect(),
+            }))
+        } else if let Some(kvlist) = any_value.kvlist_value {
+            Some(any_value::Value::KvlistValue(KeyValueList {
+                values: kvlist.values.into_iter().map(Into::into).collect(),
+            }))
+        } else {
+            any_value.bytes_value.map(any_value::Value::BytesValue)
+        };
+
+        Self { value }
+    }
+}
+
+/// 64-bit integers are encoded as strings in OTLP/JSON, but some exporters
+/// send them as plain JSON numbers, so we accept both.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString<T> {
+    Number(T),
+    String(String),
+}
+
+/// Enums are encoded as integers in OTLP/JSON, but the proto3 JSON mapping
+/// allows their string names as well.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IntegerOrName {
+    Integer(i32),
+    Name(String),
+}
+
+fn deserialize_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr + Default,
+    T::Err: Display,
+{
+    Ok(deserialize_optional_number(deserializer)?.unwrap_or_default())
+}
+
+fn deserialize_optional_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    match Option::<NumberOrString<T>>::deserialize(deserializer)? {
+        Some(NumberOrString::Number(n)) => Ok(Some(n)),
+        Some(NumberOrString::String(s)) => s.parse::<T>().map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn deserialize_span_kind<'de, D>(deserializer: D) -> Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<IntegerOrName>::deserialize(deserializer)? {
+        Some(IntegerOrName::Integer(i)) => Ok(i),
+        Some(IntegerOrName::Name(name)) => SpanKind::from_str_name(&name)
+            .map(|kind| kind as i32)
+            .ok_or_else(|| de::Error::custom(format!("Unknown span kind: {name}"))),
+        None => Ok(SpanKind::Unspecified as i32),
+    }
+}
+
+fn deserialize_status_code<'de, D>(deserializer: D) -> Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<IntegerOrName>::deserialize(deserializer)? {
+        Some(IntegerOrName::Integer(i)) => Ok(i),
+        Some(IntegerOrName::Name(name)) => StatusCode::from_str_name(&name)
+            .map(|code| code as i32)
+            .ok_or_else(|| de::Error::custom(format!("Unknown status code: {name}"))),
+        None => Ok(StatusCode::Unset as i32),
+    }
+}
+
+/// Trace and span ids are hex-encoded in OTLP/JSON, unlike other `bytes` fields.
+fn deserialize_hex_bytes<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    decode_hex(&s).map_err(de::Error::custom)
+}
+
+fn deserialize_base64_bytes<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) => BASE64_STANDARD
+            .decode(s.as_bytes())
+            .map(Some)
+            .map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    if !s.is_ascii() || s.len() % 2 != 0 {
+        return Err(anyhow::anyhow!("Invalid hex string: {s}"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&s[i..i + 2], 16)
+                .map_err(|_| anyhow::anyhow!("Invalid hex string: {s}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_export_trace_service_request() {
+        let body = r#"{
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{"key": "service.name", "value": {"stringValue": "my-service"}}]
+                },
+                "scopeSpans": [{
+                    "scope": {"name": "my-scope", "version": "1.0.0"},
+                    "spans": [{
+                        "traceId": "5b8efff798038103d269b633813fc60c",
+                        "spanId": "eee19b7ec3c1b174",
+                        "parentSpanId": "",
+                        "name": "my-span",
+                        "kind": 2,
+                        "startTimeUnixNano": "1544712660000000000",
+                        "endTimeUnixNano": 1544712661000000000,
+                        "attributes": [
+                            {"key": "int", "value": {"intValue": "42"}},
+                            {"key": "double", "value": {"doubleValue": 1.5}},
+                            {"key": "list", "value": {"arrayValue": {"values": [{"boolValue": true}]}}}
+                        ],
+                        "events": [{"timeUnixNano": "1544712660500000000", "name": "event"}],
+                        "status": {"code": "STATUS_CODE_ERROR", "message": "boom"}
+                    }]
+                }]
+            }]
+        }"#;
+
+        let request = decode_export_trace_service_request(body.as_bytes()).unwrap();
+        let resource_spans = &request.resource_spans[0];
+        assert_eq!(
+            resource_spans.resource.as_ref().unwrap().attributes[0].key,
+            "service.name"
+        );
+
+        let scope_spans = &resource_spans.scope_spans[0];
+        assert_eq!(scope_spans.scope.as_ref().unwrap().name, "my-scope");
+
+        let span = &scope_spans.spans[0];
+        assert_eq!(span.trace_id.len(), 16);
+        assert_eq!(
+            span.span_id,
+            vec![0xee, 0xe1, 0x9b, 0x7e, 0xc3, 0xc1, 0xb1, 0x74]
+        );
+        assert!(span.parent_span_id.is_empty());
+        assert_eq!(span.kind, SpanKind::Server as i32);
+        assert_eq!(span.start_time_unix_nano, 1544712660000000000);
+        assert_eq!(span.end_time_unix_nano, 1544712661000000000);
+        assert_eq!(
+            span.attributes[0].value.as_ref().unwrap().value,
+            Some(any_value::Value::IntValue(42))
+        );
+        assert_eq!(
+            span.attributes[1].value.as_ref().unwrap().value,
+            Some(any_value::Value::DoubleValue(1.5))
+        );
+        assert_eq!(span.events[0].time_unix_nano, 1544712660500000000);
+        assert_eq!(span.status.as_ref().unwrap().code, StatusCode::Error as i32);
+    }
+
+    #[test]
+    fn test_decode_invalid_trace_id() {
+        let body = r#"{"resourceSpans": [{"scopeSpans": [{"spans": [{"traceId": "not-hex"}]}]}]}"#;
+        assert!(decode_export_trace_service_request(body.as_bytes()).is_err());
+    }
+}
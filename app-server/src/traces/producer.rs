@@ -1,7 +1,7 @@
 //! This module takes trace exports from OpenTelemetry and pushes them
 //! to RabbitMQ for further processing.
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, env, sync::Arc};
 
 use anyhow::Result;
 use uuid::Uuid;
@@ -10,23 +10,119 @@ use crate::{
     api::v1::traces::RabbitMqSpanMessage,
     db::{events::Event, spans::Span},
     mq::{MessageQueue, MessageQueueTrait},
-    opentelemetry::opentelemetry::proto::collector::trace::v1::{
-        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    opentelemetry::{
+        opentelemetry::proto::collector::trace::v1::{
+            ExportTracePartialSuccess, ExportTraceServiceRequest, ExportTraceServiceResponse,
+        },
+        opentelemetry_proto_trace_v1::Span as OtelSpan,
     },
 };
 
-use super::{OBSERVATIONS_EXCHANGE, OBSERVATIONS_ROUTING_KEY};
+use super::{
+    spans::otel_resource_and_scope_attributes, OBSERVATIONS_EXCHANGE, OBSERVATIONS_ROUTING_KEY,
+};
+
+// Maximum size of a single serialized span message in the queue.
+// Defaults to the gRPC payload limit.
+const DEFAULT_MAX_SPAN_MESSAGE_BYTES: usize = 26_214_400; // 25MB
+
+/// Reasons for which a span in an export request is permanently not ingested.
+///
+/// They are reported back to the exporter in the OTLP `partial_success` field, which
+/// exporters do not retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SpanRejectionReason {
+    InvalidTraceId,
+    InvalidSpanId,
+    InvalidParentSpanId,
+    InvalidTimestamp,
+    PayloadTooLarge,
+    NotSaved,
+    LimitExceeded,
+}
+
+impl SpanRejectionReason {
+    fn description(&self) -> &'static str {
+        match self {
+            Self::InvalidTraceId => "invalid trace id",
+            Self::InvalidSpanId => "invalid span id",
+            Self::InvalidParentSpanId => "invalid parent span id",
+            Self::InvalidTimestamp => "invalid start or end time",
+            Self::PayloadTooLarge => "payload too large",
+            Self::NotSaved => "dropped by tracing level or span name",
+            Self::LimitExceeded => "workspace span limit exceeded",
+        }
+    }
+}
+
+#[derive(Default)]
+struct SpanRejections {
+    counts: BTreeMap<SpanRejectionReason, i64>,
+}
+
+impl SpanRejections {
+    fn add(&mut self, reason: SpanRejectionReason, count: i64) {
+        if count > 0 {
+            *self.counts.entry(reason).or_default() += count;
+        }
+    }
+
+    fn total(&self) -> i64 {
+        self.counts.values().sum()
+    }
+
+    fn into_response(self) -> ExportTraceServiceResponse {
+        let rejected_spans = self.total();
+        if rejected_spans == 0 {
+            return ExportTraceServiceResponse {
+                partial_success: None,
+            };
+        }
+
+        let reasons = self
+            .counts
+            .iter()
+            .map(|(reason, count)| format!("{count} {}", reason.description()))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        ExportTraceServiceResponse {
+            partial_success: Some(ExportTracePartialSuccess {
+                rejected_spans,
+                error_message: format!("Rejected {rejected_spans} span(s): {reasons}"),
+            }),
+        }
+    }
+}
 
-// TODO: Implement partial_success
 pub async fn push_spans_to_queue(
     request: ExportTraceServiceRequest,
     project_id: Uuid,
     queue: Arc<MessageQueue>,
 ) -> Result<ExportTraceServiceResponse> {
+    let max_message_bytes = env::var("MAX_SPAN_MESSAGE_BYTES")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_SPAN_MESSAGE_BYTES);
+
+    let mut rejections = SpanRejections::default();
+    let mut failed_spans = 0;
+    let mut last_publish_error = None;
+
     for resource_span in request.resource_spans {
         for scope_span in resource_span.scope_spans {
+            let resource_and_scope_attributes = otel_resource_and_scope_attributes(
+                resource_span.resource.as_ref(),
+                scope_span.scope.as_ref(),
+            );
             for otel_span in scope_span.spans {
-                let span = Span::from_otel_span(otel_span.clone());
+                if let Err(reason) = validate_otel_span(&otel_span) {
+                    rejections.add(reason, 1);
+                    continue;
+                }
+
+                let mut span = Span::from_otel_span(otel_span.clone(), scope_span.scope.as_ref());
+                span.add_default_attributes(&resource_and_scope_attributes);
 
                 let events = otel_span
                     .events
@@ -44,6 +140,7 @@ pub async fn push_spans_to_queue(
                     .collect::<Vec<Event>>();
 
                 if !span.should_save() {
+                    rejections.add(SpanRejectionReason::NotSaved, 1);
                     continue;
                 }
 
@@ -53,20 +150,197 @@ pub async fn push_spans_to_queue(
                     events,
                 };
 
-                queue
-                    .publish(
-                        &serde_json::to_vec(&rabbitmq_span_message).unwrap(),
-                        OBSERVATIONS_EXCHANGE,
-                        OBSERVATIONS_ROUTING_KEY,
-                    )
-                    .await?;
+                let message = serde_json::to_vec(&rabbitmq_span_message)?;
+                if let Err(reason) = validate_message_size(&message, max_message_bytes) {
+                    rejections.add(reason, 1);
+                    continue;
+                }
+
+                match queue
+                    .publish(&message, OBSERVATIONS_EXCHANGE, OBSERVATIONS_ROUTING_KEY)
+                    .await
+                {
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::error!(
+                            "Failed to publish span. span_id [{}], project_id [{}]: {:?}",
+                            rabbitmq_span_message.span.span_id,
+                            project_id,
+                            e
+                        );
+                        failed_spans += 1;
+                        last_publish_error = Some(e);
+                    }
+                }
             }
         }
     }
 
-    let response = ExportTraceServiceResponse {
-        partial_success: None,
-    };
+    // If any span could not be published, fail the whole export, so that the exporter
+    // retries it, instead of reporting a partial success which must not be retried.
+    // The spans that were published are then received again, and are recorded once.
+    if let Some(e) = last_publish_error {
+        return Err(e.context(format!("Failed to publish {failed_spans} span(s)")));
+    }
+
+    Ok(rejections.into_response())
+}
+
+/// Build a response that rejects every span in the request, e.g. when the workspace
+/// is over its span limit.
+pub fn reject_all_spans(request: &ExportTraceServiceRequest) -> ExportTraceServiceResponse {
+    let span_count = request
+        .resource_spans
+        .iter()
+        .flat_map(|resource_span| resource_span.scope_spans.iter())
+        .map(|scope_span| scope_span.spans.len() as i64)
+        .sum();
+
+    let mut rejections = SpanRejections::default();
+    rejections.add(SpanRejectionReason::LimitExceeded, span_count);
+    rejections.into_response()
+}
+
+fn validate_otel_span(otel_span: &OtelSpan) -> Result<(), SpanRejectionReason> {
+    if otel_span.trace_id.len() != 16 || otel_span.trace_id.iter().all(|b| *b == 0) {
+        return Err(SpanRejectionReason::InvalidTraceId);
+    }
+    if otel_span.span_id.len() != 8 || otel_span.span_id.iter().all(|b| *b == 0) {
+        return Err(SpanRejectionReason::InvalidSpanId);
+    }
+    if !otel_span.parent_span_id.is_empty() && otel_span.parent_span_id.len() != 8 {
+        return Err(SpanRejectionReason::InvalidParentSpanId);
+    }
+    if otel_span.start_time_unix_nano == 0
+        || otel_span.end_time_unix_nano < otel_span.start_time_unix_nano
+    {
+        return Err(SpanRejectionReason::InvalidTimestamp);
+    }
+    Ok(())
+}
+
+fn validate_message_size(
+    message: &[u8],
+    max_message_bytes: usize,
+) -> Result<(), SpanRejectionReason> {
+    if message.len() > max_message_bytes {
+        return Err(SpanRejectionReason::PayloadTooLarge);
+    }
+    Ok(())
+}
 
-    Ok(response)
+#[cfg(test)]
+mod tests {
+    use crate::opentelemetry::opentelemetry_proto_common_v1::{any_value, AnyValue, KeyValue};
+
+    use super::*;
+
+    fn otel_span() -> OtelSpan {
+        OtelSpan {
+            trace_id: vec![1; 16],
+            span_id: vec![2; 8],
+            name: "span".to_string(),
+            start_time_unix_nano: 1_700_000_000_000_000_000,
+            end_time_unix_nano: 1_700_000_001_000_000_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_invalid_ids_and_timestamps() {
+        assert_eq!(validate_otel_span(&otel_span()), Ok(()));
+
+        let cases = [
+            (
+                OtelSpan {
+                    trace_id: vec![0; 16],
+                    ..otel_span()
+                },
+                SpanRejectionReason::InvalidTraceId,
+            ),
+            (
+                OtelSpan {
+                    trace_id: vec![1; 8],
+                    ..otel_span()
+                },
+                SpanRejectionReason::InvalidTraceId,
+            ),
+            (
+                OtelSpan {
+                    span_id: vec![2; 16],
+                    ..otel_span()
+                },
+                SpanRejectionReason::InvalidSpanId,
+            ),
+            (
+                OtelSpan {
+                    parent_span_id: vec![3; 4],
+                    ..otel_span()
+                },
+                SpanRejectionReason::InvalidParentSpanId,
+            ),
+            (
+                OtelSpan {
+                    start_time_unix_nano: 0,
+                    ..otel_span()
+                },
+                SpanRejectionReason::InvalidTimestamp,
+            ),
+            (
+                OtelSpan {
+                    end_time_unix_nano: 1,
+                    ..otel_span()
+                },
+                SpanRejectionReason::InvalidTimestamp,
+            ),
+        ];
+        for (span, reason) in cases {
+            assert_eq!(validate_otel_span(&span), Err(reason));
+        }
+    }
+
+    #[test]
+    fn test_oversized_attribute_is_rejected() {
+        let otel_span = OtelSpan {
+            attributes: vec![KeyValue {
+                key: "input.value".to_string(),
+                value: Some(AnyValue {
+                    value: Some(any_value::Value::StringValue("a".repeat(10_000))),
+                }),
+            }],
+            ..otel_span()
+        };
+        let message = serde_json::to_vec(&RabbitMqSpanMessage {
+            project_id: Uuid::new_v4(),
+            span: Span::from_otel_span(otel_span, None),
+            events: vec![],
+        })
+        .unwrap();
+
+        assert_eq!(
+            validate_message_size(&message, 1024),
+            Err(SpanRejectionReason::PayloadTooLarge)
+        );
+        assert_eq!(validate_message_size(&message, message.len()), Ok(()));
+    }
+
+    #[test]
+    fn test_rejections_response() {
+        assert!(SpanRejections::default()
+            .into_response()
+            .partial_success
+            .is_none());
+
+        let mut rejections = SpanRejections::default();
+        rejections.add(SpanRejectionReason::InvalidSpanId, 2);
+        rejections.add(SpanRejectionReason::PayloadTooLarge, 1);
+        rejections.add(SpanRejectionReason::NotSaved, 0);
+        let partial_success = rejections.into_response().partial_success.unwrap();
+
+        assert_eq!(partial_success.rejected_spans, 3);
+        assert_eq!(
+            partial_success.error_message,
+            "Rejected 3 span(s): 2 invalid span id, 1 payload too large"
+        );
+    }
 }
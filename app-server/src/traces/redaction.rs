@@ -0,0 +1,476 @@
+//! Project-level redaction of span payloads. Rules are applied in the span consumer,
+//! before the span is written anywhere, to the span input, output, attributes and
+//! status message, and to the attributes of its events.
+
+use std::{collections::HashMap, env, sync::Arc};
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde_json::Value;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::span_attributes::REDACTION_RULES;
+use crate::{
+    cache::{keys::REDACTION_RULES_CACHE_KEY, Cache, CacheTrait},
+    db::{
+        self,
+        events::Event,
+        redaction_rules::{RedactionAction, RedactionRule, RedactionRuleType},
+        spans::Span,
+        DB,
+    },
+};
+
+const MASK: &str = "[REDACTED]";
+
+/// Checks a detector match further, to reduce false positives
+type Validator = fn(&str) -> bool;
+
+lazy_static! {
+    static ref DETECTORS: HashMap<&'static str, (Regex, Option<Validator>)> = {
+        let mut detectors: HashMap<&'static str, (Regex, Option<Validator>)> = HashMap::new();
+        detectors.insert(
+            "email",
+            (
+                Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b").unwrap(),
+                None,
+            ),
+        );
+        detectors.insert(
+            "phone",
+            (
+                Regex::new(r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)|\b\d{3})[\s.-]?\d{3}[\s.-]?\d{4}\b")
+                    .unwrap(),
+                None,
+            ),
+        );
+        detectors.insert(
+            "credit_card",
+            (
+                Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap(),
+                Some(luhn_check as Validator),
+            ),
+        );
+        detectors.insert(
+            "api_key",
+            (
+                Regex::new(concat!(
+                    r"\bsk-[A-Za-z0-9_-]{20,}",
+                    r"|\bAKIA[0-9A-Z]{16}\b",
+                    r"|\bgh[pousr]_[A-Za-z0-9]{36,}",
+                    r"|\bxox[abprs]-[A-Za-z0-9-]{10,}",
+                    r"|\bAIza[0-9A-Za-z_-]{35}",
+                    r"|\bBearer\s+[A-Za-z0-9._~+/-]{16,}=*",
+                ))
+                .unwrap(),
+                None,
+            ),
+        );
+        detectors
+    };
+    /// User patterns are compiled once per process, rules are re-read from the cache
+    static ref COMPILED_PATTERNS: moka::sync::Cache<String, Arc<Regex>> =
+        moka::sync::Cache::new(10_000);
+    /// Secret of the hashes of redacted values, so that they cannot be reversed by
+    /// hashing guesses. Defaults to AEAD_SECRET_KEY, changing it changes all new hashes.
+    static ref HASH_SECRET: Vec<u8> = env::var("REDACTION_HASH_SECRET")
+        .or_else(|_| env::var("AEAD_SECRET_KEY"))
+        .expect("REDACTION_HASH_SECRET or AEAD_SECRET_KEY must be set")
+        .into_bytes();
+}
+
+pub fn detector_names() -> Vec<&'static str> {
+    let mut names = DETECTORS.keys().copied().collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+enum Matcher {
+    Value(Arc<Regex>, Option<Validator>),
+    Key(Arc<Regex>),
+}
+
+struct CompiledRule {
+    id: Uuid,
+    matcher: Matcher,
+    action: RedactionAction,
+}
+
+impl CompiledRule {
+    fn new(rule: &RedactionRule) -> Result<Self> {
+        Ok(Self {
+            id: rule.id,
+            matcher: compile_matcher(&rule.rule_type, &rule.pattern)?,
+            action: rule.action.clone(),
+        })
+    }
+
+    /// Returns the redacted string if the rule matched
+    fn redact_str(&self, value: &str, hash_key: &[u8]) -> Option<String> {
+        let Matcher::Value(regex, validator) = &self.matcher else {
+            return None;
+        };
+        let mut matched = false;
+        let redacted = regex.replace_all(value, |caps: &Captures| {
+            let text = &caps[0];
+            if validator.map_or(true, |validate| validate(text)) {
+                matched = true;
+                replacement(&self.action, text, hash_key)
+            } else {
+                text.to_string()
+            }
+        });
+        matched.then(|| redacted.into_owned())
+    }
+
+    fn matches_key(&self, key: &str) -> bool {
+        match &self.matcher {
+            Matcher::Key(regex) => regex.is_match(key),
+            Matcher::Value(..) => false,
+        }
+    }
+}
+
+fn compile_matcher(rule_type: &RedactionRuleType, pattern: &str) -> Result<Matcher> {
+    match rule_type {
+        RedactionRuleType::DETECTOR => {
+            let Some((regex, validator)) = DETECTORS.get(pattern) else {
+                return Err(anyhow::anyhow!(
+                    "Unknown detector: {}. Available detectors: {}",
+                    pattern,
+                    detector_names().join(", ")
+                ));
+            };
+            Ok(Matcher::Value(Arc::new(regex.clone()), *validator))
+        }
+        RedactionRuleType::REGEX => Ok(Matcher::Value(compile_pattern(pattern)?, None)),
+        RedactionRuleType::ATTRIBUTE_KEY => Ok(Matcher::Key(compile_pattern(pattern)?)),
+    }
+}
+
+fn compile_pattern(pattern: &str) -> Result<Arc<Regex>> {
+    if let Some(regex) = COMPILED_PATTERNS.get(pattern) {
+        return Ok(regex);
+    }
+    let regex = Arc::new(Regex::new(pattern)?);
+    COMPILED_PATTERNS.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Check that a rule can be applied, e.g. that its regex is valid
+pub fn validate_rule(rule_type: &RedactionRuleType, pattern: &str) -> Result<()> {
+    if pattern.is_empty() {
+        return Err(anyhow::anyhow!("Pattern must not be empty"));
+    }
+    compile_matcher(rule_type, pattern).map(|_| ())
+}
+
+/// Key of the hashes of the values redacted in a project. Keys differ between projects,
+/// so that the same value cannot be matched across projects.
+pub fn project_hash_key(project_id: &Uuid) -> Vec<u8> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(&HASH_SECRET).expect("HMAC takes keys of any size");
+    mac.update(project_id.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn replacement(action: &RedactionAction, text: &str, hash_key: &[u8]) -> String {
+    match action {
+        RedactionAction::MASK => MASK.to_string(),
+        RedactionAction::HASH => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(hash_key).expect("HMAC takes keys of any size");
+            mac.update(text.as_bytes());
+            hex::encode(mac.finalize().into_bytes())
+        }
+        RedactionAction::DROP => String::new(),
+    }
+}
+
+pub struct Redactor {
+    rules: Vec<CompiledRule>,
+    hash_key: Vec<u8>,
+}
+
+impl Redactor {
+    /// Disabled rules are skipped, as well as the invalid ones, which can only be
+    /// stored if the built-in detectors change. Values are hashed with `hash_key`,
+    /// see [`project_hash_key`].
+    pub fn new(rules: &[RedactionRule], hash_key: Vec<u8>) -> Self {
+        let rules = rules
+            .iter()
+            .filter(|rule| rule.enabled)
+            .filter_map(|rule| match CompiledRule::new(rule) {
+                Ok(compiled) => Some(compiled),
+                Err(e) => {
+                    log::warn!("Skipping invalid redaction rule {}: {:?}", rule.id, e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules, hash_key }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Redact the span and its events in place and record the ids of the rules that
+    /// fired in the span attributes.
+    ///
+    /// Returns the ids of the rules that fired.
+    pub fn redact_span(&self, span: &mut Span, events: &mut [Event]) -> Vec<Uuid> {
+        let mut fired = vec![false; self.rules.len()];
+
+        if let Some(input) = span.input.as_mut() {
+            self.redact_value(input, &mut fired);
+        }
+        if let Some(output) = span.output.as_mut() {
+            self.redact_value(output, &mut fired);
+        }
+        if let Some(status_message) = span.status_message.as_mut() {
+            for (i, rule) in self.rules.iter().enumerate() {
+                if let Some(redacted) = rule.redact_str(status_message, &self.hash_key) {
+                    *status_message = redacted;
+                    fired[i] = true;
+                }
+            }
+        }
+        self.redact_value(&mut span.attributes, &mut fired);
+        for event in events.iter_mut() {
+            self.redact_value(&mut event.attributes, &mut fired);
+        }
+
+        let fired_ids = self
+            .rules
+            .iter()
+            .zip(fired)
+            .filter(|(_, fired)| *fired)
+            .map(|(rule, _)| rule.id)
+            .collect::<Vec<_>>();
+        if !fired_ids.is_empty() {
+            if let Some(attributes) = span.attributes.as_object_mut() {
+                attributes.insert(REDACTION_RULES.to_string(), serde_json::json!(fired_ids));
+            }
+        }
+        fired_ids
+    }
+
+    fn redact_value(&self, value: &mut Value, fired: &mut [bool]) {
+        match value {
+            Value::String(s) => {
+                for (i, rule) in self.rules.iter().enumerate() {
+                    if let Some(redacted) = rule.redact_str(s, &self.hash_key) {
+                        *s = redacted;
+                        fired[i] = true;
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for value in values.iter_mut() {
+                    self.redact_value(value, fired);
+                }
+            }
+            Value::Object(map) => {
+                let keys = map.keys().cloned().collect::<Vec<_>>();
+                for key in keys {
+                    for (i, rule) in self.rules.iter().enumerate() {
+                        if !rule.matches_key(&key) {
+                            continue;
+                        }
+                        fired[i] = true;
+                        match rule.action {
+                            RedactionAction::DROP => {
+                                map.remove(&key);
+                                break;
+                            }
+                            _ => {
+                                let value = map.get(&key).cloned().unwrap_or_default();
+                                let text = match value {
+                                    Value::String(s) => s,
+                                    other => other.to_string(),
+                                };
+                                map.insert(
+                                    key.clone(),
+                                    Value::String(replacement(&rule.action, &text, &self.hash_key)),
+                                );
+                            }
+                        }
+                    }
+                }
+                for value in map.values_mut() {
+                    self.redact_value(value, fired);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Get the redaction rules of the project, including the disabled ones
+pub async fn get_project_redaction_rules(
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    project_id: &Uuid,
+) -> Result<Vec<RedactionRule>> {
+    let cache_key = format!("{REDACTION_RULES_CACHE_KEY}:{project_id}");
+    match cache.get::<Vec<RedactionRule>>(&cache_key).await {
+        Ok(Some(rules)) => Ok(rules),
+        Ok(None) | Err(_) => {
+            let rules = db::redaction_rules::get_redaction_rules(&db.pool, project_id).await?;
+            let _ = cache
+                .insert::<Vec<RedactionRule>>(&cache_key, rules.clone())
+                .await;
+            Ok(rules)
+        }
+    }
+}
+
+fn luhn_check(text: &str) -> bool {
+    let digits = text
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .collect::<Vec<_>>();
+    if digits.len() < 13 || digits.len() > 19 {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                *d
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::*;
+    use crate::db::spans::{SpanStatus, SpanType};
+
+    fn rule(rule_type: RedactionRuleType, pattern: &str, action: RedactionAction) -> RedactionRule {
+        RedactionRule {
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            project_id: Uuid::nil(),
+            name: pattern.to_string(),
+            rule_type,
+            pattern: pattern.to_string(),
+            action,
+            enabled: true,
+        }
+    }
+
+    fn span(input: Value, attributes: Value) -> Span {
+        Span {
+            span_id: Uuid::new_v4(),
+            trace_id: Uuid::new_v4(),
+            parent_span_id: None,
+            name: "test".to_string(),
+            attributes,
+            input: Some(input),
+            output: None,
+            span_type: SpanType::DEFAULT,
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            events: None,
+            labels: None,
+            input_url: None,
+            output_url: None,
+            status: SpanStatus::UNSET,
+            status_message: None,
+        }
+    }
+
+    #[test]
+    fn test_detectors() {
+        let rules = vec![
+            rule(RedactionRuleType::DETECTOR, "email", RedactionAction::MASK),
+            rule(
+                RedactionRuleType::DETECTOR,
+                "credit_card",
+                RedactionAction::MASK,
+            ),
+            rule(RedactionRuleType::DETECTOR, "phone", RedactionAction::MASK),
+        ];
+        let redactor = Redactor::new(&rules, b"test-key".to_vec());
+        let mut span = span(
+            json!([{"role": "user", "content": "Mail jane.doe@example.com, card 4111 1111 1111 1111"}]),
+            json!({}),
+        );
+
+        let fired = redactor.redact_span(&mut span, &mut []);
+
+        assert_eq!(
+            span.input.unwrap(),
+            json!([{"role": "user", "content": "Mail [REDACTED], card [REDACTED]"}])
+        );
+        assert_eq!(fired, vec![rules[0].id, rules[1].id]);
+        assert_eq!(
+            span.attributes[REDACTION_RULES],
+            json!([rules[0].id, rules[1].id])
+        );
+    }
+
+    #[test]
+    fn test_luhn_rejects_invalid_numbers() {
+        assert!(luhn_check("4111 1111 1111 1111"));
+        assert!(!luhn_check("4111 1111 1111 1112"));
+        assert!(!luhn_check("1234"));
+    }
+
+    #[test]
+    fn test_attribute_key_and_hash() {
+        let rules = vec![
+            rule(
+                RedactionRuleType::ATTRIBUTE_KEY,
+                "^user\\.password$",
+                RedactionAction::DROP,
+            ),
+            rule(
+                RedactionRuleType::REGEX,
+                "secret-\\d+",
+                RedactionAction::HASH,
+            ),
+        ];
+        let redactor = Redactor::new(&rules, b"test-key".to_vec());
+        let mut span = span(
+            json!("the secret-42"),
+            json!({"user.password": "hunter2", "user.id": "1"}),
+        );
+
+        redactor.redact_span(&mut span, &mut []);
+
+        assert_eq!(
+            span.input.unwrap(),
+            json!(format!(
+                "the {}",
+                replacement(&RedactionAction::HASH, "secret-42", b"test-key")
+            ))
+        );
+        // Hashes depend on the key
+        assert_ne!(
+            replacement(&RedactionAction::HASH, "secret-42", b"test-key"),
+            replacement(&RedactionAction::HASH, "secret-42", b"other-key")
+        );
+        assert!(span.attributes.get("user.password").is_none());
+        assert_eq!(span.attributes["user.id"], json!("1"));
+    }
+}
@@ -0,0 +1,519 @@
+//! Server-side sampling of traces, see [`SamplingPolicy`].
+//!
+//! Head decisions only depend on the trace id, so that all spans of a trace get the
+//! same decision. If the policy has tail rules, traces that are not sampled by their id
+//! are buffered in the consumer, until one of their spans matches a tail rule, or their
+//! top span arrives without any span having matched. Traces whose top span does not
+//! arrive in time are kept, so that long traces are not cut.
+//!
+//! Decisions are stored in [`TraceDecisions`], so that they apply to the spans that arrive
+//! after the decision. They are only shared between the instances if Redis is configured.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use super::batch::PendingSpan;
+use crate::{
+    cache::{
+        in_memory::InMemoryCache,
+        keys::{SAMPLING_DECISION_CACHE_KEY, SAMPLING_POLICY_CACHE_KEY},
+        redis::RedisCache,
+        Cache, CacheTrait,
+    },
+    ch::spans::CHSpan,
+    db::{
+        self,
+        events::{Event, EXCEPTION_EVENT_NAME},
+        projects::SamplingPolicy,
+        spans::{Span, SpanStatus, SpanType},
+        DB,
+    },
+};
+
+const DEFAULT_SAMPLING_TRACE_WAIT_MS: u64 = 30_000;
+const DEFAULT_SAMPLING_MAX_BUFFERED_SPANS: usize = 10_000;
+const DEFAULT_SAMPLING_DECISIONS_CACHE_SIZE: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplingDecision {
+    Keep,
+    Drop,
+    /// The span must be buffered until its trace is decided
+    Defer,
+}
+
+impl SamplingPolicy {
+    /// True if all traces are kept
+    pub fn keeps_all(&self) -> bool {
+        self.rates.values().all(|rate| *rate >= 1.0)
+    }
+
+    pub fn has_tail_rules(&self) -> bool {
+        self.keep_errors
+            || self.llm_cost_threshold.is_some()
+            || self.llm_latency_threshold_ms.is_some()
+            || !self.session_ids.is_empty()
+    }
+
+    /// Decision that only depends on the trace id and type, and is the same for all
+    /// spans of a trace
+    pub fn head_keep(&self, span: &Span) -> bool {
+        if self.keeps_all() {
+            return true;
+        }
+        let trace_type = span.get_attributes().trace_type().unwrap_or_default();
+        match self.rates.get(&trace_type) {
+            Some(rate) => trace_sample_value(&span.trace_id) < *rate,
+            None => true,
+        }
+    }
+
+    fn tail_keep(&self, span: &Span, events: &[Event], ch_span: &CHSpan) -> bool {
+        if self.keep_errors
+            && (span.status == SpanStatus::ERROR
+                || events
+                    .iter()
+                    .any(|event| event.name == EXCEPTION_EVENT_NAME))
+        {
+            return true;
+        }
+        if span.span_type == SpanType::LLM {
+            if let Some(threshold) = self.llm_cost_threshold {
+                if ch_span.total_cost >= threshold {
+                    return true;
+                }
+            }
+            if let Some(threshold) = self.llm_latency_threshold_ms {
+                if (span.end_time - span.start_time).num_milliseconds() >= threshold {
+                    return true;
+                }
+            }
+        }
+        if !self.session_ids.is_empty() {
+            if let Some(session_id) = span.get_attributes().session_id() {
+                return self.session_ids.contains(&session_id);
+            }
+        }
+        false
+    }
+
+    /// Decide on a span that is not kept by the head decision, without the decisions
+    /// already made for its trace
+    pub fn tail_decide(&self, span: &Span, events: &[Event], ch_span: &CHSpan) -> SamplingDecision {
+        if self.tail_keep(span, events, ch_span) {
+            SamplingDecision::Keep
+        } else if span.parent_span_id.is_none() {
+            // The top span ends last, so the rest of the trace has been seen
+            SamplingDecision::Drop
+        } else {
+            SamplingDecision::Defer
+        }
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.rates.values().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err("Sampling rates must be between 0 and 1".to_string());
+        }
+        if self.llm_cost_threshold.is_some_and(|cost| cost < 0.0) {
+            return Err("LLM cost threshold must not be negative".to_string());
+        }
+        if self.llm_latency_threshold_ms.is_some_and(|ms| ms < 0) {
+            return Err("LLM latency threshold must not be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Uniform value in [0, 1) derived from the random bits of the trace id
+fn trace_sample_value(trace_id: &Uuid) -> f64 {
+    (trace_id.as_u128() as u64) as f64 / (u64::MAX as f64 + 1.0)
+}
+
+pub async fn get_sampling_policy(
+    db: Arc<DB>,
+    cache: Arc<Cache>,
+    project_id: &Uuid,
+) -> Result<SamplingPolicy> {
+    let cache_key = format!("{SAMPLING_POLICY_CACHE_KEY}:{project_id}");
+    match cache.get::<SamplingPolicy>(&cache_key).await {
+        Ok(Some(policy)) => Ok(policy),
+        Ok(None) | Err(_) => {
+            let policy = db::projects::get_sampling_policy(&db.pool, project_id).await?;
+            let _ = cache
+                .insert::<SamplingPolicy>(&cache_key, policy.clone())
+                .await;
+            Ok(policy)
+        }
+    }
+}
+
+/// Sampling decisions of the traces, kept apart from the shared cache, so that the
+/// decisions of busy projects neither evict other entries nor get evicted by them.
+pub struct TraceDecisions {
+    cache: Cache,
+}
+
+impl TraceDecisions {
+    /// Decisions are stored in Redis if `redis_url` is set, and are then shared with the
+    /// other instances. Otherwise each instance keeps up to SAMPLING_DECISIONS_CACHE_SIZE
+    /// decisions in memory.
+    pub async fn new(redis_url: Option<&str>) -> Result<Self> {
+        let cache = match redis_url {
+            Some(redis_url) => Cache::Redis(RedisCache::new(redis_url).await?),
+            None => {
+                let capacity = env::var("SAMPLING_DECISIONS_CACHE_SIZE")
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(DEFAULT_SAMPLING_DECISIONS_CACHE_SIZE);
+                Cache::InMemory(InMemoryCache::new(Some(capacity)))
+            }
+        };
+        Ok(Self { cache })
+    }
+
+    /// Get the decision made for the trace, if any. `true` if the trace is kept
+    pub async fn get(&self, trace_id: &Uuid) -> Option<bool> {
+        let cache_key = format!("{SAMPLING_DECISION_CACHE_KEY}:{trace_id}");
+        self.cache.get::<bool>(&cache_key).await.ok().flatten()
+    }
+
+    pub async fn set(&self, trace_id: &Uuid, keep: bool) {
+        let cache_key = format!("{SAMPLING_DECISION_CACHE_KEY}:{trace_id}");
+        if let Err(e) = self.cache.insert::<bool>(&cache_key, keep).await {
+            log::error!(
+                "Failed to store sampling decision. trace_id [{}]: {:?}",
+                trace_id,
+                e
+            );
+        }
+    }
+}
+
+/// Spans of the traces waiting for a tail decision. Their MQ deliveries are not acked
+/// until the decision is made.
+pub struct TraceBuffer {
+    traces: HashMap<Uuid, Vec<(PendingSpan, CHSpan)>>,
+    /// Trace ids in the order they were first buffered, with their deadlines
+    deadlines: VecDeque<(Instant, Uuid)>,
+    buffered_spans: usize,
+    max_wait: Duration,
+    max_buffered_spans: usize,
+}
+
+impl TraceBuffer {
+    pub fn new(max_wait: Duration, max_buffered_spans: usize) -> Self {
+        Self {
+            traces: HashMap::new(),
+            deadlines: VecDeque::new(),
+            buffered_spans: 0,
+            max_wait,
+            max_buffered_spans,
+        }
+    }
+
+    /// Buffering is configured with SAMPLING_TRACE_WAIT_MS (time to wait for the top
+    /// span of a trace) and SAMPLING_MAX_BUFFERED_SPANS. The wait should be shorter than
+    /// the time after which the queue redelivers unacked messages.
+    pub fn from_env() -> Self {
+        let max_wait_ms = env::var("SAMPLING_TRACE_WAIT_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SAMPLING_TRACE_WAIT_MS);
+        let max_buffered_spans = env::var("SAMPLING_MAX_BUFFERED_SPANS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_SAMPLING_MAX_BUFFERED_SPANS);
+        Self::new(Duration::from_millis(max_wait_ms), max_buffered_spans)
+    }
+
+    pub fn push(&mut self, pending: PendingSpan, ch_span: CHSpan) {
+        let trace_id = pending.span.trace_id;
+        let spans = self.traces.entry(trace_id).or_insert_with(|| {
+            self.deadlines
+                .push_back((Instant::now() + self.max_wait, trace_id));
+            Vec::new()
+        });
+        spans.push((pending, ch_span));
+        self.buffered_spans += 1;
+    }
+
+    /// Remove the buffered spans of the trace, once it is decided
+    pub fn take(&mut self, trace_id: &Uuid) -> Vec<(PendingSpan, CHSpan)> {
+        let spans = self.traces.remove(trace_id).unwrap_or_default();
+        self.buffered_spans -= spans.len();
+        spans
+    }
+
+    /// Time by which the oldest trace must be decided, which is now if too many spans
+    /// are buffered
+    pub fn deadline(&mut self) -> Option<Instant> {
+        self.skip_decided();
+        if self.buffered_spans > self.max_buffered_spans {
+            return Some(Instant::now());
+        }
+        self.deadlines.front().map(|(deadline, _)| *deadline)
+    }
+
+    /// Remove the traces that have waited too long for their top span, or the oldest
+    /// traces if too many spans are buffered. These traces are kept.
+    pub fn take_expired(&mut self) -> Vec<(Uuid, Vec<(PendingSpan, CHSpan)>)> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        while let Some((deadline, trace_id)) = self.deadlines.front().copied() {
+            if !self.traces.contains_key(&trace_id) {
+                self.deadlines.pop_front();
+                continue;
+            }
+            if deadline > now && self.buffered_spans <= self.max_buffered_spans {
+                break;
+            }
+            self.deadlines.pop_front();
+            expired.push((trace_id, self.take(&trace_id)));
+        }
+        expired
+    }
+
+    fn skip_decided(&mut self) {
+        while let Some((_, trace_id)) = self.deadlines.front() {
+            if self.traces.contains_key(trace_id) {
+                break;
+            }
+            self.deadlines.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration as ChronoDuration, Utc};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        db::trace::TraceType,
+        mq::MessageQueueAcker,
+        traces::{attributes::TraceAttributes, spans::SpanUsage},
+    };
+
+    fn span(trace_id: Uuid, parent_span_id: Option<Uuid>) -> Span {
+        Span {
+            span_id: Uuid::new_v4(),
+            trace_id,
+            parent_span_id,
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            attributes: json!({}),
+            ..Default::default()
+        }
+    }
+
+    fn ch_span(span: &Span, total_cost: f64) -> CHSpan {
+        let usage = SpanUsage {
+            input_tokens: 0,
+            output_tokens: 0,
+            total_tokens: 0,
+            cache_read_input_tokens: 0,
+            cache_creation_input_tokens: 0,
+            reasoning_tokens: 0,
+            input_cost: 0.0,
+            output_cost: 0.0,
+            total_cost,
+            cache_read_input_cost: 0.0,
+            cache_creation_input_cost: 0.0,
+            reasoning_cost: 0.0,
+            request_model: None,
+            response_model: None,
+            provider_name: None,
+        };
+        CHSpan::from_db_span(span, usage, Uuid::nil())
+    }
+
+    fn pending(span: Span) -> (PendingSpan, CHSpan) {
+        let ch_span = ch_span(&span, 0.0);
+        let pending = PendingSpan {
+            acker: MessageQueueAcker::TokioMpscAcker,
+            payload: Vec::new(),
+            project_id: Uuid::nil(),
+            trace_attributes: TraceAttributes::new(span.trace_id),
+            span,
+            events: Vec::new(),
+        };
+        (pending, ch_span)
+    }
+
+    fn event(span: &Span, name: &str) -> Event {
+        Event {
+            id: Uuid::new_v4(),
+            span_id: span.span_id,
+            project_id: Uuid::nil(),
+            created_at: Utc::now(),
+            timestamp: Utc::now(),
+            name: name.to_string(),
+            attributes: json!({}),
+        }
+    }
+
+    #[test]
+    fn test_head_keep() {
+        // The sample value of a trace id only depends on its lower 64 bits
+        let low_trace_id = Uuid::from_u128(0);
+        let high_trace_id = Uuid::from_u128(u64::MAX as u128);
+
+        let policy = SamplingPolicy::default();
+        assert!(policy.keeps_all());
+        assert!(policy.head_keep(&span(high_trace_id, None)));
+
+        let policy = SamplingPolicy {
+            rates: HashMap::from([(TraceType::DEFAULT, 0.5)]),
+            ..Default::default()
+        };
+        assert!(!policy.keeps_all());
+        assert!(policy.head_keep(&span(low_trace_id, None)));
+        assert!(!policy.head_keep(&span(high_trace_id, None)));
+
+        // All spans of a trace get the same decision
+        let parent_span_id = Some(Uuid::new_v4());
+        assert!(!policy.head_keep(&span(high_trace_id, parent_span_id)));
+
+        // Traces of the types without a rate are all kept
+        let mut evaluation_span = span(high_trace_id, None);
+        evaluation_span.attributes = json!({
+            "lmnr.association.properties.trace_type": "EVALUATION",
+        });
+        assert!(policy.head_keep(&evaluation_span));
+    }
+
+    #[test]
+    fn test_tail_decide() {
+        let trace_id = Uuid::new_v4();
+        let policy = SamplingPolicy {
+            rates: HashMap::from([(TraceType::DEFAULT, 0.0)]),
+            keep_errors: true,
+            llm_cost_threshold: Some(1.0),
+            llm_latency_threshold_ms: Some(10_000),
+            session_ids: vec!["kept-session".to_string()],
+        };
+        assert!(policy.has_tail_rules());
+
+        // Spans that match no rule wait for the top span, which decides the trace
+        let child = span(trace_id, Some(Uuid::new_v4()));
+        assert_eq!(
+            policy.tail_decide(&child, &[], &ch_span(&child, 0.0)),
+            SamplingDecision::Defer
+        );
+        let top = span(trace_id, None);
+        assert_eq!(
+            policy.tail_decide(&top, &[], &ch_span(&top, 0.0)),
+            SamplingDecision::Drop
+        );
+
+        let mut errored = span(trace_id, Some(Uuid::new_v4()));
+        errored.status = SpanStatus::ERROR;
+        assert_eq!(
+            policy.tail_decide(&errored, &[], &ch_span(&errored, 0.0)),
+            SamplingDecision::Keep
+        );
+
+        let events = [event(&child, EXCEPTION_EVENT_NAME)];
+        assert_eq!(
+            policy.tail_decide(&child, &events, &ch_span(&child, 0.0)),
+            SamplingDecision::Keep
+        );
+
+        let mut llm = span(trace_id, Some(Uuid::new_v4()));
+        llm.span_type = SpanType::LLM;
+        assert_eq!(
+            policy.tail_decide(&llm, &[], &ch_span(&llm, 0.5)),
+            SamplingDecision::Defer
+        );
+        assert_eq!(
+            policy.tail_decide(&llm, &[], &ch_span(&llm, 1.0)),
+            SamplingDecision::Keep
+        );
+        llm.end_time = llm.start_time + ChronoDuration::seconds(10);
+        assert_eq!(
+            policy.tail_decide(&llm, &[], &ch_span(&llm, 0.0)),
+            SamplingDecision::Keep
+        );
+
+        let mut session = span(trace_id, Some(Uuid::new_v4()));
+        session.attributes = json!({
+            "lmnr.association.properties.session_id": "kept-session",
+        });
+        assert_eq!(
+            policy.tail_decide(&session, &[], &ch_span(&session, 0.0)),
+            SamplingDecision::Keep
+        );
+        session.attributes = json!({
+            "lmnr.association.properties.session_id": "other-session",
+        });
+        assert_eq!(
+            policy.tail_decide(&session, &[], &ch_span(&session, 0.0)),
+            SamplingDecision::Defer
+        );
+    }
+
+    #[test]
+    fn test_trace_buffer_expiry() {
+        let mut buffer = TraceBuffer::new(Duration::from_secs(60), 10);
+        assert!(buffer.deadline().is_none());
+
+        let trace_id = Uuid::new_v4();
+        let (pending_span, ch_span) = pending(span(trace_id, Some(Uuid::new_v4())));
+        buffer.push(pending_span, ch_span);
+        assert!(buffer
+            .deadline()
+            .is_some_and(|deadline| deadline > Instant::now()));
+        assert!(buffer.take_expired().is_empty());
+
+        // Decided traces no longer expire
+        assert_eq!(buffer.take(&trace_id).len(), 1);
+        assert!(buffer.deadline().is_none());
+        assert!(buffer.take_expired().is_empty());
+
+        // Traces expire after the wait
+        let mut buffer = TraceBuffer::new(Duration::ZERO, 10);
+        let trace_id = Uuid::new_v4();
+        for _ in 0..2 {
+            let (pending_span, ch_span) = pending(span(trace_id, Some(Uuid::new_v4())));
+            buffer.push(pending_span, ch_span);
+        }
+        let expired = buffer.take_expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, trace_id);
+        assert_eq!(expired[0].1.len(), 2);
+        assert!(buffer.deadline().is_none());
+    }
+
+    #[test]
+    fn test_trace_buffer_overflow() {
+        let mut buffer = TraceBuffer::new(Duration::from_secs(60), 2);
+        let trace_ids = [Uuid::new_v4(), Uuid::new_v4()];
+        for trace_id in trace_ids {
+            for _ in 0..2 {
+                let (pending_span, ch_span) = pending(span(trace_id, Some(Uuid::new_v4())));
+                buffer.push(pending_span, ch_span);
+            }
+        }
+        assert!(buffer
+            .deadline()
+            .is_some_and(|deadline| deadline <= Instant::now()));
+
+        // The oldest traces are taken until the buffer is within its limit
+        let expired = buffer.take_expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, trace_ids[0]);
+        assert!(buffer
+            .deadline()
+            .is_some_and(|deadline| deadline > Instant::now()));
+        assert!(buffer.take_expired().is_empty());
+    }
+}
@@ -11,6 +11,14 @@ pub const GEN_AI_PROMPT_TOKENS: &str = "gen_ai.usage.prompt_tokens";
 pub const GEN_AI_COMPLETION_TOKENS: &str = "gen_ai.usage.completion_tokens";
 
 pub const GEN_AI_TOTAL_TOKENS: &str = "llm.usage.total_tokens";
+// Cached input and reasoning tokens are included in the input and output tokens
+pub const GEN_AI_CACHE_READ_INPUT_TOKENS: &str = "gen_ai.usage.cache_read_input_tokens";
+pub const GEN_AI_CACHE_CREATION_INPUT_TOKENS: &str = "gen_ai.usage.cache_creation_input_tokens";
+pub const GEN_AI_REASONING_TOKENS: &str = "gen_ai.usage.reasoning_tokens";
+// OpenAI usage details, as recorded by some instrumentations
+pub const GEN_AI_PROMPT_CACHED_TOKENS: &str = "gen_ai.usage.prompt_tokens_details.cached_tokens";
+pub const GEN_AI_COMPLETION_REASONING_TOKENS: &str =
+    "gen_ai.usage.completion_tokens_details.reasoning_tokens";
 pub const GEN_AI_REQUEST_MODEL: &str = "gen_ai.request.model";
 pub const GEN_AI_RESPONSE_MODEL: &str = "gen_ai.response.model";
 // pub const GEN_AI_REQUEST_IS_STREAM: &str = "gen_ai.request.is_stream";
@@ -23,6 +31,9 @@ pub const GEN_AI_TOTAL_COST: &str = "gen_ai.usage.cost";
 // These are in neither standard.
 pub const GEN_AI_INPUT_COST: &str = "gen_ai.usage.input_cost";
 pub const GEN_AI_OUTPUT_COST: &str = "gen_ai.usage.output_cost";
+pub const GEN_AI_CACHE_READ_INPUT_COST: &str = "gen_ai.usage.cache_read_input_cost";
+pub const GEN_AI_CACHE_CREATION_INPUT_COST: &str = "gen_ai.usage.cache_creation_input_cost";
+pub const GEN_AI_REASONING_COST: &str = "gen_ai.usage.reasoning_cost";
 
 // Custom lmnr attributes
 pub const ASSOCIATION_PROPERTIES_PREFIX: &str = "lmnr.association.properties";
@@ -30,3 +41,17 @@ pub const SPAN_TYPE: &str = "lmnr.span.type";
 pub const SPAN_PATH: &str = "lmnr.span.path";
 pub const SPAN_IDS_PATH: &str = "lmnr.span.ids_path";
 pub const LLM_NODE_RENDERED_PROMPT: &str = "lmnr.span.prompt";
+/// Number of the attempt of a pipeline node that was retried, starting from 1
+pub const NODE_ATTEMPT: &str = "lmnr.span.attempt";
+/// Number of the iteration of a pipeline loop node, starting from 1
+pub const LOOP_ITERATION: &str = "lmnr.span.loop_iteration";
+/// Ids of the project redaction rules that modified the span
+pub const REDACTION_RULES: &str = "lmnr.redaction.rules";
+
+// OpenTelemetry resource and instrumentation scope, recorded on every span.
+// Resource attributes are prefixed, so that they do not collide with span attributes.
+// https://opentelemetry.io/docs/specs/otel/common/mapping-to-non-otlp/#instrumentationscope
+pub const RESOURCE_ATTRIBUTES_PREFIX: &str = "resource";
+pub const SERVICE_NAME: &str = "resource.service.name";
+pub const OTEL_SCOPE_NAME: &str = "otel.scope.name";
+pub const OTEL_SCOPE_VERSION: &str = "otel.scope.version";
@@ -2,32 +2,44 @@ use std::{collections::HashMap, env, sync::Arc};
 
 use anyhow::Result;
 use chrono::{TimeZone, Utc};
-use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{
     db::{
-        spans::{Span, SpanType},
+        spans::{Span, SpanStatus, SpanType},
         trace::{CurrentTraceAndSpan, TraceType},
         utils::{convert_any_value_to_json_value, span_id_to_uuid},
     },
     language_model::{
-        ChatMessage, ChatMessageContent, ChatMessageContentPart,
+        ChatMessage, ChatMessageContent, ChatMessageContentPart, ChatMessageToolCall,
         InstrumentationChatMessageContentPart,
     },
-    opentelemetry::opentelemetry_proto_trace_v1::Span as OtelSpan,
+    opentelemetry::{
+        opentelemetry_proto_common_v1::InstrumentationScope,
+        opentelemetry_proto_resource_v1::Resource,
+        opentelemetry_proto_trace_v1::{
+            status::StatusCode, Span as OtelSpan, Status as OtelStatus,
+        },
+    },
     pipeline::{nodes::Message, trace::MetaLog},
     storage::{Storage, StorageTrait},
 };
 
 use super::{
+    instrumentation::{self, InstrumentedSpan},
     span_attributes::{
-        ASSOCIATION_PROPERTIES_PREFIX, GEN_AI_COMPLETION_TOKENS, GEN_AI_INPUT_COST,
-        GEN_AI_INPUT_TOKENS, GEN_AI_OUTPUT_COST, GEN_AI_OUTPUT_TOKENS, GEN_AI_PROMPT_TOKENS,
-        GEN_AI_REQUEST_MODEL, GEN_AI_RESPONSE_MODEL, GEN_AI_SYSTEM, GEN_AI_TOTAL_COST,
-        GEN_AI_TOTAL_TOKENS, LLM_NODE_RENDERED_PROMPT, SPAN_IDS_PATH, SPAN_PATH, SPAN_TYPE,
+        ASSOCIATION_PROPERTIES_PREFIX, GEN_AI_CACHE_CREATION_INPUT_COST,
+        GEN_AI_CACHE_CREATION_INPUT_TOKENS, GEN_AI_CACHE_READ_INPUT_COST,
+        GEN_AI_CACHE_READ_INPUT_TOKENS, GEN_AI_COMPLETION_REASONING_TOKENS,
+        GEN_AI_COMPLETION_TOKENS, GEN_AI_INPUT_COST, GEN_AI_INPUT_TOKENS, GEN_AI_OUTPUT_COST,
+        GEN_AI_OUTPUT_TOKENS, GEN_AI_PROMPT_CACHED_TOKENS, GEN_AI_PROMPT_TOKENS,
+        GEN_AI_REASONING_COST, GEN_AI_REASONING_TOKENS, GEN_AI_REQUEST_MODEL,
+        GEN_AI_RESPONSE_MODEL, GEN_AI_SYSTEM, GEN_AI_TOTAL_COST, GEN_AI_TOTAL_TOKENS,
+        LLM_NODE_RENDERED_PROMPT, LOOP_ITERATION, NODE_ATTEMPT, OTEL_SCOPE_NAME,
+        OTEL_SCOPE_VERSION, RESOURCE_ATTRIBUTES_PREFIX, SERVICE_NAME, SPAN_IDS_PATH, SPAN_PATH,
+        SPAN_TYPE,
     },
     utils::{json_value_to_string, skip_span_name},
 };
@@ -47,6 +59,8 @@ const HAS_BROWSER_SESSION_ATTRIBUTE_NAME: &str = "lmnr.internal.has_browser_sess
 // We use 7/2 as an estimate of the number of characters per token.
 // And 128K is a common input size for LLM calls.
 const DEFAULT_PAYLOAD_SIZE_THRESHOLD: usize = (7 / 2) * 128_000; // approx 448KB
+/// Maximum number of characters of the attribute values that are stored for querying
+const MAX_QUERYABLE_ATTRIBUTE_LENGTH: usize = 1024;
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -118,6 +132,29 @@ impl SpanAttributes {
         }
     }
 
+    pub fn cache_read_input_tokens(&self) -> i64 {
+        self.token_count(&[GEN_AI_CACHE_READ_INPUT_TOKENS, GEN_AI_PROMPT_CACHED_TOKENS])
+    }
+
+    pub fn cache_creation_input_tokens(&self) -> i64 {
+        self.token_count(&[GEN_AI_CACHE_CREATION_INPUT_TOKENS])
+    }
+
+    pub fn reasoning_tokens(&self) -> i64 {
+        self.token_count(&[GEN_AI_REASONING_TOKENS, GEN_AI_COMPLETION_REASONING_TOKENS])
+    }
+
+    /// First of the attributes that is set, as instrumentations record the same count
+    /// under different names
+    fn token_count(&self, keys: &[&str]) -> i64 {
+        keys.iter()
+            .find_map(|key| match self.attributes.get(*key) {
+                Some(Value::Number(n)) => n.as_i64(),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
     pub fn request_model(&self) -> Option<String> {
         match self.attributes.get(GEN_AI_REQUEST_MODEL) {
             Some(Value::String(s)) => Some(s.clone()),
@@ -173,6 +210,67 @@ impl SpanAttributes {
         }
     }
 
+    pub fn service_name(&self) -> Option<String> {
+        match self.attributes.get(SERVICE_NAME) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// Attributes of the OpenTelemetry resource, without the prefix
+    pub fn resource_attributes(&self) -> HashMap<String, String> {
+        let prefix = format!("{RESOURCE_ATTRIBUTES_PREFIX}.");
+        self.attributes
+            .iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix(&prefix)
+                    .map(|key| (key.to_string(), json_value_to_string(value)))
+            })
+            .collect()
+    }
+
+    /// Attributes to filter and group metrics by, with string values. Association
+    /// properties are without their prefix, e.g. `metadata.customer_id`, and the metadata
+    /// of the AI SDK is merged into `metadata`. Resource attributes, paths and attributes
+    /// with object values are left out.
+    pub fn queryable_attributes(&self) -> HashMap<String, String> {
+        let association_prefix = format!("{ASSOCIATION_PROPERTIES_PREFIX}.");
+        let resource_prefix = format!("{RESOURCE_ATTRIBUTES_PREFIX}.");
+        let mut attributes = self
+            .attributes
+            .iter()
+            .filter(|(key, value)| {
+                !key.starts_with(&resource_prefix)
+                    && key.as_str() != SPAN_PATH
+                    && key.as_str() != SPAN_IDS_PATH
+                    && !value.is_object()
+                    && !value.is_null()
+            })
+            .map(|(key, value)| {
+                let key = key.strip_prefix(&association_prefix).unwrap_or(key);
+                (key.to_string(), queryable_value(value))
+            })
+            .collect::<HashMap<_, _>>();
+        for (key, value) in self.metadata().unwrap_or_default() {
+            attributes.insert(format!("metadata.{key}"), truncate_value(value));
+        }
+        attributes
+    }
+
+    pub fn scope_name(&self) -> Option<String> {
+        match self.attributes.get(OTEL_SCOPE_NAME) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn scope_version(&self) -> Option<String> {
+        match self.attributes.get(OTEL_SCOPE_VERSION) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
     pub fn path(&self) -> Option<Vec<String>> {
         let raw_path = self.raw_path();
         raw_path.map(|path| {
@@ -236,6 +334,38 @@ impl SpanAttributes {
         self.attributes
             .insert(GEN_AI_OUTPUT_COST.to_string(), json!(usage.output_cost));
 
+        // The breakdown is only recorded for the calls that used the prompt cache or reasoning
+        if usage.cache_read_input_tokens > 0 {
+            self.attributes.insert(
+                GEN_AI_CACHE_READ_INPUT_TOKENS.to_string(),
+                json!(usage.cache_read_input_tokens),
+            );
+            self.attributes.insert(
+                GEN_AI_CACHE_READ_INPUT_COST.to_string(),
+                json!(usage.cache_read_input_cost),
+            );
+        }
+        if usage.cache_creation_input_tokens > 0 {
+            self.attributes.insert(
+                GEN_AI_CACHE_CREATION_INPUT_TOKENS.to_string(),
+                json!(usage.cache_creation_input_tokens),
+            );
+            self.attributes.insert(
+                GEN_AI_CACHE_CREATION_INPUT_COST.to_string(),
+                json!(usage.cache_creation_input_cost),
+            );
+        }
+        if usage.reasoning_tokens > 0 {
+            self.attributes.insert(
+                GEN_AI_REASONING_TOKENS.to_string(),
+                json!(usage.reasoning_tokens),
+            );
+            self.attributes.insert(
+                GEN_AI_REASONING_COST.to_string(),
+                json!(usage.reasoning_cost),
+            );
+        }
+
         if let Some(request_model) = &usage.request_model {
             self.attributes
                 .insert(GEN_AI_REQUEST_MODEL.to_string(), json!(request_model));
@@ -365,6 +495,17 @@ impl Span {
         self.attributes = serde_json::to_value(&attributes.attributes).unwrap();
     }
 
+    /// Add attributes that the span does not set itself, e.g. the resource attributes
+    pub fn add_default_attributes(&mut self, attributes: &serde_json::Map<String, Value>) {
+        if let Value::Object(span_attributes) = &mut self.attributes {
+            for (key, value) in attributes {
+                span_attributes
+                    .entry(key.clone())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+    }
+
     pub fn should_save(&self) -> bool {
         self.get_attributes().tracing_level() != Some(TracingLevel::Off)
             && !skip_span_name(&self.name)
@@ -374,7 +515,7 @@ impl Span {
     ///
     /// This is called on the producer side of the MQ, i.e. at the OTel ingester
     /// side, so it must be lightweight.
-    pub fn from_otel_span(otel_span: OtelSpan) -> Self {
+    pub fn from_otel_span(otel_span: OtelSpan, scope: Option<&InstrumentationScope>) -> Self {
         let trace_id = Uuid::from_slice(&otel_span.trace_id).unwrap();
 
         let span_id = span_id_to_uuid(&otel_span.span_id);
@@ -413,98 +554,19 @@ impl Span {
             end_time: Utc.timestamp_nanos(otel_span.end_time_unix_nano as i64),
             ..Default::default()
         };
+        (span.status, span.status_message) = span_status_from_otel(otel_span.status);
 
         span.span_type = span.get_attributes().span_type();
 
-        // to handle Traceloop's prompt/completion messages
-        if span.span_type == SpanType::LLM {
-            if attributes.get("gen_ai.prompt.0.content").is_some() {
-                let input_messages =
-                    input_chat_messages_from_prompt_content(&attributes, "gen_ai.prompt");
-
-                span.input = Some(json!(input_messages));
-                span.output = output_from_completion_content(
-                    &attributes,
-                    "gen_ai.completion",
-                    "tool_calls",
-                    true,
-                );
-            } else if attributes.get("ai.prompt.messages").is_some() {
-                // handling the Vercel's AI SDK auto-instrumentation
-                if let Ok(input_messages) = serde_json::from_str::<Vec<ChatMessage>>(
-                    attributes
-                        .get("ai.prompt.messages")
-                        .unwrap()
-                        .as_str()
-                        .unwrap(),
-                ) {
-                    span.input = Some(json!(input_messages));
-                }
-
-                if let Some(serde_json::Value::String(s)) = attributes.get("ai.response.text") {
-                    span.output = Some(serde_json::Value::String(s.clone()));
-                } else if let Some(serde_json::Value::String(s)) =
-                    attributes.get("ai.response.object")
-                {
-                    span.output = Some(
-                        serde_json::from_str::<Value>(s)
-                            .unwrap_or(serde_json::Value::String(s.clone())),
-                    );
-                }
-            } else if attributes
-                .get("SpanAttributes.LLM_PROMPTS.0.content")
-                .is_some()
-            {
-                // handling the LiteLLM auto-instrumentation
-                let input_messages = input_chat_messages_from_prompt_content(
-                    &attributes,
-                    "SpanAttributes.LLM_PROMPTS",
-                );
-                span.input = Some(json!(input_messages));
-
-                span.output = output_from_completion_content(
-                    &attributes,
-                    "SpanAttributes.LLM_COMPLETIONS",
-                    "function_call",
-                    false,
-                );
-            }
-        }
-
-        // Vercel AI SDK wraps "raw" LLM spans in an additional `ai.generateText` span.
-        // Which is not really an LLM span, but it has the prompt in its attributes.
-        // Set the input to the prompt and the output to the response.
-        if let Some(serde_json::Value::String(s)) = attributes.get("ai.prompt") {
-            span.input = Some(
-                serde_json::from_str::<Value>(s).unwrap_or(serde_json::Value::String(s.clone())),
-            );
-        }
-        if let Some(serde_json::Value::String(s)) = attributes.get("ai.response.text") {
-            span.output = Some(
-                serde_json::from_str::<Value>(s).unwrap_or(serde_json::Value::String(s.clone())),
-            );
-        } else if let Some(serde_json::Value::String(s)) = attributes.get("ai.response.object") {
-            span.output = Some(
-                serde_json::from_str::<Value>(s).unwrap_or(serde_json::Value::String(s.clone())),
-            );
-        }
-
-        // Traceloop hard-codes these attributes to LangChain auto-instrumented spans.
-        // Take their values if input/output are not already set.
-        if let Some(input) = attributes.get("traceloop.entity.input") {
-            if span.input.is_none() {
-                span.input = Some(input.clone());
-            }
-        }
-        if let Some(output) = attributes.get("traceloop.entity.output") {
-            if span.output.is_none() {
-                span.output = Some(output.clone());
-            }
-        }
+        let instrumented_span = InstrumentedSpan {
+            scope_name: scope.map(|scope| scope.name.as_str()),
+            attributes: &attributes,
+            events: &otel_span.events,
+        };
+        instrumentation::apply_adapters(&mut span, &instrumented_span);
 
         // If an LLM span is sent manually, we prefer `lmnr.span.input` and `lmnr.span.output`
-        // attributes over gen_ai/vercel/LiteLLM attributes.
-        // Therefore this block is outside and after the LLM span type check.
+        // attributes over the ones parsed by the instrumentation adapters.
         if let Some(serde_json::Value::String(s)) = attributes.get(INPUT_ATTRIBUTE_NAME) {
             let input =
                 serde_json::from_str::<Value>(s).unwrap_or(serde_json::Value::String(s.clone()));
@@ -520,10 +582,18 @@ impl Span {
             }
         }
         if let Some(serde_json::Value::String(s)) = attributes.get(OUTPUT_ATTRIBUTE_NAME) {
-            // TODO: try parse output as ChatMessage with tool calls
-            span.output = Some(
-                serde_json::from_str::<Value>(s).unwrap_or(serde_json::Value::String(s.clone())),
-            );
+            let output =
+                serde_json::from_str::<Value>(s).unwrap_or(serde_json::Value::String(s.clone()));
+            // Assistant messages with tool calls, e.g. in the OpenAI format, are rendered
+            // as text and tool call blocks, like the outputs parsed by the adapters
+            span.output = match serde_json::from_value::<ChatMessage>(output.clone()) {
+                Ok(message)
+                    if span.span_type == SpanType::LLM && !message.tool_calls.is_empty() =>
+                {
+                    instrumentation::chat_message_output(&message)
+                }
+                _ => Some(output),
+            };
         }
 
         // Spans with this attribute are wrapped in a NonRecordingSpan that, and we only
@@ -597,6 +667,8 @@ impl Span {
             labels: None,
             input_url: None,
             output_url: None,
+            status: SpanStatus::UNSET,
+            status_message: None,
         }
     }
 
@@ -604,16 +676,120 @@ impl Span {
     ///
     /// At this point, the whole pipeline run acts as a parent span.
     /// So trace id, parent span id, and parent span path are all not None.
+    ///
+    /// Failed attempts of the nodes that were retried or fell back are separate spans.
+    /// Loop nodes are spans with their iterations as children, and the iterations have the
+    /// spans of their body's nodes as children.
     pub fn from_messages(
         messages: &HashMap<Uuid, Message>,
         trace_id: Uuid,
         parent_span_id: Uuid,
         parent_span_path: Vec<String>,
     ) -> Vec<Self> {
+        let attempt_spans = messages
+            .values()
+            .flat_map(|message| {
+                let Some(MetaLog::Retry(retry_meta)) = &message.meta_log else {
+                    return vec![];
+                };
+                let mut span_path = parent_span_path.clone();
+                span_path.push(message.node_name.clone());
+                let input_values = message_input_values(message, messages);
+
+                retry_meta
+                    .attempts
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, attempt)| {
+                        let error = attempt.error.as_ref()?;
+                        let mut attributes = HashMap::new();
+                        attributes.insert(SPAN_PATH.to_string(), json!(span_path));
+                        attributes.insert(NODE_ATTEMPT.to_string(), json!(index + 1));
+                        Some(Span {
+                            span_id: Uuid::new_v4(),
+                            start_time: attempt.start_time,
+                            end_time: attempt.end_time,
+                            trace_id,
+                            parent_span_id: Some(parent_span_id),
+                            name: message.node_name.clone(),
+                            attributes: json!(attributes),
+                            input: Some(serde_json::to_value(&input_values).unwrap()),
+                            output: None,
+                            span_type: SpanType::DEFAULT,
+                            events: None,
+                            labels: None,
+                            input_url: None,
+                            output_url: None,
+                            status: SpanStatus::ERROR,
+                            status_message: Some(error.clone()),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let loop_spans = messages
+            .iter()
+            .flat_map(|(msg_id, message)| {
+                let Some(MetaLog::Loop(loop_meta)) =
+                    message.meta_log.as_ref().and_then(|m| m.node_meta_log())
+                else {
+                    return vec![];
+                };
+                let mut span_path = parent_span_path.clone();
+                span_path.push(message.node_name.clone());
+
+                loop_meta
+                    .iterations
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, iteration)| {
+                        let iteration_span_id = Uuid::new_v4();
+                        let input_values = iteration
+                            .messages
+                            .values()
+                            .filter(|message| message.node_type == "Input")
+                            .map(|message| (message.node_name.clone(), message.value.clone()))
+                            .collect::<HashMap<_, _>>();
+                        let mut attributes = HashMap::new();
+                        attributes.insert(SPAN_PATH.to_string(), json!(span_path));
+                        attributes.insert(LOOP_ITERATION.to_string(), json!(index + 1));
+                        let iteration_span = Span {
+                            span_id: iteration_span_id,
+                            start_time: iteration.start_time,
+                            end_time: iteration.end_time,
+                            trace_id,
+                            parent_span_id: Some(*msg_id),
+                            name: message.node_name.clone(),
+                            attributes: json!(attributes),
+                            input: serde_json::to_value(input_values).ok(),
+                            output: Some(iteration.output.clone().into()),
+                            span_type: SpanType::DEFAULT,
+                            events: None,
+                            labels: None,
+                            input_url: None,
+                            output_url: None,
+                            status: SpanStatus::UNSET,
+                            status_message: None,
+                        };
+
+                        let mut spans = Self::from_messages(
+                            &iteration.messages,
+                            trace_id,
+                            iteration_span_id,
+                            span_path.clone(),
+                        );
+                        spans.push(iteration_span);
+                        spans
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
         messages
             .iter()
             .filter_map(|(msg_id, message)| {
-                if !["LLM", "SemanticSearch"].contains(&message.node_type.as_str()) {
+                if !["LLM", "SemanticSearch", "Loop"].contains(&message.node_type.as_str()) {
                     return None;
                 }
 
@@ -630,17 +806,7 @@ impl Span {
                     path
                 };
 
-                let input_values = message
-                    .input_message_ids
-                    .iter()
-                    .map(|input_id| {
-                        let input_message = messages.get(input_id).unwrap();
-                        (
-                            input_message.node_name.clone(),
-                            input_message.value.clone().into(),
-                        )
-                    })
-                    .collect::<HashMap<String, Value>>();
+                let input_values = message_input_values(message, messages);
                 let span = Span {
                     span_id: *msg_id,
                     start_time: message.start_time,
@@ -659,9 +825,13 @@ impl Span {
                     labels: None,
                     input_url: None,
                     output_url: None,
+                    status: SpanStatus::UNSET,
+                    status_message: None,
                 };
                 Some(span)
             })
+            .chain(attempt_spans)
+            .chain(loop_spans)
             .collect()
     }
 
@@ -715,10 +885,28 @@ impl Span {
     }
 }
 
+fn message_input_values(
+    message: &Message,
+    messages: &HashMap<Uuid, Message>,
+) -> HashMap<String, Value> {
+    message
+        .input_message_ids
+        .iter()
+        .filter_map(|input_id| {
+            // input messages can be missing from the checkpoints of resumed runs
+            let input_message = messages.get(input_id)?;
+            Some((
+                input_message.node_name.clone(),
+                input_message.value.clone().into(),
+            ))
+        })
+        .collect()
+}
+
 fn span_attributes_from_meta_log(meta_log: Option<MetaLog>, span_path: Vec<String>) -> Value {
     let mut attributes = HashMap::new();
 
-    if let Some(MetaLog::LLM(llm_log)) = meta_log {
+    if let Some(MetaLog::LLM(llm_log)) = meta_log.as_ref().and_then(|m| m.node_meta_log()) {
         attributes.insert(
             GEN_AI_INPUT_TOKENS.to_string(),
             json!(llm_log.input_token_count),
@@ -744,113 +932,89 @@ fn span_attributes_from_meta_log(meta_log: Option<MetaLog>, span_path: Vec<Strin
     serde_json::to_value(attributes).unwrap()
 }
 
-fn should_keep_attribute(attribute: &str) -> bool {
-    // do not duplicate function input/output as they are stored in DEFAULT span's input/output
-    if attribute == INPUT_ATTRIBUTE_NAME || attribute == OUTPUT_ATTRIBUTE_NAME {
-        return false;
-    }
-    // remove traceloop.entity.input/output as we parse them to span's input/output
-    // These are hard-coded by opentelemetry-instrumentation-langchain for some of
-    // the deeply nested spans
-    if attribute == "traceloop.entity.input" || attribute == "traceloop.entity.output" {
-        return false;
+/// Attributes of the OpenTelemetry resource and instrumentation scope,
+/// to be added to every span exported with them
+pub fn otel_resource_and_scope_attributes(
+    resource: Option<&Resource>,
+    scope: Option<&InstrumentationScope>,
+) -> serde_json::Map<String, Value> {
+    let mut attributes = serde_json::Map::new();
+    if let Some(resource) = resource {
+        for kv in resource.attributes.iter() {
+            attributes.insert(
+                format!("{RESOURCE_ATTRIBUTES_PREFIX}.{}", kv.key),
+                convert_any_value_to_json_value(kv.value.clone()),
+            );
+        }
     }
-
-    if attribute == "traceloop.entity.path" {
-        return false;
+    if let Some(scope) = scope {
+        if !scope.name.is_empty() {
+            attributes.insert(OTEL_SCOPE_NAME.to_string(), json!(scope.name));
+        }
+        if !scope.version.is_empty() {
+            attributes.insert(OTEL_SCOPE_VERSION.to_string(), json!(scope.version));
+        }
     }
+    attributes
+}
 
-    if attribute == OVERRIDE_PARENT_SPAN_ATTRIBUTE_NAME {
-        return false;
-    }
+fn span_status_from_otel(status: Option<OtelStatus>) -> (SpanStatus, Option<String>) {
+    let Some(status) = status else {
+        return (SpanStatus::UNSET, None);
+    };
+    let code = match StatusCode::try_from(status.code) {
+        Ok(StatusCode::Ok) => SpanStatus::OK,
+        Ok(StatusCode::Error) => SpanStatus::ERROR,
+        _ => SpanStatus::UNSET,
+    };
+    let message = Some(status.message).filter(|message| !message.is_empty());
+    (code, message)
+}
 
-    // OpenLLMetry
-    // remove gen_ai.prompt/completion attributes as they are stored in LLM span's input/output
-    let pattern = Regex::new(r"gen_ai\.(prompt|completion)\.\d+\.(content|role)").unwrap();
-    if pattern.is_match(attribute) {
+fn should_keep_attribute(attribute: &str) -> bool {
+    // do not duplicate function input/output as they are stored in DEFAULT span's input/output
+    if attribute == INPUT_ATTRIBUTE_NAME || attribute == OUTPUT_ATTRIBUTE_NAME {
         return false;
     }
+    // the attributes that are parsed to the span's input/output are removed by the
+    // instrumentation adapters that parse them
+    attribute != OVERRIDE_PARENT_SPAN_ATTRIBUTE_NAME
+}
 
-    // LiteLLM
-    // remove SpanAttributes.LLM_PROMPTS/COMPLETIONS attributes as they are stored in LLM span's input/output
-    let pattern =
-        Regex::new(r"SpanAttributes\.LLM_(PROMPTS|COMPLETIONS)\.\d+\.(content|role)").unwrap();
-    if pattern.is_match(attribute) {
-        return false;
-    }
+fn queryable_value(value: &Value) -> String {
+    truncate_value(json_value_to_string(value))
+}
 
-    // AI SDK
-    // remove ai.prompt.messages as it is stored in LLM span's input
-    if attribute == "ai.prompt.messages" {
-        return false;
+/// Long values, e.g. prompts recorded as attributes, are not useful to group by
+fn truncate_value(value: String) -> String {
+    if value.chars().count() > MAX_QUERYABLE_ATTRIBUTE_LENGTH {
+        value.chars().take(MAX_QUERYABLE_ATTRIBUTE_LENGTH).collect()
+    } else {
+        value
     }
-
-    true
 }
 
 pub struct SpanUsage {
     pub input_tokens: i64,
     pub output_tokens: i64,
     pub total_tokens: i64,
+    /// Included in the input tokens
+    pub cache_read_input_tokens: i64,
+    /// Included in the input tokens
+    pub cache_creation_input_tokens: i64,
+    /// Included in the output tokens
+    pub reasoning_tokens: i64,
     pub input_cost: f64,
     pub output_cost: f64,
     pub total_cost: f64,
+    pub cache_read_input_cost: f64,
+    pub cache_creation_input_cost: f64,
+    pub reasoning_cost: f64,
     pub request_model: Option<String>,
     pub response_model: Option<String>,
     pub provider_name: Option<String>,
 }
 
-fn input_chat_messages_from_prompt_content(
-    attributes: &serde_json::Map<String, serde_json::Value>,
-    prefix: &str,
-) -> Vec<ChatMessage> {
-    let mut input_messages: Vec<ChatMessage> = vec![];
-
-    let mut i = 0;
-    while attributes
-        .get(format!("{prefix}.{i}.content").as_str())
-        .is_some()
-    {
-        // TODO: handle case where content is not a string, e.g. LangChain tool messages
-        let content = if let Some(serde_json::Value::String(s)) =
-            attributes.get(format!("{prefix}.{i}.content").as_str())
-        {
-            s.clone()
-        } else {
-            "".to_string()
-        };
-
-        let role = if let Some(serde_json::Value::String(s)) =
-            attributes.get(format!("{prefix}.{i}.role").as_str())
-        {
-            s.clone()
-        } else {
-            "user".to_string()
-        };
-
-        input_messages.push(ChatMessage {
-            role,
-            content: match serde_json::from_str::<Vec<InstrumentationChatMessageContentPart>>(
-                &content,
-            ) {
-                Ok(otel_parts) => {
-                    let mut parts = Vec::new();
-                    for part in otel_parts {
-                        parts.push(ChatMessageContentPart::from_instrumentation_content_part(
-                            part,
-                        ));
-                    }
-                    ChatMessageContent::ContentPartList(parts)
-                }
-                Err(_) => ChatMessageContent::Text(content.clone()),
-            },
-        });
-        i += 1;
-    }
-
-    input_messages
-}
-
 fn input_chat_messages_from_json(input: &serde_json::Value) -> Result<Vec<ChatMessage>> {
     if let Some(messages) = input.as_array() {
         messages
@@ -859,8 +1023,23 @@ fn input_chat_messages_from_json(input: &serde_json::Value) -> Result<Vec<ChatMe
                 let Some(role) = message.get("role").and_then(|v| v.as_str()) else {
                     return Err(anyhow::anyhow!("Can't find role in message"));
                 };
-                let Some(otel_content) = message.get("content") else {
-                    return Err(anyhow::anyhow!("Can't find content in message"));
+                // Messages in the OpenAI format, with tool calls nested in `function`, are
+                // also accepted
+                let tool_calls = match message.get("tool_calls") {
+                    Some(tool_calls) => {
+                        serde_json::from_value::<Vec<ChatMessageToolCall>>(tool_calls.clone())?
+                    }
+                    None => Vec::new(),
+                };
+                let tool_call_id = message
+                    .get("tool_call_id")
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+                let otel_content = match message.get("content") {
+                    Some(content) => content,
+                    // Assistant messages that only call tools have no content
+                    None if !tool_calls.is_empty() => &serde_json::Value::Null,
+                    None => return Err(anyhow::anyhow!("Can't find content in message")),
                 };
                 let content = match serde_json::from_value::<
                     Vec<InstrumentationChatMessageContentPart>,
@@ -875,11 +1054,14 @@ fn input_chat_messages_from_json(input: &serde_json::Value) -> Result<Vec<ChatMe
                         }
                         ChatMessageContent::ContentPartList(parts)
                     }
+                    Err(_) if otel_content.is_null() => ChatMessageContent::Text(String::new()),
                     Err(_) => ChatMessageContent::Text(json_value_to_string(otel_content)),
                 };
                 Ok(ChatMessage {
                     role: role.to_string(),
                     content,
+                    tool_calls,
+                    tool_call_id,
                 })
             })
             .collect()
@@ -887,122 +1069,3 @@ fn input_chat_messages_from_json(input: &serde_json::Value) -> Result<Vec<ChatMe
         Err(anyhow::anyhow!("Input is not a list"))
     }
 }
-
-#[derive(Serialize)]
-struct ToolCall {
-    name: String,
-    id: Option<String>,
-    arguments: Option<serde_json::Value>,
-    #[serde(rename = "type")]
-    content_block_type: String,
-}
-
-#[derive(Serialize)]
-struct TextBlock {
-    content: String,
-    #[serde(rename = "type")]
-    content_block_type: String,
-}
-
-fn tool_call_attribute(
-    prefix: &str,
-    tool_call_attribute_name: &str,
-    use_index_in_tools: bool,
-    index: usize,
-    attribute: &str,
-) -> String {
-    if use_index_in_tools {
-        format!("{prefix}.0.{tool_call_attribute_name}.{index}.{attribute}")
-    } else {
-        format!("{prefix}.0.{tool_call_attribute_name}.{attribute}")
-    }
-}
-
-fn output_from_completion_content(
-    attributes: &serde_json::Map<String, serde_json::Value>,
-    prefix: &str,
-    tool_call_attribute_name: &str,
-    use_index_in_tools: bool,
-) -> Option<serde_json::Value> {
-    let text_msg = attributes.get(format!("{prefix}.0.content").as_str());
-
-    let mut tool_calls = Vec::new();
-    let mut i = 0;
-
-    while let Some(serde_json::Value::String(tool_call_name)) = attributes.get(
-        tool_call_attribute(
-            prefix,
-            tool_call_attribute_name,
-            use_index_in_tools,
-            i,
-            "name",
-        )
-        .as_str(),
-    ) {
-        let tool_call_id = attributes
-            .get(
-                tool_call_attribute(
-                    prefix,
-                    tool_call_attribute_name,
-                    use_index_in_tools,
-                    i,
-                    "id",
-                )
-                .as_str(),
-            )
-            .and_then(|id| id.as_str())
-            .map(String::from);
-        let tool_call_arguments_raw = attributes.get(
-            tool_call_attribute(
-                prefix,
-                tool_call_attribute_name,
-                use_index_in_tools,
-                i,
-                "arguments",
-            )
-            .as_str(),
-        );
-        let tool_call_arguments = match tool_call_arguments_raw {
-            Some(serde_json::Value::String(s)) => {
-                let parsed = serde_json::from_str::<HashMap<String, Value>>(s);
-                if let Ok(parsed) = parsed {
-                    serde_json::to_value(parsed).ok()
-                } else {
-                    Some(serde_json::Value::String(s.clone()))
-                }
-            }
-            _ => tool_call_arguments_raw.cloned(),
-        };
-        let tool_call = ToolCall {
-            name: tool_call_name.clone(),
-            id: tool_call_id,
-            arguments: tool_call_arguments,
-            content_block_type: "tool_call".to_string(),
-        };
-        tool_calls.push(serde_json::to_value(tool_call).unwrap());
-        i += 1;
-        if !use_index_in_tools {
-            break;
-        }
-    }
-
-    if tool_calls.is_empty() {
-        if let Some(Value::String(s)) = text_msg {
-            Some(serde_json::Value::String(s.clone()))
-        } else {
-            None
-        }
-    } else {
-        let mut out_vec = if let Some(Value::String(s)) = text_msg {
-            let text_block = TextBlock {
-                content: s.clone(),
-                content_block_type: "text".to_string(),
-            };
-            vec![serde_json::to_value(text_block).unwrap()]
-        } else {
-            vec![]
-        };
-        out_vec.extend(tool_calls);
-        Some(Value::Array(out_vec))
-    }
-}
@@ -1,19 +1,24 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use backoff::ExponentialBackoffBuilder;
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde_json::Value;
 use uuid::Uuid;
 
 use crate::{
     cache::Cache,
+    ch::labels::CHLabel,
     db::{
         self,
         labels::LabelSource,
         spans::{Span, SpanType},
         trace, DB,
     },
-    language_model::costs::estimate_cost_by_provider_name,
+    language_model::costs::{estimate_cost_by_provider_name, CostEntry, TokenUsage},
 };
 
 use super::{
@@ -33,19 +38,25 @@ pub fn json_value_to_string(v: &Value) -> String {
     }
 }
 
-/// Calculate usage for both default and LLM spans
+/// Calculate usage for both default and LLM spans.
+///
+/// The cost is calculated at the price of the project that was valid when the span started.
 pub async fn get_llm_usage_for_span(
     // mut because input and output tokens are updated to new convention
     attributes: &mut SpanAttributes,
     db: Arc<DB>,
     cache: Arc<Cache>,
+    project_id: &Uuid,
+    start_time: &DateTime<Utc>,
 ) -> SpanUsage {
     let input_tokens = attributes.input_tokens();
     let output_tokens = attributes.completion_tokens();
     let total_tokens = input_tokens + output_tokens;
+    let cache_read_input_tokens = attributes.cache_read_input_tokens();
+    let cache_creation_input_tokens = attributes.cache_creation_input_tokens();
+    let reasoning_tokens = attributes.reasoning_tokens();
 
-    let mut input_cost: f64 = 0.0;
-    let mut output_cost: f64 = 0.0;
+    let mut cost = CostEntry::default();
     let mut total_cost: f64 = 0.0;
 
     let response_model = attributes.response_model();
@@ -56,20 +67,27 @@ pub async fn get_llm_usage_for_span(
 
     if let Some(model) = model_name.as_deref() {
         if let Some(provider) = &provider_name {
+            let token_usage = TokenUsage {
+                input_tokens: input_tokens as u32,
+                output_tokens: output_tokens as u32,
+                cache_read_input_tokens: cache_read_input_tokens as u32,
+                cache_creation_input_tokens: cache_creation_input_tokens as u32,
+                reasoning_tokens: reasoning_tokens as u32,
+            };
             let cost_entry = estimate_cost_by_provider_name(
                 db.clone(),
                 cache.clone(),
+                Some(project_id),
                 provider,
                 model,
-                input_tokens as u32,
-                output_tokens as u32,
+                &token_usage,
+                start_time,
             )
             .await;
             if let Some(cost_entry) = cost_entry {
-                input_cost = cost_entry.input_cost;
-                output_cost = cost_entry.output_cost;
+                cost = cost_entry;
             }
-            total_cost = input_cost + output_cost;
+            total_cost = cost.total_cost();
         }
     }
 
@@ -77,21 +95,24 @@ pub async fn get_llm_usage_for_span(
         input_tokens,
         output_tokens,
         total_tokens,
-        input_cost,
-        output_cost,
+        cache_read_input_tokens,
+        cache_creation_input_tokens,
+        reasoning_tokens,
+        input_cost: cost.input_cost,
+        output_cost: cost.output_cost,
         total_cost,
+        cache_read_input_cost: cost.cache_read_input_cost,
+        cache_creation_input_cost: cost.cache_creation_input_cost,
+        reasoning_cost: cost.reasoning_cost,
         response_model: attributes.response_model().clone(),
         request_model: attributes.request_model().clone(),
         provider_name,
     }
 }
 
-pub async fn record_span_to_db(
-    db: Arc<DB>,
-    span_usage: &SpanUsage,
-    project_id: &Uuid,
-    span: &mut Span,
-) -> anyhow::Result<()> {
+/// Fill in the span usage, path and parent, and return the attributes the span
+/// contributes to its trace
+pub fn prepare_span_for_recording(span_usage: &SpanUsage, span: &mut Span) -> TraceAttributes {
     let mut trace_attributes = TraceAttributes::new(span.trace_id);
 
     trace_attributes.update_start_time(span.start_time);
@@ -136,20 +157,51 @@ pub async fn record_span_to_db(
     span_attributes.update_path();
     span.set_attributes(&span_attributes);
 
-    let insert_span = || async {
-        db::spans::record_span(&db.pool, &span, project_id)
-            .await
-            .map_err(|e| {
-                log::error!(
-                    "Failed attempt to record span [{}]. Will retry according to backoff policy. Error: {:?}",
-                    span.span_id,
-                    e
-                );
-                backoff::Error::Transient {
-                    err: e,
-                    retry_after: None,
-                }
-            })
+    trace_attributes
+}
+
+/// Record spans to the database with a single multi-row upsert, and then upsert their
+/// traces, once per trace, with the trace attributes of their spans merged in memory.
+///
+/// Spans and traces are written in one transaction, which is retried as a whole, so that
+/// the token counts and costs of the spans are added to their traces exactly once: only
+/// spans that had not been recorded before count towards the usage of their traces.
+///
+/// Returns the number of spans that had not been recorded before, by project.
+pub async fn record_spans_to_db(
+    db: Arc<DB>,
+    spans: &[(Uuid, &Span, &TraceAttributes)],
+) -> anyhow::Result<HashMap<Uuid, i64>> {
+    if spans.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let db_spans = spans
+        .iter()
+        .map(|(project_id, span, _)| (*project_id, *span))
+        .collect::<Vec<_>>();
+
+    let record_spans_and_traces = || async {
+        let record = async {
+            let mut transaction = db.pool.begin().await?;
+            let inserted_spans = db::spans::record_spans(&mut transaction, &db_spans).await?;
+            // Insert or update traces only after the spans have been successfully inserted
+            let traces = merge_trace_attributes(spans, &inserted_spans);
+            trace::update_traces_attributes(&mut transaction, &traces).await?;
+            transaction.commit().await?;
+            Ok::<_, anyhow::Error>(inserted_spans)
+        };
+        record.await.map_err(|e| {
+            log::error!(
+                "Failed attempt to record {} spans. Will retry according to backoff policy. Error: {:?}",
+                db_spans.len(),
+                e
+            );
+            backoff::Error::Transient {
+                err: e,
+                retry_after: None,
+            }
+        })
     };
 
     // Starting with 0.5 second delay, delay multiplies by random factor between 1 and 2
@@ -162,40 +214,72 @@ pub async fn record_span_to_db(
         .with_max_interval(std::time::Duration::from_secs(1 * 60))
         .with_max_elapsed_time(Some(std::time::Duration::from_secs(5 * 60)))
         .build();
-    backoff::future::retry(exponential_backoff, insert_span)
+    let inserted_spans = backoff::future::retry(exponential_backoff, record_spans_and_traces)
         .await
         .map_err(|e| {
             log::error!(
-                "Exhausted backoff retries for span [{}]: {:?}",
-                span.span_id,
+                "Exhausted backoff retries for {} spans: {:?}",
+                db_spans.len(),
                 e
             );
             e
         })?;
 
-    // Insert or update trace only after the span has been successfully inserted
-    if let Err(e) = trace::update_trace_attributes(&db.pool, project_id, &trace_attributes).await {
-        log::error!(
-            "Failed to update trace attributes [{}]: {:?}",
-            span.span_id,
-            e
-        );
+    let mut inserted_by_project = HashMap::new();
+    for (project_id, _) in inserted_spans {
+        *inserted_by_project.entry(project_id).or_default() += 1;
     }
+    Ok(inserted_by_project)
+}
+
+/// Merge the trace attributes of the spans by trace. Only newly inserted spans count
+/// towards the token counts and costs of their traces, and a span that appears more than
+/// once only counts with its last occurrence, which is the one that was recorded.
+fn merge_trace_attributes(
+    spans: &[(Uuid, &Span, &TraceAttributes)],
+    inserted_spans: &HashSet<(Uuid, Uuid)>,
+) -> Vec<(Uuid, TraceAttributes)> {
+    let last_occurrences = spans
+        .iter()
+        .enumerate()
+        .map(|(index, (project_id, span, _))| ((*project_id, span.span_id), index))
+        .collect::<HashMap<_, _>>();
 
-    Ok(())
+    let mut traces: Vec<(Uuid, TraceAttributes)> = Vec::new();
+    let mut trace_indices: HashMap<Uuid, usize> = HashMap::new();
+    for (index, (project_id, span, attributes)) in spans.iter().enumerate() {
+        let mut attributes = (*attributes).clone();
+        let span_key = (*project_id, span.span_id);
+        if !inserted_spans.contains(&span_key) || last_occurrences.get(&span_key) != Some(&index) {
+            attributes.clear_usage();
+        }
+        match trace_indices.get(&attributes.id) {
+            Some(index) => traces[*index].1.merge(attributes),
+            None => {
+                trace_indices.insert(attributes.id, traces.len());
+                traces.push((*project_id, attributes));
+            }
+        }
+    }
+    traces
 }
 
-pub async fn record_labels_to_db_and_ch(
+/// Record the labels set in the span attributes to the database and return the rows
+/// to be inserted into Clickhouse
+pub async fn record_labels_to_db(
     db: Arc<DB>,
-    clickhouse: clickhouse::Client,
     span: &Span,
     project_id: &Uuid,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Vec<CHLabel>> {
+    let labels = span.get_attributes().labels();
+    if labels.is_empty() {
+        return Ok(Vec::new());
+    }
+
     let project_labels =
         db::labels::get_label_classes_by_project_id(&db.pool, *project_id, None).await?;
 
-    let labels = span.get_attributes().labels();
-
+    let mut ch_labels = Vec::new();
     for (label_name, label_value_key) in labels {
         let label_class = project_labels.iter().find(|l| l.name == label_name);
         if let Some(label_class) = label_class {
@@ -209,26 +293,32 @@ pub async fn record_labels_to_db_and_ch(
             let label_value = value_map.get(&key).cloned();
             let id = Uuid::new_v4();
             if let Some(label_value) = label_value {
-                crate::labels::insert_or_update_label(
+                db::labels::update_span_label(
                     &db.pool,
-                    clickhouse.clone(),
-                    *project_id,
                     id,
                     span.span_id,
+                    label_value,
+                    None,
                     label_class.id,
+                    &LabelSource::CODE,
                     None,
+                )
+                .await?;
+                ch_labels.push(CHLabel::new(
+                    *project_id,
+                    label_class.id,
+                    id,
                     label_name,
+                    LabelSource::CODE,
                     key,
                     label_value,
-                    LabelSource::CODE,
-                    None,
-                )
-                .await?;
+                    span.span_id,
+                ));
             }
         }
     }
 
-    Ok(())
+    Ok(ch_labels)
 }
 
 pub fn skip_span_name(name: &str) -> bool {
@@ -91,3 +91,83 @@ ALTER TABLE default.spans
     -- Improved index configuration
     ADD INDEX input_case_insensitive_idx input_lower TYPE tokenbf_v1(3, 4, 0) GRANULARITY 4,
     ADD INDEX output_case_insensitive_idx output_lower TYPE tokenbf_v1(3, 4, 0) GRANULARITY 4;
+
+CREATE TABLE default.metrics
+(
+    `project_id` UUID,
+    `name` String,
+    `description` String,
+    `unit` String,
+    `metric_type` UInt8,
+    `timestamp` DateTime64(9, 'UTC'),
+    `start_timestamp` DateTime64(9, 'UTC'),
+    `value` Float64,
+    `count` UInt64,
+    `sum` Float64,
+    `min` Nullable(Float64),
+    `max` Nullable(Float64),
+    `bucket_counts` Array(UInt64),
+    `explicit_bounds` Array(Float64),
+    `is_monotonic` Bool,
+    `aggregation_temporality` UInt8,
+    `attributes` Map(String, String),
+    `resource_attributes` Map(String, String),
+    `scope_name` String
+)
+ENGINE = MergeTree
+PARTITION BY toYYYYMM(timestamp)
+ORDER BY (project_id, name, timestamp)
+SETTINGS index_granularity = 8192;
+
+CREATE TABLE default.logs
+(
+    `id` UUID,
+    `project_id` UUID,
+    `trace_id` UUID,
+    `span_id` UUID,
+    `timestamp` DateTime64(9, 'UTC'),
+    `observed_timestamp` DateTime64(9, 'UTC'),
+    `severity_number` UInt8,
+    `severity_text` String,
+    `body` String CODEC(ZSTD(3)),
+    `body_lower` String MATERIALIZED lower(body) CODEC(ZSTD(3)),
+    `attributes` Map(String, String),
+    `resource_attributes` Map(String, String),
+    `scope_name` String,
+    INDEX body_case_insensitive_idx body_lower TYPE tokenbf_v1(3, 4, 0) GRANULARITY 4
+)
+ENGINE = MergeTree
+PARTITION BY toYYYYMM(timestamp)
+ORDER BY (project_id, trace_id, span_id, timestamp)
+SETTINGS index_granularity = 8192;
+
+ALTER TABLE default.spans
+    -- OpenTelemetry status code: 0 unset, 1 ok, 2 error
+    ADD COLUMN IF NOT EXISTS status_code UInt8 DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS status_message String DEFAULT '';
+
+ALTER TABLE default.spans
+    -- OpenTelemetry resource and instrumentation scope
+    ADD COLUMN IF NOT EXISTS service_name String DEFAULT '',
+    ADD COLUMN IF NOT EXISTS resource_attributes Map(String, String),
+    ADD COLUMN IF NOT EXISTS scope_name String DEFAULT '',
+    ADD COLUMN IF NOT EXISTS scope_version String DEFAULT '';
+
+ALTER TABLE default.spans
+    -- Breakdown of the tokens and costs, included in the input and output ones
+    ADD COLUMN IF NOT EXISTS cache_read_input_tokens Int64 DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS cache_creation_input_tokens Int64 DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS reasoning_tokens Int64 DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS cache_read_input_cost Float64 DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS cache_creation_input_cost Float64 DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS reasoning_cost Float64 DEFAULT 0;
+
+ALTER TABLE default.spans
+    -- Flattened span attributes, e.g. `metadata.customer_id`, to filter and group by
+    ADD COLUMN IF NOT EXISTS attributes Map(String, String),
+    -- Skip the granules without the attribute keys and values that are filtered by
+    ADD INDEX IF NOT EXISTS attributes_keys_idx mapKeys(attributes) TYPE bloom_filter(0.01) GRANULARITY 1,
+    ADD INDEX IF NOT EXISTS attributes_values_idx mapValues(attributes) TYPE bloom_filter(0.01) GRANULARITY 1;
+
+ALTER TABLE default.events
+    ADD COLUMN IF NOT EXISTS attributes Map(String, String);
@@ -1,15 +1,6 @@
-import { GetObjectCommand, S3Client } from '@aws-sdk/client-s3';
 import { NextRequest } from 'next/server';
 
-const client = new S3Client({
-  region: 'us-east-1',
-  credentials: {
-    accessKeyId: process.env.AWS_ACCESS_KEY_ID ?? '',
-    secretAccessKey: process.env.AWS_SECRET_ACCESS_KEY ?? ''
-  }
-});
-
-const bucket = process.env.S3_TRACE_PAYLOADS_BUCKET ?? '';
+import { fetcher, fetcherJSON } from '@/lib/utils';
 
 export async function GET(
   req: NextRequest,
@@ -18,24 +9,28 @@ export async function GET(
   const params = await props.params;
   const { projectId, payloadId } = params;
   const payloadType = req.nextUrl.searchParams.get('payloadType');
-  const getObjectRequest = new GetObjectCommand({
-    Bucket: bucket,
-    Key: `project/${projectId}/${payloadId}`
+  const query = payloadType ? `?payloadType=${encodeURIComponent(payloadType)}` : '';
+  const payloadPath = `/projects/${projectId}/payloads/${encodeURIComponent(payloadId)}`;
+
+  // if the storage supports it, load the payload directly from the storage
+  const payloadUrl = await fetcherJSON<{ url: string; presigned: boolean }>(`${payloadPath}/url${query}`, {
+    method: 'GET'
   });
-  const blob = await client.send(getObjectRequest);
-  const bytes = await blob.Body?.transformToByteArray();
+  if (payloadUrl.presigned) {
+    return Response.redirect(payloadUrl.url, 307);
+  }
+
+  // otherwise, payloads are read by the app-server, which knows the configured storage
+  const res = await fetcher(`${payloadPath}${query}`, {
+    method: 'GET'
+  });
+
   const headers = new Headers();
-  // backend sets the key to *.pdf, if we know from the LLM provider
-  // that the media-type is application/pdf
-  if (payloadType === 'image') {
-    return new Response(bytes);
-  } else if (payloadType === 'raw') {
-    return new Response(bytes);
-  } else if (payloadId.endsWith('.pdf')) {
-    headers.set('Content-Type', 'application/pdf');
-  } else {
-    headers.set('Content-Type', 'application/octet-stream');
+  for (const header of ['Content-Type', 'Content-Disposition']) {
+    const value = res.headers.get(header);
+    if (value) {
+      headers.set(header, value);
+    }
   }
-  headers.set('Content-Disposition', `attachment; filename="${payloadId}"`);
-  return new Response(bytes, { headers });
+  return new Response(res.body, { headers });
 }
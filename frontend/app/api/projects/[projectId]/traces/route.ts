@@ -148,8 +148,21 @@ export async function GET(
       const [key, value] = filter.value.split(/=(.*)/);
       return sql`metadata @> ${JSON.stringify({ [key]: value })}`;
     });
+  // service.name of the OpenTelemetry resource, recorded on every span
+  const serviceFilters = urlParamFilters
+    .filter(filter => filter.column === "service" && filter.operator === "eq")
+    .map(filter => inArray(
+      sql`id`,
+      db
+        .select({ id: spans.traceId })
+        .from(spans)
+        .where(and(
+          eq(spans.projectId, projectId),
+          sql`attributes ->> 'resource.service.name' = ${filter.value}`
+        ))
+    ));
   const otherFilters = urlParamFilters
-    .filter(filter => filter.column !== "labels" && filter.column !== "metadata")
+    .filter(filter => !["labels", "metadata", "service"].includes(filter.column))
     .map(filter => {
       if (filter.column === "traceType") {
         filter.castType = "trace_type";
@@ -168,11 +181,11 @@ export async function GET(
   );
 
   const traceQuery = query
-    .where(and(...filters.concat(labelFilters, metadataFilters, sqlFilters)))
+    .where(and(...filters.concat(labelFilters, metadataFilters, serviceFilters, sqlFilters)))
     .orderBy(desc(baseQuery.startTime))
     .limit(pageSize)
     .offset(pageNumber * pageSize);
-  const countQuery = baseCountQuery.where(and(...filters.concat(labelFilters, metadataFilters, sqlFilters)));
+  const countQuery = baseCountQuery.where(and(...filters.concat(labelFilters, metadataFilters, serviceFilters, sqlFilters)));
 
   const [items, totalCount] = await Promise.all([
     traceQuery,
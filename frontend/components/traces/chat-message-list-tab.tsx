@@ -1,6 +1,11 @@
 import React from 'react';
 
-import { ChatMessage, ChatMessageContentPart, OpenAIImageUrl } from '@/lib/types';
+import {
+  ChatMessage,
+  ChatMessageContentPart,
+  ChatMessageToolCall,
+  OpenAIImageUrl
+} from '@/lib/types';
 import { isStringType } from '@/lib/utils';
 
 import DownloadButton from '../ui/download-button';
@@ -87,6 +92,35 @@ function ContentParts({ contentParts }: ContentPartsProps) {
   );
 }
 
+interface ToolCallsProps {
+  toolCalls: ChatMessageToolCall[];
+  presetKey?: string | null;
+}
+
+function ToolCalls({ toolCalls, presetKey }: ToolCallsProps) {
+  return (
+    <div className="flex flex-col w-full">
+      {toolCalls.map((toolCall, index) => (
+        <div key={index} className="flex flex-col border-t">
+          <div className="text-xs text-secondary-foreground px-2 pt-2">
+            Tool call <span className="font-mono">{toolCall.name}</span>
+            {toolCall.id && <span className="font-mono"> ({toolCall.id})</span>}
+          </div>
+          <Formatter
+            collapsible
+            value={isStringType(toolCall.arguments)
+              ? toolCall.arguments
+              : JSON.stringify(toolCall.arguments, null, 2)}
+            className="rounded-none max-h-[400px] border-none"
+            defaultMode="json"
+            presetKey={presetKey ? `${presetKey}-tool-call-${index}` : null}
+          />
+        </div>
+      ))}
+    </div>
+  );
+}
+
 interface ChatMessageListTabProps {
   messages: ChatMessage[];
   presetKey?: string | null;
@@ -109,6 +143,9 @@ export default function ChatMessageListTab({
         >
           <div className="font-medium text-sm text-secondary-foreground border-b p-2">
             {message.role.toUpperCase()}
+            {message.tool_call_id && (
+              <span className="font-mono font-normal text-xs ml-2">{message.tool_call_id}</span>
+            )}
           </div>
           <div>
             {isStringType(message.content) ? (
@@ -119,6 +156,9 @@ export default function ChatMessageListTab({
             ) : (
               <ContentParts contentParts={message.content} />
             )}
+            {message.tool_calls && message.tool_calls.length > 0 && (
+              <ToolCalls toolCalls={message.tool_calls} presetKey={`${presetKey}-${index}`} />
+            )}
           </div>
         </div>
       ))}
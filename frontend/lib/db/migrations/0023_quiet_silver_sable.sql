@@ -0,0 +1 @@
+ALTER TABLE "projects" ADD COLUMN "payload_url_expiry_seconds" integer;
\ No newline at end of file
@@ -0,0 +1,3 @@
+CREATE TYPE "public"."span_status" AS ENUM('UNSET', 'OK', 'ERROR');--> statement-breakpoint
+ALTER TABLE "spans" ADD COLUMN "status" "span_status" DEFAULT 'UNSET' NOT NULL;--> statement-breakpoint
+ALTER TABLE "spans" ADD COLUMN "status_message" text;
\ No newline at end of file
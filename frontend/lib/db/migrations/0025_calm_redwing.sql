@@ -0,0 +1,18 @@
+CREATE TYPE "public"."redaction_action" AS ENUM('MASK', 'HASH', 'DROP');--> statement-breakpoint
+CREATE TYPE "public"."redaction_rule_type" AS ENUM('REGEX', 'DETECTOR', 'ATTRIBUTE_KEY');--> statement-breakpoint
+CREATE TABLE IF NOT EXISTS "redaction_rules" (
+	"id" uuid PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
+	"created_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"project_id" uuid NOT NULL,
+	"name" text NOT NULL,
+	"rule_type" "redaction_rule_type" NOT NULL,
+	"pattern" text NOT NULL,
+	"action" "redaction_action" DEFAULT 'MASK' NOT NULL,
+	"enabled" boolean DEFAULT true NOT NULL
+);
+--> statement-breakpoint
+DO $$ BEGIN
+ ALTER TABLE "redaction_rules" ADD CONSTRAINT "redaction_rules_project_id_fkey" FOREIGN KEY ("project_id") REFERENCES "public"."projects"("id") ON DELETE cascade ON UPDATE cascade;
+EXCEPTION
+ WHEN duplicate_object THEN null;
+END $$;
@@ -0,0 +1 @@
+ALTER TABLE "projects" ADD COLUMN "sampling_policy" jsonb;
\ No newline at end of file
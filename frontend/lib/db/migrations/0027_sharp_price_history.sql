@@ -0,0 +1,38 @@
+CREATE TYPE "public"."model_name_rule_type" AS ENUM('ALIAS', 'REGEX');--> statement-breakpoint
+CREATE TABLE IF NOT EXISTS "model_name_rules" (
+	"id" uuid PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
+	"created_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"project_id" uuid NOT NULL,
+	"provider" text,
+	"rule_type" "model_name_rule_type" DEFAULT 'ALIAS' NOT NULL,
+	"pattern" text NOT NULL,
+	"model" text NOT NULL
+);
+--> statement-breakpoint
+CREATE TABLE IF NOT EXISTS "project_llm_prices" (
+	"id" uuid PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
+	"created_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"project_id" uuid NOT NULL,
+	"provider" text NOT NULL,
+	"model" text NOT NULL,
+	"input_price_per_million" double precision NOT NULL,
+	"output_price_per_million" double precision NOT NULL,
+	"input_cached_price_per_million" double precision,
+	"additional_prices" jsonb DEFAULT '{}'::jsonb NOT NULL,
+	"valid_from" timestamp with time zone,
+	"valid_to" timestamp with time zone
+);
+--> statement-breakpoint
+ALTER TABLE "llm_prices" ADD COLUMN "valid_from" timestamp with time zone;--> statement-breakpoint
+ALTER TABLE "llm_prices" ADD COLUMN "valid_to" timestamp with time zone;--> statement-breakpoint
+DO $$ BEGIN
+ ALTER TABLE "model_name_rules" ADD CONSTRAINT "model_name_rules_project_id_fkey" FOREIGN KEY ("project_id") REFERENCES "public"."projects"("id") ON DELETE cascade ON UPDATE cascade;
+EXCEPTION
+ WHEN duplicate_object THEN null;
+END $$;
+--> statement-breakpoint
+DO $$ BEGIN
+ ALTER TABLE "project_llm_prices" ADD CONSTRAINT "project_llm_prices_project_id_fkey" FOREIGN KEY ("project_id") REFERENCES "public"."projects"("id") ON DELETE cascade ON UPDATE cascade;
+EXCEPTION
+ WHEN duplicate_object THEN null;
+END $$;
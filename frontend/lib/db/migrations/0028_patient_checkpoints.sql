@@ -0,0 +1,37 @@
+CREATE TYPE "public"."pipeline_run_status" AS ENUM('RUNNING', 'SUCCEEDED', 'FAILED');--> statement-breakpoint
+CREATE TABLE IF NOT EXISTS "pipeline_runs" (
+	"id" uuid PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
+	"created_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"updated_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"project_id" uuid NOT NULL,
+	"pipeline_version_id" uuid NOT NULL,
+	"pipeline_version_name" text NOT NULL,
+	"status" "pipeline_run_status" DEFAULT 'RUNNING' NOT NULL,
+	"graph" jsonb NOT NULL,
+	"metadata" jsonb DEFAULT '{}'::jsonb NOT NULL,
+	"outputs" jsonb,
+	"error" text
+);
+--> statement-breakpoint
+CREATE TABLE IF NOT EXISTS "pipeline_run_messages" (
+	"id" uuid NOT NULL,
+	"created_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"run_id" uuid NOT NULL,
+	"node_id" uuid NOT NULL,
+	"message" jsonb NOT NULL,
+	CONSTRAINT "pipeline_run_messages_pkey" PRIMARY KEY("run_id","id")
+);
+--> statement-breakpoint
+DO $$ BEGIN
+ ALTER TABLE "pipeline_runs" ADD CONSTRAINT "pipeline_runs_project_id_fkey" FOREIGN KEY ("project_id") REFERENCES "public"."projects"("id") ON DELETE cascade ON UPDATE cascade;
+EXCEPTION
+ WHEN duplicate_object THEN null;
+END $$;
+--> statement-breakpoint
+DO $$ BEGIN
+ ALTER TABLE "pipeline_run_messages" ADD CONSTRAINT "pipeline_run_messages_run_id_fkey" FOREIGN KEY ("run_id") REFERENCES "public"."pipeline_runs"("id") ON DELETE cascade ON UPDATE cascade;
+EXCEPTION
+ WHEN duplicate_object THEN null;
+END $$;
+--> statement-breakpoint
+CREATE INDEX IF NOT EXISTS "pipeline_runs_project_id_created_at_idx" ON "pipeline_runs" USING btree ("project_id","created_at");
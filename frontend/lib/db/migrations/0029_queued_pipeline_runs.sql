@@ -0,0 +1,4 @@
+ALTER TYPE "public"."pipeline_run_status" ADD VALUE 'QUEUED';--> statement-breakpoint
+ALTER TYPE "public"."pipeline_run_status" ADD VALUE 'CANCELLED';--> statement-breakpoint
+ALTER TABLE "pipeline_runs" ADD COLUMN "webhook_url" text;--> statement-breakpoint
+ALTER TABLE "pipeline_runs" ADD COLUMN "webhook_secret" text;
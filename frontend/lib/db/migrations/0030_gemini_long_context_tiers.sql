@@ -0,0 +1,14 @@
+UPDATE "llm_prices" AS "base"
+SET
+	"additional_prices" = "base"."additional_prices" || jsonb_strip_nulls(jsonb_build_object(
+		'input_price_per_million_above_128k', "long_context"."input_price_per_million",
+		'output_price_per_million_above_128k', "long_context"."output_price_per_million",
+		'input_cached_price_per_million_above_128k', "long_context"."input_cached_price_per_million"
+	)),
+	"updated_at" = now()
+FROM "llm_prices" AS "long_context"
+WHERE "long_context"."provider" = "base"."provider"
+	AND "long_context"."model" = "base"."model" || '--long-context'
+	AND "long_context"."valid_from" IS NOT DISTINCT FROM "base"."valid_from"
+	AND "long_context"."valid_to" IS NOT DISTINCT FROM "base"."valid_to";--> statement-breakpoint
+DELETE FROM "llm_prices" WHERE "provider" = 'gemini' AND "model" LIKE '%--long-context';
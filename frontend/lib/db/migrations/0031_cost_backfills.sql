@@ -0,0 +1,18 @@
+CREATE TYPE "public"."cost_backfill_status" AS ENUM('RUNNING', 'SUCCEEDED', 'FAILED');--> statement-breakpoint
+CREATE TABLE IF NOT EXISTS "cost_backfills" (
+	"id" uuid PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
+	"created_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"updated_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"project_id" uuid NOT NULL,
+	"start_time" timestamp with time zone NOT NULL,
+	"end_time" timestamp with time zone NOT NULL,
+	"status" "cost_backfill_status" DEFAULT 'RUNNING' NOT NULL,
+	"span_count" bigint DEFAULT '0' NOT NULL,
+	"error" text
+);
+--> statement-breakpoint
+DO $$ BEGIN
+ ALTER TABLE "cost_backfills" ADD CONSTRAINT "cost_backfills_project_id_fkey" FOREIGN KEY ("project_id") REFERENCES "public"."projects"("id") ON DELETE cascade ON UPDATE cascade;
+EXCEPTION
+ WHEN duplicate_object THEN null;
+END $$;
@@ -1,6 +1,6 @@
 import { relations } from "drizzle-orm/relations";
 
-import { apiKeys, datapointToSpan,datasetDatapoints, datasets, evaluationResults, evaluations, evaluationScores, events, labelClasses, labelClassesForPath, labelingQueueItems, labelingQueues, labels, machines, membersOfWorkspaces, pipelines, pipelineVersions, playgrounds, projectApiKeys, projects, providerApiKeys, renderTemplates, spans, subscriptionTiers, targetPipelineVersions, traces, users, userSubscriptionInfo, workspaces, workspaceUsage } from "./schema";
+import { apiKeys, datapointToSpan,datasetDatapoints, datasets, evaluationResults, evaluations, evaluationScores, events, labelClasses, labelClassesForPath, labelingQueueItems, labelingQueues, labels, machines, membersOfWorkspaces, modelNameRules, pipelineRunMessages, pipelineRuns, pipelines, pipelineVersions, playgrounds, projectApiKeys, projectLlmPrices, projects, providerApiKeys, redactionRules, renderTemplates, spans, subscriptionTiers, targetPipelineVersions, traces, users, userSubscriptionInfo, workspaces, workspaceUsage } from "./schema";
 
 export const renderTemplatesRelations = relations(renderTemplates, ({one}) => ({
   project: one(projects, {
@@ -20,6 +20,10 @@ export const projectsRelations = relations(projects, ({one, many}) => ({
     references: [workspaces.id]
   }),
   providerApiKeys: many(providerApiKeys),
+  redactionRules: many(redactionRules),
+  projectLlmPrices: many(projectLlmPrices),
+  modelNameRules: many(modelNameRules),
+  pipelineRuns: many(pipelineRuns),
   traces: many(traces),
   playgrounds: many(playgrounds),
   pipelines: many(pipelines),
@@ -151,6 +155,42 @@ export const providerApiKeysRelations = relations(providerApiKeys, ({one}) => ({
   }),
 }));
 
+export const redactionRulesRelations = relations(redactionRules, ({one}) => ({
+  project: one(projects, {
+    fields: [redactionRules.projectId],
+    references: [projects.id]
+  }),
+}));
+
+export const projectLlmPricesRelations = relations(projectLlmPrices, ({one}) => ({
+  project: one(projects, {
+    fields: [projectLlmPrices.projectId],
+    references: [projects.id]
+  }),
+}));
+
+export const modelNameRulesRelations = relations(modelNameRules, ({one}) => ({
+  project: one(projects, {
+    fields: [modelNameRules.projectId],
+    references: [projects.id]
+  }),
+}));
+
+export const pipelineRunsRelations = relations(pipelineRuns, ({one, many}) => ({
+  project: one(projects, {
+    fields: [pipelineRuns.projectId],
+    references: [projects.id]
+  }),
+  pipelineRunMessages: many(pipelineRunMessages),
+}));
+
+export const pipelineRunMessagesRelations = relations(pipelineRunMessages, ({one}) => ({
+  pipelineRun: one(pipelineRuns, {
+    fields: [pipelineRunMessages.runId],
+    references: [pipelineRuns.id]
+  }),
+}));
+
 export const subscriptionTiersRelations = relations(subscriptionTiers, ({many}) => ({
   workspaces: many(workspaces),
 }));
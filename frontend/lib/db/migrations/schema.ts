@@ -1,7 +1,13 @@
 import { sql } from "drizzle-orm";
 import { bigint, boolean, doublePrecision, foreignKey, index, integer, jsonb, pgEnum,pgPolicy, pgTable, primaryKey, text, timestamp, unique, uuid } from "drizzle-orm/pg-core";
 
+export const costBackfillStatus = pgEnum("cost_backfill_status", ['RUNNING', 'SUCCEEDED', 'FAILED']);
 export const labelSource = pgEnum("label_source", ['MANUAL', 'AUTO', 'CODE']);
+export const modelNameRuleType = pgEnum("model_name_rule_type", ['ALIAS', 'REGEX']);
+export const pipelineRunStatus = pgEnum("pipeline_run_status", ['RUNNING', 'SUCCEEDED', 'FAILED', 'QUEUED', 'CANCELLED']);
+export const redactionAction = pgEnum("redaction_action", ['MASK', 'HASH', 'DROP']);
+export const redactionRuleType = pgEnum("redaction_rule_type", ['REGEX', 'DETECTOR', 'ATTRIBUTE_KEY']);
+export const spanStatus = pgEnum("span_status", ['UNSET', 'OK', 'ERROR']);
 export const spanType = pgEnum("span_type", ['DEFAULT', 'LLM', 'PIPELINE', 'EXECUTOR', 'EVALUATOR', 'EVALUATION', 'TOOL']);
 export const traceType = pgEnum("trace_type", ['DEFAULT', 'EVENT', 'EVALUATION']);
 export const workspaceRole = pgEnum("workspace_role", ['member', 'owner']);
@@ -109,6 +115,8 @@ export const llmPrices = pgTable("llm_prices", {
   outputPricePerMillion: doublePrecision("output_price_per_million").notNull(),
   inputCachedPricePerMillion: doublePrecision("input_cached_price_per_million"),
   additionalPrices: jsonb("additional_prices").default({}).notNull(),
+  validFrom: timestamp("valid_from", { withTimezone: true, mode: 'string' }),
+  validTo: timestamp("valid_to", { withTimezone: true, mode: 'string' }),
 });
 
 export const pipelineTemplates = pgTable("pipeline_templates", {
@@ -193,6 +201,8 @@ export const projects = pgTable("projects", {
   createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
   name: text().notNull(),
   workspaceId: uuid("workspace_id").notNull(),
+  payloadUrlExpirySeconds: integer("payload_url_expiry_seconds"),
+  samplingPolicy: jsonb("sampling_policy"),
 }, (table) => [
   index("projects_workspace_id_idx").using("btree", table.workspaceId.asc().nullsLast().op("uuid_ops")),
   foreignKey({
@@ -217,6 +227,115 @@ export const providerApiKeys = pgTable("provider_api_keys", {
   }).onUpdate("cascade").onDelete("cascade"),
 ]);
 
+export const redactionRules = pgTable("redaction_rules", {
+  id: uuid().defaultRandom().primaryKey().notNull(),
+  createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  projectId: uuid("project_id").notNull(),
+  name: text().notNull(),
+  ruleType: redactionRuleType("rule_type").notNull(),
+  pattern: text().notNull(),
+  action: redactionAction().default('MASK').notNull(),
+  enabled: boolean().default(true).notNull(),
+}, (table) => [
+  foreignKey({
+    columns: [table.projectId],
+    foreignColumns: [projects.id],
+    name: "redaction_rules_project_id_fkey"
+  }).onUpdate("cascade").onDelete("cascade"),
+]);
+
+export const projectLlmPrices = pgTable("project_llm_prices", {
+  id: uuid().defaultRandom().primaryKey().notNull(),
+  createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  projectId: uuid("project_id").notNull(),
+  provider: text().notNull(),
+  model: text().notNull(),
+  inputPricePerMillion: doublePrecision("input_price_per_million").notNull(),
+  outputPricePerMillion: doublePrecision("output_price_per_million").notNull(),
+  inputCachedPricePerMillion: doublePrecision("input_cached_price_per_million"),
+  additionalPrices: jsonb("additional_prices").default({}).notNull(),
+  validFrom: timestamp("valid_from", { withTimezone: true, mode: 'string' }),
+  validTo: timestamp("valid_to", { withTimezone: true, mode: 'string' }),
+}, (table) => [
+  foreignKey({
+    columns: [table.projectId],
+    foreignColumns: [projects.id],
+    name: "project_llm_prices_project_id_fkey"
+  }).onUpdate("cascade").onDelete("cascade"),
+]);
+
+export const costBackfills = pgTable("cost_backfills", {
+  id: uuid().defaultRandom().primaryKey().notNull(),
+  createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  updatedAt: timestamp("updated_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  projectId: uuid("project_id").notNull(),
+  startTime: timestamp("start_time", { withTimezone: true, mode: 'string' }).notNull(),
+  endTime: timestamp("end_time", { withTimezone: true, mode: 'string' }).notNull(),
+  status: costBackfillStatus().default('RUNNING').notNull(),
+  spanCount: bigint("span_count", { mode: "number" }).default(sql`'0'`).notNull(),
+  error: text(),
+}, (table) => [
+  foreignKey({
+    columns: [table.projectId],
+    foreignColumns: [projects.id],
+    name: "cost_backfills_project_id_fkey"
+  }).onUpdate("cascade").onDelete("cascade"),
+]);
+
+export const modelNameRules = pgTable("model_name_rules", {
+  id: uuid().defaultRandom().primaryKey().notNull(),
+  createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  projectId: uuid("project_id").notNull(),
+  provider: text(),
+  ruleType: modelNameRuleType("rule_type").default('ALIAS').notNull(),
+  pattern: text().notNull(),
+  model: text().notNull(),
+}, (table) => [
+  foreignKey({
+    columns: [table.projectId],
+    foreignColumns: [projects.id],
+    name: "model_name_rules_project_id_fkey"
+  }).onUpdate("cascade").onDelete("cascade"),
+]);
+
+export const pipelineRuns = pgTable("pipeline_runs", {
+  id: uuid().defaultRandom().primaryKey().notNull(),
+  createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  updatedAt: timestamp("updated_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  projectId: uuid("project_id").notNull(),
+  pipelineVersionId: uuid("pipeline_version_id").notNull(),
+  pipelineVersionName: text("pipeline_version_name").notNull(),
+  status: pipelineRunStatus().default('RUNNING').notNull(),
+  graph: jsonb().notNull(),
+  metadata: jsonb().default({}).notNull(),
+  outputs: jsonb(),
+  error: text(),
+  webhookUrl: text("webhook_url"),
+  webhookSecret: text("webhook_secret"),
+}, (table) => [
+  index("pipeline_runs_project_id_created_at_idx").using("btree", table.projectId.asc().nullsLast(), table.createdAt.asc().nullsLast()),
+  foreignKey({
+    columns: [table.projectId],
+    foreignColumns: [projects.id],
+    name: "pipeline_runs_project_id_fkey"
+  }).onUpdate("cascade").onDelete("cascade"),
+]);
+
+export const pipelineRunMessages = pgTable("pipeline_run_messages", {
+  id: uuid().notNull(),
+  createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+  runId: uuid("run_id").notNull(),
+  nodeId: uuid("node_id").notNull(),
+  message: jsonb().notNull(),
+}, (table) => [
+  foreignKey({
+    columns: [table.runId],
+    foreignColumns: [pipelineRuns.id],
+    name: "pipeline_run_messages_run_id_fkey"
+  }).onUpdate("cascade").onDelete("cascade"),
+  primaryKey({ columns: [table.runId, table.id], name: "pipeline_run_messages_pkey" }),
+]);
+
 export const workspaces = pgTable("workspaces", {
   id: uuid().defaultRandom().primaryKey().notNull(),
   createdAt: timestamp("created_at", { withTimezone: true, mode: 'string' }).defaultNow().notNull(),
@@ -547,6 +666,8 @@ export const spans = pgTable("spans", {
   projectId: uuid("project_id").notNull(),
   inputUrl: text("input_url"),
   outputUrl: text("output_url"),
+  status: spanStatus("status").default('UNSET').notNull(),
+  statusMessage: text("status_message"),
 }, (table) => [
   index("span_path_idx").using("btree", sql`(attributes -> 'lmnr.span.path'::text)`),
   index("spans_project_id_idx").using("hash", table.projectId.asc().nullsLast().op("uuid_ops")),
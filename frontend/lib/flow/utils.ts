@@ -722,12 +722,20 @@ export const renderChatMessageList = (messages: ChatMessage[]): string =>
   messages
     .map((message) => {
       let tag = message.role;
+      const openTag = message.tool_call_id ? `${tag} tool_call_id="${message.tool_call_id}"` : tag;
 
-      if (isStringType(message.content)) {
-        return `<${tag}>\n${message.content}\n</${tag}>\n`;
-      } else {
-        return `<${tag}>\n${renderChatMessageContentParts(message.content)}\n</${tag}>\n`;
-      }
+      const text = isStringType(message.content)
+        ? message.content
+        : renderChatMessageContentParts(message.content);
+      const toolCalls = (message.tool_calls ?? []).map((toolCall) => {
+        const args = isStringType(toolCall.arguments)
+          ? toolCall.arguments
+          : JSON.stringify(toolCall.arguments ?? {});
+        return `<tool_call id="${toolCall.id ?? ''}" name="${toolCall.name}">\n${args}\n</tool_call>`;
+      });
+      const blocks = text || toolCalls.length === 0 ? [text, ...toolCalls] : toolCalls;
+
+      return `<${openTag}>\n${blocks.join('\n')}\n</${tag}>\n`;
     })
     .join('\n\n');
 
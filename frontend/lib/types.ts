@@ -44,9 +44,18 @@ export type ChatMessageContentPart =
 
 export type ChatMessageContent = string | ChatMessageContentPart[];
 
+export type ChatMessageToolCall = {
+  id?: string | null;
+  name: string;
+  arguments: any;
+};
+
 export type ChatMessage = {
   content: ChatMessageContent;
-  role: 'user' | 'assistant' | 'system';
+  role: 'user' | 'assistant' | 'system' | 'tool';
+  tool_calls?: ChatMessageToolCall[];
+  // set on messages with the tool role
+  tool_call_id?: string;
 };
 
 export type DatatableFilter = {
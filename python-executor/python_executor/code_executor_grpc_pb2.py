@@ -14,7 +14,7 @@ _sym_db = _symbol_database.Default()
 
 
 
-DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(b'\n\x18\x63ode_executor_grpc.proto\x12\x12\x63ode_executor_grpc\"\x1f\n\x0f\x43hatMessageText\x12\x0c\n\x04text\x18\x01 \x01(\t\"\"\n\x13\x43hatMessageImageUrl\x12\x0b\n\x03url\x18\x01 \x01(\t\"4\n\x10\x43hatMessageImage\x12\x12\n\nmedia_type\x18\x01 \x01(\t\x12\x0c\n\x04\x64\x61ta\x18\x02 \x01(\t\"\xcb\x01\n\x16\x43hatMessageContentPart\x12\x33\n\x04text\x18\x01 \x01(\x0b\x32#.code_executor_grpc.ChatMessageTextH\x00\x12<\n\timage_url\x18\x02 \x01(\x0b\x32\'.code_executor_grpc.ChatMessageImageUrlH\x00\x12\x35\n\x05image\x18\x03 \x01(\x0b\x32$.code_executor_grpc.ChatMessageImageH\x00\x42\x07\n\x05value\"L\n\x0f\x43ontentPartList\x12\x39\n\x05parts\x18\x01 \x03(\x0b\x32*.code_executor_grpc.ChatMessageContentPart\"o\n\x12\x43hatMessageContent\x12\x0e\n\x04text\x18\x01 \x01(\tH\x00\x12@\n\x11\x63ontent_part_list\x18\x02 \x01(\x0b\x32#.code_executor_grpc.ContentPartListH\x00\x42\x07\n\x05value\"\xaa\x01\n\x0f\x43hatMessageList\x12\x41\n\x08messages\x18\x01 \x03(\x0b\x32/.code_executor_grpc.ChatMessageList.ChatMessage\x1aT\n\x0b\x43hatMessage\x12\x0c\n\x04role\x18\x01 \x01(\t\x12\x37\n\x07\x63ontent\x18\x02 \x01(\x0b\x32&.code_executor_grpc.ChatMessageContent\"\x1c\n\nStringList\x12\x0e\n\x06values\x18\x01 \x03(\t\"\xcf\x01\n\x03\x41rg\x12\x16\n\x0cstring_value\x18\x01 \x01(\tH\x00\x12=\n\x0emessages_value\x18\x02 \x01(\x0b\x32#.code_executor_grpc.ChatMessageListH\x00\x12;\n\x11string_list_value\x18\x03 \x01(\x0b\x32\x1e.code_executor_grpc.StringListH\x00\x12\x15\n\x0b\x66loat_value\x18\x04 \x01(\x01H\x00\x12\x14\n\nbool_value\x18\x05 \x01(\x08H\x00\x42\x07\n\x05value\"\xee\x01\n\x12\x45xecuteCodeRequest\x12\x0c\n\x04\x63ode\x18\x01 \x01(\t\x12\x0f\n\x07\x66n_name\x18\x02 \x01(\t\x12>\n\x04\x61rgs\x18\x03 \x03(\x0b\x32\x30.code_executor_grpc.ExecuteCodeRequest.ArgsEntry\x12\x33\n\x0breturn_type\x18\x04 \x01(\x0e\x32\x1e.code_executor_grpc.HandleType\x1a\x44\n\tArgsEntry\x12\x0b\n\x03key\x18\x01 \x01(\t\x12&\n\x05value\x18\x02 \x01(\x0b\x32\x17.code_executor_grpc.Arg:\x02\x38\x01\"\xb4\x01\n\x13\x45xecuteCodeResponse\x12)\n\x06result\x18\x01 \x01(\x0b\x32\x17.code_executor_grpc.ArgH\x00\x12\x45\n\x05\x65rror\x18\x02 \x01(\x0b\x32\x34.code_executor_grpc.ExecuteCodeResponse.ErrorMessageH\x00\x1a\x1f\n\x0c\x45rrorMessage\x12\x0f\n\x07message\x18\x01 \x01(\tB\n\n\x08response*T\n\nHandleType\x12\x07\n\x03\x41NY\x10\x00\x12\n\n\x06STRING\x10\x01\x12\x0f\n\x0bSTRING_LIST\x10\x02\x12\x15\n\x11\x43HAT_MESSAGE_LIST\x10\x03\x12\t\n\x05\x46LOAT\x10\x04\x32j\n\x0c\x43odeExecutor\x12Z\n\x07\x45xecute\x12&.code_executor_grpc.ExecuteCodeRequest\x1a\'.code_executor_grpc.ExecuteCodeResponseb\x06proto3')
+DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(b'\n\x18\x63ode_executor_grpc.proto\x12\x12\x63ode_executor_grpc\"\x1f\n\x0f\x43hatMessageText\x12\x0c\n\x04text\x18\x01 \x01(\t\"\"\n\x13\x43hatMessageImageUrl\x12\x0b\n\x03url\x18\x01 \x01(\t\"4\n\x10\x43hatMessageImage\x12\x12\n\nmedia_type\x18\x01 \x01(\t\x12\x0c\n\x04\x64\x61ta\x18\x02 \x01(\t\"\xcb\x01\n\x16\x43hatMessageContentPart\x12\x33\n\x04text\x18\x01 \x01(\x0b\x32#.code_executor_grpc.ChatMessageTextH\x00\x12<\n\timage_url\x18\x02 \x01(\x0b\x32\'.code_executor_grpc.ChatMessageImageUrlH\x00\x12\x35\n\x05image\x18\x03 \x01(\x0b\x32$.code_executor_grpc.ChatMessageImageH\x00\x42\x07\n\x05value\"L\n\x0f\x43ontentPartList\x12\x39\n\x05parts\x18\x01 \x03(\x0b\x32*.code_executor_grpc.ChatMessageContentPart\"o\n\x12\x43hatMessageContent\x12\x0e\n\x04text\x18\x01 \x01(\tH\x00\x12@\n\x11\x63ontent_part_list\x18\x02 \x01(\x0b\x32#.code_executor_grpc.ContentPartListH\x00\x42\x07\n\x05value\"B\n\x13\x43hatMessageToolCall\x12\n\n\x02id\x18\x01 \x01(\t\x12\x0c\n\x04name\x18\x02 \x01(\t\x12\x11\n\targuments\x18\x03 \x01(\t\"\xfe\x01\n\x0f\x43hatMessageList\x12\x41\n\x08messages\x18\x01 \x03(\x0b\x32/.code_executor_grpc.ChatMessageList.ChatMessage\x1a\xa7\x01\n\x0b\x43hatMessage\x12\x0c\n\x04role\x18\x01 \x01(\t\x12\x37\n\x07\x63ontent\x18\x02 \x01(\x0b\x32&.code_executor_grpc.ChatMessageContent\x12;\n\ntool_calls\x18\x03 \x03(\x0b\x32\'.code_executor_grpc.ChatMessageToolCall\x12\x14\n\x0ctool_call_id\x18\x04 \x01(\t\"\x1c\n\nStringList\x12\x0e\n\x06values\x18\x01 \x03(\t\"\xcf\x01\n\x03\x41rg\x12\x16\n\x0cstring_value\x18\x01 \x01(\tH\x00\x12=\n\x0emessages_value\x18\x02 \x01(\x0b\x32#.code_executor_grpc.ChatMessageListH\x00\x12;\n\x11string_list_value\x18\x03 \x01(\x0b\x32\x1e.code_executor_grpc.StringListH\x00\x12\x15\n\x0b\x66loat_value\x18\x04 \x01(\x01H\x00\x12\x14\n\nbool_value\x18\x05 \x01(\x08H\x00\x42\x07\n\x05value\"\xee\x01\n\x12\x45xecuteCodeRequest\x12\x0c\n\x04\x63ode\x18\x01 \x01(\t\x12\x0f\n\x07\x66n_name\x18\x02 \x01(\t\x12>\n\x04\x61rgs\x18\x03 \x03(\x0b\x32\x30.code_executor_grpc.ExecuteCodeRequest.ArgsEntry\x12\x33\n\x0breturn_type\x18\x04 \x01(\x0e\x32\x1e.code_executor_grpc.HandleType\x1a\x44\n\tArgsEntry\x12\x0b\n\x03key\x18\x01 \x01(\t\x12&\n\x05value\x18\x02 \x01(\x0b\x32\x17.code_executor_grpc.Arg:\x02\x38\x01\"\xb4\x01\n\x13\x45xecuteCodeResponse\x12)\n\x06result\x18\x01 \x01(\x0b\x32\x17.code_executor_grpc.ArgH\x00\x12\x45\n\x05\x65rror\x18\x02 \x01(\x0b\x32\x34.code_executor_grpc.ExecuteCodeResponse.ErrorMessageH\x00\x1a\x1f\n\x0c\x45rrorMessage\x12\x0f\n\x07message\x18\x01 \x01(\tB\n\n\x08response*T\n\nHandleType\x12\x07\n\x03\x41NY\x10\x00\x12\n\n\x06STRING\x10\x01\x12\x0f\n\x0bSTRING_LIST\x10\x02\x12\x15\n\x11\x43HAT_MESSAGE_LIST\x10\x03\x12\t\n\x05\x46LOAT\x10\x04\x32j\n\x0c\x43odeExecutor\x12Z\n\x07\x45xecute\x12&.code_executor_grpc.ExecuteCodeRequest\x1a\'.code_executor_grpc.ExecuteCodeResponseb\x06proto3')
 
 _globals = globals()
 _builder.BuildMessageAndEnumDescriptors(DESCRIPTOR, _globals)
@@ -23,8 +23,8 @@ if not _descriptor._USE_C_DESCRIPTORS:
   DESCRIPTOR._loaded_options = None
   _globals['_EXECUTECODEREQUEST_ARGSENTRY']._loaded_options = None
   _globals['_EXECUTECODEREQUEST_ARGSENTRY']._serialized_options = b'8\001'
-  _globals['_HANDLETYPE']._serialized_start=1405
-  _globals['_HANDLETYPE']._serialized_end=1489
+  _globals['_HANDLETYPE']._serialized_start=1557
+  _globals['_HANDLETYPE']._serialized_end=1641
   _globals['_CHATMESSAGETEXT']._serialized_start=48
   _globals['_CHATMESSAGETEXT']._serialized_end=79
   _globals['_CHATMESSAGEIMAGEURL']._serialized_start=81
@@ -37,22 +37,24 @@ if not _descriptor._USE_C_DESCRIPTORS:
   _globals['_CONTENTPARTLIST']._serialized_end=453
   _globals['_CHATMESSAGECONTENT']._serialized_start=455
   _globals['_CHATMESSAGECONTENT']._serialized_end=566
-  _globals['_CHATMESSAGELIST']._serialized_start=569
-  _globals['_CHATMESSAGELIST']._serialized_end=739
-  _globals['_CHATMESSAGELIST_CHATMESSAGE']._serialized_start=655
-  _globals['_CHATMESSAGELIST_CHATMESSAGE']._serialized_end=739
-  _globals['_STRINGLIST']._serialized_start=741
-  _globals['_STRINGLIST']._serialized_end=769
-  _globals['_ARG']._serialized_start=772
-  _globals['_ARG']._serialized_end=979
-  _globals['_EXECUTECODEREQUEST']._serialized_start=982
-  _globals['_EXECUTECODEREQUEST']._serialized_end=1220
-  _globals['_EXECUTECODEREQUEST_ARGSENTRY']._serialized_start=1152
-  _globals['_EXECUTECODEREQUEST_ARGSENTRY']._serialized_end=1220
-  _globals['_EXECUTECODERESPONSE']._serialized_start=1223
-  _globals['_EXECUTECODERESPONSE']._serialized_end=1403
-  _globals['_EXECUTECODERESPONSE_ERRORMESSAGE']._serialized_start=1360
-  _globals['_EXECUTECODERESPONSE_ERRORMESSAGE']._serialized_end=1391
-  _globals['_CODEEXECUTOR']._serialized_start=1491
-  _globals['_CODEEXECUTOR']._serialized_end=1597
+  _globals['_CHATMESSAGETOOLCALL']._serialized_start=568
+  _globals['_CHATMESSAGETOOLCALL']._serialized_end=634
+  _globals['_CHATMESSAGELIST']._serialized_start=637
+  _globals['_CHATMESSAGELIST']._serialized_end=891
+  _globals['_CHATMESSAGELIST_CHATMESSAGE']._serialized_start=724
+  _globals['_CHATMESSAGELIST_CHATMESSAGE']._serialized_end=891
+  _globals['_STRINGLIST']._serialized_start=893
+  _globals['_STRINGLIST']._serialized_end=921
+  _globals['_ARG']._serialized_start=924
+  _globals['_ARG']._serialized_end=1131
+  _globals['_EXECUTECODEREQUEST']._serialized_start=1134
+  _globals['_EXECUTECODEREQUEST']._serialized_end=1372
+  _globals['_EXECUTECODEREQUEST_ARGSENTRY']._serialized_start=1304
+  _globals['_EXECUTECODEREQUEST_ARGSENTRY']._serialized_end=1372
+  _globals['_EXECUTECODERESPONSE']._serialized_start=1375
+  _globals['_EXECUTECODERESPONSE']._serialized_end=1555
+  _globals['_EXECUTECODERESPONSE_ERRORMESSAGE']._serialized_start=1512
+  _globals['_EXECUTECODERESPONSE_ERRORMESSAGE']._serialized_end=1543
+  _globals['_CODEEXECUTOR']._serialized_start=1643
+  _globals['_CODEEXECUTOR']._serialized_end=1749
 # @@protoc_insertion_point(module_scope)
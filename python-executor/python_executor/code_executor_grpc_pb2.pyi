@@ -63,15 +63,29 @@ class ChatMessageContent(_message.Message):
     content_part_list: ContentPartList
     def __init__(self, text: _Optional[str] = ..., content_part_list: _Optional[_Union[ContentPartList, _Mapping]] = ...) -> None: ...
 
+class ChatMessageToolCall(_message.Message):
+    __slots__ = ("id", "name", "arguments")
+    ID_FIELD_NUMBER: _ClassVar[int]
+    NAME_FIELD_NUMBER: _ClassVar[int]
+    ARGUMENTS_FIELD_NUMBER: _ClassVar[int]
+    id: str
+    name: str
+    arguments: str
+    def __init__(self, id: _Optional[str] = ..., name: _Optional[str] = ..., arguments: _Optional[str] = ...) -> None: ...
+
 class ChatMessageList(_message.Message):
     __slots__ = ("messages",)
     class ChatMessage(_message.Message):
-        __slots__ = ("role", "content")
+        __slots__ = ("role", "content", "tool_calls", "tool_call_id")
         ROLE_FIELD_NUMBER: _ClassVar[int]
         CONTENT_FIELD_NUMBER: _ClassVar[int]
+        TOOL_CALLS_FIELD_NUMBER: _ClassVar[int]
+        TOOL_CALL_ID_FIELD_NUMBER: _ClassVar[int]
         role: str
         content: ChatMessageContent
-        def __init__(self, role: _Optional[str] = ..., content: _Optional[_Union[ChatMessageContent, _Mapping]] = ...) -> None: ...
+        tool_calls: _containers.RepeatedCompositeFieldContainer[ChatMessageToolCall]
+        tool_call_id: str
+        def __init__(self, role: _Optional[str] = ..., content: _Optional[_Union[ChatMessageContent, _Mapping]] = ..., tool_calls: _Optional[_Iterable[_Union[ChatMessageToolCall, _Mapping]]] = ..., tool_call_id: _Optional[str] = ...) -> None: ...
     MESSAGES_FIELD_NUMBER: _ClassVar[int]
     messages: _containers.RepeatedCompositeFieldContainer[ChatMessageList.ChatMessage]
     def __init__(self, messages: _Optional[_Iterable[_Union[ChatMessageList.ChatMessage, _Mapping]]] = ...) -> None: ...
@@ -3,11 +3,34 @@ If you define any type in this file, make sure to re-import it in server.py,
 because executed code needs to see the type definitions.
 """
 
+from typing import Any, Optional
+
+
+class ToolCall:
+    id: Optional[str]
+    name: str
+    arguments: Any  # usually a dict, or the raw string if it is not valid JSON
+
+    def __init__(self, name: str, arguments: Any, id: Optional[str] = None) -> None:
+        self.id = id
+        self.name = name
+        self.arguments = arguments
+
 
 class ChatMessage:
     role: str
     content: str  # TODO: support list[ChatMessageContentPart] later
+    tool_calls: list[ToolCall]
+    tool_call_id: Optional[str]  # set on messages with the "tool" role
 
-    def __init__(self, role: str, content: str) -> None:
+    def __init__(
+        self,
+        role: str,
+        content: str,
+        tool_calls: Optional[list[ToolCall]] = None,
+        tool_call_id: Optional[str] = None,
+    ) -> None:
         self.role = role
         self.content = content
+        self.tool_calls = tool_calls or []
+        self.tool_call_id = tool_call_id
@@ -1,5 +1,6 @@
 from concurrent import futures
 
+import json
 import logging
 from python_executor.log import VerboseColorfulFormatter
 import grpc
@@ -17,7 +18,7 @@ from python_executor.code_executor_grpc_pb2_grpc import (
 from python_executor.code_executor_grpc_pb2_grpc import (
     CodeExecutorServicer as GrpcCodeExecutorServicer,
 )
-from python_executor.lmnr_types import ChatMessage
+from python_executor.lmnr_types import ChatMessage, ToolCall
 from typing import Any
 
 from python_executor.utils import (
@@ -49,6 +50,26 @@ Returns assignment string, e.g. "msg = ChatMessage(role='bot', content='Hello')"
 """
 
 
+def parse_tool_call_arguments(arguments: str) -> Any:
+    try:
+        return json.loads(arguments)
+    except json.JSONDecodeError:
+        return arguments
+
+
+def to_chat_message_str(message: ChatMessageList.ChatMessage) -> str:
+    tool_calls_str = ", ".join(
+        [
+            f"ToolCall(name={repr(tool_call.name)}, arguments={repr(parse_tool_call_arguments(tool_call.arguments))}, id={repr(tool_call.id or None)})"
+            for tool_call in message.tool_calls
+        ]
+    )
+    return (
+        f"ChatMessage(role={repr(message.role)}, content={repr(message.content.text)}, "
+        f"tool_calls=[{tool_calls_str}], tool_call_id={repr(message.tool_call_id or None)})"
+    )
+
+
 def to_assignment_str(var: str, arg: Arg) -> str:
     fieldname = arg.WhichOneof("value")
     data = getattr(arg, fieldname)
@@ -60,10 +81,7 @@ def to_assignment_str(var: str, arg: Arg) -> str:
                     "only text is supported in ChatMessage. Images will be supported later."
                 )
         chat_messages_str = ", ".join(
-            [
-                f"ChatMessage(role={repr(message.role)}, content={repr(message.content.text)})"
-                for message in data.messages
-            ]
+            [to_chat_message_str(message) for message in data.messages]
         )
         return f"{var} = [{chat_messages_str}]"
 
@@ -1,12 +1,15 @@
+import json
+
 from python_executor.code_executor_grpc_pb2 import (
     Arg,
     ChatMessageContent,
     ChatMessageList,
+    ChatMessageToolCall,
     ExecuteCodeResponse,
     HandleType,
     StringList,
 )
-from python_executor.lmnr_types import ChatMessage
+from python_executor.lmnr_types import ChatMessage, ToolCall
 
 
 def handle_string(
@@ -59,6 +62,17 @@ def handle_string_list(
     )
 
 
+def to_tool_call(tool_call: ToolCall) -> ChatMessageToolCall:
+    arguments = (
+        tool_call.arguments
+        if isinstance(tool_call.arguments, str)
+        else json.dumps(tool_call.arguments)
+    )
+    return ChatMessageToolCall(
+        id=tool_call.id or "", name=tool_call.name, arguments=arguments
+    )
+
+
 def handle_chat_message_list(
     exec_result: list[ChatMessage], expected_return_type: HandleType
 ) -> ExecuteCodeResponse:
@@ -79,7 +93,10 @@ def handle_chat_message_list(
         )
     chat_messages = [
         ChatMessageList.ChatMessage(
-            role=message.role, content=ChatMessageContent(text=message.content)
+            role=message.role,
+            content=ChatMessageContent(text=message.content),
+            tool_calls=[to_tool_call(tool_call) for tool_call in message.tool_calls],
+            tool_call_id=message.tool_call_id or "",
         )
         for message in exec_result
     ]